@@ -0,0 +1,53 @@
+/*
+ * C interface for embedding the Rote task runner.
+ *
+ * All functions returning `int` return 0 on success and -1 on error, in which
+ * case `rote_last_error()` describes the error.
+ */
+#ifndef ROTE_H
+#define ROTE_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define ROTE_EVENT_TASK_STARTED 1
+#define ROTE_EVENT_TASK_FINISHED 2
+#define ROTE_EVENT_RUN_FINISHED 3
+
+typedef struct rote_runner rote_runner;
+
+typedef void (*rote_list_callback)(const char *name, const char *description, void *data);
+typedef void (*rote_event_callback)(int event, const char *task, const char *error, void *data);
+
+/* Creates a runner for the Rotefile at the given path, or returns NULL. */
+rote_runner *rote_runner_new(const char *path);
+
+/* Frees a runner. */
+void rote_runner_free(rote_runner *runner);
+
+/* Loads the runner's Rotefile. */
+int rote_runner_load(rote_runner *runner);
+
+/* Calls `callback` for each named task. The description may be NULL. */
+int rote_runner_list_tasks(rote_runner *runner, rote_list_callback callback, void *data);
+
+/* Runs the given tasks, or the default task if `count` is 0. */
+int rote_runner_run(rote_runner *runner, const char *const *tasks, size_t count);
+
+/* Registers a callback to receive events while tasks run. */
+int rote_runner_add_callback(rote_runner *runner, rote_event_callback callback, void *data);
+
+/* Gets the last error on the current thread, or NULL. */
+const char *rote_last_error(void);
+
+/* Gets the library version. */
+const char *rote_version(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
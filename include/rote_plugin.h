@@ -0,0 +1,39 @@
+/*
+ * C interface for native Rote plugins.
+ *
+ * A plugin is a shared library exporting `rote_module_entry`, which Rote
+ * calls when the plugin is loaded. The entry function registers Lua modules
+ * with the given registry and returns 0 on success.
+ */
+#ifndef ROTE_PLUGIN_H
+#define ROTE_PLUGIN_H
+
+#include <lua.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define ROTE_PLUGIN_API_VERSION 1
+
+typedef struct rote_registry rote_registry;
+
+struct rote_registry {
+    /* Version of the plugin interface of the running Rote. */
+    int version;
+
+    /* Registers a module loaded by `loader` under `name`. The name is copied. */
+    void (*register_module)(rote_registry *registry, const char *name, lua_CFunction loader);
+
+    /* Private to Rote. */
+    void *modules;
+};
+
+/* Entry function every plugin must export. */
+int rote_module_entry(rote_registry *registry);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
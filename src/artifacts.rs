@@ -0,0 +1,388 @@
+//! Stores the outputs of tasks so that they can be restored instead of running the task again.
+//!
+//! A task that sets `cache = true` and declares its inputs and outputs has its outputs copied into
+//! the output cache after it runs successfully, under a key computed from the contents of its
+//! inputs, the names of its outputs, and the Rotefile. When the task runs again with the same key,
+//! its outputs are copied back from the cache and its action is skipped.
+//!
+//! The cache is kept in `.rote/outputs` in the project directory, or in the directory given by the
+//! `ROTE_OUTPUT_CACHE` environment variable, which may be shared between several checkouts.
+//!
+//! Entries can also be shared with other machines through a remote cache `Backend`, such as a
+//! server that CI machines upload their outputs to and developers download them from. Entries that
+//! aren't in the local cache are looked up in the remote cache, and every entry stored locally is
+//! uploaded to it as a gzipped tar archive.
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use hash;
+use hyper::client::Client;
+use modules::fs::read_file;
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tar;
+use task::Task;
+
+
+/// Version of the key format. Changing it makes every existing entry unreachable.
+const VERSION: u32 = 1;
+
+/// A place that entries of the output cache are shared through, as archives named after their
+/// keys.
+pub trait Backend {
+    /// Downloads the archive of an entry to a file. Returns `false` if there is no such entry.
+    fn get(&self, key: &str, dest: &Path) -> Result<bool, Box<Error>>;
+
+    /// Uploads the archive of an entry from a file.
+    fn put(&self, key: &str, src: &Path) -> Result<(), Box<Error>>;
+}
+
+/// Creates the backend of a remote cache URL. HTTP URLs use a server that entries are read from with
+/// GET requests and written to with PUT requests, and `file://` URLs use a directory, such as one on
+/// a network drive.
+pub fn backend(url: &str) -> Result<Box<Backend>, Box<Error>> {
+    // Rote is built without TLS support, so every request would fail.
+    if url.starts_with("https://") {
+        Err(format!("cache URL '{}' uses HTTPS, which is not supported; use an http:// URL, such \
+                     as one of a proxy that adds TLS, or a file:// URL",
+                    url)
+            .into())
+    } else if url.starts_with("http://") {
+        Ok(Box::new(HttpBackend {
+            url: url.trim_right_matches('/').to_string(),
+        }))
+    } else if url.starts_with("file://") {
+        Ok(Box::new(DirectoryBackend {
+            directory: PathBuf::from(&url[7..]),
+        }))
+    } else {
+        Err(format!("unsupported cache URL '{}'", url).into())
+    }
+}
+
+/// A remote cache on an HTTP server.
+struct HttpBackend {
+    url: String,
+}
+
+impl HttpBackend {
+    fn entry_url(&self, key: &str) -> String {
+        format!("{}/{}.tar.gz", self.url, key)
+    }
+}
+
+impl Backend for HttpBackend {
+    fn get(&self, key: &str, dest: &Path) -> Result<bool, Box<Error>> {
+        let mut response = try!(Client::new().get(&self.entry_url(key)).send());
+
+        let status = response.status.to_u16();
+        if status == 404 {
+            return Ok(false);
+        }
+        if status < 200 || status >= 300 {
+            return Err(format!("server responded with status {}", status).into());
+        }
+
+        try!(io::copy(&mut response, &mut try!(File::create(dest))));
+        Ok(true)
+    }
+
+    fn put(&self, key: &str, src: &Path) -> Result<(), Box<Error>> {
+        let mut body = Vec::new();
+        try!(try!(File::open(src)).read_to_end(&mut body));
+
+        let response = try!(Client::new().put(&self.entry_url(key)).body(&body[..]).send());
+
+        let status = response.status.to_u16();
+        if status < 200 || status >= 300 {
+            return Err(format!("server responded with status {}", status).into());
+        }
+
+        Ok(())
+    }
+}
+
+/// A remote cache in a directory.
+struct DirectoryBackend {
+    directory: PathBuf,
+}
+
+impl Backend for DirectoryBackend {
+    fn get(&self, key: &str, dest: &Path) -> Result<bool, Box<Error>> {
+        match fs::copy(self.directory.join(format!("{}.tar.gz", key)), dest) {
+            Ok(_) => Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn put(&self, key: &str, src: &Path) -> Result<(), Box<Error>> {
+        try!(fs::create_dir_all(&self.directory));
+
+        // Copy to a temporary file first, so that readers never see a partial archive.
+        let temp = self.directory.join(format!(".{}-{}.tar.gz", key, stamp()));
+        let result = fs::copy(src, &temp).and_then(|_| fs::rename(&temp, self.directory.join(format!("{}.tar.gz", key))));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+
+        result.map_err(|e| e.into())
+    }
+}
+
+
+/// A directory of task outputs, keyed by the inputs they were built from.
+pub struct OutputCache {
+    directory: PathBuf,
+    remote: Option<Box<Backend>>,
+}
+
+impl OutputCache {
+    /// Opens the output cache of the project in the given directory.
+    pub fn open<P: AsRef<Path>>(project_dir: P) -> OutputCache {
+        let directory = match env::var_os("ROTE_OUTPUT_CACHE") {
+            Some(dir) => PathBuf::from(dir),
+            None => project_dir.as_ref().join(".rote").join("outputs"),
+        };
+
+        OutputCache {
+            directory: directory,
+            remote: None,
+        }
+    }
+
+    /// Shares entries with a remote cache.
+    pub fn remote(mut self, backend: Box<Backend>) -> OutputCache {
+        self.remote = Some(backend);
+        self
+    }
+
+    /// Computes the key of the outputs of a task from its current inputs.
+    ///
+    /// The task's action can't be hashed, so the contents of every loaded script, including the
+    /// ones it included, are part of the key instead; editing any of them means every cached task
+    /// runs again.
+    pub fn key<P: AsRef<Path>>(&self, task: &Task, scripts: &[P]) -> io::Result<String> {
+        let mut data = format!("rote outputs {}\n{}\n", VERSION, task.name()).into_bytes();
+
+        // A task may be defined in an included script, or use functions defined in one.
+        for script in scripts {
+            data.extend(format!("script {}\n", hash::to_hex(&try!(hash::sha256_file(script)))).bytes());
+        }
+
+        // Dependencies that are files are read by the task just like its inputs are.
+        let mut inputs: Vec<&String> = task.inputs()
+            .iter()
+            .chain(task.dependencies().iter().filter(|dependency| Path::new(dependency).is_file()))
+            .collect();
+        inputs.sort();
+        inputs.dedup();
+
+        for input in inputs {
+            // Inputs that don't exist are ignored when checking if a task is up to date, so they
+            // are recorded as missing instead of failing.
+            let digest = match read_file(input) {
+                Ok(contents) => hash::to_hex(&hash::sha256(&contents)),
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::from("-"),
+                Err(e) => return Err(e),
+            };
+            data.extend(format!("input {} {}\n", input, digest).bytes());
+        }
+
+        for output in task.outputs() {
+            data.extend(format!("output {}\n", output).bytes());
+        }
+
+        Ok(hash::to_hex(&hash::sha256(&data)))
+    }
+
+    /// Copies the outputs stored under a key back into place, downloading the entry from the
+    /// remote cache first if it isn't in the local one. Returns `false` if there is no complete
+    /// entry for the key, in which case no outputs are changed.
+    pub fn restore(&self, key: &str, task: &Task) -> Result<bool, Box<Error>> {
+        let entry = self.directory.join(key);
+        let outputs = task.outputs();
+
+        if !entry.is_dir() && !try!(self.download(key)) {
+            return Ok(false);
+        }
+
+        if !entry.is_dir() || (0..outputs.len()).any(|i| fs::symlink_metadata(entry.join(i.to_string())).is_err()) {
+            return Ok(false);
+        }
+
+        for (i, output) in outputs.iter().enumerate() {
+            let output = Path::new(output);
+            try!(remove_path(output));
+
+            if let Some(parent) = output.parent() {
+                if !parent.as_os_str().is_empty() {
+                    try!(fs::create_dir_all(parent));
+                }
+            }
+
+            try!(copy_path(&entry.join(i.to_string()), output));
+        }
+
+        Ok(true)
+    }
+
+    /// Stores copies of the outputs of a task that has just run successfully under a key, and
+    /// uploads them to the remote cache. Nothing is stored if the task didn't create all of its
+    /// outputs.
+    pub fn store(&self, key: &str, task: &Task) -> Result<(), Box<Error>> {
+        let outputs = task.outputs();
+        if let Some(missing) = outputs.iter().find(|output| fs::metadata(output).is_err()) {
+            debug!("not caching outputs of task '{}', since '{}' was not created", task.name(), missing);
+            return Ok(());
+        }
+
+        let entry = self.directory.join(key);
+        if !entry.is_dir() {
+            // Copy into a temporary directory first, so that an interrupted copy never leaves an
+            // incomplete entry, and tasks storing the same key on other threads don't collide.
+            let temp = self.directory.join(format!(".{}-{}", key, stamp()));
+            try!(fs::create_dir_all(&temp));
+
+            let result = outputs.iter()
+                .enumerate()
+                .map(|(i, output)| copy_path(Path::new(output), &temp.join(i.to_string())))
+                .collect::<io::Result<Vec<_>>>()
+                .and_then(|_| fs::rename(&temp, &entry));
+
+            if result.is_err() || temp.exists() {
+                let _ = fs::remove_dir_all(&temp);
+            }
+
+            // Another run may have stored the same entry in the meantime, which is just as good.
+            if !entry.is_dir() {
+                try!(result);
+            }
+        }
+
+        self.upload(key)
+    }
+
+    /// Downloads an entry from the remote cache into the local one. Returns `false` if there is no
+    /// remote cache or it doesn't have the entry.
+    fn download(&self, key: &str) -> Result<bool, Box<Error>> {
+        let remote = match self.remote {
+            Some(ref remote) => remote,
+            None => return Ok(false),
+        };
+
+        try!(fs::create_dir_all(&self.directory));
+
+        let temp = self.directory.join(format!(".{}-{}", key, stamp()));
+        let archive = temp.with_extension("tar.gz");
+
+        let result = remote.get(key, &archive).and_then(|found| {
+            if found {
+                debug!("downloaded cache entry {}", key);
+                try!(unpack(&archive, &temp));
+                try!(fs::rename(&temp, self.directory.join(key)));
+            }
+            Ok(found)
+        });
+
+        let _ = fs::remove_file(&archive);
+        if temp.exists() {
+            let _ = fs::remove_dir_all(&temp);
+        }
+
+        result
+    }
+
+    /// Uploads an entry of the local cache to the remote cache, if there is one.
+    fn upload(&self, key: &str) -> Result<(), Box<Error>> {
+        let remote = match self.remote {
+            Some(ref remote) => remote,
+            None => return Ok(()),
+        };
+
+        let archive = self.directory.join(format!(".{}-{}.tar.gz", key, stamp()));
+        let result = pack(&self.directory.join(key), &archive).map_err(|e| e.into()).and_then(|_| {
+            remote.put(key, &archive)
+        });
+
+        let _ = fs::remove_file(&archive);
+        if result.is_ok() {
+            debug!("uploaded cache entry {}", key);
+        }
+
+        result
+    }
+}
+
+/// Writes the contents of a directory to a gzipped tar archive.
+fn pack(dir: &Path, dest: &Path) -> io::Result<()> {
+    let encoder = GzEncoder::new(try!(File::create(dest)), Compression::Default);
+    let mut builder = tar::Builder::new(encoder);
+
+    try!(append_dir(&mut builder, dir, Path::new("")));
+    try!(try!(builder.into_inner()).finish());
+
+    Ok(())
+}
+
+/// Adds everything in a directory to a tar archive, with names relative to `name`.
+fn append_dir<W: Write>(builder: &mut tar::Builder<W>, dir: &Path, name: &Path) -> io::Result<()> {
+    let mut entries = try!(fs::read_dir(dir).and_then(|entries| entries.collect::<io::Result<Vec<_>>>()));
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let name = name.join(entry.file_name());
+
+        if try!(fs::metadata(&path)).is_dir() {
+            try!(builder.append_dir(&name, &path));
+            try!(append_dir(builder, &path, &name));
+        } else {
+            try!(builder.append_file(&name, &mut try!(File::open(&path))));
+        }
+    }
+
+    Ok(())
+}
+
+/// Extracts a gzipped tar archive into a directory.
+fn unpack(archive: &Path, dest: &Path) -> io::Result<()> {
+    try!(fs::create_dir_all(dest));
+    tar::Archive::new(try!(GzDecoder::new(try!(File::open(archive))))).unpack(dest)
+}
+
+/// Gets a number to tell temporary files apart.
+fn stamp() -> u32 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0)
+}
+
+/// Copies a file, or a directory and everything in it.
+fn copy_path(from: &Path, to: &Path) -> io::Result<()> {
+    if try!(fs::metadata(from)).is_dir() {
+        try!(fs::create_dir_all(to));
+
+        for entry in try!(fs::read_dir(from)) {
+            let entry = try!(entry);
+            try!(copy_path(&entry.path(), &to.join(entry.file_name())));
+        }
+
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Removes a file or directory, if it exists.
+fn remove_path(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(ref metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
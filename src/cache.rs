@@ -0,0 +1,408 @@
+//! Remembers the contents of task inputs and outputs between runs.
+//!
+//! File tasks are normally up to date when their output is newer than their inputs, but timestamps
+//! change for many reasons that don't change any contents, such as checking out a branch or
+//! restoring files from a backup. After a file task runs, a fingerprint of each of its input files
+//! and its output is saved in `.rote/cache`, and a task whose files still match their fingerprints
+//! is considered up to date no matter what their timestamps say.
+//!
+//! When checking by hash, timestamps aren't looked at at all for tasks that have fingerprints: a
+//! file task is up to date exactly when its files match their fingerprints.
+//!
+//! Reading every input of a large project to fingerprint it takes a while, so the fingerprint of
+//! each file is saved along with its size and modification time, and a file is only read again
+//! once one of those changes. Files that do need to be read are fingerprinted on several threads.
+use json::{self, JsonValue};
+use modules::fs::read_file;
+use num_cpus;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::UNIX_EPOCH;
+use task::Task;
+
+
+/// Version of the cache file format. Caches written in other versions are ignored.
+const VERSION: u32 = 1;
+
+/// Fingerprints of the files of a task when it last ran successfully.
+struct Entry {
+    output: u64,
+    inputs: Vec<(String, u64)>,
+}
+
+/// The size and modification time of a file, which tell if it may have changed since it was
+/// fingerprinted.
+#[derive(Clone, Copy, PartialEq)]
+struct Stamp {
+    size: u64,
+    modified: u64,
+}
+
+/// A persistent cache of file fingerprints.
+pub struct Cache {
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+    changed: bool,
+
+    /// Fingerprints of files by their size and modification time, kept between runs.
+    files: RefCell<HashMap<String, (Stamp, u64)>>,
+    files_changed: Cell<bool>,
+
+    /// Whether only fingerprints decide if a file task is up to date.
+    hashes_only: bool,
+
+    /// Fingerprints computed since the cache was opened, so that files read by many tasks are only
+    /// read once.
+    fingerprints: RefCell<HashMap<String, Option<u64>>>,
+}
+
+impl Cache {
+    /// Opens the cache of the project in the given directory. If there is no cache yet or it can't
+    /// be read, the cache starts out empty.
+    pub fn open<P: AsRef<Path>>(directory: P) -> Cache {
+        let path = directory.as_ref().join(".rote").join("cache");
+        let (entries, files) = match load(&path) {
+            Ok(cache) => cache,
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("ignoring build cache: {}", e);
+                }
+                (HashMap::new(), HashMap::new())
+            }
+        };
+
+        Cache {
+            path: path,
+            entries: entries,
+            changed: false,
+            files: RefCell::new(files),
+            files_changed: Cell::new(false),
+            hashes_only: false,
+            fingerprints: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Decides if file tasks are up to date by the contents of their files only, ignoring their
+    /// timestamps. Tasks that haven't run yet are still checked by their timestamps.
+    pub fn hashes_only(mut self, hashes_only: bool) -> Cache {
+        self.hashes_only = hashes_only;
+        self
+    }
+
+    /// Checks if the files of a task are checked by their contents only.
+    pub fn checks_hashes(&self, task: &Task) -> bool {
+        self.hashes_only && task.is_file() && self.entries.contains_key(task.name())
+    }
+
+    /// Checks if the files of a task are the same as when it last ran successfully.
+    pub fn unchanged(&self, task: &Task) -> bool {
+        let entry = match self.entries.get(task.name()) {
+            Some(entry) => entry,
+            None => return false,
+        };
+
+        // The inputs must be the same files, since the rule may have changed.
+        let inputs = input_files(task);
+        if inputs.len() != entry.inputs.len() ||
+           inputs.iter().zip(&entry.inputs).any(|(input, &(ref name, _))| input != name) {
+            return false;
+        }
+
+        self.fingerprint_all(&inputs);
+
+        self.fingerprint(task.name()) == Some(entry.output) &&
+        inputs.iter().zip(&entry.inputs).all(|(input, &(_, hash))| self.fingerprint(input) == Some(hash))
+    }
+
+    /// Gets the fingerprint of a file, computing it only the first time.
+    fn fingerprint(&self, path: &str) -> Option<u64> {
+        if let Some(&hash) = self.fingerprints.borrow().get(path) {
+            return hash;
+        }
+
+        self.fingerprint_all(&[path.to_string()]);
+        self.fingerprints.borrow().get(path).cloned().unwrap_or(None)
+    }
+
+    /// Computes the fingerprints of the given files that haven't been computed yet. Files whose
+    /// size and modification time are the same as when they were last fingerprinted keep their
+    /// fingerprint, and the others are read on several threads at once.
+    fn fingerprint_all(&self, paths: &[String]) {
+        let mut unknown = Vec::new();
+
+        {
+            let mut fingerprints = self.fingerprints.borrow_mut();
+            let files = self.files.borrow();
+
+            for path in paths {
+                if fingerprints.contains_key(path) {
+                    continue;
+                }
+
+                match stamp(path) {
+                    Some(stamp) => {
+                        match files.get(path) {
+                            Some(&(known, hash)) if known == stamp => {
+                                fingerprints.insert(path.clone(), Some(hash));
+                            }
+                            _ => unknown.push((path.clone(), stamp)),
+                        }
+                    }
+                    None => {
+                        fingerprints.insert(path.clone(), None);
+                    }
+                }
+            }
+        }
+
+        if unknown.is_empty() {
+            return;
+        }
+
+        for (path, stamp, hash) in fingerprint_files(unknown) {
+            if let Some(hash) = hash {
+                self.files.borrow_mut().insert(path.clone(), (stamp, hash));
+                self.files_changed.set(true);
+            }
+            self.fingerprints.borrow_mut().insert(path, hash);
+        }
+    }
+
+    /// Remembers the files of a task that has just run successfully.
+    pub fn record(&mut self, task: &Task) {
+        // The task has just written its output, and may have changed its inputs too.
+        self.fingerprints.borrow_mut().clear();
+
+        let mut files = input_files(task);
+        files.push(task.name().to_string());
+        self.fingerprint_all(&files);
+
+        let output = match self.fingerprint(task.name()) {
+            Some(output) => output,
+            None => {
+                self.forget(task.name());
+                return;
+            }
+        };
+
+        let mut inputs = Vec::new();
+        for input in input_files(task) {
+            match self.fingerprint(&input) {
+                Some(hash) => inputs.push((input, hash)),
+                None => {
+                    self.forget(task.name());
+                    return;
+                }
+            }
+        }
+
+        self.entries.insert(task.name().to_string(), Entry {
+            output: output,
+            inputs: inputs,
+        });
+        self.changed = true;
+    }
+
+    /// Forgets the files of a task, such as when it fails.
+    pub fn forget(&mut self, name: &str) {
+        if self.entries.remove(name).is_some() {
+            self.changed = true;
+        }
+    }
+
+    /// Writes the cache to disk if anything has changed.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.changed && !self.files_changed.get() {
+            return Ok(());
+        }
+
+        let mut tasks = JsonValue::new_object();
+        for (name, entry) in &self.entries {
+            let mut inputs = JsonValue::new_object();
+            for &(ref input, hash) in &entry.inputs {
+                inputs[input.as_str()] = format!("{:016x}", hash).into();
+            }
+
+            let mut value = JsonValue::new_object();
+            value["output"] = format!("{:016x}", entry.output).into();
+            value["inputs"] = inputs;
+            tasks[name.as_str()] = value;
+        }
+
+        // Only keep the fingerprints of files that some task still uses.
+        let used: HashSet<&str> = self.entries
+            .iter()
+            .flat_map(|(name, entry)| {
+                Some(name.as_str()).into_iter().chain(entry.inputs.iter().map(|&(ref input, _)| input.as_str()))
+            })
+            .collect();
+
+        let mut files = JsonValue::new_object();
+        for (path, &(stamp, hash)) in self.files.borrow().iter() {
+            if used.contains(path.as_str()) {
+                let mut value = JsonValue::new_object();
+                value["size"] = stamp.size.into();
+                value["modified"] = stamp.modified.into();
+                value["hash"] = format!("{:016x}", hash).into();
+                files[path.as_str()] = value;
+            }
+        }
+
+        let mut cache = JsonValue::new_object();
+        cache["version"] = VERSION.into();
+        cache["tasks"] = tasks;
+        cache["files"] = files;
+
+        if let Some(directory) = self.path.parent() {
+            try!(fs::create_dir_all(directory));
+        }
+
+        // Write to a temporary file first, so that an interrupted write can't corrupt the cache.
+        let temp = self.path.with_extension("tmp");
+        try!(try!(File::create(&temp)).write_all(cache.dump().as_bytes()));
+        try!(fs::rename(&temp, &self.path));
+
+        self.changed = false;
+        self.files_changed.set(false);
+        Ok(())
+    }
+}
+
+/// Reads the entries and file fingerprints of a cache file.
+fn load(path: &Path) -> io::Result<(HashMap<String, Entry>, HashMap<String, (Stamp, u64)>)> {
+    let mut contents = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut contents));
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid cache file");
+    let cache = try!(json::parse(&contents).map_err(|_| invalid()));
+    let mut entries = HashMap::new();
+    let mut files = HashMap::new();
+
+    if cache["version"].as_u32() != Some(VERSION) {
+        return Ok((entries, files));
+    }
+
+    for (name, value) in cache["tasks"].entries() {
+        let output = try!(parse_hash(&value["output"]).ok_or_else(&invalid));
+        let mut inputs = Vec::new();
+
+        for (input, hash) in value["inputs"].entries() {
+            inputs.push((input.to_string(), try!(parse_hash(hash).ok_or_else(&invalid))));
+        }
+        inputs.sort();
+
+        entries.insert(name.to_string(), Entry {
+            output: output,
+            inputs: inputs,
+        });
+    }
+
+    // Caches written before file fingerprints were kept don't have any.
+    for (path, value) in cache["files"].entries() {
+        let stamp = Stamp {
+            size: try!(value["size"].as_u64().ok_or_else(&invalid)),
+            modified: try!(value["modified"].as_u64().ok_or_else(&invalid)),
+        };
+        let hash = try!(parse_hash(&value["hash"]).ok_or_else(&invalid));
+        files.insert(path.to_string(), (stamp, hash));
+    }
+
+    Ok((entries, files))
+}
+
+fn parse_hash(value: &JsonValue) -> Option<u64> {
+    value.as_str().and_then(|hash| u64::from_str_radix(hash, 16).ok())
+}
+
+/// Gets the inputs of a task that are files, sorted by name.
+fn input_files(task: &Task) -> Vec<String> {
+    let mut inputs: Vec<String> = task.dependencies()
+        .iter()
+        .chain(task.files())
+        .filter(|input| Path::new(input).is_file())
+        .cloned()
+        .collect();
+    inputs.sort();
+    inputs.dedup();
+    inputs
+}
+
+/// Gets the size and modification time of a file, in nanoseconds since the Unix epoch.
+fn stamp(path: &str) -> Option<Stamp> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return None,
+    };
+
+    metadata.modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|time| {
+            Stamp {
+                size: metadata.len(),
+                modified: time.as_secs() * 1_000_000_000 + time.subsec_nanos() as u64,
+            }
+        })
+}
+
+/// Fingerprints files on as many threads as there are CPU cores.
+fn fingerprint_files(files: Vec<(String, Stamp)>) -> Vec<(String, Stamp, Option<u64>)> {
+    if files.len() == 1 {
+        return files.into_iter().map(|(path, stamp)| {
+            let hash = fingerprint(&path).ok();
+            (path, stamp, hash)
+        }).collect();
+    }
+
+    let jobs = cmp::min(num_cpus::get(), files.len());
+    let queue = Arc::new(Mutex::new(files));
+    let results = Arc::new(Mutex::new(Vec::new()));
+
+    let threads: Vec<_> = (0..jobs).map(|_| {
+        let queue = queue.clone();
+        let results = results.clone();
+
+        thread::spawn(move || {
+            loop {
+                let next = queue.lock().unwrap().pop();
+                let (path, stamp) = match next {
+                    Some(file) => file,
+                    None => break,
+                };
+
+                let hash = fingerprint(&path).ok();
+                results.lock().unwrap().push((path, stamp, hash));
+            }
+        })
+    }).collect();
+
+    for thread in threads {
+        // Files of a thread that panicked are left without a fingerprint, which only makes the
+        // tasks using them run again.
+        let _ = thread.join();
+    }
+
+    let mut results = results.lock().unwrap();
+    results.drain(..).collect()
+}
+
+/// Computes a fingerprint of the contents of a file, using 64-bit FNV-1a.
+///
+/// This is not a cryptographic hash, and only needs to tell when a file has changed.
+pub fn fingerprint<P: AsRef<Path>>(path: P) -> io::Result<u64> {
+    let contents = try!(read_file(path));
+    let mut hash: u64 = 0xcbf29ce484222325;
+
+    for &byte in contents.iter() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    Ok(hash)
+}
@@ -0,0 +1,20 @@
+//! Shell completion scripts.
+//!
+//! The scripts complete task names by running `rote --list --format=plain` in the current directory, so
+//! they don't need to be regenerated when tasks change.
+use std::error::Error;
+
+
+const BASH: &'static str = include_str!("rote.bash");
+const FISH: &'static str = include_str!("rote.fish");
+const ZSH: &'static str = include_str!("rote.zsh");
+
+/// Gets the completion script for the given shell (bash, fish, zsh).
+pub fn script(shell: &str) -> Result<&'static str, Box<Error>> {
+    match shell {
+        "bash" => Ok(BASH),
+        "fish" => Ok(FISH),
+        "zsh" => Ok(ZSH),
+        _ => Err(format!("unsupported shell '{}'", shell).into()),
+    }
+}
@@ -0,0 +1,18 @@
+# Bash completion for rote. Load it with:
+#
+#     source <(rote --completions bash)
+
+_rote() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+
+    # Leave options and their values to the default completion.
+    if [[ "$cur" == -* ]]; then
+        return
+    fi
+
+    local tasks
+    tasks="$(rote --list --format=plain 2>/dev/null | cut -f1)"
+    COMPREPLY=($(compgen -W "$tasks" -- "$cur"))
+}
+
+complete -o default -F _rote rote
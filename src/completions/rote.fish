@@ -0,0 +1,7 @@
+# Fish completion for rote. Load it with:
+#
+#     rote --completions fish | source
+
+# The first two columns of each line are a task name and its description separated by a tab, just
+# like fish expects.
+complete -c rote -a '(rote --list --format=plain 2>/dev/null | cut -f1,2)'
@@ -0,0 +1,377 @@
+//! User and system configuration files.
+//!
+//! Settings that apply to every project, such as the number of jobs to run or extra module include
+//! paths, can be set in `/etc/rote/config.toml` for the whole system and in
+//! `~/.config/rote/config.toml` for a user. The user configuration overrides the system one, and
+//! flags given on the command line override both.
+//!
+//! Only the parts of TOML needed for these settings are understood: tables, strings, integers,
+//! booleans, and arrays of those, which may span several lines.
+use std::cmp;
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+
+
+/// Settings read from configuration files.
+#[derive(Default)]
+pub struct Config {
+    /// Number of jobs to run at once, in any form accepted by `--jobs`.
+    pub jobs: Option<String>,
+
+    /// Extra paths to search for modules.
+    pub include_paths: Vec<PathBuf>,
+
+    /// Whether output should be colored.
+    pub color: Option<bool>,
+
+    /// Environment variables to set before loading the Rotefile.
+    pub env: Vec<(String, String)>,
+
+    /// URL of the remote cache to share cached task outputs through.
+    pub cache_url: Option<String>,
+}
+
+impl Config {
+    /// Loads the system and user configuration files that exist. Files that can't be read are
+    /// skipped with a warning.
+    pub fn load() -> Config {
+        let mut config = Config::default();
+
+        for path in paths() {
+            if !path.is_file() {
+                continue;
+            }
+
+            debug!("reading configuration from '{}'", path.to_string_lossy());
+
+            match read(&path) {
+                Ok(other) => config.merge(other),
+                Err(e) => warn!("ignoring configuration file '{}': {}", path.to_string_lossy(), e),
+            }
+        }
+
+        config
+    }
+
+    /// Parses the contents of a configuration file.
+    pub fn parse(source: &str) -> Result<Config, Box<Error>> {
+        let mut config = Config::default();
+
+        for (key, value) in try!(parse_toml(source)) {
+            match (key.as_str(), value) {
+                ("jobs", Value::Integer(jobs)) => config.jobs = Some(jobs.to_string()),
+                ("jobs", Value::String(jobs)) => config.jobs = Some(jobs),
+                ("color", Value::Boolean(color)) => config.color = Some(color),
+                ("cache_url", Value::String(url)) => config.cache_url = Some(url),
+                ("include_paths", Value::Array(paths)) => {
+                    for path in paths {
+                        match path {
+                            Value::String(path) => config.include_paths.push(expand_home(&path)),
+                            _ => return Err("include_paths must be a list of strings".into()),
+                        }
+                    }
+                }
+                (key, Value::String(value)) if key.starts_with("env.") => {
+                    config.env.push((key[4..].to_string(), value));
+                }
+                (key, _) if key.starts_with("env.") => {
+                    return Err(format!("environment variable '{}' must be a string", &key[4..]).into());
+                }
+                ("jobs", _) | ("color", _) | ("include_paths", _) | ("cache_url", _) => {
+                    return Err(format!("invalid value for '{}'", key).into());
+                }
+                (key, _) => warn!("unknown configuration setting '{}'", key),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Merges another configuration into this one. Settings of the other configuration win, and
+    /// include paths of both are kept.
+    fn merge(&mut self, other: Config) {
+        if other.jobs.is_some() {
+            self.jobs = other.jobs;
+        }
+        if other.color.is_some() {
+            self.color = other.color;
+        }
+        if other.cache_url.is_some() {
+            self.cache_url = other.cache_url;
+        }
+
+        self.include_paths.extend(other.include_paths);
+
+        for (name, value) in other.env {
+            self.env.retain(|&(ref existing, _)| *existing != name);
+            self.env.push((name, value));
+        }
+    }
+}
+
+/// Gets the paths of the configuration files to read, in the order they are applied.
+fn paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    if cfg!(windows) {
+        if let Some(dir) = env::var_os("PROGRAMDATA") {
+            paths.push(PathBuf::from(dir).join("rote").join("config.toml"));
+        }
+        if let Some(dir) = env::var_os("APPDATA") {
+            paths.push(PathBuf::from(dir).join("rote").join("config.toml"));
+        }
+    } else {
+        paths.push(PathBuf::from("/etc/rote/config.toml"));
+
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::home_dir().map(|home| home.join(".config")));
+        if let Some(dir) = config_home {
+            paths.push(dir.join("rote").join("config.toml"));
+        }
+    }
+
+    paths
+}
+
+/// Reads a configuration file.
+fn read(path: &PathBuf) -> Result<Config, Box<Error>> {
+    let mut source = String::new();
+    try!(try!(File::open(path)).read_to_string(&mut source));
+
+    Config::parse(&source)
+}
+
+/// Replaces a leading `~` in a path with the home directory.
+fn expand_home(path: &str) -> PathBuf {
+    if path == "~" || path.starts_with("~/") {
+        if let Some(home) = env::home_dir() {
+            return home.join(&path[cmp::min(2, path.len())..]);
+        }
+    }
+
+    PathBuf::from(path)
+}
+
+/// A value in a configuration file.
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+/// Parses a TOML document into a map of values by their full dotted key.
+fn parse_toml(source: &str) -> Result<BTreeMap<String, Value>, Box<Error>> {
+    let mut values = BTreeMap::new();
+    let mut table = String::new();
+    let mut lines = source.lines().enumerate();
+
+    while let Some((number, line)) = lines.next() {
+        let error = |message: &str| -> Box<Error> {
+            format!("line {}: {}", number + 1, message).into()
+        };
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // A table header sets the prefix of the keys that follow it.
+        if line.starts_with('[') {
+            let end = match line.find(']') {
+                Some(end) => end,
+                None => return Err(error("unterminated table header")),
+            };
+            if !is_comment(&line[end + 1..]) {
+                return Err(error("unexpected text after table header"));
+            }
+
+            table = line[1..end].trim().to_string();
+            continue;
+        }
+
+        let equals = match line.find('=') {
+            Some(equals) => equals,
+            None => return Err(error("expected '='")),
+        };
+
+        let key = line[..equals].trim().trim_matches('"');
+        if key.is_empty() {
+            return Err(error("missing key"));
+        }
+
+        // Arrays may continue on the lines that follow, until they are closed.
+        let mut text = line[equals + 1..].trim().to_string();
+        let value;
+        loop {
+            let parsed = parse_value(&text).map(|(value, rest)| (value, is_comment(rest)));
+
+            match parsed {
+                Some((_, false)) => return Err(error("unexpected text after value")),
+                Some((parsed, true)) => {
+                    value = parsed;
+                    break;
+                }
+                None if text.starts_with('[') => {
+                    match lines.next() {
+                        Some((_, next)) => {
+                            text.push('\n');
+                            text.push_str(next);
+                        }
+                        None => return Err(error("invalid array")),
+                    }
+                }
+                None => return Err(error("invalid value")),
+            }
+        }
+
+        let key = if table.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", table, key)
+        };
+
+        if values.insert(key, value).is_some() {
+            return Err(error("duplicate key"));
+        }
+    }
+
+    Ok(values)
+}
+
+/// Checks if the rest of a line is empty or a comment.
+fn is_comment(rest: &str) -> bool {
+    let rest = rest.trim();
+    rest.is_empty() || rest.starts_with('#')
+}
+
+/// Skips whitespace, line breaks and comments between the items of an array.
+fn skip_blank(mut input: &str) -> &str {
+    loop {
+        input = input.trim_left();
+
+        if !input.starts_with('#') {
+            return input;
+        }
+
+        input = match input.find('\n') {
+            Some(end) => &input[end..],
+            None => "",
+        };
+    }
+}
+
+/// Parses a value at the start of a string, returning it along with the rest of the string.
+fn parse_value(input: &str) -> Option<(Value, &str)> {
+    if input.starts_with('"') {
+        let mut value = String::new();
+        let mut chars = input[1..].char_indices();
+
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((Value::String(value), &input[i + 2..])),
+                '\\' => {
+                    match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((_, '"')) => value.push('"'),
+                        Some((_, '\\')) => value.push('\\'),
+                        _ => return None,
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+
+        None
+    } else if input.starts_with('\'') {
+        // Literal strings have no escapes.
+        input[1..].find('\'').map(|end| {
+            (Value::String(input[1..end + 1].to_string()), &input[end + 2..])
+        })
+    } else if input.starts_with('[') {
+        let mut items = Vec::new();
+        let mut rest = skip_blank(&input[1..]);
+
+        loop {
+            if rest.starts_with(']') {
+                return Some((Value::Array(items), &rest[1..]));
+            }
+
+            let (item, after) = match parse_value(rest) {
+                Some(parsed) => parsed,
+                None => return None,
+            };
+            items.push(item);
+
+            rest = skip_blank(after);
+            if rest.starts_with(',') {
+                rest = skip_blank(&rest[1..]);
+            } else if !rest.starts_with(']') {
+                return None;
+            }
+        }
+    } else {
+        let end = input.find(|c: char| c == ',' || c == ']' || c == '#' || c.is_whitespace())
+            .unwrap_or(input.len());
+        let (word, rest) = input.split_at(end);
+
+        match word {
+            "true" => Some((Value::Boolean(true), rest)),
+            "false" => Some((Value::Boolean(false), rest)),
+            _ => word.replace('_', "").parse::<i64>().ok().map(|number| (Value::Integer(number), rest)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_toml, Config, Value};
+
+    #[test]
+    fn tables_prefix_keys() {
+        let values = parse_toml("jobs = 2\n[env]\nCC = \"clang\" # compiler\n").unwrap();
+
+        assert_eq!(values.get("jobs"), Some(&Value::Integer(2)));
+        assert_eq!(values.get("env.CC"), Some(&Value::String("clang".to_string())));
+    }
+
+    #[test]
+    fn env_values_must_be_strings() {
+        let config = Config::parse("[env]\nCC = \"clang\"\nCFLAGS = '-O2 \\n'\n").unwrap();
+        assert_eq!(config.env,
+                   vec![("CC".to_string(), "clang".to_string()),
+                        ("CFLAGS".to_string(), "-O2 \\n".to_string())]);
+
+        assert!(Config::parse("env.DEBUG = true").is_err());
+    }
+
+    #[test]
+    fn literal_strings_have_no_escapes() {
+        let values = parse_toml("path = 'C:\\rote\\modules'").unwrap();
+        assert_eq!(values.get("path"), Some(&Value::String("C:\\rote\\modules".to_string())));
+    }
+
+    #[test]
+    fn arrays_span_lines() {
+        let source = "include_paths = [\n    \"a\", # first\n    'b',\n]\ncolor = false\n";
+        let config = Config::parse(source).unwrap();
+
+        assert_eq!(config.include_paths.len(), 2);
+        assert_eq!(config.color, Some(false));
+
+        assert!(parse_toml("include_paths = [\n    \"a\",\n").is_err());
+    }
+
+    #[test]
+    fn duplicate_keys_are_errors() {
+        assert!(parse_toml("jobs = 1\njobs = 2").is_err());
+        assert!(parse_toml("env.CC = \"gcc\"\n[env]\nCC = \"clang\"").is_err());
+    }
+}
@@ -0,0 +1,458 @@
+//! Writes all console output from a single thread.
+//!
+//! Tasks run on many threads at once, and writing colored text takes several writes that can be
+//! torn apart by other threads. Instead, output is sent to a dedicated writer thread as whole
+//! messages, which are written in order and flushed once no more messages are waiting.
+//!
+//! A thread can also hold back its output in a buffer instead, to be written out later all at once.
+//!
+//! While a status line is set, it is kept below everything else written to a terminal, and is
+//! erased and drawn again around each write.
+use std::cell::RefCell;
+use std::cmp;
+use std::env;
+use std::io::{self, Write};
+use std::mem;
+use std::sync::{Arc, Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use term::{self, color, Attr, Terminal};
+
+
+/// How often the elapsed time on the status line is updated.
+const STATUS_INTERVAL_MS: u64 = 1000;
+
+
+/// A console stream to write to.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// A piece of text written with a single style.
+#[derive(Clone)]
+pub struct Segment {
+    pub text: Vec<u8>,
+    pub color: Option<color::Color>,
+    pub bold: bool,
+}
+
+impl Segment {
+    /// Creates a segment of unstyled text.
+    pub fn plain<T: Into<Vec<u8>>>(text: T) -> Segment {
+        Segment {
+            text: text.into(),
+            color: None,
+            bold: false,
+        }
+    }
+
+    /// Creates a segment of colored text.
+    pub fn colored<T: Into<Vec<u8>>>(text: T, color: color::Color) -> Segment {
+        Segment {
+            text: text.into(),
+            color: Some(color),
+            bold: false,
+        }
+    }
+
+    /// Makes the segment bold.
+    pub fn bold(mut self) -> Segment {
+        self.bold = true;
+        self
+    }
+}
+
+enum Message {
+    Write(Stream, Vec<Segment>),
+    Status(Option<(Vec<Segment>, Instant)>),
+    Flush(Sender<()>),
+}
+
+static INIT: Once = ONCE_INIT;
+static STDOUT_TO_STDERR: AtomicBool = ATOMIC_BOOL_INIT;
+static HAS_STATUS: AtomicBool = ATOMIC_BOOL_INIT;
+static NO_COLORS: AtomicBool = ATOMIC_BOOL_INIT;
+static mut SENDER: *const Mutex<Sender<Message>> = 0 as *const Mutex<Sender<Message>>;
+
+thread_local! {
+    static THREAD_SENDER: Sender<Message> = unsafe {
+        INIT.call_once(|| {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || run(receiver));
+            SENDER = Box::into_raw(Box::new(Mutex::new(sender)));
+        });
+
+        (*SENDER).lock().unwrap().clone()
+    };
+
+    static THREAD_BUFFER: RefCell<Option<Buffer>> = RefCell::new(None);
+}
+
+/// Output that is held back instead of being written right away.
+///
+/// A buffer can be shared between threads, so that the output of helper threads is kept together
+/// with the output of the thread that started them.
+#[derive(Clone)]
+pub struct Buffer(Arc<Mutex<Vec<(Stream, Vec<Segment>)>>>);
+
+impl Buffer {
+    /// Creates an empty buffer.
+    pub fn new() -> Buffer {
+        Buffer(Arc::new(Mutex::new(Vec::new())))
+    }
+
+    /// Writes everything in the buffer to the console, leaving the buffer empty.
+    pub fn release(&self) {
+        let messages = mem::replace(&mut *self.0.lock().unwrap(), Vec::new());
+
+        THREAD_SENDER.with(|sender| {
+            for (stream, segments) in messages {
+                sender.send(Message::Write(stream, segments)).ok();
+            }
+        });
+    }
+}
+
+/// Gets the buffer that output from the current thread is held in, if any.
+pub fn buffer() -> Option<Buffer> {
+    THREAD_BUFFER.with(|buffer| buffer.borrow().clone())
+}
+
+/// Sets a buffer to hold output written by the current thread in, or `None` to write output right
+/// away again. Returns the previous buffer.
+pub fn set_buffer(buffer: Option<Buffer>) -> Option<Buffer> {
+    THREAD_BUFFER.with(|current| mem::replace(&mut *current.borrow_mut(), buffer))
+}
+
+/// Writes a message made of styled segments to a stream.
+pub fn write(stream: Stream, segments: Vec<Segment>) {
+    let segments = THREAD_BUFFER.with(|buffer| {
+        match *buffer.borrow() {
+            Some(ref buffer) => {
+                buffer.0.lock().unwrap().push((stream, segments));
+                None
+            }
+            None => Some(segments),
+        }
+    });
+
+    if let Some(segments) = segments {
+        THREAD_SENDER.with(|sender| sender.send(Message::Write(stream, segments)).ok());
+    }
+}
+
+/// Picks a color for a task name, so that each task keeps the same color throughout a run.
+pub fn task_color(name: &str) -> color::Color {
+    const COLORS: [color::Color; 6] = [
+        color::BRIGHT_CYAN,
+        color::BRIGHT_MAGENTA,
+        color::BRIGHT_YELLOW,
+        color::BRIGHT_BLUE,
+        color::BRIGHT_GREEN,
+        color::BRIGHT_RED,
+    ];
+
+    let hash = name.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte as usize)
+    });
+    COLORS[hash % COLORS.len()]
+}
+
+/// Writes raw bytes to a stream.
+pub fn write_bytes(stream: Stream, bytes: &[u8]) {
+    write(stream, vec![Segment::plain(bytes)]);
+}
+
+/// Writes a line of text to standard output.
+pub fn println<S: Into<String>>(line: S) {
+    let mut line = line.into();
+    line.push('\n');
+    write(Stream::Stdout, vec![Segment::plain(line)]);
+}
+
+/// Shows a status line at the bottom of the terminal, replacing the previous one. The time elapsed
+/// since the given instant is added to the end of the line and kept up to date.
+///
+/// Nothing is shown if standard error is not a terminal.
+pub fn set_status(segments: Vec<Segment>, started: Instant) {
+    HAS_STATUS.store(true, Ordering::SeqCst);
+    THREAD_SENDER.with(|sender| sender.send(Message::Status(Some((segments, started)))).ok());
+}
+
+/// Removes the status line.
+pub fn clear_status() {
+    HAS_STATUS.store(false, Ordering::SeqCst);
+    THREAD_SENDER.with(|sender| sender.send(Message::Status(None)).ok());
+}
+
+/// Checks if a status line is set. Anything written to the terminal while it is shown should go
+/// through the console, or it will be mixed up with the status line.
+pub fn has_status() -> bool {
+    HAS_STATUS.load(Ordering::SeqCst)
+}
+
+/// Checks if a stream is written to a terminal.
+#[cfg(unix)]
+pub fn is_terminal(stream: Stream) -> bool {
+    use std::os::raw::c_int;
+
+    extern "C" {
+        fn isatty(fd: c_int) -> c_int;
+    }
+
+    let fd = match stream {
+        Stream::Stdout => 1,
+        Stream::Stderr => 2,
+    };
+
+    unsafe { isatty(fd) != 0 }
+}
+
+/// Checks if a stream is written to a terminal.
+#[cfg(windows)]
+pub fn is_terminal(stream: Stream) -> bool {
+    match stream {
+        Stream::Stdout => term::stdout().is_some(),
+        Stream::Stderr => term::stderr().is_some(),
+    }
+}
+
+/// Sets whether output is written with colors and other styles when written to a terminal.
+pub fn set_colors(enabled: bool) {
+    NO_COLORS.store(!enabled, Ordering::SeqCst);
+}
+
+/// Writes everything written to standard output from now on to standard error instead, so that
+/// standard output can be used for machine-readable output.
+pub fn redirect_stdout() {
+    STDOUT_TO_STDERR.store(true, Ordering::SeqCst);
+}
+
+/// Takes standard output for machine-readable output only, and returns a writer for it.
+///
+/// Besides redirecting our own output like `redirect_stdout()`, anything else written to standard
+/// output, such as by Lua's `io.write()` or by commands, goes to standard error from now on.
+#[cfg(unix)]
+pub fn take_stdout() -> io::Result<Box<Write + Send>> {
+    use std::fs::File;
+    use std::os::raw::c_int;
+    use std::os::unix::io::FromRawFd;
+
+    extern "C" {
+        fn dup(fd: c_int) -> c_int;
+        fn dup2(fd: c_int, fd2: c_int) -> c_int;
+    }
+
+    redirect_stdout();
+    try!(io::stdout().flush());
+
+    unsafe {
+        let fd = dup(1);
+        if fd < 0 || dup2(2, 1) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Box::new(File::from_raw_fd(fd)))
+    }
+}
+
+/// Takes standard output for machine-readable output only, and returns a writer for it.
+///
+/// Only our own output is redirected on Windows.
+#[cfg(windows)]
+pub fn take_stdout() -> io::Result<Box<Write + Send>> {
+    redirect_stdout();
+    Ok(Box::new(io::stdout()))
+}
+
+/// Waits until everything written so far has been written out.
+pub fn flush() {
+    let (sender, receiver) = mpsc::channel();
+
+    THREAD_SENDER.with(|writer| writer.send(Message::Flush(sender)).ok());
+    receiver.recv().ok();
+}
+
+/// Writes messages until every sender is gone.
+fn run(receiver: Receiver<Message>) {
+    let mut stdout = Output::new(Stream::Stdout);
+    let mut stderr = Output::new(Stream::Stderr);
+    let shows_status = is_terminal(Stream::Stderr);
+    let mut status = None;
+
+    loop {
+        // Wake up now and then while a status line is shown to update the elapsed time.
+        let message = if status.is_some() {
+            match receiver.recv_timeout(Duration::from_millis(STATUS_INTERVAL_MS)) {
+                Ok(message) => Some(message),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match receiver.recv() {
+                Ok(message) => Some(message),
+                Err(_) => break,
+            }
+        };
+
+        let mut waiting = Vec::new();
+        let mut next = message;
+
+        // Get the status line out of the way of the new output.
+        if status.is_some() {
+            stderr.erase_line();
+        }
+
+        // Write everything that is queued up before flushing.
+        while let Some(message) = next {
+            match message {
+                Message::Write(Stream::Stdout, segments) => {
+                    if STDOUT_TO_STDERR.load(Ordering::SeqCst) {
+                        stderr.write(segments);
+                    } else {
+                        stdout.write(segments);
+                    }
+                }
+                Message::Write(Stream::Stderr, segments) => stderr.write(segments),
+                Message::Status(new_status) => {
+                    if shows_status {
+                        status = new_status;
+                    }
+                }
+                Message::Flush(sender) => waiting.push(sender),
+            }
+
+            next = receiver.try_recv().ok();
+        }
+
+        stdout.flush();
+
+        if let Some((ref segments, started)) = status {
+            stderr.write(status_line(segments, started));
+        }
+        stderr.flush();
+
+        for sender in waiting {
+            sender.send(()).ok();
+        }
+    }
+}
+
+/// Gets the segments of a status line, cut short to fit on a single line of the terminal.
+fn status_line(segments: &[Segment], started: Instant) -> Vec<Segment> {
+    let elapsed = started.elapsed().as_secs();
+    let elapsed = format!(" {}:{:02}", elapsed / 60, elapsed % 60);
+
+    // Lines that wrap around can't be erased again, so stay clear of the last column.
+    let width = env::var("COLUMNS").ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+        .unwrap_or(80)
+        .saturating_sub(1);
+    let mut remaining = width.saturating_sub(elapsed.len());
+    let mut line = Vec::new();
+
+    for segment in segments {
+        let mut segment = segment.clone();
+        let mut length = cmp::min(segment.text.len(), remaining);
+
+        // Never cut a character in half.
+        while length < segment.text.len() && length > 0 && segment.text[length] & 0xc0 == 0x80 {
+            length -= 1;
+        }
+
+        segment.text.truncate(length);
+        remaining -= length;
+        line.push(segment);
+    }
+
+    line.push(Segment::colored(elapsed, color::BRIGHT_BLACK));
+    line
+}
+
+/// A stream that is written with colors if it is a terminal.
+enum Output {
+    Stdout(Box<term::StdoutTerminal>),
+    Stderr(Box<term::StderrTerminal>),
+    Plain(Box<Write + Send>),
+}
+
+impl Output {
+    fn new(stream: Stream) -> Output {
+        match stream {
+            Stream::Stdout => {
+                match term::stdout() {
+                    Some(terminal) => Output::Stdout(terminal),
+                    None => Output::Plain(Box::new(io::stdout())),
+                }
+            }
+            Stream::Stderr => {
+                match term::stderr() {
+                    Some(terminal) => Output::Stderr(terminal),
+                    None => Output::Plain(Box::new(io::stderr())),
+                }
+            }
+        }
+    }
+
+    fn write(&mut self, segments: Vec<Segment>) {
+        let plain = NO_COLORS.load(Ordering::SeqCst);
+
+        for mut segment in segments {
+            if plain {
+                segment.color = None;
+                segment.bold = false;
+            }
+
+            match *self {
+                Output::Stdout(ref mut out) => write_styled(&mut **out, segment),
+                Output::Stderr(ref mut out) => write_styled(&mut **out, segment),
+                Output::Plain(ref mut out) => {
+                    out.write_all(&segment.text).ok();
+                }
+            }
+        }
+    }
+
+    /// Erases the line the cursor is on.
+    fn erase_line(&mut self) {
+        match *self {
+            Output::Stdout(ref mut out) => {
+                out.carriage_return().ok();
+                out.delete_line().ok();
+            }
+            Output::Stderr(ref mut out) => {
+                out.carriage_return().ok();
+                out.delete_line().ok();
+            }
+            Output::Plain(_) => {}
+        }
+    }
+
+    fn flush(&mut self) {
+        match *self {
+            Output::Stdout(ref mut out) => out.flush().ok(),
+            Output::Stderr(ref mut out) => out.flush().ok(),
+            Output::Plain(ref mut out) => out.flush().ok(),
+        };
+    }
+}
+
+/// Writes a segment to a terminal in its style.
+fn write_styled<T: Terminal + ?Sized>(out: &mut T, segment: Segment) {
+    if segment.bold {
+        out.attr(Attr::Bold).ok();
+    }
+    if let Some(color) = segment.color {
+        out.fg(color).ok();
+    }
+
+    out.write_all(&segment.text).ok();
+
+    if segment.bold || segment.color.is_some() {
+        out.reset().ok();
+    }
+}
@@ -0,0 +1,217 @@
+use json::{self, JsonValue};
+use report::{EventReporter, EventStream};
+use runner::Runner;
+use std::error::Error;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+#[cfg(unix)]
+use process;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+
+/// A request received from a client, along with the stream to respond to.
+struct Request {
+    id: JsonValue,
+    method: String,
+    params: JsonValue,
+    client: EventStream,
+}
+
+/// Serves a JSON-RPC API over a Unix socket, keeping the script loaded between requests.
+///
+/// Worker threads and their runtimes are kept alive between runs as well, so that a run doesn't
+/// have to load the script again for every thread. They are replaced when the script is reloaded.
+///
+/// Requests and responses are newline-delimited JSON-RPC 2.0 messages. The following methods are
+/// supported:
+///
+/// - `list`: Returns the named tasks and the default task.
+/// - `run`: Runs the tasks given in `params.tasks`, or the default task. Events are sent to the
+///   client as `event` notifications while the tasks run. If `params.changed` lists the files
+///   changed since the last run, only tasks depending on them are checked again.
+/// - `cancel`: Cancels the current run.
+/// - `reload`: Reloads the script.
+/// - `shutdown`: Stops the daemon.
+#[cfg(unix)]
+pub fn serve<P: AsRef<Path>>(runner: &mut Runner, path: P) -> Result<(), Box<Error>> {
+    let path = path.as_ref();
+
+    // Remove a socket left behind by a previous daemon.
+    if path.exists() && UnixStream::connect(path).is_err() {
+        try!(fs::remove_file(path));
+    }
+
+    runner.keep_workers();
+
+    let listener = try!(UnixListener::bind(path));
+    info!("listening on {}", path.to_string_lossy());
+
+    let (sender, receiver) = mpsc::channel::<Request>();
+    let cancelled = runner.cancel_flag();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let sender = sender.clone();
+                    let cancelled = cancelled.clone();
+                    thread::spawn(move || handle_client(stream, sender, cancelled));
+                }
+                Err(e) => warn!("failed to accept connection: {}", e),
+            }
+        }
+    });
+
+    // Requests are handled one at a time on this thread, since the runner cannot be shared.
+    for request in receiver {
+        let result = match request.method.as_str() {
+            "list" => Ok(runner.tasks_to_json()),
+            "run" => run(runner, &request),
+            "reload" => runner.reload().map(|_| JsonValue::Null).map_err(|e| e.to_string()),
+            "shutdown" => {
+                respond(&request.client, request.id, Ok(JsonValue::Null));
+                break;
+            }
+            method => {
+                let message = format!("unknown method '{}'", method);
+                respond_error(&request.client, request.id, -32601, &message);
+                continue;
+            }
+        };
+
+        respond(&request.client, request.id, result);
+    }
+
+    fs::remove_file(path).ok();
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn serve<P: AsRef<Path>>(_runner: &mut Runner, _path: P) -> Result<(), Box<Error>> {
+    Err("daemon mode is only supported on Unix".into())
+}
+
+/// Reads requests from a client and passes them to the daemon.
+#[cfg(unix)]
+fn handle_client(stream: UnixStream, sender: mpsc::Sender<Request>, cancelled: Arc<AtomicBool>) {
+    let client = match stream.try_clone() {
+        Ok(writer) => EventStream::new(writer),
+        Err(e) => {
+            warn!("failed to open connection: {}", e);
+            return;
+        }
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut message = match json::parse(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                respond_error(&client, JsonValue::Null, -32700, &e.to_string());
+                continue;
+            }
+        };
+
+        let id = message["id"].take();
+        let method = match message["method"].as_str() {
+            Some(method) => method.to_string(),
+            None => {
+                respond_error(&client, id, -32600, "missing method");
+                continue;
+            }
+        };
+
+        // Cancelling is handled here, since the daemon thread is busy while tasks are running.
+        if method == "cancel" {
+            cancelled.store(true, Ordering::SeqCst);
+            process::kill_all();
+            respond(&client, id, Ok(JsonValue::Null));
+            continue;
+        }
+
+        let request = Request {
+            id: id,
+            method: method,
+            params: message["params"].take(),
+            client: client.clone(),
+        };
+
+        if sender.send(request).is_err() {
+            break;
+        }
+    }
+}
+
+fn run(runner: &mut Runner, request: &Request) -> Result<JsonValue, String> {
+    let mut tasks: Vec<String> = request.params["tasks"]
+        .members()
+        .filter_map(|task| task.as_str())
+        .map(String::from)
+        .collect();
+
+    if tasks.is_empty() {
+        match runner.default_task() {
+            Some(name) => tasks.push(name),
+            None => return Err("no default task defined".to_string()),
+        }
+    }
+
+    runner.add_reporter(Box::new(EventReporter::new(request.client.notifications("event"))));
+
+    let result = if request.params["changed"].is_array() {
+        let changed: Vec<&str> = request.params["changed"]
+            .members()
+            .filter_map(|path| path.as_str())
+            .collect();
+
+        runner.run_changed(&tasks, &changed)
+    } else {
+        runner.run(&tasks)
+    };
+
+    runner.pop_reporter();
+
+    result.map(|_| JsonValue::Null).map_err(|e| e.to_string())
+}
+
+/// Sends a response to a request.
+fn respond(client: &EventStream, id: JsonValue, result: Result<JsonValue, String>) {
+    match result {
+        Ok(result) => {
+            let mut response = object! {
+                "jsonrpc" => "2.0"
+            };
+            response["id"] = id;
+            response["result"] = result;
+            client.write(&response).ok();
+        }
+        Err(message) => respond_error(client, id, -32000, &message),
+    }
+}
+
+/// Sends an error response to a request.
+fn respond_error(client: &EventStream, id: JsonValue, code: i32, message: &str) {
+    let mut response = object! {
+        "jsonrpc" => "2.0",
+        "error" => object! {
+            "code" => code,
+            "message" => message
+        }
+    };
+    response["id"] = id;
+    client.write(&response).ok();
+}
@@ -0,0 +1,145 @@
+use graph::Graph;
+use std::io::{self, Write};
+use std::path::Path;
+
+
+/// Writes a task graph as a Ninja build file.
+///
+/// Task actions are written in Lua and cannot be translated, so each task is run by calling back
+/// into Rote with dependencies disabled, leaving ordering and up-to-date checks to Ninja. These runs
+/// share the project lock so that Ninja can run them in parallel. File
+/// tasks become regular build edges, so Ninja's own timestamp checks apply to them. Named tasks
+/// depend on an always-dirty phony target so that they are run every time, just like they are in
+/// Rote.
+pub fn ninja<W: Write>(out: &mut W, graph: &Graph, defaults: &[String], rote: &Path, rotefile: &Path) -> io::Result<()> {
+    try!(writeln!(out, "# Generated by Rote {} from {}. Do not edit.", ::ROTE_VERSION, rotefile.to_string_lossy()));
+    try!(writeln!(out, "ninja_required_version = 1.3"));
+    try!(writeln!(out, ""));
+    try!(writeln!(out, "rote = {}", ninja_escape(&shell_quote(&rote.to_string_lossy()))));
+    try!(writeln!(out, "rotefile = {}", ninja_escape(&shell_quote(&rotefile.to_string_lossy()))));
+    try!(writeln!(out, ""));
+    try!(writeln!(out, "rule rote"));
+    try!(writeln!(out, "  command = $rote -f $rotefile --wait --shared-lock --no-deps $task"));
+    try!(writeln!(out, "  description = $task"));
+    try!(writeln!(out, ""));
+    try!(writeln!(out, "build rote_always: phony"));
+
+    for task in graph.tasks() {
+        let dependencies: Vec<String> = task.dependencies()
+            .iter()
+            .map(|name| ninja_escape_path(name))
+            .collect();
+
+        try!(writeln!(out, ""));
+
+        // Tasks without an action just group their dependencies.
+        if !task.has_action() {
+            try!(writeln!(out, "build {}: phony {}", ninja_escape_path(task.name()), dependencies.join(" ")));
+            continue;
+        }
+
+        try!(write!(out, "build {}: rote", ninja_escape_path(task.name())));
+
+        for dependency in &dependencies {
+            try!(write!(out, " {}", dependency));
+        }
+
+        if !task.is_file() {
+            try!(write!(out, " | rote_always"));
+        }
+
+        try!(writeln!(out, ""));
+        try!(writeln!(out, "  task = {}", ninja_escape(&shell_quote(task.name()))));
+    }
+
+    if !defaults.is_empty() {
+        let defaults: Vec<String> = defaults.iter().map(|name| ninja_escape_path(name)).collect();
+
+        try!(writeln!(out, ""));
+        try!(writeln!(out, "default {}", defaults.join(" ")));
+    }
+
+    Ok(())
+}
+
+/// Writes a task graph as a Makefile.
+///
+/// Like Ninja files, tasks with actions are run by calling back into Rote. Tasks that only group
+/// their dependencies are translated directly, so those targets can still be driven by Make alone.
+/// The default tasks are written first so that they become Make's default goal.
+pub fn make<W: Write>(out: &mut W, graph: &Graph, defaults: &[String], rote: &Path, rotefile: &Path) -> io::Result<()> {
+    try!(writeln!(out, "# Generated by Rote {} from {}. Do not edit.", ::ROTE_VERSION, rotefile.to_string_lossy()));
+    try!(writeln!(out, "ROTE ?= {}", make_escape(&shell_quote(&rote.to_string_lossy()))));
+    try!(writeln!(out, "ROTEFILE ?= {}", make_escape(&shell_quote(&rotefile.to_string_lossy()))));
+
+    // Order the tasks so that the default tasks come first.
+    let mut tasks = graph.tasks();
+    tasks.sort_by_key(|task| {
+        defaults.iter().position(|name| name == task.name()).unwrap_or(defaults.len())
+    });
+
+    let phony: Vec<String> = tasks.iter()
+        .filter(|task| !task.is_file())
+        .map(|task| make_escape_path(task.name()))
+        .collect();
+
+    if !phony.is_empty() {
+        try!(writeln!(out, ""));
+        try!(writeln!(out, ".PHONY: {}", phony.join(" ")));
+    }
+
+    for task in tasks {
+        try!(writeln!(out, ""));
+        try!(write!(out, "{}:", make_escape_path(task.name())));
+
+        for dependency in task.dependencies() {
+            try!(write!(out, " {}", make_escape_path(dependency)));
+        }
+
+        try!(writeln!(out, ""));
+
+        if task.has_action() {
+            try!(writeln!(out, "\t$(ROTE) -f $(ROTEFILE) --wait --shared-lock --no-deps {}", make_escape(&shell_quote(task.name()))));
+        }
+    }
+
+    Ok(())
+}
+
+/// Escapes a string for use in a Makefile variable or recipe.
+fn make_escape(value: &str) -> String {
+    value.replace("$", "$$")
+}
+
+/// Escapes a string for use as a Makefile target or prerequisite.
+fn make_escape_path(path: &str) -> String {
+    make_escape(path)
+        .replace(" ", "\\ ")
+        .replace(":", "\\:")
+        .replace("#", "\\#")
+}
+
+/// Escapes a string for use in a Ninja variable value.
+fn ninja_escape(value: &str) -> String {
+    value.replace("$", "$$")
+}
+
+/// Escapes a string for use as a Ninja path.
+fn ninja_escape_path(path: &str) -> String {
+    ninja_escape(path).replace(" ", "$ ").replace(":", "$:")
+}
+
+/// Quotes a string for use as a single shell argument, if necessary.
+pub fn shell_quote(value: &str) -> String {
+    let safe = !value.is_empty() && value.chars().all(|c| {
+        c.is_alphanumeric() || "_-./:=+,@%".contains(c)
+    });
+
+    if safe {
+        value.to_string()
+    } else if cfg!(windows) {
+        format!("\"{}\"", value.replace("\"", "\\\""))
+    } else {
+        format!("'{}'", value.replace("'", "'\\''"))
+    }
+}
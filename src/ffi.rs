@@ -0,0 +1,224 @@
+use report::{Reporter, TaskReport};
+use runner::Runner;
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::time::Duration;
+use task::Task;
+
+
+/// Event passed to callbacks when a task is sent to be run.
+pub const ROTE_EVENT_TASK_STARTED: c_int = 1;
+
+/// Event passed to callbacks when a task finishes running.
+pub const ROTE_EVENT_TASK_FINISHED: c_int = 2;
+
+/// Event passed to callbacks when a run finishes.
+pub const ROTE_EVENT_RUN_FINISHED: c_int = 3;
+
+/// Callback for listing tasks, given a task name and description, which may be null.
+pub type ListCallback = extern "C" fn(name: *const c_char,
+                                      description: *const c_char,
+                                      data: *mut c_void);
+
+/// Callback for run events, given the event type, the task name, and an error message if the task
+/// or run failed. Strings may be null and are only valid during the call.
+pub type EventCallback = extern "C" fn(event: c_int,
+                                       task: *const c_char,
+                                       error: *const c_char,
+                                       data: *mut c_void);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Creates a new runner for the Rotefile at the given path. Returns null on error.
+#[no_mangle]
+pub extern "C" fn rote_runner_new(path: *const c_char) -> *mut Runner {
+    let result = guard(|| {
+        let path = try!(string(path));
+        Runner::new(path)
+    });
+
+    match result {
+        Some(runner) => Box::into_raw(Box::new(runner)),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Frees a runner.
+#[no_mangle]
+pub extern "C" fn rote_runner_free(runner: *mut Runner) {
+    if !runner.is_null() {
+        drop(unsafe { Box::from_raw(runner) });
+    }
+}
+
+/// Loads the runner's Rotefile. Returns 0 on success and -1 on error.
+#[no_mangle]
+pub extern "C" fn rote_runner_load(runner: *mut Runner) -> c_int {
+    status(guard(|| try!(runner_mut(runner)).load()))
+}
+
+/// Calls a callback for each named task defined by the Rotefile. Returns 0 on success and -1 on
+/// error.
+#[no_mangle]
+pub extern "C" fn rote_runner_list_tasks(runner: *mut Runner,
+                                         callback: ListCallback,
+                                         data: *mut c_void)
+                                         -> c_int {
+    status(guard(|| {
+        for task in try!(runner_mut(runner)).tasks() {
+            let name = try!(CString::new(task.name()));
+            let description = match task.description() {
+                Some(description) => Some(try!(CString::new(description))),
+                None => None,
+            };
+
+            let description = description.as_ref().map_or(ptr::null(), |d| d.as_ptr());
+            callback(name.as_ptr(), description, data);
+        }
+
+        Ok(())
+    }))
+}
+
+/// Runs the given tasks, or the default task if `count` is zero. Returns 0 on success and -1 on
+/// error.
+#[no_mangle]
+pub extern "C" fn rote_runner_run(runner: *mut Runner,
+                                  tasks: *const *const c_char,
+                                  count: usize)
+                                  -> c_int {
+    status(guard(|| {
+        let runner = try!(runner_mut(runner));
+
+        if count == 0 {
+            return runner.run_default();
+        }
+
+        let mut names = Vec::new();
+        for i in 0..count {
+            names.push(try!(string(unsafe { *tasks.offset(i as isize) })));
+        }
+
+        runner.run(&names)
+    }))
+}
+
+/// Registers a callback to receive events while tasks run. Returns 0 on success and -1 on error.
+#[no_mangle]
+pub extern "C" fn rote_runner_add_callback(runner: *mut Runner,
+                                           callback: EventCallback,
+                                           data: *mut c_void)
+                                           -> c_int {
+    status(guard(|| {
+        try!(runner_mut(runner)).add_reporter(Box::new(CallbackReporter {
+            callback: callback,
+            data: data,
+        }));
+
+        Ok(())
+    }))
+}
+
+/// Gets the message of the last error that occurred on the current thread, or null if there was
+/// none. The string is valid until the next call into the library.
+#[no_mangle]
+pub extern "C" fn rote_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error.borrow().as_ref().map_or(ptr::null(), |error| error.as_ptr())
+    })
+}
+
+/// Gets the library version.
+#[no_mangle]
+pub extern "C" fn rote_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+/// Forwards runner events to a C callback.
+struct CallbackReporter {
+    callback: EventCallback,
+    data: *mut c_void,
+}
+
+impl CallbackReporter {
+    fn call(&self, event: c_int, task: Option<&str>, error: Option<&str>) {
+        let task = task.and_then(|task| CString::new(task).ok());
+        let error = error.and_then(|error| CString::new(error).ok());
+
+        (self.callback)(event,
+                        task.as_ref().map_or(ptr::null(), |task| task.as_ptr()),
+                        error.as_ref().map_or(ptr::null(), |error| error.as_ptr()),
+                        self.data);
+    }
+}
+
+impl Reporter for CallbackReporter {
+    fn task_started(&mut self, name: &str) {
+        self.call(ROTE_EVENT_TASK_STARTED, Some(name), None);
+    }
+
+    fn task_finished(&mut self, report: &TaskReport) {
+        let error = report.error.as_ref().map(|e| e.as_str());
+        self.call(ROTE_EVENT_TASK_FINISHED, Some(&report.name), error);
+    }
+
+    fn run_finished(&mut self, _duration: Duration, success: bool) -> ::std::io::Result<()> {
+        let error = if success {
+            None
+        } else {
+            Some("not all tasks completed successfully")
+        };
+
+        self.call(ROTE_EVENT_RUN_FINISHED, None, error);
+        Ok(())
+    }
+}
+
+/// Runs a function, storing any error or panic as the last error instead of letting it cross the
+/// FFI boundary.
+fn guard<T, F: FnOnce() -> Result<T, Box<Error>>>(f: F) -> Option<T> {
+    LAST_ERROR.with(|error| *error.borrow_mut() = None);
+
+    let message = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => return Some(value),
+        Ok(Err(e)) => e.to_string(),
+        Err(_) => "rote panicked".to_string(),
+    };
+
+    LAST_ERROR.with(|error| {
+        *error.borrow_mut() = CString::new(message.replace("\0", "")).ok();
+    });
+
+    None
+}
+
+fn status(result: Option<()>) -> c_int {
+    if result.is_some() {
+        0
+    } else {
+        -1
+    }
+}
+
+fn runner_mut<'a>(runner: *mut Runner) -> Result<&'a mut Runner, Box<Error>> {
+    if runner.is_null() {
+        Err("runner is null".into())
+    } else {
+        Ok(unsafe { &mut *runner })
+    }
+}
+
+fn string(value: *const c_char) -> Result<String, Box<Error>> {
+    if value.is_null() {
+        return Err("string is null".into());
+    }
+
+    let value = unsafe { CStr::from_ptr(value) };
+    Ok(try!(value.to_str()).to_string())
+}
@@ -0,0 +1,90 @@
+//! Caches filesystem metadata and glob results.
+//!
+//! Rules, modules and up-to-date checks tend to stat and glob the same paths over and over while a
+//! script is loaded and its tasks are solved. The cache is kept per thread, since each thread has
+//! its own runtime, and must be cleared whenever files may have changed, such as before a task
+//! action runs or after a command is executed.
+use glob;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+
+
+thread_local! {
+    static METADATA: RefCell<HashMap<PathBuf, Option<Metadata>>> = RefCell::new(HashMap::new());
+    static GLOBS: RefCell<HashMap<String, Vec<PathBuf>>> = RefCell::new(HashMap::new());
+}
+
+/// Gets the metadata for a path, following symbolic links.
+pub fn metadata<P: AsRef<Path>>(path: P) -> io::Result<Metadata> {
+    let path = path.as_ref();
+
+    if let Some(cached) = METADATA.with(|cache| cache.borrow().get(path).cloned()) {
+        return cached.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "file not found"));
+    }
+
+    let result = fs::metadata(path);
+
+    // Only remember whether a path exists, since other errors may be temporary.
+    let cached = match result {
+        Ok(ref metadata) => Some(Some(metadata.clone())),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Some(None),
+        Err(_) => None,
+    };
+
+    if let Some(cached) = cached {
+        METADATA.with(|cache| cache.borrow_mut().insert(path.to_path_buf(), cached));
+    }
+
+    result
+}
+
+/// Gets the paths matching a glob pattern. Paths that cannot be read are skipped.
+pub fn glob(pattern: &str) -> Result<Vec<PathBuf>, glob::PatternError> {
+    if let Some(paths) = GLOBS.with(|cache| cache.borrow().get(pattern).cloned()) {
+        return Ok(paths);
+    }
+
+    let mut paths = Vec::new();
+
+    for entry in try!(glob::glob(pattern)) {
+        match entry {
+            Ok(path) => paths.push(path),
+            // If the path matched but was unreadable, thereby preventing its contents from
+            // matching, just continue until we find a path we care about.
+            Err(_) => warn!("unreadable path in glob"),
+        }
+    }
+
+    GLOBS.with(|cache| cache.borrow_mut().insert(pattern.to_string(), paths.clone()));
+
+    Ok(paths)
+}
+
+/// Gets the paths matching a glob pattern, matching relative patterns against a directory instead of
+/// the current directory. Paths matched by relative patterns are returned relative to the directory.
+pub fn glob_in(dir: &Path, pattern: &str) -> Result<Vec<PathBuf>, glob::PatternError> {
+    if Path::new(pattern).is_absolute() {
+        return glob(pattern);
+    }
+
+    // Directories whose names aren't valid UTF-8 can't be part of a pattern.
+    let prefix = match dir.to_str() {
+        Some(dir) => glob::Pattern::escape(dir),
+        None => return glob(pattern),
+    };
+
+    let paths = try!(glob(&format!("{}/{}", prefix, pattern)));
+
+    Ok(paths.into_iter()
+        .map(|path| path.strip_prefix(dir).map(Path::to_path_buf).unwrap_or(path))
+        .collect())
+}
+
+/// Forgets everything cached by the current thread.
+pub fn clear() {
+    METADATA.with(|cache| cache.borrow_mut().clear());
+    GLOBS.with(|cache| cache.borrow_mut().clear());
+}
@@ -1,5 +1,8 @@
+use cache::Cache;
+use json::JsonValue;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
+use std::io::{self, Write};
 use std::rc::Rc;
 use task::Task;
 
@@ -30,6 +33,13 @@ impl Graph {
         }
     }
 
+    /// Gets all tasks in the graph, sorted by name.
+    pub fn tasks(&self) -> Vec<Rc<Task>> {
+        let mut tasks: Vec<Rc<Task>> = self.tasks.values().cloned().collect();
+        tasks.sort_by(|a, b| a.name().cmp(b.name()));
+        tasks
+    }
+
     /// Adds a task to the graph.
     pub fn insert(&mut self, rule: Rc<Task>) {
         self.tasks.insert(rule.name().into(), rule);
@@ -40,8 +50,226 @@ impl Graph {
     /// Dependency solving is done by performing a topological sort of the entire graph using a
     /// depth-first search-based algorithm.
     pub fn solve(&self, skip_satisfied_tasks: bool) -> Result<VecDeque<Rc<Task>>, Box<Error>> {
-        Solver::new(&self, skip_satisfied_tasks).solve()
+        let roots: Vec<&String> = self.tasks.keys().collect();
+        self.solve_cached(&roots, skip_satisfied_tasks, &mut HashMap::new(), None)
+    }
+
+    /// Produces a queue of tasks to run in order to satisfy the given tasks only.
+    ///
+    /// Whether a task is up to date is looked up in `status` first, and any tasks checked are added
+    /// to it. Entries must be removed with `dependents()` when the files of a task change. If a
+    /// build cache is given, file tasks that look out of date are also up to date if their files
+    /// are unchanged since they last ran, and if the cache checks hashes only, timestamps aren't
+    /// looked at for tasks that have run before.
+    pub fn solve_cached<S: AsRef<str>>(&self, roots: &[S], skip_satisfied_tasks: bool, status: &mut HashMap<String, bool>, cache: Option<&Cache>) -> Result<VecDeque<Rc<Task>>, Box<Error>> {
+        let mut solver = Solver::new(&self, skip_satisfied_tasks, status, cache);
+
+        for root in roots {
+            let task = try!(self.get(root));
+
+            // If this task has not already been visited, search its dependencies to verify that it
+            // can be satisfied.
+            if !solver.resolved.contains(&task) {
+                try!(solver.resolve(task));
+            }
+        }
+
+        Ok(solver.schedule)
+    }
+
+    /// Gets the given tasks and all tasks that they depend on, directly or indirectly.
+    pub fn closure<S: AsRef<str>>(&self, roots: &[S]) -> Vec<Rc<Task>> {
+        let mut found = HashSet::new();
+        let mut tasks = Vec::new();
+        let mut stack: Vec<&str> = roots.iter().map(|name| name.as_ref()).collect();
+
+        while let Some(name) = stack.pop() {
+            if let Some(task) = self.tasks.get(name) {
+                if found.insert(name) {
+                    stack.extend(task.dependencies().iter().map(|dependency| dependency.as_str()));
+                    tasks.push(task.clone());
+                }
+            }
+        }
+
+        tasks
+    }
+
+    /// Writes the given tasks and everything they depend on in the Graphviz DOT format.
+    ///
+    /// Edges point from each task to the tasks it depends on. Tasks that produce files are drawn as
+    /// ellipses, and other tasks as boxes.
+    pub fn write_dot<S: AsRef<str>, W: Write>(&self, out: &mut W, roots: &[S]) -> io::Result<()> {
+        let mut tasks = self.closure(roots);
+        tasks.sort_by(|a, b| a.name().cmp(b.name()));
+
+        try!(writeln!(out, "digraph rote {{"));
+
+        for task in &tasks {
+            let shape = if task.is_file() {
+                "ellipse"
+            } else {
+                "box"
+            };
+            try!(writeln!(out, "    {} [shape={}];", dot_quote(task.name()), shape));
+        }
+
+        for task in &tasks {
+            for dependency in task.dependencies() {
+                try!(writeln!(out, "    {} -> {};", dot_quote(task.name()), dot_quote(dependency)));
+            }
+        }
+
+        writeln!(out, "}}")
+    }
+
+    /// Gets the given tasks and everything they depend on as a JSON object.
+    pub fn to_json<S: AsRef<str>>(&self, roots: &[S]) -> JsonValue {
+        let mut tasks = self.closure(roots);
+        tasks.sort_by(|a, b| a.name().cmp(b.name()));
+
+        let mut json = JsonValue::new_object();
+        json["tasks"] = JsonValue::Array(tasks.iter()
+            .map(|task| {
+                let mut value = JsonValue::new_object();
+                value["name"] = task.name().into();
+                value["file"] = task.is_file().into();
+                value["dependencies"] = JsonValue::Array(task.dependencies()
+                    .iter()
+                    .map(|dependency| dependency.as_str().into())
+                    .collect());
+                value
+            })
+            .collect());
+
+        json
+    }
+
+    /// Finds a circular dependency among the given tasks and the tasks they depend on, returning an
+    /// error that lists every task in the cycle, such as `a -> b -> c -> a`. Dependencies that aren't
+    /// in the graph are ignored.
+    pub fn check_cycles<S: AsRef<str>>(&self, roots: &[S]) -> Result<(), Box<Error>> {
+        let mut done = HashSet::new();
+
+        for root in roots {
+            let mut path = Vec::new();
+            if let Some(cycle) = self.find_cycle(root.as_ref(), &mut path, &mut done) {
+                return Err(cycle_error(&cycle));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Searches the dependencies of a task depth-first for a path back to a task on `path`.
+    fn find_cycle<'a>(&'a self, name: &'a str, path: &mut Vec<&'a str>, done: &mut HashSet<&'a str>) -> Option<Vec<&'a str>> {
+        if let Some(start) = path.iter().position(|task| *task == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name);
+            return Some(cycle);
+        }
+
+        let task = match self.tasks.get(name) {
+            Some(task) if !done.contains(name) => task,
+            _ => return None,
+        };
+
+        path.push(name);
+        for dependency in task.dependencies() {
+            if let Some(cycle) = self.find_cycle(dependency, path, done) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+
+        done.insert(name);
+        None
+    }
+
+    /// Gets the length of the critical path of each of the given tasks: the number of tasks in the
+    /// longest chain of the given tasks that depend on each other, starting with the task itself.
+    ///
+    /// Starting tasks with longer critical paths first keeps the tasks that everything else waits
+    /// on from being left until the end. Only dependencies between the given tasks are counted.
+    pub fn critical_paths<S: AsRef<str>>(&self, names: &[S]) -> Vec<usize> {
+        let ids: HashMap<&str, usize> = names.iter()
+            .enumerate()
+            .map(|(id, name)| (name.as_ref(), id))
+            .collect();
+
+        let mut dependents = vec![Vec::new(); names.len()];
+        for (id, name) in names.iter().enumerate() {
+            if let Some(task) = self.tasks.get(name.as_ref()) {
+                for dependency in task.dependencies() {
+                    if let Some(&dependency) = ids.get(dependency.as_str()) {
+                        dependents[dependency].push(id);
+                    }
+                }
+            }
+        }
+
+        let mut lengths = vec![0; names.len()];
+        for id in 0..names.len() {
+            path_length(id, &dependents, &mut lengths);
+        }
+
+        lengths
+    }
+
+    /// Gets the given names and the names of all tasks that depend on them, directly or
+    /// indirectly. The names do not have to be tasks themselves, so changed source files can be
+    /// given too, and tasks that read a file as an input count as depending on it.
+    pub fn dependents<S: AsRef<str>>(&self, names: &[S]) -> HashSet<String> {
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for task in self.tasks.values() {
+            for dependency in task.dependencies().iter().chain(task.files()).chain(task.inputs()) {
+                dependents.entry(dependency.as_str()).or_insert_with(Vec::new).push(task.name());
+            }
+        }
+
+        let mut found = HashSet::new();
+        let mut stack: Vec<&str> = names.iter().map(|name| name.as_ref()).collect();
+
+        while let Some(name) = stack.pop() {
+            if found.insert(name.to_string()) {
+                if let Some(tasks) = dependents.get(name) {
+                    stack.extend(tasks.iter().cloned());
+                }
+            }
+        }
+
+        found
+    }
+}
+
+/// Computes the length of the critical path of a task from the lengths of the tasks that depend on
+/// it, which are computed first if they aren't known yet. A length of zero means not known yet.
+fn path_length(id: usize, dependents: &[Vec<usize>], lengths: &mut [usize]) -> usize {
+    if lengths[id] == 0 {
+        // Count the task itself while its dependents are computed, so that a cycle can't recurse
+        // forever.
+        lengths[id] = 1;
+
+        let longest = dependents[id].iter()
+            .map(|&dependent| path_length(dependent, dependents, lengths))
+            .max()
+            .unwrap_or(0);
+        lengths[id] = longest + 1;
     }
+
+    lengths[id]
+}
+
+/// Creates the error for a circular dependency, given the names of the tasks in the cycle with the
+/// first one repeated at the end.
+fn cycle_error<S: AsRef<str>>(cycle: &[S]) -> Box<Error> {
+    let names: Vec<&str> = cycle.iter().map(|name| name.as_ref()).collect();
+    format!("circular dependency detected: {}", names.join(" -> ")).into()
+}
+
+/// Quotes a string as a DOT identifier.
+fn dot_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace("\\", "\\\\").replace("\"", "\\\""))
 }
 
 struct Solver<'a> {
@@ -50,36 +278,32 @@ struct Solver<'a> {
     resolved: HashSet<Rc<Task>>,
     // Set of tasks that have been visited but not resolved.
     unresolved: HashSet<Rc<Task>>,
+    // Chain of dependencies from a root to the task being resolved.
+    path: Vec<Rc<Task>>,
     // Resulting queue of tasks in solved order.
     schedule: VecDeque<Rc<Task>>,
     // Skip satisfied tasks?
     skip_satisfied_tasks: bool,
+    // Known up-to-date status of tasks.
+    status: &'a mut HashMap<String, bool>,
+    // Fingerprints of files from previous runs.
+    cache: Option<&'a Cache>,
 }
 
 impl<'a> Solver<'a> {
-    fn new<'b>(graph: &'b Graph, skip_satisfied_tasks: bool) -> Solver<'b> {
+    fn new<'b>(graph: &'b Graph, skip_satisfied_tasks: bool, status: &'b mut HashMap<String, bool>, cache: Option<&'b Cache>) -> Solver<'b> {
         Solver {
             graph: graph,
             resolved: HashSet::new(),
             unresolved: HashSet::new(),
+            path: Vec::new(),
             schedule: VecDeque::new(),
             skip_satisfied_tasks: skip_satisfied_tasks,
+            status: status,
+            cache: cache,
         }
     }
 
-    fn solve(mut self) -> Result<VecDeque<Rc<Task>>, Box<Error>> {
-        // Loop over each task in the graph.
-        for task in self.graph.tasks.values() {
-            // If this task has not already been visited, search its dependencies to verify that it
-            // can be satisfied.
-            if !self.resolved.contains(task) {
-                try!(self.resolve(task.clone()));
-            }
-        }
-
-        Ok(self.schedule)
-    }
-
     fn resolve(&mut self, task: Rc<Task>) -> Result<(), Box<Error>> {
         // First, check if the task is already satisfied. If it is, it and its dependencies do not
         // need to run and we can skip this task in the schedule.
@@ -91,6 +315,7 @@ impl<'a> Solver<'a> {
 
         // Mark this task as unresolved.
         self.unresolved.insert(task.clone());
+        self.path.push(task.clone());
 
         // Resolve each dependency.
         for dependency in task.dependencies() {
@@ -101,7 +326,11 @@ impl<'a> Solver<'a> {
 
             if !self.resolved.contains(&dependency) {
                 if self.unresolved.contains(&dependency) {
-                    return Err(format!("circular dependency detected: {} -> {}", task.name(), dependency.name()).into());
+                    let start = self.path.iter().position(|task| task.name() == dependency.name()).unwrap_or(0);
+                    let mut cycle: Vec<&str> = self.path[start..].iter().map(|task| task.name()).collect();
+                    cycle.push(dependency.name());
+
+                    return Err(cycle_error(&cycle));
                 }
 
                 try!(self.resolve(dependency.clone()));
@@ -111,6 +340,7 @@ impl<'a> Solver<'a> {
         // The task is now resolved.
         trace!("task '{}' resolved", task.name());
         self.unresolved.remove(&task);
+        self.path.pop();
         self.resolved.insert(task.clone());
         self.schedule.push_back(task.clone());
 
@@ -119,9 +349,39 @@ impl<'a> Solver<'a> {
 
     /// Determines recursively if a task is satisfied. For a task to be satisfied, its dependencies
     /// must also be satisfied.
-    fn satisfied(&self, task: Rc<Task>) -> Result<bool, Box<Error>> {
-        if !task.satisfied() {
-            return Ok(false);
+    fn satisfied(&mut self, task: Rc<Task>) -> Result<bool, Box<Error>> {
+        if let Some(&satisfied) = self.status.get(task.name()) {
+            return Ok(satisfied);
+        }
+
+        // A task that is reached again while checking its own dependencies is in a cycle, which
+        // is reported once the task is resolved.
+        self.status.insert(task.name().to_string(), false);
+
+        let satisfied = try!(self.check_satisfied(&task));
+        self.status.insert(task.name().to_string(), satisfied);
+
+        Ok(satisfied)
+    }
+
+    fn check_satisfied(&mut self, task: &Rc<Task>) -> Result<bool, Box<Error>> {
+        match self.cache {
+            // Only the contents of the files count, whatever their timestamps say.
+            Some(cache) if cache.checks_hashes(&**task) => {
+                if !cache.unchanged(&**task) {
+                    return Ok(false);
+                }
+            }
+            _ => {
+                if !task.satisfied() {
+                    // Timestamps can change without the contents of files changing.
+                    if task.is_file() && self.cache.map(|cache| cache.unchanged(&**task)).unwrap_or(false) {
+                        debug!("files of task '{}' are unchanged", task.name());
+                    } else {
+                        return Ok(false);
+                    }
+                }
+            }
         }
 
         for dependency in task.dependencies() {
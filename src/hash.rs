@@ -0,0 +1,224 @@
+//! Cryptographic hashes of data and files.
+use modules::fs::read_file;
+use std::io;
+use std::path::Path;
+
+
+/// Round constants of SHA-256.
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Per-round shift amounts of MD5.
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+/// Computes the MD5 hash of some data.
+///
+/// MD5 is broken and should only be used to check against hashes published by others.
+pub fn md5(data: &[u8]) -> [u8; 16] {
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    for_each_block(data, false, |block| md5_block(&mut state, block));
+
+    let mut digest = [0u8; 16];
+    for (i, value) in state.iter().enumerate() {
+        digest[i * 4] = *value as u8;
+        digest[i * 4 + 1] = (value >> 8) as u8;
+        digest[i * 4 + 2] = (value >> 16) as u8;
+        digest[i * 4 + 3] = (value >> 24) as u8;
+    }
+    digest
+}
+
+/// Computes the SHA-1 hash of some data.
+///
+/// SHA-1 is broken and should only be used to check against hashes published by others.
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    for_each_block(data, true, |block| sha1_block(&mut state, block));
+
+    let mut digest = [0u8; 20];
+    for (i, value) in state.iter().enumerate() {
+        digest[i * 4] = (value >> 24) as u8;
+        digest[i * 4 + 1] = (value >> 16) as u8;
+        digest[i * 4 + 2] = (value >> 8) as u8;
+        digest[i * 4 + 3] = *value as u8;
+    }
+    digest
+}
+
+/// Computes the SHA-256 hash of some data.
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+
+    for_each_block(data, true, |block| sha256_block(&mut state, block));
+
+    let mut digest = [0u8; 32];
+    for (i, value) in state.iter().enumerate() {
+        digest[i * 4] = (value >> 24) as u8;
+        digest[i * 4 + 1] = (value >> 16) as u8;
+        digest[i * 4 + 2] = (value >> 8) as u8;
+        digest[i * 4 + 3] = *value as u8;
+    }
+    digest
+}
+
+/// Computes the SHA-256 hash of the contents of a file.
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> io::Result<[u8; 32]> {
+    let contents = try!(read_file(path));
+    Ok(sha256(&contents))
+}
+
+/// Updates the MD5 state with a single 64-byte block.
+fn md5_block(state: &mut [u32; 4], block: &[u8]) {
+    let mut m = [0u32; 16];
+    for i in 0..16 {
+        m[i] = block[i * 4] as u32 | (block[i * 4 + 1] as u32) << 8 | (block[i * 4 + 2] as u32) << 16 |
+               (block[i * 4 + 3] as u32) << 24;
+    }
+
+    let (mut a, mut b, mut c, mut d) = (state[0], state[1], state[2], state[3]);
+
+    for i in 0..64 {
+        let (f, g) = match i / 16 {
+            0 => ((b & c) | (!b & d), i),
+            1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            2 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16),
+        };
+
+        // The constants are the integer parts of the sines of each round number, scaled by 2^32.
+        let k = ((i as f64 + 1.0).sin().abs() * 4294967296.0) as u32;
+        let shift = MD5_SHIFTS[i / 16 * 4 + i % 4];
+
+        let f = f.wrapping_add(a).wrapping_add(k).wrapping_add(m[g]);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(f.rotate_left(shift));
+    }
+
+    for (value, new) in state.iter_mut().zip(&[a, b, c, d]) {
+        *value = value.wrapping_add(*new);
+    }
+}
+
+/// Updates the SHA-1 state with a single 64-byte block.
+fn sha1_block(state: &mut [u32; 5], block: &[u8]) {
+    let mut w = [0u32; 80];
+    for i in 0..16 {
+        w[i] = (block[i * 4] as u32) << 24 | (block[i * 4 + 1] as u32) << 16 |
+               (block[i * 4 + 2] as u32) << 8 | block[i * 4 + 3] as u32;
+    }
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+
+    let (mut a, mut b, mut c, mut d, mut e) = (state[0], state[1], state[2], state[3], state[4]);
+
+    for i in 0..80 {
+        let (f, k) = match i / 20 {
+            0 => ((b & c) | (!b & d), 0x5a827999),
+            1 => (b ^ c ^ d, 0x6ed9eba1),
+            2 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+            _ => (b ^ c ^ d, 0xca62c1d6),
+        };
+
+        let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(w[i]);
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+
+    for (value, new) in state.iter_mut().zip(&[a, b, c, d, e]) {
+        *value = value.wrapping_add(*new);
+    }
+}
+
+/// Updates the SHA-256 state with a single 64-byte block.
+fn sha256_block(state: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for i in 0..16 {
+        w[i] = (block[i * 4] as u32) << 24 | (block[i * 4 + 1] as u32) << 16 |
+               (block[i * 4 + 2] as u32) << 8 | block[i * 4 + 3] as u32;
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+    }
+
+    let (mut a, mut b, mut c, mut d) = (state[0], state[1], state[2], state[3]);
+    let (mut e, mut f, mut g, mut h) = (state[4], state[5], state[6], state[7]);
+
+    for i in 0..64 {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+
+        h = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+
+    for (value, new) in state.iter_mut().zip(&[a, b, c, d, e, f, g, h]) {
+        *value = value.wrapping_add(*new);
+    }
+}
+
+/// Formats a hash as a lowercase hexadecimal string.
+pub fn to_hex(hash: &[u8]) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Splits a message into 64-byte blocks, padding the last ones with the length of the message in
+/// bits in the given byte order, as done by the MD4 family of hashes.
+fn for_each_block<F: FnMut(&[u8])>(data: &[u8], big_endian: bool, mut f: F) {
+    // Whole blocks are used as they are, so that the message doesn't need to be copied.
+    let whole = data.len() / 64 * 64;
+    for block in data[..whole].chunks(64) {
+        f(block);
+    }
+
+    let bits = (data.len() as u64).wrapping_mul(8);
+    let mut tail = Vec::with_capacity(128);
+
+    tail.extend_from_slice(&data[whole..]);
+    tail.push(0x80);
+    while tail.len() % 64 != 56 {
+        tail.push(0);
+    }
+
+    for i in 0..8 {
+        let shift = if big_endian {
+            56 - i * 8
+        } else {
+            i * 8
+        };
+        tail.push((bits >> shift) as u8);
+    }
+
+    for block in tail.chunks(64) {
+        f(block);
+    }
+}
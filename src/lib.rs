@@ -0,0 +1,43 @@
+extern crate flate2;
+extern crate glob;
+extern crate hyper;
+#[macro_use]
+extern crate json;
+#[macro_use]
+extern crate log;
+extern crate lua;
+extern crate memmap;
+extern crate num_cpus;
+extern crate regex;
+extern crate tar;
+extern crate term;
+extern crate yaml_rust;
+extern crate zip;
+
+mod artifacts;
+mod cache;
+pub mod completions;
+pub mod config;
+pub mod console;
+pub mod daemon;
+mod export;
+pub mod ffi;
+mod fscache;
+mod graph;
+mod hash;
+mod lock;
+pub mod logger;
+pub mod lsp;
+mod modules;
+pub mod plugins;
+mod remote;
+mod process;
+pub mod report;
+mod rule;
+pub mod runner;
+mod runtime;
+mod task;
+mod watcher;
+
+
+pub const ROTE_VERSION: &'static str = env!("CARGO_PKG_VERSION");
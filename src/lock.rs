@@ -0,0 +1,186 @@
+//! Advisory locking of a project, so that only one process builds it at a time.
+//!
+//! Two runs building the same project at once can write the same outputs and state files at the
+//! same time and corrupt them. A run holds a lock on `.rote/lock` in the project directory while
+//! it is running, which other processes check before they start running tasks.
+//!
+//! Tasks often run Rote again themselves, such as a test task running `rote test` on a fixture.
+//! While a process holds the lock, commands it starts get `ROTE_PROJECT_LOCK` set to the project
+//! directory, and processes they start that build the same project run under its lock instead of
+//! waiting for it.
+//!
+//! Build files exported for Ninja or Make run each task in a Rote process of its own, many at a
+//! time. Those processes share the lock instead, so that they run in parallel with each other but
+//! not with a regular run.
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, Once, ONCE_INIT};
+
+
+/// Environment variable set to the directory of the project whose lock the process holds.
+const HELD_VAR: &'static str = "ROTE_PROJECT_LOCK";
+
+static INIT: Once = ONCE_INIT;
+static mut HELD: *const Mutex<Option<PathBuf>> = 0 as *const Mutex<Option<PathBuf>>;
+
+/// Gets the directory of the project whose lock this process holds, if any.
+fn held() -> &'static Mutex<Option<PathBuf>> {
+    unsafe {
+        INIT.call_once(|| {
+            HELD = Box::into_raw(Box::new(Mutex::new(None)));
+        });
+
+        &*HELD
+    }
+}
+
+/// Lets a command know that it runs under the lock this process holds, if any.
+pub fn mark_held(command: &mut Command) {
+    if let Some(ref project) = *held().lock().unwrap() {
+        command.env(HELD_VAR, project);
+    }
+}
+
+/// A lock held on a project. The lock is released when dropped.
+pub struct ProjectLock {
+    /// The locked file, or `None` if the lock is held by a parent process.
+    #[allow(dead_code)]
+    file: Option<File>,
+}
+
+impl Drop for ProjectLock {
+    fn drop(&mut self) {
+        if self.file.is_some() {
+            *held().lock().unwrap() = None;
+        }
+    }
+}
+
+impl ProjectLock {
+    /// Takes the lock for the project in the given directory.
+    ///
+    /// If another process holds the lock, this waits for it to be released if `wait` is true, or
+    /// returns `Ok(None)` immediately otherwise. A `shared` lock can be held by any number of
+    /// processes at once, as long as none of them holds the lock exclusively.
+    pub fn acquire<P: AsRef<Path>>(directory: P,
+                                   wait: bool,
+                                   shared: bool)
+                                   -> io::Result<Option<ProjectLock>> {
+        let project = canonical(directory.as_ref());
+
+        // A parent process already holds the lock for us.
+        if env::var_os(HELD_VAR).map(PathBuf::from) == Some(project.clone()) {
+            debug!("project is locked by a parent process");
+            return Ok(Some(ProjectLock {
+                file: None,
+            }));
+        }
+
+        let directory = directory.as_ref().join(".rote");
+        try!(fs::create_dir_all(&directory));
+
+        let path = directory.join("lock");
+
+        let file = match try!(lock(&path, shared, false)) {
+            Some(file) => file,
+            None if !wait => return Ok(None),
+            None => {
+                info!("waiting for another rote process to finish...");
+
+                match try!(lock(&path, shared, true)) {
+                    Some(file) => file,
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        // Let processes started by tasks know that they are covered by this lock.
+        *held().lock().unwrap() = Some(project);
+
+        Ok(Some(ProjectLock {
+            file: Some(file),
+        }))
+    }
+}
+
+/// Gets the absolute form of a directory, so that the same project is recognized however its path
+/// is written.
+fn canonical(directory: &Path) -> PathBuf {
+    fs::canonicalize(directory).unwrap_or_else(|_| directory.to_path_buf())
+}
+
+/// Opens the lock file and locks it. If `blocking` is false and the file is already locked,
+/// returns `Ok(None)` instead of waiting.
+#[cfg(unix)]
+fn lock(path: &Path, shared: bool, blocking: bool) -> io::Result<Option<File>> {
+    use std::os::raw::c_int;
+    use std::os::unix::io::AsRawFd;
+
+    const LOCK_SH: c_int = 1;
+    const LOCK_EX: c_int = 2;
+    const LOCK_NB: c_int = 4;
+
+    extern "C" {
+        fn flock(fd: c_int, operation: c_int) -> c_int;
+    }
+
+    let file = try!(OpenOptions::new().write(true).create(true).open(path));
+    let mut operation = if shared {
+        LOCK_SH
+    } else {
+        LOCK_EX
+    };
+    if !blocking {
+        operation |= LOCK_NB;
+    }
+
+    loop {
+        if unsafe { flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(Some(file));
+        }
+
+        let error = io::Error::last_os_error();
+        match error.kind() {
+            io::ErrorKind::Interrupted => continue,
+            io::ErrorKind::WouldBlock => return Ok(None),
+            _ => return Err(error),
+        }
+    }
+}
+
+/// Opens the lock file without sharing it with other processes, which fails while another process
+/// has it open. A shared lock lets others open the file too, unless they want it to themselves.
+/// Windows has no blocking equivalent, so waiting is done by polling.
+#[cfg(windows)]
+fn lock(path: &Path, shared: bool, blocking: bool) -> io::Result<Option<File>> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::thread;
+    use std::time::Duration;
+
+    const ERROR_SHARING_VIOLATION: i32 = 32;
+    const FILE_SHARE_READ: u32 = 1;
+    const FILE_SHARE_WRITE: u32 = 2;
+
+    let share_mode = if shared {
+        FILE_SHARE_READ | FILE_SHARE_WRITE
+    } else {
+        0
+    };
+
+    loop {
+        match OpenOptions::new().write(true).create(true).share_mode(share_mode).open(path) {
+            Ok(file) => return Ok(Some(file)),
+            Err(ref e) if e.raw_os_error() == Some(ERROR_SHARING_VIOLATION) => {
+                if !blocking {
+                    return Ok(None);
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+}
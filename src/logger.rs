@@ -1,13 +1,16 @@
+use console::{self, Segment, Stream};
 use log::*;
-use term;
+use report::EventStream;
+use term::color;
 
 pub use log::LogLevelFilter as Filter;
 
 
 /// Writes log messages to standard error.
 ///
-/// The enabled filter level can be customized by passing in a specific filter.
-struct Logger(LogLevelFilter);
+/// The enabled filter level can be customized by passing in a specific filter. If an event stream
+/// is given, messages are also written to it as log events.
+struct Logger(LogLevelFilter, Option<EventStream>);
 
 impl Log for Logger {
     fn enabled(&self, metadata: &LogMetadata) -> bool {
@@ -16,62 +19,60 @@ impl Log for Logger {
 
     fn log(&self, record: &LogRecord) {
         if self.enabled(record.metadata()) {
-            let mut err = term::stderr().expect("failed to open stderr");
+            if let Some(ref events) = self.1 {
+                events.emit("log", object! {
+                    "level" => record.level().to_string().to_lowercase(),
+                    "message" => record.args().to_string()
+                });
+            }
+
+            let message = record.args().to_string();
 
             // Print with colors matching the level.
-            match record.level() {
+            let segments = match record.level() {
                 LogLevel::Error => {
-                    err.attr(term::Attr::Bold).ok();
-                    err.fg(term::color::BRIGHT_RED).ok();
-                    write!(err, "error: ").unwrap();
-
-                    err.fg(term::color::BRIGHT_WHITE).ok();
-                    writeln!(err, "{}", record.args()).unwrap();
-                    err.reset().ok();
+                    vec![
+                        Segment::colored("error: ", color::BRIGHT_RED).bold(),
+                        Segment::colored(message + "\n", color::BRIGHT_WHITE).bold(),
+                    ]
                 }
                 LogLevel::Warn => {
-                    err.fg(term::color::BRIGHT_YELLOW).ok();
-                    write!(err, "warn: ").unwrap();
-                    err.reset().ok();
-
-                    err.attr(term::Attr::Bold).ok();
-                    err.fg(term::color::BRIGHT_WHITE).ok();
-                    writeln!(err, "{}", record.args()).unwrap();
-                    err.reset().ok();
+                    vec![
+                        Segment::colored("warn: ", color::BRIGHT_YELLOW),
+                        Segment::colored(message + "\n", color::BRIGHT_WHITE).bold(),
+                    ]
                 }
                 LogLevel::Info => {
-                    err.fg(term::color::BRIGHT_GREEN).ok();
-                    write!(err, "info: ").unwrap();
-
-                    err.fg(term::color::BRIGHT_WHITE).ok();
-                    writeln!(err, "{}", record.args()).unwrap();
-                    err.reset().ok();
+                    vec![
+                        Segment::colored("info: ", color::BRIGHT_GREEN),
+                        Segment::colored(message + "\n", color::BRIGHT_WHITE),
+                    ]
                 }
                 LogLevel::Debug => {
-                    err.fg(term::color::BRIGHT_BLUE).ok();
-                    write!(err, "debug: ").unwrap();
-                    err.reset().ok();
-
-                    err.fg(term::color::BRIGHT_WHITE).ok();
-                    writeln!(err, "{}", record.args()).unwrap();
-                    err.reset().ok();
+                    vec![
+                        Segment::colored("debug: ", color::BRIGHT_BLUE),
+                        Segment::colored(message + "\n", color::BRIGHT_WHITE),
+                    ]
                 }
                 LogLevel::Trace => {
-                    err.fg(term::color::BRIGHT_WHITE).ok();
-                    writeln!(err, "trace: {}", record.args()).unwrap();
-                    err.reset().ok();
+                    vec![Segment::colored(format!("trace: {}\n", message), color::BRIGHT_WHITE)]
                 }
-            }
+            };
 
-            err.flush().unwrap();
+            console::write(Stream::Stderr, segments);
+
+            // Errors are often the last thing printed before exiting, so make sure they get out.
+            if record.level() == LogLevel::Error {
+                console::flush();
+            }
         }
     }
 }
 
-/// Initializes the global logger with a given level filter.
-pub fn init(level: LogLevelFilter) -> Result<(), SetLoggerError> {
+/// Initializes the global logger with a given level filter and an optional event stream.
+pub fn init(level: LogLevelFilter, events: Option<EventStream>) -> Result<(), SetLoggerError> {
     set_logger(|max_log_level| {
         max_log_level.set(level);
-        Box::new(Logger(level))
+        Box::new(Logger(level, events))
     })
 }
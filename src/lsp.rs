@@ -0,0 +1,417 @@
+use console;
+use json::{self, JsonValue};
+use regex::Regex;
+use report;
+use runner::Runner;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+use task::Task;
+
+
+/// A minimal language server for Rotefiles, speaking the Language Server Protocol over standard
+/// input and output.
+///
+/// Supports completion of task names, go-to-definition for tasks and dependencies, and diagnostics
+/// from the same checks as `--check`. Diagnostics are updated whenever the Rotefile is opened or
+/// saved, since the script has to be loaded from disk to check it.
+///
+/// Positions are counted in UTF-16 code units, as the protocol requires.
+pub fn serve(runner: &mut Runner) -> Result<(), Box<Error>> {
+    // Anything the script or its commands print would corrupt the messages.
+    let output = try!(console::take_stdout());
+
+    let mut server = Server {
+        runner: runner,
+        documents: HashMap::new(),
+        output: output,
+    };
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    while let Some(message) = try!(read_message(&mut input)) {
+        let method = message["method"].as_str().unwrap_or("").to_string();
+        let params = &message["params"];
+
+        let result = match method.as_str() {
+            "initialize" => Some(capabilities()),
+            "shutdown" => Some(JsonValue::Null),
+            "exit" => break,
+            "textDocument/didOpen" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+                let text = params["textDocument"]["text"].as_str().unwrap_or("").to_string();
+                server.documents.insert(uri_to_path(uri), text);
+                try!(server.publish_diagnostics());
+                None
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+
+                // Only full document sync is supported, so the last change has the whole text.
+                if let Some(change) = params["contentChanges"].members().last() {
+                    server.documents.insert(uri_to_path(uri), change["text"].as_str().unwrap_or("").to_string());
+                }
+                None
+            }
+            "textDocument/didSave" => {
+                try!(server.publish_diagnostics());
+                None
+            }
+            "textDocument/didClose" => {
+                server.documents.remove(&uri_to_path(params["textDocument"]["uri"].as_str().unwrap_or("")));
+                None
+            }
+            "textDocument/completion" => Some(server.completion(params)),
+            "textDocument/definition" => Some(server.definition(params)),
+            _ => {
+                // Requests we do not understand must still get a response.
+                if !message["id"].is_null() {
+                    let mut response = object! {
+                        "jsonrpc" => "2.0",
+                        "error" => object! {
+                            "code" => -32601,
+                            "message" => format!("unknown method '{}'", method)
+                        }
+                    };
+                    response["id"] = message["id"].clone();
+                    try!(server.write_message(&response));
+                }
+                None
+            }
+        };
+
+        // Notifications do not get a response.
+        if let Some(result) = result {
+            if !message["id"].is_null() {
+                let mut response = object! {
+                    "jsonrpc" => "2.0"
+                };
+                response["id"] = message["id"].clone();
+                response["result"] = result;
+                try!(server.write_message(&response));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+struct Server<'a> {
+    runner: &'a mut Runner,
+
+    /// Text of all open documents by path.
+    documents: HashMap<PathBuf, String>,
+
+    /// The real standard output, which messages are written to.
+    output: Box<Write + Send>,
+}
+
+/// A task definition found in a document.
+struct Definition {
+    name: String,
+    line: usize,
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Server<'a> {
+    /// Gets the current text of a document, preferring the open document to the file on disk. Only
+    /// the Rotefile is read from disk.
+    fn text(&self, path: &Path) -> String {
+        if let Some(text) = self.documents.get(path) {
+            return text.clone();
+        }
+
+        let mut text = String::new();
+        if path == self.runner.path() {
+            if let Ok(mut file) = File::open(path) {
+                file.read_to_string(&mut text).ok();
+            }
+        }
+        text
+    }
+
+    /// Writes a single message to the client.
+    fn write_message(&mut self, message: &JsonValue) -> io::Result<()> {
+        let body = message.dump();
+        try!(write!(self.output, "Content-Length: {}\r\n\r\n{}", body.len(), body));
+        self.output.flush()
+    }
+
+    /// Reloads the Rotefile and sends diagnostics for it.
+    fn publish_diagnostics(&mut self) -> io::Result<()> {
+        let path = self.runner.path().to_path_buf();
+        let uri = path_to_uri(&path);
+        let text = self.text(&path);
+        let definitions = definitions(&text);
+        let mut diagnostics = JsonValue::new_array();
+
+        let problems = match self.runner.reload() {
+            Ok(_) => self.runner.check(),
+            Err(e) => vec![(None, e.to_string())],
+        };
+
+        for (task, message) in problems {
+            // Find the line the problem is on, either from the error itself or from where the
+            // task is defined.
+            let (line, message) = match report::error_location(&message) {
+                Some((_, line, message)) => (line as usize - 1, message),
+                None => {
+                    let line = task.and_then(|task| {
+                        definitions.iter().find(|d| d.name == task).map(|d| d.line)
+                    });
+                    (line.unwrap_or(0), message)
+                }
+            };
+
+            diagnostics.push(object! {
+                "range" => range(line, 0, line, line_length(&text, line)),
+                "severity" => 1,
+                "source" => "rote",
+                "message" => message
+            }).ok();
+        }
+
+        let mut params = object! {
+            "uri" => uri
+        };
+        params["diagnostics"] = diagnostics;
+
+        let mut notification = object! {
+            "jsonrpc" => "2.0",
+            "method" => "textDocument/publishDiagnostics"
+        };
+        notification["params"] = params;
+        self.write_message(&notification)
+    }
+
+    /// Lists all known task names as completion items.
+    fn completion(&self, params: &JsonValue) -> JsonValue {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let mut items = JsonValue::new_array();
+        let mut names = Vec::new();
+
+        for task in self.runner.tasks() {
+            names.push(task.name().to_string());
+            items.push(object! {
+                "label" => task.name(),
+                "kind" => 3,
+                "detail" => task.description()
+            }).ok();
+        }
+
+        // Include tasks defined in unsaved changes too.
+        for definition in definitions(&self.text(&uri_to_path(uri))) {
+            if !names.contains(&definition.name) {
+                items.push(object! {
+                    "label" => definition.name.as_str(),
+                    "kind" => 3
+                }).ok();
+                names.push(definition.name);
+            }
+        }
+
+        items
+    }
+
+    /// Finds the definition of the task named by the string under the cursor.
+    fn definition(&self, params: &JsonValue) -> JsonValue {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let line = params["position"]["line"].as_usize().unwrap_or(0);
+        let character = params["position"]["character"].as_usize().unwrap_or(0);
+        let text = self.text(&uri_to_path(uri));
+
+        let name = match string_at(&text, line, character) {
+            Some(name) => name,
+            None => return JsonValue::Null,
+        };
+
+        match definitions(&text).into_iter().find(|d| d.name == name) {
+            Some(definition) => object! {
+                "uri" => uri,
+                "range" => range(definition.line, definition.start, definition.line, definition.end)
+            },
+            None => JsonValue::Null,
+        }
+    }
+}
+
+/// Describes what the server supports.
+fn capabilities() -> JsonValue {
+    object! {
+        "capabilities" => object! {
+            "textDocumentSync" => object! {
+                "openClose" => true,
+                "change" => 1,
+                "save" => true
+            },
+            "completionProvider" => object! {
+                "triggerCharacters" => array!["\"", "'"]
+            },
+            "definitionProvider" => true
+        },
+        "serverInfo" => object! {
+            "name" => "rote",
+            "version" => ::ROTE_VERSION
+        }
+    }
+}
+
+/// Finds all task and rule definitions in a document by looking for calls that define them.
+fn definitions(text: &str) -> Vec<Definition> {
+    let regex = Regex::new(r#"\b(?:task|rule|create_task|create_rule)\s*\(?\s*["']([^"']+)["']"#).unwrap();
+    let mut definitions = Vec::new();
+
+    for (line_no, line) in text.lines().enumerate() {
+        for captures in regex.captures_iter(line) {
+            let (start, end) = captures.pos(1).unwrap();
+
+            definitions.push(Definition {
+                name: captures.at(1).unwrap().to_string(),
+                line: line_no,
+                start: line[..start].encode_utf16().count(),
+                end: line[..end].encode_utf16().count(),
+            });
+        }
+    }
+
+    definitions
+}
+
+/// Gets the contents of the string literal at a position in a document.
+fn string_at(text: &str, line: usize, character: usize) -> Option<String> {
+    let line: Vec<char> = match text.lines().nth(line) {
+        Some(line) => line.chars().collect(),
+        None => return None,
+    };
+
+    let mut quote = None;
+    let mut start = 0;
+
+    // The position of the current character in UTF-16 code units, and of the start of the string.
+    let mut position = 0;
+    let mut start_position = 0;
+
+    for (i, &c) in line.iter().enumerate() {
+        match quote {
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                start = i + 1;
+                start_position = position + 1;
+            }
+            Some(q) if c == q => {
+                if character >= start_position && character <= position {
+                    return Some(line[start..i].iter().cloned().collect());
+                }
+                quote = None;
+            }
+            _ => {}
+        }
+
+        position += c.len_utf16();
+    }
+
+    None
+}
+
+/// Gets the length of a line in UTF-16 code units.
+fn line_length(text: &str, line: usize) -> usize {
+    text.lines().nth(line).map(|line| line.encode_utf16().count()).unwrap_or(0)
+}
+
+/// Gets the URI of a file.
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    uri
+}
+
+/// Gets the path of a file URI, decoding any escaped characters.
+fn uri_to_path(uri: &str) -> PathBuf {
+    let encoded = uri.trim_left_matches("file://").as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+
+    while i < encoded.len() {
+        let escaped = if encoded[i] == b'%' && i + 2 < encoded.len() {
+            str::from_utf8(&encoded[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn range(start_line: usize, start: usize, end_line: usize, end: usize) -> JsonValue {
+    object! {
+        "start" => object! {
+            "line" => start_line,
+            "character" => start
+        },
+        "end" => object! {
+            "line" => end_line,
+            "character" => end
+        }
+    }
+}
+
+/// Reads a single message with its headers from the client.
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<JsonValue>, Box<Error>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if try!(input.read_line(&mut header)) == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+
+        let mut parts = header.splitn(2, ':');
+        if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+            if name.trim().to_lowercase() == "content-length" {
+                length = Some(try!(value.trim().parse::<usize>()));
+            }
+        }
+    }
+
+    let length = match length {
+        Some(length) => length,
+        None => return Err("message is missing a Content-Length header".into()),
+    };
+
+    let mut body = vec![0; length];
+    try!(input.read_exact(&mut body));
+
+    Ok(Some(try!(json::parse(&String::from_utf8_lossy(&body)))))
+}
@@ -1,31 +1,48 @@
 extern crate getopts;
-extern crate glob;
-extern crate hyper;
-extern crate json;
 #[macro_use]
 extern crate log;
-extern crate lua;
-extern crate num_cpus;
-extern crate regex;
-extern crate term;
+extern crate rote;
 
 use getopts::Options;
-use runner::Runner;
+use rote::{completions, config, console, daemon, logger, lsp, report, ROTE_VERSION};
+use rote::runner::{self, InternalError, Interrupted, Runner, TaskFailure};
 use std::env;
+use std::error::Error;
 use std::path;
 use std::process;
+use std::time::Duration;
 
-mod graph;
-mod logger;
-mod modules;
-mod rule;
-mod runner;
-mod runtime;
-mod task;
 
+/// Exit code used when a task fails.
+const EXIT_TASK_FAILED: i32 = 1;
 
-const ROTE_VERSION: &'static str = env!("CARGO_PKG_VERSION");
+/// Exit code used when the command line is invalid.
+const EXIT_USAGE: i32 = 2;
 
+/// Exit code used when the script can't be loaded or has errors.
+const EXIT_SCRIPT_ERROR: i32 = 3;
+
+/// Exit code used when something goes wrong in Rote itself.
+const EXIT_INTERNAL_ERROR: i32 = 4;
+
+/// Exit code used when Rote is interrupted, such as by pressing Ctrl-C.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Names of the files to look for when no Rotefile is given, in order of preference.
+const ROTEFILE_NAMES: &'static [&'static str] = &["Rotefile", "Rotefile.lua", "rotefile.lua"];
+
+/// Gets the exit code for an error returned by the runner.
+fn exit_code(error: &(Error + 'static)) -> i32 {
+    if error.is::<TaskFailure>() {
+        EXIT_TASK_FAILED
+    } else if error.is::<Interrupted>() {
+        EXIT_INTERRUPTED
+    } else if error.is::<InternalError>() {
+        EXIT_INTERNAL_ERROR
+    } else {
+        EXIT_SCRIPT_ERROR
+    }
+}
 
 /// Prints the program usage to the console.
 fn print_usage(options: Options) {
@@ -48,32 +65,124 @@ Rote home page: <https://github.com/sagebind/rote>"
     , options.usage(&short_usage));
 }
 
-/// Parses command-line options and runs retest.
+/// Parses a named argument of the form `NAME=VALUE`, where the name is a valid Lua identifier.
+fn parse_arg(arg: &str) -> Option<(&str, &str)> {
+    let index = match arg.find('=') {
+        Some(index) => index,
+        None => return None,
+    };
+    let name = &arg[..index];
+
+    let valid = name.chars().enumerate().all(|(i, c)| match c {
+        'a'...'z' | 'A'...'Z' | '_' => true,
+        '0'...'9' => i > 0,
+        _ => false,
+    });
+
+    if valid && !name.is_empty() {
+        Some((name, &arg[index + 1..]))
+    } else {
+        None
+    }
+}
+
+/// Finds the Rotefile in a directory. If `search` is true and the directory doesn't have one, its
+/// parent directories are searched too, just like Git looks for a repository.
+fn find_rotefile(dir: &path::Path, search: bool) -> Option<path::PathBuf> {
+    let mut dir = Some(dir);
+
+    while let Some(current) = dir {
+        for name in ROTEFILE_NAMES {
+            let path = current.join(name);
+            if path.is_file() {
+                return Some(path);
+            }
+        }
+
+        dir = if search {
+            current.parent()
+        } else {
+            None
+        };
+    }
+
+    None
+}
+
 fn main() {
+    run();
+
+    // Wait for any output still being written to the console before exiting.
+    console::flush();
+}
+
+/// Parses command-line options and runs retest.
+fn run() {
     let args: Vec<String> = env::args().collect();
 
     // Parse command-line flags.
     let mut options = Options::new();
     options.optflag("B", "run-all", "Unconditionally run all tasks, including those up-to-date.");
+    options.optflag("", "always-make", "Same as --run-all.");
+    options.optopt("", "cache-url", "Share the outputs of cached tasks through the remote cache at URL.", "URL");
+    options.optopt("", "ci", "Format output for the CI service NAME (github, teamcity, jenkins).", "NAME");
+    options.optflagopt("", "daemon", "Serve a JSON-RPC API on SOCKET instead of running tasks.", "SOCKET");
+    options.optflag("", "check", "Check the Rotefile for errors and exit.");
+    options.optopt("", "check-mode", "Decide if files are up to date by MODE (mtime, hash).", "MODE");
+    options.optopt("", "completions", "Print a completion script for SHELL (bash, fish, zsh) and exit.", "SHELL");
     options.optopt("C", "directory", "Change to DIRECTORY before running tasks.", "DIRECTORY");
     options.optmulti("D", "var", "Override a variable value.", "NAME=VALUE");
-    options.optopt("f", "file", "Read FILE as the Rotefile.", "FILE");
+    options.optmulti("", "force", "Run TASK and the tasks depending on it, even if they are up to date.", "TASK");
+    options.optflag("", "exec", "Run the command given after -- as a task.");
+    options.optopt("", "export-make", "Write the task graph to FILE as a Makefile and exit.", "FILE");
+    options.optopt("", "export-ninja", "Write the task graph to FILE as a Ninja build file and exit.", "FILE");
+    options.optopt("", "format", "Print the task list in FORMAT (pretty, plain, json).", "FORMAT");
+    options.optopt("f", "file", "Read FILE as the Rotefile, or the Rotefile in FILE if it is a directory.", "FILE");
+    options.optflagopt("", "graph", "Print the task graph in FORMAT (dot, json) and exit.", "FORMAT");
     options.optflag("h", "help", "Print this help message and exit.");
+    options.optmulti("", "include", "Same as --include-path.", "PATH");
     options.optmulti("I", "include-path", "Include PATH in the search path for modules.", "PATH");
-    options.optopt("j", "jobs", "The number of jobs to run simultaneously.", "N");
+    options.optopt("j", "jobs", "The number of jobs to run simultaneously, as a number, 'auto', or a percentage of CPU cores.", "N");
     options.optflag("k", "keep-going", "Keep going if some tasks fail.");
     options.optflag("l", "list", "List all tasks and exit.");
-    options.optflag("n", "dry-run", "Simulate running tasks without executing them.");
+    options.optopt("", "load-average", "Don't start new tasks while the load average is above N.", "N");
+    options.optflag("", "lsp", "Run a language server for the Rotefile over standard I/O.");
+    options.optflag("n", "dry-run", "Show the commands tasks would run without running them.");
+    options.optflag("", "no-plugins", "Don't load native plugins.");
+    options.optflag("", "no-search", "Only look for the Rotefile in the current directory, not its parents.");
+    options.optflag("", "no-color", "Don't write output with colors.");
+    options.optflag("", "no-deps", "Run only the given tasks, without their dependencies.");
+    options.optflag("", "ordered-output", "Write task output in schedule order, even when running in parallel.");
+    options.optflag("", "no-prefix", "Don't prefix command output with task names when running in parallel.");
+    options.optopt("", "output", "Write a stream of events in FORMAT (json) to FILE or stdout.", "FORMAT[=FILE]");
     options.optflag("q", "quiet", "Supress all non-task output.");
+    options.optmulti("", "report", "Write a report of the run to FILE in FORMAT (junit, sarif).", "FORMAT=FILE");
+    options.optflag("", "shared-lock", "Run alongside other runs in the same project that also use --shared-lock.");
+    options.optflag("", "summary", "Print how long each task took once all tasks are done.");
+    options.optflag("", "no-status", "Don't show a live status line while tasks are running.");
+    options.optopt("", "timeout", "Kill the commands of tasks that run longer than SECONDS.", "SECONDS");
     options.optflagmulti("v", "verbose", "Enable verbose logging.");
     options.optflag("V", "version", "Print the program version and exit.");
+    options.optflag("", "wait", "Wait for other runs in the same project to finish instead of failing.");
+    options.optflag("w", "watch", "Run the tasks again whenever the files they depend on change.");
 
-    let matches = options.parse(&args[1..]).unwrap_or_else(|err| {
-        logger::init(logger::Filter::Error).unwrap();
+    let mut matches = options.parse(&args[1..]).unwrap_or_else(|err| {
+        logger::init(logger::Filter::Error, None).unwrap();
         error!("{}", err);
-        process::exit(2);
+        process::exit(EXIT_USAGE);
     });
 
+    // Open the event stream, if requested.
+    let events = match matches.opt_str("output").map(|spec| report::event_stream(&spec)) {
+        Some(Ok(events)) => Some(events),
+        Some(Err(e)) => {
+            logger::init(logger::Filter::Error, None).unwrap();
+            error!("{}", e);
+            process::exit(EXIT_USAGE);
+        }
+        None => None,
+    };
+
     // Set the logging verbosity level.
     logger::init(if matches.opt_present("quiet") {
         logger::Filter::Error
@@ -83,12 +192,27 @@ fn main() {
             1 => logger::Filter::Debug,
             _ => logger::Filter::Trace,
         }
-    }).unwrap();
+    }, events.clone()).unwrap();
 
     // Notify the user if higher vebosity has been achieved.
     debug!("debug messages turned on");
     trace!("trace messages turned on");
 
+    // Read the user and system configuration. Flags given on the command line take precedence.
+    let config = config::Config::load();
+
+    if !matches.opt_present("no-color") {
+        if let Some(color) = config.color {
+            console::set_colors(color);
+        }
+    } else {
+        console::set_colors(false);
+    }
+
+    for &(ref name, ref value) in &config.env {
+        env::set_var(name, value);
+    }
+
     // If the help flag is present show the usage message.
     if matches.opt_present("help") {
         print_usage(options);
@@ -101,27 +225,69 @@ fn main() {
         return;
     }
 
+    // Print a completion script, which doesn't need a Rotefile.
+    if let Some(shell) = matches.opt_str("completions") {
+        match completions::script(&shell) {
+            Ok(script) => print!("{}", script),
+            Err(e) => {
+                error!("{}", e);
+                process::exit(EXIT_USAGE);
+            }
+        }
+        return;
+    }
+
     // If the directory flag is present, change directories first.
     if let Some(directory) = matches.opt_str("directory") {
         if env::set_current_dir(&directory).is_err() {
             error!("failed to change directory to '{}'", &directory);
-            process::exit(1);
+            process::exit(EXIT_USAGE);
         }
     }
 
-    // Get the full path of the Rotefile to run.
-    let filename = matches.opt_str("file").unwrap_or("Rotefile".to_string());
-    let path = path::Path::new(&filename)
-        .canonicalize()
-        .unwrap_or_else(|_| {
-            error!("the path '{}' is not a file or is not readable", filename);
-            process::exit(1);
-        });
+    // Get the full path of the Rotefile to run. Without one given, look for it in the current
+    // directory and then in its parents, so that tasks can be run from anywhere in a project.
+    let path = match matches.opt_str("file") {
+        Some(filename) => {
+            let mut path = path::PathBuf::from(&filename);
+
+            // A directory stands for the Rotefile inside it.
+            if path.is_dir() {
+                path = find_rotefile(&path, false).unwrap_or_else(|| {
+                    error!("no Rotefile found in '{}'", filename);
+                    process::exit(EXIT_USAGE);
+                });
+            }
+
+            path.canonicalize().unwrap_or_else(|_| {
+                error!("the path '{}' is not a file or is not readable", filename);
+                process::exit(EXIT_USAGE);
+            })
+        }
+        None => {
+            let dir = env::current_dir().unwrap_or_else(|e| {
+                error!("failed to get the current directory: {}", e);
+                process::exit(EXIT_INTERNAL_ERROR);
+            });
+
+            match find_rotefile(&dir, !matches.opt_present("no-search")) {
+                Some(path) => path.canonicalize().unwrap_or(path),
+                None => {
+                    if matches.opt_present("no-search") {
+                        error!("no Rotefile found in the current directory");
+                    } else {
+                        error!("no Rotefile found in the current directory or any of its parents");
+                    }
+                    process::exit(EXIT_USAGE);
+                }
+            }
+        }
+    };
 
     // Create a new task runner.
     let mut runner = Runner::new(path).unwrap_or_else(|e| {
         error!("{}", e);
-        process::exit(1);
+        process::exit(EXIT_USAGE);
     });
 
     info!("build file: {}", runner.path().to_string_lossy());
@@ -129,18 +295,39 @@ fn main() {
     // Set the new current directory to the directory containing the Rotefile.
     if env::set_current_dir(runner.directory()).is_err() {
         error!("failed to change directory to '{}'", runner.directory().to_string_lossy());
-        process::exit(1);
+        process::exit(EXIT_INTERNAL_ERROR);
     }
 
     // Set project-local and global include path.
     runner.include_path("./rote");
     runner.include_path("/usr/lib/rote/plugins");
 
+    // Paths from the configuration.
+    for path in &config.include_paths {
+        runner.include_path(path);
+    }
+
+    // Paths from the environment, separated like `PATH`.
+    if let Some(paths) = env::var_os("ROTE_PATH") {
+        for path in env::split_paths(&paths) {
+            if !path.as_os_str().is_empty() {
+                runner.include_path(path);
+            }
+        }
+    }
+
     // User-specified paths.
-    for value in matches.opt_strs("include-path") {
+    for value in matches.opt_strs("include").into_iter().chain(matches.opt_strs("include-path")) {
         runner.include_path(value);
     }
 
+    // Load native plugins, which are found relative to the project directory too.
+    if !matches.opt_present("no-plugins") {
+        for dir in rote::plugins::dirs() {
+            runner.load_plugins(dir);
+        }
+    }
+
     // Set environment variables.
     for value in matches.opt_strs("var") {
         let parts: Vec<_> = value.split('=').collect();
@@ -152,51 +339,263 @@ fn main() {
         }
     }
 
+    // Set named arguments, given as `NAME=VALUE` among the tasks to run. Commands given to --exec
+    // are passed through as is.
+    if !matches.opt_present("exec") {
+        let (named, tasks) = matches.free.drain(..).partition(|arg| parse_arg(arg).is_some());
+        matches.free = tasks;
+
+        for arg in named {
+            let (name, value) = parse_arg(&arg).unwrap();
+            runner.set_arg(name, value);
+        }
+    }
+
     // Toggle dry run.
     if matches.opt_present("dry-run") {
-        info!("dry run is enabled; no task actions will be run");
+        info!("dry run is enabled; commands run by tasks will only be shown");
         runner.dry_run();
     }
 
     // Toggle always run.
-    if matches.opt_present("run-all") {
+    if matches.opt_present("run-all") || matches.opt_present("always-make") {
         info!("running all tasks unconditionally");
         runner.always_run();
     }
 
+    // Force individual tasks to run.
+    for tasks in matches.opt_strs("force") {
+        for task in tasks.split(',').filter(|task| !task.is_empty()) {
+            runner.force(task);
+        }
+    }
+
     // Toggle keep going.
     if matches.opt_present("keep-going") {
         info!("errors will be ignored");
         runner.keep_going();
     }
 
-    // Set number of jobs.
-    if let Some(jobs) = matches.opt_str("jobs") {
-        if let Ok(jobs) = jobs.parse::<usize>() {
-            runner.jobs(jobs);
+    // Write task output in a consistent order.
+    if matches.opt_present("ordered-output") {
+        runner.ordered_output();
+    }
+
+    // Write command output as it is.
+    if matches.opt_present("no-prefix") {
+        runner.no_prefix();
+    }
+
+    // Wait for other runs to finish.
+    if matches.opt_present("wait") {
+        runner.wait_for_lock();
+    }
+
+    // Share the project lock, for build files exported by Rote.
+    if matches.opt_present("shared-lock") {
+        runner.shared_lock();
+    }
+
+    // Toggle running tasks without their dependencies.
+    if matches.opt_present("no-deps") {
+        runner.no_deps();
+    }
+
+    // Set up reports.
+    for value in matches.opt_strs("report") {
+        match report::create(&value) {
+            Ok(reporter) => runner.add_reporter(reporter),
+            Err(e) => {
+                error!("{}", e);
+                process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    // Print a summary of the run at the end.
+    if matches.opt_present("summary") {
+        runner.add_reporter(Box::new(report::SummaryReporter::new()));
+    }
+
+    // Write events to the event stream.
+    if let Some(events) = events {
+        runner.add_reporter(Box::new(report::EventReporter::new(events)));
+    }
+
+    // Export traces if an OpenTelemetry endpoint is configured.
+    if let Some(reporter) = report::OtelReporter::from_env() {
+        debug!("exporting traces to OpenTelemetry");
+        runner.add_reporter(Box::new(reporter));
+    }
+
+    // Format output for a CI service, if one is requested or detected.
+    let ci = matches.opt_str("ci").or_else(|| report::detect_ci().map(String::from));
+    if let Some(ref name) = ci {
+        match report::ci(name) {
+            Ok(reporter) => runner.add_reporter(reporter),
+            Err(e) => {
+                error!("{}", e);
+                process::exit(EXIT_USAGE);
+            }
+        }
+    }
+
+    // Show progress on a live status line, but only when a person is watching.
+    if !matches.opt_present("no-status") && ci.is_none() && console::is_terminal(console::Stream::Stdout) &&
+       console::is_terminal(console::Stream::Stderr) {
+        runner.add_reporter(Box::new(report::StatusReporter::new()));
+    }
+
+    // Set number of jobs, falling back to the environment so that it can be set for a machine.
+    if let Some(jobs) = matches.opt_str("jobs").or_else(|| env::var("ROTE_JOBS").ok()).or(config.jobs.clone()) {
+        match runner::parse_jobs(&jobs) {
+            Some(jobs) => runner.jobs(jobs),
+            None => warn!("invalid number of jobs '{}'", jobs),
+        }
+    }
+
+    // Set the default task timeout.
+    if let Some(timeout) = matches.opt_str("timeout") {
+        match timeout.parse::<f64>() {
+            Ok(seconds) if seconds > 0.0 => {
+                runner.timeout(Duration::new(seconds as u64, (seconds.fract() * 1e9) as u32));
+            }
+            _ => warn!("invalid timeout"),
+        }
+    }
+
+    // Set how files are checked.
+    match matches.opt_str("check-mode").as_ref().map(|mode| mode.as_str()) {
+        None | Some("mtime") => {}
+        Some("hash") => runner.check_hashes(),
+        Some(mode) => warn!("invalid check mode '{}'", mode),
+    }
+
+    // Set the remote cache, falling back to the environment so that it can be set for CI machines.
+    if let Some(url) = matches.opt_str("cache-url").or_else(|| env::var("ROTE_CACHE_URL").ok()).or(config.cache_url.clone()) {
+        if let Err(e) = runner.cache_url(url) {
+            warn!("{}", e);
+        }
+    }
+
+    // Set the maximum load average.
+    if let Some(load) = matches.opt_str("load-average") {
+        if let Ok(load) = load.parse::<f64>() {
+            runner.max_load(load);
         } else {
-            warn!("invalid number of jobs");
+            warn!("invalid load average");
         }
     }
 
+    // Wrap an ad-hoc command in a task to run instead of tasks from the script.
+    let command_task = if matches.opt_present("exec") {
+        if matches.free.is_empty() {
+            error!("no command given to --exec");
+            process::exit(EXIT_USAGE);
+        }
+
+        Some(runner.add_command(matches.free.clone()))
+    } else {
+        None
+    };
+
+    // Run a language server instead of running tasks. Errors loading the script are reported to
+    // the editor as diagnostics, so the script is loaded by the server itself.
+    if matches.opt_present("lsp") {
+        if let Err(e) = lsp::serve(&mut runner) {
+            error!("{}", e);
+            process::exit(EXIT_INTERNAL_ERROR);
+        }
+        return;
+    }
+
     // Load the script.
     if let Err(e) = runner.load() {
         error!("{}", e);
-        process::exit(1);
+        process::exit(EXIT_SCRIPT_ERROR);
+    }
+
+    // Check the script for problems instead of running tasks.
+    if matches.opt_present("check") {
+        let problems = runner.check();
+
+        for &(_, ref problem) in &problems {
+            error!("{}", problem);
+        }
+
+        if !problems.is_empty() {
+            process::exit(EXIT_SCRIPT_ERROR);
+        }
+
+        info!("no problems found");
+        return;
     }
 
     // List all tasks instead of running one.
     if matches.opt_present("list") {
-        runner.print_task_list();
+        let format = matches.opt_str("format").unwrap_or("pretty".to_string());
+
+        if let Err(e) = runner.print_task_list(&format) {
+            error!("{}", e);
+            process::exit(EXIT_USAGE);
+        }
         return;
     }
 
+    // Serve requests until told to stop instead of running tasks.
+    if matches.opt_present("daemon") {
+        let socket = matches.opt_str("daemon").unwrap_or(".rote.sock".to_string());
+
+        if let Err(e) = daemon::serve(&mut runner, socket) {
+            error!("{}", e);
+            process::exit(EXIT_INTERNAL_ERROR);
+        }
+        return;
+    }
+
+    // Print the task graph instead of running tasks.
+    if matches.opt_present("graph") {
+        let format = matches.opt_str("graph").unwrap_or("dot".to_string());
+
+        if let Err(e) = runner.print_graph(&matches.free, &format) {
+            error!("{}", e);
+            process::exit(exit_code(&*e));
+        }
+        return;
+    }
+
+    // Export the task graph instead of running tasks.
+    if let Some(file) = matches.opt_str("export-ninja") {
+        if let Err(e) = runner.export_ninja(&matches.free, file) {
+            error!("{}", e);
+            process::exit(exit_code(&*e));
+        }
+        return;
+    }
+
+    if let Some(file) = matches.opt_str("export-make") {
+        if let Err(e) = runner.export_make(&matches.free, file) {
+            error!("{}", e);
+            process::exit(exit_code(&*e));
+        }
+        return;
+    }
+
+    // Stop running tasks and their commands when interrupted.
+    runner.handle_interrupts();
+
     // Get all of the tasks to run.
-    let tasks = matches.free;
+    let watch = matches.opt_present("watch");
+    let tasks = match command_task {
+        Some(name) => vec![name],
+        None => matches.free,
+    };
 
     // Run the specified task, or the default if none is specified.
     if let Err(e) = {
-        if tasks.is_empty() {
+        if watch {
+            runner.watch(&tasks)
+        } else if tasks.is_empty() {
             runner.run_default()
         } else {
             // Run the specified tasks.
@@ -204,6 +603,6 @@ fn main() {
         }
     } {
         error!("{}", e);
-        process::exit(1);
+        process::exit(exit_code(&*e));
     }
 }
@@ -0,0 +1,266 @@
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use fscache;
+use lua;
+use runtime::{Runtime, ScriptResult};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use tar;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use zip::write::FileOptions;
+
+
+/// Creates a zip archive.
+///
+/// # Lua arguments
+/// * `files: table`            - A list of files and directories to add. Directories are added
+///                               with everything inside them.
+/// * `dest: string`            - Path of the archive to create.
+fn zip(runtime: Runtime) -> ScriptResult {
+    let files = try!(get_files(runtime.clone(), 1));
+    let dest = runtime.check_file_path(2);
+    if runtime.skip_change(format!("write \"{}\"", dest.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
+
+    let mut writer = ZipWriter::new(try!(File::create(&dest)));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (path, name) in files {
+        try!(writer.start_file(name, options));
+        try!(io::copy(&mut try!(File::open(&path)), &mut writer));
+    }
+
+    try!(writer.finish());
+    Ok(0)
+}
+
+/// Extracts a zip archive.
+///
+/// # Lua arguments
+/// * `src: string`             - Path of the archive to extract.
+/// * `dest: string`            - Directory to extract the archive into.
+fn unzip(runtime: Runtime) -> ScriptResult {
+    let src = runtime.check_file_path(1);
+    let dest = runtime.check_file_path(2);
+    if runtime.skip_change(format!("extract \"{}\" to \"{}\"", src.display(), dest.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
+
+    let mut archive = try!(ZipArchive::new(try!(File::open(&src))));
+
+    for i in 0..archive.len() {
+        let mut file = try!(archive.by_index(i));
+
+        // Never write outside of the destination, no matter what the archive says.
+        let path = match relative_path(Path::new(file.name())) {
+            Some(path) => dest.join(path),
+            None => return Err(format!("unsafe path in archive: {}", file.name()).into()),
+        };
+
+        if file.name().ends_with('/') {
+            try!(fs::create_dir_all(&path));
+        } else {
+            if let Some(parent) = path.parent() {
+                try!(fs::create_dir_all(parent));
+            }
+            try!(io::copy(&mut file, &mut try!(File::create(&path))));
+        }
+    }
+
+    Ok(0)
+}
+
+/// Creates a tar archive.
+///
+/// # Lua arguments
+/// * `files: table`            - A list of files and directories to add. Directories are added
+///                               with everything inside them.
+/// * `dest: string`            - Path of the archive to create.
+/// * `options: table`          - Options for creating the archive. (Optional)
+///   * `compression: string`   - Either `"gz"` to compress the archive with gzip, or `"none"`.
+///                               Defaults to `"none"`.
+fn tar(runtime: Runtime) -> ScriptResult {
+    let files = try!(get_files(runtime.clone(), 1));
+    let dest = runtime.check_file_path(2);
+    let compressed = try!(get_compression(runtime.clone(), 3)).unwrap_or(false);
+    if runtime.skip_change(format!("write \"{}\"", dest.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
+
+    let file = try!(File::create(&dest));
+
+    if compressed {
+        let encoder = try!(write_tar(GzEncoder::new(file, Compression::Default), files));
+        try!(encoder.finish());
+    } else {
+        try!(write_tar(file, files));
+    }
+
+    Ok(0)
+}
+
+/// Extracts a tar archive.
+///
+/// # Lua arguments
+/// * `src: string`             - Path of the archive to extract.
+/// * `dest: string`            - Directory to extract the archive into.
+/// * `options: table`          - Options for extracting the archive. (Optional)
+///   * `compression: string`   - Either `"gz"` if the archive is compressed with gzip, or
+///                               `"none"`. Detected from the archive if not given.
+fn untar(runtime: Runtime) -> ScriptResult {
+    let src = runtime.check_file_path(1);
+    let dest = runtime.check_file_path(2);
+    let compressed = try!(get_compression(runtime.clone(), 3));
+    if runtime.skip_change(format!("extract \"{}\" to \"{}\"", src.display(), dest.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
+
+    let mut file = BufReader::new(try!(File::open(&src)));
+
+    // Gzip files always start with the same two bytes.
+    let compressed = match compressed {
+        Some(compressed) => compressed,
+        None => {
+            let mut magic = [0; 2];
+            let is_gzip = file.read_exact(&mut magic).is_ok() && magic == [0x1f, 0x8b];
+            try!(file.seek(SeekFrom::Start(0)));
+            is_gzip
+        }
+    };
+
+    try!(fs::create_dir_all(&dest));
+
+    if compressed {
+        try!(tar::Archive::new(try!(GzDecoder::new(file))).unpack(&dest));
+    } else {
+        try!(tar::Archive::new(file).unpack(&dest));
+    }
+
+    Ok(0)
+}
+
+/// Writes files to a tar archive and returns the underlying writer.
+fn write_tar<W: Write>(writer: W, files: Vec<(PathBuf, String)>) -> io::Result<W> {
+    let mut builder = tar::Builder::new(writer);
+
+    for (path, name) in files {
+        try!(builder.append_file(name, &mut try!(File::open(&path))));
+    }
+
+    builder.into_inner()
+}
+
+/// Gets the compression option from an options table, if it is given.
+fn get_compression(runtime: Runtime, index: lua::Index) -> Result<Option<bool>, Box<Error>> {
+    if !runtime.state().is_table(index) {
+        return Ok(None);
+    }
+
+    runtime.state().get_field(index, "compression");
+    let compression = runtime.state().to_str_in_place(-1).map(String::from);
+    runtime.state().pop(1);
+
+    match compression.as_ref().map(|s| s.as_str()) {
+        None => Ok(None),
+        Some("gz") => Ok(Some(true)),
+        Some("none") => Ok(Some(false)),
+        Some(other) => Err(format!("unsupported compression '{}'", other).into()),
+    }
+}
+
+/// Gets the files to add to an archive from a list of paths, along with the name of each file in
+/// the archive. Directories are walked recursively.
+fn get_files(runtime: Runtime, index: lua::Index) -> Result<Vec<(PathBuf, String)>, Box<Error>> {
+    if !runtime.state().is_table(index) {
+        return Err("files must be a table".into());
+    }
+
+    let mut paths = Vec::new();
+    for (_, value) in runtime.iter(index) {
+        match runtime.to_path(value) {
+            Some(path) => paths.push(path),
+            None => return Err("files must be paths".into()),
+        }
+    }
+
+    // Names in the archive are relative to the current directory of the script.
+    let base = runtime.environment().working_dir().unwrap_or(PathBuf::new());
+
+    let mut files = Vec::new();
+    for path in paths {
+        try!(add_files(&base, &path, &mut files));
+    }
+
+    Ok(files)
+}
+
+/// Adds a file, or all files in a directory, to a list of files to archive. Relative paths are
+/// relative to `base`.
+fn add_files(base: &Path, path: &Path, files: &mut Vec<(PathBuf, String)>) -> Result<(), Box<Error>> {
+    let full_path = base.join(path);
+
+    if try!(fs::metadata(&full_path)).is_dir() {
+        let mut entries = Vec::new();
+        for entry in try!(fs::read_dir(&full_path)) {
+            entries.push(path.join(try!(entry).file_name()));
+        }
+        entries.sort();
+
+        for entry in entries {
+            try!(add_files(base, &entry, files));
+        }
+    } else {
+        // Files are stored under their path relative to the current directory.
+        let name = match relative_path(path) {
+            Some(name) => name,
+            None => return Err(format!("cannot archive '{}': paths must be relative", path.display()).into()),
+        };
+
+        let name: Vec<String> = name.components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        files.push((full_path, name.join("/")));
+    }
+
+    Ok(())
+}
+
+/// Normalizes a relative path, or returns `None` if the path is absolute or leads outside of the
+/// directory it is relative to.
+fn relative_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    if normalized.as_os_str().is_empty() {
+        None
+    } else {
+        Some(normalized)
+    }
+}
+
+/// Module loader.
+pub fn load(runtime: Runtime) -> ScriptResult {
+    runtime.load_lib(&[
+        ("tar", tar),
+        ("untar", untar),
+        ("unzip", unzip),
+        ("zip", zip),
+    ]);
+
+    Ok(1)
+}
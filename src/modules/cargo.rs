@@ -0,0 +1,494 @@
+use console::{self, Stream};
+use json::{self, JsonValue};
+use lua;
+use modules::stdlib::{program_command, run_command, run_command_output};
+use process;
+use regex::Regex;
+use report::Diagnostic;
+use runtime::{Runtime, ScriptResult};
+use std::error::Error;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+
+/// Reads the metadata of the workspace by running `cargo metadata`.
+///
+/// # Lua arguments
+/// * `options: table`          - Options for reading the metadata. (Optional)
+///   * `manifest_path: string` - Path of the `Cargo.toml` of the workspace. Defaults to the one
+///                               Cargo finds from the current directory.
+///
+/// # Lua returns
+/// * `metadata: table`         - The `workspace_root` and `target_directory` paths, the `version`
+///                               of the root package if there is one, and a list of `members`
+///                               with the `name`, `version`, `manifest_path` and `targets` of each
+///                               package in the workspace.
+fn metadata(runtime: Runtime) -> ScriptResult {
+    let mut command = cargo_command(&runtime, "metadata");
+    command.arg("--format-version").arg("1").arg("--no-deps");
+
+    if let Some(path) = try!(get_string(&runtime, 1, "manifest_path")) {
+        command.arg("--manifest-path").arg(path);
+    }
+
+    let output = try!(capture(command));
+
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("cargo metadata failed: {}", message.trim()).into());
+    }
+
+    let metadata = try!(json::parse(&String::from_utf8_lossy(&output.stdout)));
+    push_metadata(&runtime, &metadata);
+
+    Ok(1)
+}
+
+/// Builds the workspace with `cargo build`.
+///
+/// # Lua arguments
+/// * `options: table`          - Options for building. (Optional)
+///   * `release: boolean`      - Build with the release profile.
+///   * `package: string`       - Only build the given package of the workspace.
+///   * `features: table`       - A list of features to enable.
+///   * `args: table`           - More arguments to pass to Cargo.
+fn build(runtime: Runtime) -> ScriptResult {
+    let command = try!(options_command(&runtime, "build"));
+    run_command(runtime, command)
+}
+
+/// Runs the tests of the workspace with `cargo test`. Failing tests don't raise an error, so that
+/// the results can be checked.
+///
+/// # Lua arguments
+/// * `options: table`          - The same options as `build()`. (Optional)
+///
+/// # Lua returns
+/// * `result: table`           - Whether all tests passed as `ok`, the number of tests `passed`,
+///                               `failed` and `ignored`, and a list of `tests` with the `name` and
+///                               `status` of each test.
+fn test(runtime: Runtime) -> ScriptResult {
+    let command = try!(options_command(&runtime, "test"));
+    let (code, output) = try!(run_command_output(&runtime, command));
+
+    push_tests(&runtime, code == 0, &output);
+    Ok(1)
+}
+
+/// Runs the benchmarks of the workspace with `cargo bench`. Failing benchmarks don't raise an
+/// error, so that the results can be checked.
+///
+/// # Lua arguments
+/// * `options: table`          - The same options as `build()`. (Optional)
+///
+/// # Lua returns
+/// * `result: table`           - The same results as `test()`, where each benchmark also has the
+///                               number of nanoseconds each iteration took as `ns_per_iter`.
+fn bench(runtime: Runtime) -> ScriptResult {
+    let command = try!(options_command(&runtime, "bench"));
+    let (code, output) = try!(run_command_output(&runtime, command));
+
+    push_tests(&runtime, code == 0, &output);
+    Ok(1)
+}
+
+/// Checks the workspace with `cargo clippy`. Lints don't raise an error, so that the results can
+/// be checked. Each lint is also reported as a diagnostic of the current task.
+///
+/// # Lua arguments
+/// * `options: table`          - The same options as `build()`. (Optional)
+///
+/// # Lua returns
+/// * `result: table`           - Whether Clippy succeeded as `ok`, the number of `warnings` and
+///                               `errors`, and a list of `messages` with the `level`, `message`,
+///                               `code`, `file`, `line` and `column` of each lint.
+fn clippy(runtime: Runtime) -> ScriptResult {
+    let mut command = try!(options_command(&runtime, "clippy"));
+    command.arg("--message-format=json");
+
+    let output = try!(capture(command));
+
+    // Show the progress of Cargo and the lints as Cargo would have without JSON output.
+    console::write_bytes(Stream::Stderr, &output.stderr);
+
+    let mut messages = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let value = match json::parse(line) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        if value["reason"].as_str() == Some("compiler-message") {
+            let message = &value["message"];
+
+            if let Some(rendered) = message["rendered"].as_str() {
+                console::write_bytes(Stream::Stderr, rendered.as_bytes());
+            }
+
+            // Summaries such as "2 warnings emitted" aren't about any code.
+            let primary = message["spans"]
+                .members()
+                .find(|span| span["is_primary"].as_bool() == Some(true));
+            if let Some(span) = primary {
+                messages.push(Diagnostic {
+                    tool: "clippy".to_string(),
+                    rule: message["code"]["code"].as_str().map(String::from),
+                    level: message["level"].as_str().unwrap_or("warning").to_string(),
+                    message: message["message"].as_str().unwrap_or("").to_string(),
+                    file: span["file_name"].as_str().map(String::from),
+                    line: span["line_start"].as_u32(),
+                    column: span["column_start"].as_u32(),
+                });
+            }
+        }
+    }
+
+    runtime.state().new_table();
+    runtime.state().push_bool(output.status.success());
+    runtime.state().set_field(-2, "ok");
+    let count = |level: &str| messages.iter().filter(|message| message.level == level).count();
+    set_count(&runtime, "warnings", count("warning"));
+    set_count(&runtime, "errors", count("error"));
+
+    runtime.state().new_table();
+    for (i, message) in messages.iter().enumerate() {
+        runtime.state().push_number((i + 1) as f64);
+        runtime.state().new_table();
+        set_string(&runtime, "level", Some(&message.level));
+        set_string(&runtime, "message", Some(&message.message));
+        set_string(&runtime, "code", message.rule.as_ref().map(|rule| rule.as_str()));
+        set_string(&runtime, "file", message.file.as_ref().map(|file| file.as_str()));
+        if let Some(line) = message.line {
+            set_count(&runtime, "line", line as usize);
+        }
+        if let Some(column) = message.column {
+            set_count(&runtime, "column", column as usize);
+        }
+        runtime.state().set_table(-3);
+    }
+    runtime.state().set_field(-2, "messages");
+
+    for message in messages {
+        runtime.environment().add_diagnostic(message);
+    }
+
+    Ok(1)
+}
+
+/// Formats the workspace with `cargo fmt`, or checks if it is formatted.
+///
+/// # Lua arguments
+/// * `options: table`          - Options for formatting. (Optional)
+///   * `check: boolean`        - Only check the formatting instead of changing any files.
+///   * `package: string`       - Only format the given package of the workspace.
+///   * `args: table`           - More arguments to pass to Cargo.
+///
+/// # Lua returns
+/// * `result: table`           - Whether the code is formatted as `ok`, and a list of `files`
+///                               that are not.
+fn fmt(runtime: Runtime) -> ScriptResult {
+    let mut command = try!(options_command(&runtime, "fmt"));
+
+    let check = runtime.state().is_table(1) && {
+        runtime.state().get_field(1, "check");
+        let check = runtime.state().to_bool(-1);
+        runtime.state().pop(1);
+        check
+    };
+
+    if check {
+        command.arg("--").arg("--check");
+    }
+
+    let (code, output) = try!(run_command_output(&runtime, command));
+
+    // Rustfmt lists each difference it finds in a file, and the format has changed over time.
+    let pattern = Regex::new(r"^Diff in (.+?)(?: at line \d+|:\d+):").unwrap();
+    let mut files: Vec<String> = Vec::new();
+
+    for line in output.lines() {
+        if let Some(file) = pattern.captures(line).and_then(|caps| caps.at(1)) {
+            if !files.iter().any(|seen| seen == file) {
+                files.push(file.to_string());
+            }
+        }
+    }
+
+    runtime.state().new_table();
+    runtime.state().push_bool(code == 0);
+    runtime.state().set_field(-2, "ok");
+
+    runtime.state().new_table();
+    for (i, file) in files.iter().enumerate() {
+        runtime.state().push_number((i + 1) as f64);
+        runtime.state().push_string(file);
+        runtime.state().set_table(-3);
+    }
+    runtime.state().set_field(-2, "files");
+
+    Ok(1)
+}
+
+/// Removes the target directory with `cargo clean`.
+fn clean(runtime: Runtime) -> ScriptResult {
+    let command = cargo_command(&runtime, "clean");
+    run_command(runtime, command)
+}
+
+/// Runs a command to completion and collects its output without showing it.
+fn capture(mut command: Command) -> Result<Output, Box<Error>> {
+    command.stdin(Stdio::null());
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+
+    let (child, _group) = match process::spawn(&mut command) {
+        Ok(child) => child,
+        Err(e) => return Err(format!("failed to execute process: {}", e).into()),
+    };
+
+    Ok(try!(child.wait_with_output()))
+}
+
+/// Creates a command for a Cargo subcommand.
+fn cargo_command(runtime: &Runtime, subcommand: &str) -> Command {
+    let mut command = program_command("cargo");
+    command.arg(subcommand);
+
+    // Tasks of included scripts run commands in the directory of the script.
+    if let Some(dir) = runtime.environment().working_dir() {
+        command.current_dir(dir);
+    }
+
+    command
+}
+
+/// Creates a command for a Cargo subcommand with the build options in the table at the given
+/// index.
+fn options_command(runtime: &Runtime, subcommand: &str) -> Result<Command, Box<Error>> {
+    let mut command = cargo_command(runtime, subcommand);
+
+    if !runtime.state().is_table(1) {
+        return Ok(command);
+    }
+
+    runtime.state().get_field(1, "release");
+    if runtime.state().to_bool(-1) {
+        command.arg("--release");
+    }
+    runtime.state().pop(1);
+
+    if let Some(package) = try!(get_string(runtime, 1, "package")) {
+        command.arg("--package").arg(package);
+    }
+
+    let features = try!(get_list(runtime, 1, "features"));
+    if !features.is_empty() {
+        command.arg("--features").arg(features.join(","));
+    }
+
+    command.args(&try!(get_list(runtime, 1, "args")));
+
+    Ok(command)
+}
+
+/// Gets a string field from an options table, if the table and the field are given.
+fn get_string(runtime: &Runtime, index: lua::Index, field: &str) -> Result<Option<String>, Box<Error>> {
+    if !runtime.state().is_table(index) {
+        return Ok(None);
+    }
+
+    let value = match runtime.state().get_field(index, field) {
+        lua::Type::Nil => Ok(None),
+        lua::Type::String => Ok(runtime.state().to_str_in_place(-1).map(String::from)),
+        _ => Err(format!("{} must be a string", field).into()),
+    };
+    runtime.state().pop(1);
+
+    value
+}
+
+/// Gets a list of strings from a field of an options table.
+fn get_list(runtime: &Runtime, index: lua::Index, field: &str) -> Result<Vec<String>, Box<Error>> {
+    let mut list = Vec::new();
+
+    match runtime.state().get_field(index, field) {
+        lua::Type::Nil => {}
+        lua::Type::Table => {
+            let table = runtime.state().get_top();
+            for (_, value) in runtime.iter(table) {
+                match runtime.state().to_str_in_place(value) {
+                    Some(item) => list.push(item.to_string()),
+                    None => {
+                        runtime.state().pop(3);
+                        return Err(format!("{} must be a list of strings", field).into());
+                    }
+                }
+            }
+        }
+        _ => {
+            runtime.state().pop(1);
+            return Err(format!("{} must be a list of strings", field).into());
+        }
+    }
+    runtime.state().pop(1);
+
+    Ok(list)
+}
+
+/// Pushes the parts of the output of `cargo metadata` that are useful to scripts onto the stack as
+/// a table.
+fn push_metadata(runtime: &Runtime, metadata: &JsonValue) {
+    let members: Vec<&str> = metadata["workspace_members"]
+        .members()
+        .filter_map(|id| id.as_str())
+        .collect();
+    let root_manifest = Path::new(metadata["workspace_root"].as_str().unwrap_or("")).join("Cargo.toml");
+
+    runtime.state().new_table();
+    set_string(runtime, "workspace_root", metadata["workspace_root"].as_str());
+    set_string(runtime, "target_directory", metadata["target_directory"].as_str());
+
+    // Only packages in the workspace are listed, in the order Cargo gives them.
+    let mut version = None;
+    let mut index = 1;
+
+    runtime.state().new_table();
+    for package in metadata["packages"].members() {
+        if !package["id"].as_str().map_or(false, |id| members.contains(&id)) {
+            continue;
+        }
+
+        // The root package is the one in the root of the workspace, if it isn't a virtual one.
+        if package["manifest_path"].as_str().map_or(false, |path| Path::new(path) == root_manifest) {
+            version = package["version"].as_str();
+        }
+
+        runtime.state().push_number(index as f64);
+        push_package(runtime, package);
+        runtime.state().set_table(-3);
+
+        index += 1;
+    }
+    runtime.state().set_field(-2, "members");
+
+    set_string(runtime, "version", version);
+}
+
+/// Pushes a package of the workspace onto the stack as a table.
+fn push_package(runtime: &Runtime, package: &JsonValue) {
+    runtime.state().new_table();
+    set_string(runtime, "name", package["name"].as_str());
+    set_string(runtime, "version", package["version"].as_str());
+    set_string(runtime, "manifest_path", package["manifest_path"].as_str());
+
+    let mut index = 1;
+
+    runtime.state().new_table();
+    for target in package["targets"].members() {
+        runtime.state().push_number(index as f64);
+        runtime.state().new_table();
+        set_string(runtime, "name", target["name"].as_str());
+        set_string(runtime, "src_path", target["src_path"].as_str());
+
+        // A target can be of several kinds at once, such as both `lib` and `rlib`.
+        let mut kind_index = 1;
+
+        runtime.state().new_table();
+        for kind in target["kind"].members().filter_map(|kind| kind.as_str()) {
+            runtime.state().push_number(kind_index as f64);
+            runtime.state().push_string(kind);
+            runtime.state().set_table(-3);
+
+            kind_index += 1;
+        }
+        runtime.state().set_field(-2, "kind");
+
+        runtime.state().set_table(-3);
+
+        index += 1;
+    }
+    runtime.state().set_field(-2, "targets");
+}
+
+/// Pushes the results of running tests or benchmarks onto the stack as a table, from the output of
+/// the test harness.
+fn push_tests(runtime: &Runtime, ok: bool, output: &str) {
+    let pattern = Regex::new(r"^test (.+) \.\.\. (ok|FAILED|ignored.*|bench:\s+([\d,.]+) ns/iter.*)$")
+        .unwrap();
+    let (mut passed, mut failed, mut ignored) = (0, 0, 0);
+    let mut index = 1;
+
+    runtime.state().new_table();
+    runtime.state().push_bool(ok);
+    runtime.state().set_field(-2, "ok");
+
+    runtime.state().new_table();
+    for line in output.lines() {
+        let caps = match pattern.captures(line.trim_right()) {
+            Some(caps) => caps,
+            None => continue,
+        };
+
+        let status = match caps.at(2).unwrap_or("") {
+            "FAILED" => {
+                failed += 1;
+                "failed"
+            }
+            result if result.starts_with("ignored") => {
+                ignored += 1;
+                "ignored"
+            }
+            _ => {
+                passed += 1;
+                "passed"
+            }
+        };
+
+        runtime.state().push_number(index as f64);
+        runtime.state().new_table();
+        set_string(runtime, "name", caps.at(1));
+        set_string(runtime, "status", Some(status));
+
+        if let Some(time) = caps.at(3).and_then(|time| time.replace(",", "").parse::<f64>().ok()) {
+            runtime.state().push_number(time);
+            runtime.state().set_field(-2, "ns_per_iter");
+        }
+
+        runtime.state().set_table(-3);
+        index += 1;
+    }
+    runtime.state().set_field(-2, "tests");
+
+    set_count(runtime, "passed", passed);
+    set_count(runtime, "failed", failed);
+    set_count(runtime, "ignored", ignored);
+}
+
+/// Sets a number field of the table on top of the stack.
+fn set_count(runtime: &Runtime, field: &str, count: usize) {
+    runtime.state().push_number(count as f64);
+    runtime.state().set_field(-2, field);
+}
+
+/// Sets a string field of the table on top of the stack, if there is a value.
+fn set_string(runtime: &Runtime, field: &str, value: Option<&str>) {
+    if let Some(value) = value {
+        runtime.state().push_string(value);
+        runtime.state().set_field(-2, field);
+    }
+}
+
+/// Module loader.
+pub fn load(runtime: Runtime) -> ScriptResult {
+    runtime.load_lib(&[
+        ("bench", bench),
+        ("build", build),
+        ("clean", clean),
+        ("clippy", clippy),
+        ("fmt", fmt),
+        ("metadata", metadata),
+        ("test", test),
+    ]);
+
+    Ok(1)
+}
@@ -1,88 +1,442 @@
 -- Module for generating C/C++ build tasks.
-local cpp = {}
+local fs = require "fs"
+local json = require "json"
+local cpp = {
+    -- Compile commands of all object files created so far, in the format of
+    -- a clang compilation database.
+    compile_commands = {},
 
+    -- Files written by all targets defined so far, which are removed by
+    -- `cpp.clean()`.
+    outputs = {},
+}
 
-function cpp.binary(options)
-    options = rote.merge({
-        standard = "c++11",
-        debug = true,
-        includes = {},
-        opt_level = 0,
-        macros = {},
-    }, options)
+-- Libraries defined so far by name, with the file each is built to and the
+-- libraries it links to.
+local libraries = {}
 
-    if not options.name then
-        error("name must be specified")
+
+-- Reads the files an object file was built from out of a dependency file
+-- written by the compiler with `-MMD`. Returns an empty list if the file
+-- doesn't exist yet.
+function cpp.read_depfile(path)
+    if not fs.exists(path) then
+        return {}
     end
 
-    if not options.srcs then
-        error("srcs must be specified")
+    -- Join continued lines.
+    local source = fs.get(path):gsub("\\\r?\n", " ")
+    local deps = {}
+
+    for line in source:gmatch("[^\r\n]+") do
+        -- The target ends at the first colon followed by a space, so that
+        -- Windows drive letters are left alone.
+        local prereqs = line:match("^.-:%s(.*)$")
+
+        if prereqs then
+            -- Spaces in file names are escaped with backslashes.
+            prereqs = prereqs:gsub("\\ ", "\0"):gsub("%$%$", "$")
+
+            for dep in prereqs:gmatch("%S+") do
+                table.insert(deps, (dep:gsub("\0", " ")))
+            end
+        end
+    end
+
+    return deps
+end
+
+-- Appends every value in a list to another list.
+local function append(list, values)
+    for _, value in ipairs(values) do
+        table.insert(list, value)
+    end
+    return list
+end
+
+-- Creates a toolchain for a compiler that takes GCC-style flags.
+local function gnu_toolchain(name, compiler)
+    local toolchain = {
+        name = name,
+        compiler = compiler,
+        archiver = "ar",
+        object_suffix = ".o",
+    }
+
+    function toolchain:compile_args(options, source, object_file, depfile)
+        local args = {self.compiler, "-Wall", "--std=" .. string.lower(options.standard)}
+
+        for _, include in ipairs(options.includes) do
+            table.insert(args, "-I" .. include)
+        end
+
+        if options.debug then
+            table.insert(args, "-g")
+        end
+
+        table.insert(args, "-O" .. options.opt_level)
+
+        -- Macros are set to `1` if given as a truthy, non-string value.
+        for macro, def in pairs(options.macros) do
+            if type(def) == "string" then
+                table.insert(args, "-D" .. macro .. "=" .. def)
+            elseif def then
+                table.insert(args, "-D" .. macro)
+            end
+        end
+
+        -- Code in a shared library may be loaded at any address.
+        if options.type == "shared" then
+            table.insert(args, "-fPIC")
+        end
+
+        return append(args, {"-MMD", "-MF", depfile, "-c", "-o", object_file, source})
+    end
+
+    function toolchain:link_args(options, inputs, output)
+        if options.type == "static" then
+            return append({self.archiver, "rcs", output}, inputs)
+        end
+
+        local args = {self.compiler, "-O" .. options.opt_level}
+        if options.type == "shared" then
+            table.insert(args, "-shared")
+        end
+
+        return append(append(args, {"-o", output}), inputs)
     end
 
-    local compiler = CC or "g++"
-    local linker = CC or "g++"
+    function toolchain:depfile(object_file)
+        return object_file .. ".d"
+    end
 
-    local compiler_flags = {"-Wall", "--std=" .. string.lower(options.standard)}
-    local linker_flags = {}
+    function toolchain:system_lib(name)
+        return "-l" .. name
+    end
+
+    -- Programs can be linked to a shared library directly.
+    function toolchain:import_lib(file)
+        return file
+    end
+
+    return toolchain
+end
+
+-- Optimization flags of MSVC for each optimization level.
+local msvc_opt_levels = {
+    ["0"] = "/Od",
+    ["1"] = "/O1",
+    ["2"] = "/O2",
+    ["3"] = "/O2",
+    ["s"] = "/O1",
+}
+
+-- Toolchains that can be used to build C and C++ code, by name.
+cpp.toolchains = {
+    gcc = gnu_toolchain("gcc", "g++"),
+    clang = gnu_toolchain("clang", "clang++"),
+    msvc = {
+        name = "msvc",
+        compiler = "cl",
+        archiver = "lib",
+        object_suffix = ".obj",
+    },
+}
+
+function cpp.toolchains.msvc:compile_args(options, source, object_file)
+    -- MSVC doesn't support any standard older than C++14.
+    local standard = string.lower(options.standard)
+    if standard == "c++11" then
+        standard = "c++14"
+    end
+
+    local args = {self.compiler, "/nologo", "/W3", "/std:" .. standard}
 
-    -- Add include paths.
     for _, include in ipairs(options.includes) do
-        table.insert(compiler_flags, "-I" .. include)
+        table.insert(args, "/I" .. include)
     end
 
     if options.debug then
-        table.insert(compiler_flags, "-g")
+        table.insert(args, "/Zi")
     end
 
-    -- Set optimization level.
-    table.insert(compiler_flags, ("-O%d"):format(options.opt_level))
-    table.insert(linker_flags, ("-O%d"):format(options.opt_level))
+    table.insert(args, msvc_opt_levels[tostring(options.opt_level)] or "/O2")
 
-    -- Pass all macro definitions to the preprocessor. Macros will be set to `1`
-    -- if given as a truthy, non-string value.
-    for macro, def in ipairs(options.macros) do
+    for macro, def in pairs(options.macros) do
         if type(def) == "string" then
-            table.insert(compiler_flags, "-D" .. macro .. "=" .. def)
+            table.insert(args, "/D" .. macro .. "=" .. def)
         elseif def then
-            table.insert(compiler_flags, "-D" .. macro)
+            table.insert(args, "/D" .. macro)
+        end
+    end
+
+    return append(args, {"/c", "/Fo" .. object_file, source})
+end
+
+function cpp.toolchains.msvc:link_args(options, inputs, output)
+    if options.type == "static" then
+        return append({self.archiver, "/nologo", "/OUT:" .. output}, inputs)
+    end
+
+    local args = {self.compiler, "/nologo"}
+    if options.type == "shared" then
+        table.insert(args, "/LD")
+    end
+
+    return append(append(args, {"/Fe" .. output}), inputs)
+end
+
+-- MSVC can't write dependency files, so headers aren't tracked.
+function cpp.toolchains.msvc:depfile()
+    return nil
+end
+
+function cpp.toolchains.msvc:system_lib(name)
+    return name .. ".lib"
+end
+
+-- Programs are linked to a DLL through the import library written next to
+-- it.
+function cpp.toolchains.msvc:import_lib(file)
+    return (file:gsub("%.dll$", ".lib"))
+end
+
+-- Gets a toolchain by name, or the toolchain to use by default if no name is
+-- given. The default is MSVC on Windows and GCC everywhere else, unless the
+-- global variable `CC` names another compiler.
+function cpp.toolchain(name)
+    if type(name) == "table" then
+        return name
+    end
+
+    if not name then
+        if CC then
+            local program = CC:lower():match("[^/\\]+$"):gsub("%.exe$", "")
+
+            if program == "cl" then
+                name = "msvc"
+            elseif program:match("clang") then
+                name = "clang"
+            else
+                name = "gcc"
+            end
+        else
+            name = OS == "windows" and "msvc" or "gcc"
+        end
+    end
+
+    local toolchain = cpp.toolchains[name]
+    if not toolchain then
+        error("unknown toolchain '" .. tostring(name) .. "'")
+    end
+
+    -- A compiler given in `CC` replaces the usual compiler of the toolchain.
+    if CC then
+        return setmetatable({compiler = CC}, {__index = toolchain})
+    end
+
+    return toolchain
+end
+
+-- Gets the file name of a library for the current platform, such as
+-- `libfoo.a` or `foo.lib` for a static library named `foo`.
+function cpp.library_file(name, type, toolchain)
+    toolchain = cpp.toolchain(toolchain)
+
+    if type == "static" then
+        return toolchain.name == "msvc" and name .. ".lib" or "lib" .. name .. ".a"
+    elseif OS == "windows" then
+        return name .. ".dll"
+    elseif PLATFORM == "macos" then
+        return "lib" .. name .. ".dylib"
+    else
+        return "lib" .. name .. ".so"
+    end
+end
+
+-- Orders the libraries a target links to, along with the libraries they link
+-- to in turn. Each library comes before every library it depends on, which
+-- is the order linkers look for symbols in.
+local function link_order(libs)
+    local order = {}
+    local visiting = {}
+    local visited = {}
+
+    local function visit(name)
+        if visiting[name] then
+            error("library '" .. name .. "' depends on itself")
         end
+        if visited[name] then
+            return
+        end
+
+        visiting[name] = true
+        if libraries[name] then
+            for _, dep in ipairs(libraries[name].libs) do
+                visit(dep)
+            end
+        end
+        visiting[name] = nil
+        visited[name] = true
+
+        table.insert(order, 1, name)
+    end
+
+    for i = #libs, 1, -1 do
+        visit(libs[i])
+    end
+
+    return order
+end
+
+-- Defines rules that compile the sources of a target and link them into its
+-- output file.
+local function build(options)
+    options = rote.merge({
+        standard = "c++11",
+        debug = true,
+        includes = {},
+        opt_level = 0,
+        macros = {},
+        libs = {},
+        type = "exe",
+    }, options)
+
+    if not options.name then
+        error("name must be specified")
+    end
+
+    if not options.srcs then
+        error("srcs must be specified")
     end
 
+    if options.type ~= "exe" and options.type ~= "static" and options.type ~= "shared" then
+        error("type must be one of 'exe', 'static', or 'shared'")
+    end
+
+    local toolchain = cpp.toolchain(options.toolchain)
+
     -- Create individual rules for each source file.
     local object_files = {}
     for i, file in ipairs(options.srcs) do
-        local object_file = file .. ".o"
+        local object_file = file .. toolchain.object_suffix
+        local depfile = toolchain:depfile(object_file)
+        local args = toolchain:compile_args(options, file, object_file, depfile)
         table.insert(object_files, object_file)
+        append(cpp.outputs, {object_file, depfile})
 
-        rote.create_rule(object_file, function(object_file)
-            local args = {}
-            for _, flag in ipairs(compiler_flags) do
-                table.insert(args, flag)
-            end
-            table.insert(args, "-c")
-            table.insert(args, "-o")
-            table.insert(args, object_file)
-            table.insert(args, file)
+        table.insert(cpp.compile_commands, {
+            directory = rote.current_dir(),
+            file = file,
+            output = object_file,
+            arguments = args,
+        })
+
+        -- The headers the source included when it was last compiled are
+        -- inputs too, so that changing a header compiles only the sources
+        -- that include it again.
+        local inputs = {file}
+        if depfile then
+            append(inputs, cpp.read_depfile(depfile))
+        end
 
-            exec(compiler, table.unpack(args))
+        rote.create_rule(object_file, {inputs = inputs}, function()
+            exec(table.unpack(args))
         end)
     end
 
-    -- Create a rule for the final binary.
-    rote.create_rule(options.name, object_files, function(name)
-        local args = {}
-        for _, flag in ipairs(linker_flags) do
-            table.insert(args, flag)
-        end
-        table.insert(args, "-o")
-        table.insert(args, name)
-        for _, object_file in ipairs(object_files) do
-            table.insert(args, object_file)
+    -- Libraries are linked after the object files that use them. Libraries
+    -- defined by this module are built first, and anything else is left to
+    -- the linker to find. Static libraries are only linked into programs and
+    -- shared libraries.
+    local deps = append({}, object_files)
+    local inputs = append({}, object_files)
+
+    if options.type ~= "static" then
+        for _, name in ipairs(link_order(options.libs)) do
+            local library = libraries[name]
+
+            if library then
+                table.insert(deps, library.file)
+                if library.type == "shared" then
+                    table.insert(inputs, toolchain:import_lib(library.file))
+                else
+                    table.insert(inputs, library.file)
+                end
+            else
+                table.insert(inputs, toolchain:system_lib(name))
+            end
         end
+    end
 
-        exec(linker, table.unpack(args))
+    table.insert(cpp.outputs, options.name)
+    if options.type == "shared" and toolchain:import_lib(options.name) ~= options.name then
+        table.insert(cpp.outputs, toolchain:import_lib(options.name))
+    end
+
+    -- Create a rule for the final binary.
+    rote.create_rule(options.name, deps, function(name)
+        exec(table.unpack(toolchain:link_args(options, inputs, name)))
     end)
 end
 
+-- Defines rules to build an executable, or a library if `type` is given.
+function cpp.binary(options)
+    build(options)
+end
+
+-- Defines a library, whose file name is picked from its name and the kind of
+-- library, and placed in the directory `dir` if given.
+local function library(options, type)
+    if not options.name then
+        error("name must be specified")
+    end
+
+    local file = cpp.library_file(options.name, type, options.toolchain)
+    if options.dir then
+        file = options.dir .. "/" .. file
+    end
+
+    libraries[options.name] = {
+        file = file,
+        libs = options.libs or {},
+        type = type,
+    }
+
+    build(rote.merge(options, {
+        name = file,
+        type = type,
+    }))
+
+    return file
+end
+
+-- Defines rules to build a static library. Returns the path of the library
+-- file.
+function cpp.static_lib(options)
+    return library(options, "static")
+end
+
+-- Defines rules to build a shared library. Returns the path of the library
+-- file.
+function cpp.shared_lib(options)
+    return library(options, "shared")
+end
+
+-- Removes every file written by the targets defined so far.
+function cpp.clean()
+    for _, file in ipairs(cpp.outputs) do
+        if fs.exists(file) then
+            fs.remove(file)
+        end
+    end
+end
+
+-- Writes a clang compilation database for all object files defined so far,
+-- for use by clangd and other tools.
+function cpp.write_compile_commands(path)
+    fs.put(path or "compile_commands.json", json.stringify(cpp.compile_commands, true, 2))
+end
+
 
 return cpp
@@ -5,7 +5,7 @@ const SOURCE: &'static str = include_str!("cpp.lua");
 
 /// Module loader.
 pub fn load(runtime: Runtime) -> ScriptResult {
-    try!(runtime.eval(SOURCE));
+    try!(runtime.eval_module("cpp", SOURCE));
 
     Ok(1)
 }
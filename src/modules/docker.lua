@@ -0,0 +1,176 @@
+-- Module for building, publishing, and running Docker images.
+local fs = require "fs"
+local path = require "path"
+
+local docker = {
+    -- The docker CLI executable to invoke.
+    executable = DOCKER or "docker",
+}
+
+
+-- Returns the keys of a table in sorted order, so that commands are the same
+-- every time they are run.
+local function sorted_keys(t)
+    local keys = {}
+    for key in pairs(t) do
+        table.insert(keys, key)
+    end
+    table.sort(keys)
+
+    return keys
+end
+
+-- Builds the arguments of a `docker build` command, without the image ID file.
+function docker.build_args(options)
+    options = options or {}
+    local args = {"build"}
+
+    for _, tag in ipairs(options.tags or {}) do
+        table.insert(args, "-t")
+        table.insert(args, tag)
+    end
+
+    local build_args = options.build_args or {}
+    for _, name in ipairs(sorted_keys(build_args)) do
+        table.insert(args, "--build-arg")
+        table.insert(args, name .. "=" .. tostring(build_args[name]))
+    end
+
+    if options.file then
+        table.insert(args, "-f")
+        table.insert(args, options.file)
+    end
+
+    if options.target then
+        table.insert(args, "--target")
+        table.insert(args, options.target)
+    end
+
+    if options.no_cache then
+        table.insert(args, "--no-cache")
+    end
+
+    for _, arg in ipairs(options.args or {}) do
+        table.insert(args, arg)
+    end
+
+    table.insert(args, options.path or ".")
+
+    return args
+end
+
+-- Builds an image and returns its ID.
+function docker.build(options)
+    local args = docker.build_args(options)
+
+    -- Docker writes the ID of the built image to a file we ask it for, which is
+    -- more reliable than scraping it from the build output.
+    local iidfile = os.tmpname()
+    table.insert(args, #args, "--iidfile")
+    table.insert(args, #args, iidfile)
+
+    local ok, err = pcall(exec, docker.executable, table.unpack(args))
+
+    -- Nothing is built in a dry run, so there is no image.
+    if ok and rote.dry_run() then
+        os.remove(iidfile)
+        return nil
+    end
+
+    local id = ok and fs.exists(iidfile) and fs.get(iidfile):match("^%s*(.-)%s*$")
+    os.remove(iidfile)
+
+    if not ok then
+        error(err, 0)
+    end
+    if not id or id == "" then
+        error("docker build did not report an image ID")
+    end
+
+    return id
+end
+
+-- Pushes an image or a repository to a registry.
+function docker.push(image)
+    exec(docker.executable, "push", image)
+end
+
+-- Builds the arguments of a `docker run` command.
+function docker.run_args(options)
+    if not options or not options.image then
+        error("an image to run must be specified")
+    end
+
+    local args = {"run", "--rm"}
+
+    -- Docker only accepts absolute paths for bind mounts.
+    local volumes = options.volumes or {}
+    for _, host in ipairs(sorted_keys(volumes)) do
+        table.insert(args, "-v")
+        table.insert(args, path.absolute(host) .. ":" .. volumes[host])
+    end
+
+    local env = options.env or {}
+    for _, name in ipairs(sorted_keys(env)) do
+        table.insert(args, "-e")
+        table.insert(args, name .. "=" .. tostring(env[name]))
+    end
+
+    if options.workdir then
+        table.insert(args, "-w")
+        table.insert(args, options.workdir)
+    end
+
+    for _, arg in ipairs(options.args or {}) do
+        table.insert(args, arg)
+    end
+
+    table.insert(args, options.image)
+
+    -- A command given as a string is run by the shell of the image.
+    if type(options.cmd) == "string" then
+        table.insert(args, "sh")
+        table.insert(args, "-c")
+        table.insert(args, options.cmd)
+    else
+        for _, arg in ipairs(options.cmd or {}) do
+            table.insert(args, arg)
+        end
+    end
+
+    return args
+end
+
+-- Runs a command in a new container, which is removed once it exits.
+function docker.run(options)
+    exec(docker.executable, table.unpack(docker.run_args(options)))
+end
+
+-- Defines a task that runs a command in a container, with the project
+-- directory mounted at `mount` and used as the working directory.
+function docker.task_in_container(name, deps, options)
+    if options == nil then
+        deps, options = {}, deps
+    end
+
+    options = rote.merge({
+        mount = "/src",
+    }, options)
+
+    local volumes = rote.merge({
+        [rote.current_dir()] = options.mount,
+    }, options.volumes)
+
+    local run_options = rote.merge(options, {
+        volumes = volumes,
+        workdir = options.workdir or options.mount,
+    })
+    run_options.mount = nil
+
+    rote.create_task(name, deps, function()
+        docker.run(run_options)
+    end)
+end
+
+
+return docker
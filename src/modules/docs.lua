@@ -0,0 +1,240 @@
+-- Module for building documentation.
+local fs = require "fs"
+local docs = {}
+
+
+-- Expands a source list into a list of file paths. Sources may be a single
+-- glob pattern or a table of paths and glob patterns.
+local function expand_sources(srcs)
+    if type(srcs) == "string" then
+        srcs = {srcs}
+    end
+
+    local files = {}
+    for _, pattern in ipairs(srcs) do
+        for file in glob(pattern) do
+            table.insert(files, file)
+        end
+    end
+
+    table.sort(files)
+    return files
+end
+
+-- Creates a directory and all of its missing parents.
+local function mkdir_all(path)
+    local current = path:sub(1, 1) == "/" and "/" or ""
+
+    for part in path:gmatch("[^/\\]+") do
+        current = current .. part
+        if not fs.is_dir(current) then
+            fs.mkdir(current)
+        end
+        current = current .. "/"
+    end
+end
+
+
+-- Builds an mdBook project.
+function docs.mdbook(options)
+    options = rote.merge({
+        source = ".",
+    }, options)
+
+    local args = {"build", options.source}
+
+    if options.dest then
+        mkdir_all(options.dest)
+        table.insert(args, "--dest-dir")
+        table.insert(args, options.dest)
+    end
+
+    exec("mdbook", table.unpack(args))
+end
+
+-- Converts one or more documents with pandoc.
+function docs.pandoc(options)
+    options = rote.merge({
+        standalone = true,
+        toc = false,
+        metadata = {},
+    }, options)
+
+    if not options.srcs then
+        error("srcs must be specified")
+    end
+
+    if not options.dest then
+        error("dest must be specified")
+    end
+
+    local dir = options.dest:match("^(.*)[/\\]")
+    if dir then
+        mkdir_all(dir)
+    end
+
+    local args = {"-o", options.dest}
+
+    if options.standalone then
+        table.insert(args, "--standalone")
+    end
+
+    if options.toc then
+        table.insert(args, "--toc")
+    end
+
+    if options.from then
+        table.insert(args, "--from=" .. options.from)
+    end
+
+    if options.to then
+        table.insert(args, "--to=" .. options.to)
+    end
+
+    if options.template then
+        table.insert(args, "--template=" .. options.template)
+    end
+
+    for key, value in pairs(options.metadata) do
+        table.insert(args, "--metadata=" .. key .. ":" .. tostring(value))
+    end
+
+    for _, file in ipairs(expand_sources(options.srcs)) do
+        table.insert(args, file)
+    end
+
+    exec("pandoc", table.unpack(args))
+end
+
+-- Builds the Doxygen configuration used by `docs.doxygen()`.
+--
+-- The given configuration file is used as a base, and any values in
+-- `overrides` are appended to it in order of their names.
+function docs.doxygen_config(options)
+    options = rote.merge({
+        config = "Doxyfile",
+        overrides = {},
+    }, options)
+
+    local config = ""
+    if fs.exists(options.config) then
+        config = fs.get(options.config)
+    end
+
+    -- Copy the overrides, so that the table of the caller is left alone.
+    local overrides = {}
+    for key, value in pairs(options.overrides) do
+        overrides[key] = value
+    end
+
+    if options.dest then
+        overrides.OUTPUT_DIRECTORY = options.dest
+    end
+
+    if options.srcs then
+        overrides.INPUT = table.concat(expand_sources(options.srcs), " ")
+    end
+
+    local keys = {}
+    for key in pairs(overrides) do
+        table.insert(keys, key)
+    end
+    table.sort(keys)
+
+    for _, key in ipairs(keys) do
+        config = config .. "\n" .. key .. " = " .. tostring(overrides[key])
+    end
+
+    return config
+end
+
+-- Generates API documentation with Doxygen.
+--
+-- The given configuration file is used as a base, and any values in
+-- `overrides` are appended to it before being passed to Doxygen.
+function docs.doxygen(options)
+    options = options or {}
+    local config = docs.doxygen_config(options)
+
+    if options.dest then
+        mkdir_all(options.dest)
+    end
+
+    if rote.dry_run() then
+        print("would run: doxygen -")
+        return
+    end
+
+    local stdout, stderr, status = pipe(config, "doxygen", "-")
+    io.stdout:write(stdout)
+    io.stderr:write(stderr)
+
+    if status ~= 0 then
+        error("doxygen failed")
+    end
+end
+
+-- Checks generated documentation for broken relative links.
+--
+-- Scans all HTML and Markdown files in `dir` for links to local files and
+-- returns a list of links whose targets do not exist. Raises an error if any
+-- are found, unless `warn_only` is set.
+function docs.check_links(dir, warn_only)
+    local broken = {}
+
+    local function check(file, link)
+        -- Ignore external links, anchors, and other URI schemes.
+        if link == "" or link:match("^#") or link:match("^%a[%w+.-]*:") or link:match("^//") then
+            return
+        end
+
+        local target = link:gsub("[?#].*$", "")
+        if target == "" then
+            return
+        end
+
+        if target:sub(1, 1) ~= "/" then
+            target = (file:match("^(.*)[/\\]") or ".") .. "/" .. target
+        end
+
+        if not fs.exists(target) then
+            table.insert(broken, {
+                file = file,
+                link = link,
+            })
+        end
+    end
+
+    for _, file in ipairs(expand_sources({
+        dir .. "/**/*.html",
+        dir .. "/**/*.htm",
+        dir .. "/**/*.md",
+    })) do
+        local contents = fs.get(file)
+
+        for link in contents:gmatch("href%s*=%s*[\"']([^\"']*)[\"']") do
+            check(file, link)
+        end
+
+        for link in contents:gmatch("src%s*=%s*[\"']([^\"']*)[\"']") do
+            check(file, link)
+        end
+
+        for link in contents:gmatch("%]%(([^%)%s]*)[^%)]*%)") do
+            check(file, link)
+        end
+    end
+
+    for _, entry in ipairs(broken) do
+        io.stderr:write(("%s: broken link '%s'\n"):format(entry.file, entry.link))
+    end
+
+    if #broken > 0 and not warn_only then
+        error(("found %d broken link(s)"):format(#broken))
+    end
+
+    return broken
+end
+
+
+return docs
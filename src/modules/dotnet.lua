@@ -0,0 +1,192 @@
+-- Module for .NET Core build tasks.
+local dotnet = {
+    -- The dotnet CLI executable to invoke.
+    executable = DOTNET or "dotnet",
+}
+
+
+-- Parses MSBuild-formatted output into a list of structured diagnostics.
+--
+-- MSBuild reports problems in the form
+-- `file(line,col): severity CODE: message [project]`, or without a location
+-- for problems not tied to a source file. Duplicate entries (MSBuild repeats
+-- all errors in its final summary) are only returned once.
+function dotnet.parse_diagnostics(output)
+    local diagnostics = {}
+    local seen = {}
+
+    for line in output:gmatch("[^\r\n]+") do
+        local diagnostic
+
+        local file, line_no, column, rest = line:match("^%s*(.-)%((%d+),(%d+)%)%s*:%s*(.*)$")
+        if file then
+            diagnostic = {
+                file = file,
+                line = tonumber(line_no),
+                column = tonumber(column),
+            }
+        else
+            file, rest = line:match("^%s*(.-)%s*:%s*(.*)$")
+            if file then
+                diagnostic = {}
+            end
+        end
+
+        if diagnostic then
+            local severity, code, message = rest:match("^(%a+)%s+(%w+)%s*:%s*(.*)$")
+
+            if severity == "error" or severity == "warning" then
+                diagnostic.severity = severity
+                diagnostic.code = code
+
+                -- Split off the trailing project path, if present.
+                local text, project = message:match("^(.-)%s*%[(.-)%]$")
+                diagnostic.message = text or message
+                diagnostic.project = project
+
+                if not diagnostic.file and file ~= "" then
+                    diagnostic.source = file
+                end
+
+                local key = table.concat({
+                    file,
+                    line_no or "",
+                    column or "",
+                    code,
+                    diagnostic.message,
+                }, ":")
+
+                if not seen[key] then
+                    seen[key] = true
+                    table.insert(diagnostics, diagnostic)
+                end
+            end
+        end
+    end
+
+    return diagnostics
+end
+
+-- Builds the arguments shared by most dotnet commands.
+local function common_args(options, flags)
+    local args = {}
+
+    if options.project then
+        table.insert(args, options.project)
+    end
+
+    for _, flag in ipairs(flags) do
+        local value = options[flag]
+
+        if value == true then
+            table.insert(args, "--" .. flag:gsub("_", "-"))
+        elseif value then
+            table.insert(args, "--" .. flag:gsub("_", "-"))
+            table.insert(args, tostring(value))
+        end
+    end
+
+    if options.verbosity then
+        table.insert(args, "--verbosity")
+        table.insert(args, options.verbosity)
+    end
+
+    for _, arg in ipairs(options.args or {}) do
+        table.insert(args, arg)
+    end
+
+    return args
+end
+
+-- Runs a dotnet command, echoing its output and collecting diagnostics.
+local function run(command, args)
+    if rote.dry_run() then
+        print(("would run: %s %s %s"):format(dotnet.executable, command, table.concat(args, " ")))
+        return {}
+    end
+
+    local stdout, stderr, status = pipe(nil, dotnet.executable, command, table.unpack(args))
+    io.stdout:write(stdout)
+    io.stderr:write(stderr)
+
+    local diagnostics = dotnet.parse_diagnostics(stdout .. "\n" .. stderr)
+
+    if status ~= 0 then
+        local errors = {}
+        for _, diagnostic in ipairs(diagnostics) do
+            if diagnostic.severity == "error" then
+                table.insert(errors, diagnostic)
+            end
+        end
+
+        error(("dotnet %s failed with %d error(s)"):format(command, #errors))
+    end
+
+    return diagnostics
+end
+
+
+-- Restores the dependencies of a project.
+function dotnet.restore(options)
+    options = options or {}
+
+    return run("restore", common_args(options, {"runtime"}))
+end
+
+-- Builds a project.
+function dotnet.build(options)
+    options = rote.merge({
+        configuration = "Debug",
+    }, options)
+
+    return run("build", common_args(options, {
+        "configuration",
+        "framework",
+        "runtime",
+        "output",
+        "no_restore",
+    }))
+end
+
+-- Runs the unit tests of a project.
+function dotnet.test(options)
+    options = rote.merge({
+        configuration = "Debug",
+    }, options)
+
+    return run("test", common_args(options, {
+        "configuration",
+        "framework",
+        "runtime",
+        "output",
+        "filter",
+        "no_build",
+        "no_restore",
+    }))
+end
+
+-- Publishes a project and its dependencies for deployment.
+function dotnet.publish(options)
+    options = rote.merge({
+        configuration = "Release",
+    }, options)
+
+    local args = common_args(options, {
+        "configuration",
+        "framework",
+        "runtime",
+        "output",
+        "no_build",
+        "no_restore",
+    })
+
+    if options.self_contained ~= nil then
+        table.insert(args, "--self-contained")
+        table.insert(args, tostring(options.self_contained))
+    end
+
+    return run("publish", args)
+end
+
+
+return dotnet
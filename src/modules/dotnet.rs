@@ -0,0 +1,11 @@
+use runtime::{Runtime, ScriptResult};
+
+const SOURCE: &'static str = include_str!("dotnet.lua");
+
+
+/// Module loader.
+pub fn load(runtime: Runtime) -> ScriptResult {
+    try!(runtime.eval_module("dotnet", SOURCE));
+
+    Ok(1)
+}
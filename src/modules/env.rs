@@ -0,0 +1,109 @@
+use runtime::{Runtime, ScriptResult};
+use std::env;
+
+
+/// Gets the value of an environment variable.
+///
+/// # Lua arguments
+/// * `name: string`            - The variable name.
+/// * `default: string`         - Value to return if the variable is not set. (Optional)
+///
+/// # Lua returns
+/// * `value: string`           - The value of the variable, or `default` if it is not set.
+fn get(runtime: Runtime) -> ScriptResult {
+    let name = runtime.state().check_string(1).to_string();
+
+    match env::var_os(name) {
+        Some(value) => runtime.push_path(value),
+        None => runtime.state().push_value(2),
+    }
+
+    Ok(1)
+}
+
+/// Sets the value of an environment variable. Unlike `export()`, variables in the value are not
+/// expanded.
+///
+/// # Lua arguments
+/// * `name: string`            - The variable name.
+/// * `value: string`           - The value to set.
+fn set(runtime: Runtime) -> ScriptResult {
+    let name = runtime.state().check_string(1).to_string();
+    let value = runtime.check_path(2);
+
+    if name.is_empty() || name.contains('=') {
+        return Err(format!("invalid environment variable name \"{}\"", name).into());
+    }
+
+    env::set_var(name, value);
+    Ok(0)
+}
+
+/// Removes an environment variable.
+///
+/// # Lua arguments
+/// * `name: string`            - The variable name.
+fn unset(runtime: Runtime) -> ScriptResult {
+    let name = runtime.state().check_string(1).to_string();
+
+    if !name.is_empty() && !name.contains('=') {
+        env::remove_var(name);
+    }
+
+    Ok(0)
+}
+
+/// Gets all environment variables.
+///
+/// # Lua returns
+/// * `vars: table`             - A table of variable values, by name.
+fn vars(runtime: Runtime) -> ScriptResult {
+    runtime.state().new_table();
+
+    for (name, value) in env::vars_os() {
+        // Names are always looked up as strings, so names that aren't valid Unicode are useless.
+        if let Some(name) = name.to_str() {
+            runtime.state().push_string(name);
+            runtime.push_path(value);
+            runtime.state().set_table(-3);
+        }
+    }
+
+    Ok(1)
+}
+
+/// Adds a directory to the front of a search path variable, such as `PATH`.
+///
+/// # Lua arguments
+/// * `path: string`            - The directory to add.
+/// * `name: string`            - The variable to add to. Defaults to `PATH`. (Optional)
+fn path_prepend(runtime: Runtime) -> ScriptResult {
+    let path = runtime.check_path(1);
+    let name = runtime.state().opt_string(2, "PATH").to_string();
+
+    // Move the directory to the front if it is already in the list.
+    let mut paths = vec![path.clone()];
+    if let Some(value) = env::var_os(&name) {
+        paths.extend(env::split_paths(&value).filter(|existing| *existing != path));
+    }
+
+    match env::join_paths(paths) {
+        Ok(value) => env::set_var(name, value),
+        Err(e) => return Err(format!("cannot add path to {}: {}", name, e).into()),
+    }
+
+    Ok(0)
+}
+
+/// Module loader.
+pub fn load(runtime: Runtime) -> ScriptResult {
+    runtime.load_lib(&[
+        ("get", get),
+        ("set", set),
+        ("unset", unset),
+        ("vars", vars),
+        ("path_prepend", path_prepend),
+    ]);
+
+    Ok(1)
+}
@@ -0,0 +1,158 @@
+use fscache;
+use lua;
+use modules::stdlib::program_command;
+use process;
+use runtime::{Runtime, ScriptResult};
+use std::io::{Read, Write};
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+
+/// Runs a program and returns its exit code and output, without raising an error if it fails.
+///
+/// Unlike `exec()`, arguments are passed as is without expanding variables, and the output of the
+/// program is returned instead of being printed.
+///
+/// # Lua arguments
+/// * `program: string`      - The program to run.
+/// * `args: table`          - A list of arguments to pass to the program. (Optional)
+/// * `options: table`       - Options for running the program. (Optional)
+///   * `cwd: string`        - Directory to run the program in.
+///   * `env: table`         - Environment variables to set for the program, by name.
+///   * `stdin: string`      - Input to write to the program.
+///   * `timeout: number`    - Number of seconds to wait before killing the program and raising an
+///                            error.
+///
+/// # Lua returns
+/// * `code: number`         - The exit code of the program, or -1 if it was killed by a signal.
+/// * `stdout: string`       - Everything the program wrote to standard output.
+/// * `stderr: string`       - Everything the program wrote to standard error.
+fn run(runtime: Runtime) -> ScriptResult {
+    let program = runtime.state().check_string(1).to_string();
+    let mut command = program_command(&program);
+
+    // Tasks of included scripts run commands in the directory of the script.
+    if let Some(dir) = runtime.environment().working_dir() {
+        command.current_dir(dir);
+    }
+
+    if runtime.state().is_table(2) {
+        for (_, value) in runtime.iter(2) {
+            match runtime.to_path(value) {
+                Some(arg) => {
+                    command.arg(arg);
+                }
+                None => return Err("arguments must be strings".into()),
+            }
+        }
+    }
+
+    let mut input = None;
+    let mut timeout = None;
+
+    if runtime.state().is_table(3) {
+        if runtime.state().get_field(3, "cwd") == lua::Type::String {
+            command.current_dir(runtime.environment().resolve(runtime.to_path(-1).unwrap()));
+        }
+        runtime.state().pop(1);
+
+        if runtime.state().get_field(3, "env") == lua::Type::Table {
+            let env = runtime.state().get_top();
+            for (key, value) in runtime.iter(env) {
+                let name = runtime.state().to_str_in_place(key).map(String::from);
+                let value = runtime.state().to_str_in_place(value).map(String::from);
+
+                match (name, value) {
+                    (Some(name), Some(value)) => {
+                        command.env(name, value);
+                    }
+                    _ => return Err("environment variables must be strings".into()),
+                }
+            }
+        }
+        runtime.state().pop(1);
+
+        if runtime.state().get_field(3, "stdin") == lua::Type::String {
+            input = runtime.state().to_bytes_in_place(-1).map(|bytes| bytes.to_vec());
+        }
+        runtime.state().pop(1);
+
+        if runtime.state().get_field(3, "timeout") == lua::Type::Number {
+            let seconds = runtime.state().to_number(-1).max(0.0);
+            timeout = Some(Duration::new(seconds as u64, (seconds.fract() * 1e9) as u32));
+        }
+        runtime.state().pop(1);
+    }
+
+    command.stdin(if input.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::null()
+    });
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+
+    let (mut child, group) = match process::spawn(&mut command) {
+        Ok(child) => child,
+        Err(e) => return Err(format!("failed to execute process: {}", e).into()),
+    };
+
+    // Feed the input and read the output in the background, so that the program never blocks on a
+    // full pipe.
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), input) {
+        thread::spawn(move || {
+            stdin.write_all(&input).ok();
+        });
+    }
+    let stdout = read_all(child.stdout.take().unwrap());
+    let stderr = read_all(child.stderr.take().unwrap());
+
+    // Wait for the program to exit in another thread, so that we can give up waiting.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        sender.send(child.wait()).ok();
+    });
+
+    let status = match timeout {
+        Some(timeout) => {
+            match receiver.recv_timeout(timeout) {
+                Ok(status) => status,
+                Err(_) => {
+                    group.kill();
+                    receiver.recv().ok();
+                    return Err(format!("'{}' timed out after {} seconds", program, timeout.as_secs()).into());
+                }
+            }
+        }
+        None => receiver.recv().unwrap(),
+    };
+
+    // The program may have changed any file.
+    fscache::clear();
+
+    let status = try!(status);
+    let stdout = stdout.join().unwrap_or(Vec::new());
+    let stderr = stderr.join().unwrap_or(Vec::new());
+
+    runtime.ret((status.code().unwrap_or(-1) as f64, &stdout[..], &stderr[..]))
+}
+
+/// Reads everything from a reader in a background thread.
+fn read_all<R: Read + Send + 'static>(mut reader: R) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer).ok();
+        buffer
+    })
+}
+
+/// Module loader.
+pub fn load(runtime: Runtime) -> ScriptResult {
+    runtime.load_lib(&[
+        ("run", run),
+    ]);
+
+    Ok(1)
+}
@@ -1,7 +1,54 @@
+use fscache;
+use memmap::{Mmap, Protection};
 use runtime::{Runtime, ScriptResult};
 use std::fs;
 use std::fs::{File, OpenOptions};
+use std::io;
 use std::io::prelude::*;
+use std::ops::Deref;
+use std::path::Path;
+use std::str;
+use std::time::UNIX_EPOCH;
+
+
+/// Files at least this large are memory-mapped when read instead of being copied into a buffer.
+const MMAP_THRESHOLD: u64 = 1024 * 1024;
+
+/// The contents of a file that has been read.
+pub enum Contents {
+    Mapped(Mmap),
+    Buffered(Vec<u8>),
+}
+
+impl Deref for Contents {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {
+            // The map is private to us and read-only, though the file could still be modified by
+            // another process while we are reading it.
+            Contents::Mapped(ref map) => unsafe { map.as_slice() },
+            Contents::Buffered(ref buffer) => buffer,
+        }
+    }
+}
+
+/// Reads the entire contents of a file. Large files are memory-mapped, falling back to reading
+/// into a buffer if the file cannot be mapped.
+pub fn read_file<P: AsRef<Path>>(path: P) -> io::Result<Contents> {
+    let mut file = try!(File::open(path));
+    let len = try!(file.metadata()).len();
+
+    if len >= MMAP_THRESHOLD {
+        if let Ok(map) = Mmap::open(&file, Protection::Read) {
+            return Ok(Contents::Mapped(map));
+        }
+    }
+
+    let mut buffer = Vec::with_capacity(len as usize);
+    try!(file.read_to_end(&mut buffer));
+    Ok(Contents::Buffered(buffer))
+}
 
 
 /// Checks if a file exists and is readable.
@@ -9,9 +56,9 @@ use std::io::prelude::*;
 /// # Lua arguments
 /// * `path: string`            - Path to the file to check.
 fn exists(runtime: Runtime) -> ScriptResult {
-    let path = runtime.state().check_string(1).to_string();
+    let path = runtime.check_file_path(1);
 
-    runtime.state().push_bool(fs::metadata(path).is_ok());
+    runtime.state().push_bool(fscache::metadata(path).is_ok());
 
     Ok(1)
 }
@@ -21,9 +68,9 @@ fn exists(runtime: Runtime) -> ScriptResult {
 /// # Lua arguments
 /// * `path: string`            - Path to check.
 fn is_dir(runtime: Runtime) -> ScriptResult {
-    let path = runtime.state().check_string(1).to_string();
+    let path = runtime.check_file_path(1);
 
-    let metadata = fs::metadata(path);
+    let metadata = fscache::metadata(path);
     runtime.state().push_bool(metadata.is_ok() && metadata.unwrap().file_type().is_dir());
 
     Ok(1)
@@ -34,9 +81,9 @@ fn is_dir(runtime: Runtime) -> ScriptResult {
 /// # Lua arguments
 /// * `path: string`            - Path to check.
 fn is_file(runtime: Runtime) -> ScriptResult {
-    let path = runtime.state().check_string(1).to_string();
+    let path = runtime.check_file_path(1);
 
-    let metadata = fs::metadata(path);
+    let metadata = fscache::metadata(path);
     runtime.state().push_bool(metadata.is_ok() && metadata.unwrap().file_type().is_file());
 
     Ok(1)
@@ -47,7 +94,7 @@ fn is_file(runtime: Runtime) -> ScriptResult {
 /// # Lua arguments
 /// * `path: string`            - Path to check.
 fn is_symlink(runtime: Runtime) -> ScriptResult {
-    let path = runtime.state().check_string(1).to_string();
+    let path = runtime.check_file_path(1);
 
     let metadata = fs::metadata(path);
     runtime.state().push_bool(metadata.is_ok() && metadata.unwrap().file_type().is_symlink());
@@ -55,16 +102,45 @@ fn is_symlink(runtime: Runtime) -> ScriptResult {
     Ok(1)
 }
 
+/// Gets the time a file was last modified, in seconds since the Unix epoch.
+///
+/// # Lua arguments
+/// * `path: string`            - Path to the file.
+///
+/// # Lua returns
+/// * `time: number`            - The modification time, or nil if the file doesn't exist.
+fn modified(runtime: Runtime) -> ScriptResult {
+    let path = runtime.check_file_path(1);
+
+    let time = fscache::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+
+    match time {
+        Some(time) => {
+            runtime.state().push_number(time.as_secs() as f64 + time.subsec_nanos() as f64 / 1e9);
+        }
+        None => runtime.state().push_nil(),
+    }
+
+    Ok(1)
+}
+
 /// Creates a directory.
 ///
 /// # Lua arguments
 /// * `path: string`            - Path to create the directory.
 fn mkdir(runtime: Runtime) -> ScriptResult {
     // Get the path as the first argument.
-    let path = runtime.state().check_string(1).to_string();
+    let path = runtime.check_file_path(1);
+    if runtime.skip_change(format!("create directory \"{}\"", path.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
 
     if fs::create_dir(&path).is_err() {
-        return Err(format!("failed to create directory \"{}\"", path).into());
+        return Err(format!("failed to create directory \"{}\"", path.display()).into());
     }
 
     Ok(0)
@@ -76,11 +152,15 @@ fn mkdir(runtime: Runtime) -> ScriptResult {
 /// * `source: string`          - Path of the file to copy.
 /// * `dest: string`            - Path to copy the file to.
 fn copy(runtime: Runtime) -> ScriptResult {
-    let source = runtime.state().check_string(1).to_string();
-    let dest = runtime.state().check_string(2).to_string();
+    let source = runtime.check_file_path(1);
+    let dest = runtime.check_file_path(2);
+    if runtime.skip_change(format!("copy \"{}\" to \"{}\"", source.display(), dest.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
 
     if fs::copy(&source, dest).is_err() {
-        return Err(format!("failed to copy \"{}\"", source).into());
+        return Err(format!("failed to copy \"{}\"", source.display()).into());
     }
 
     Ok(0)
@@ -92,8 +172,12 @@ fn copy(runtime: Runtime) -> ScriptResult {
 /// * `source: string`          - Path of the file to move.
 /// * `dest: string`            - Path to move the file to.
 fn rename(runtime: Runtime) -> ScriptResult {
-    let source = runtime.state().check_string(1).to_string();
-    let destination = runtime.state().check_string(2).to_string();
+    let source = runtime.check_file_path(1);
+    let destination = runtime.check_file_path(2);
+    if runtime.skip_change(format!("move \"{}\" to \"{}\"", source.display(), destination.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
 
     if fs::rename(source, destination).is_err() {
         return Err("no such file or directory".into());
@@ -107,7 +191,11 @@ fn rename(runtime: Runtime) -> ScriptResult {
 /// # Lua arguments
 /// * `path: string`            - Path of the file or directory to remove.
 fn remove(runtime: Runtime) -> ScriptResult {
-    let path = runtime.state().check_string(1).to_string();
+    let path = runtime.check_file_path(1);
+    if runtime.skip_change(format!("remove \"{}\"", path.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
 
     if let Ok(metadata) = fs::metadata(&path) {
         if metadata.file_type().is_dir() {
@@ -129,22 +217,17 @@ fn remove(runtime: Runtime) -> ScriptResult {
 /// # Lua arguments
 /// * `path: string`            - Path of the file to read from.
 fn get(runtime: Runtime) -> ScriptResult {
-    let path = runtime.state().check_string(1).to_string();
+    let path = runtime.check_file_path(1);
 
-    let file = File::open(path);
+    let contents = match read_file(path) {
+        Ok(contents) => contents,
+        Err(_) => return Err("failed to open file".into()),
+    };
 
-    if file.is_err() {
-        return Err("failed to open file".into());
-    }
-
-    let mut file = file.unwrap();
-    let mut buffer = String::new();
-
-    if file.read_to_string(&mut buffer).is_err() {
-        return Err("failed to read file".into());
-    }
-
-    runtime.state().push_string(&buffer);
+    match str::from_utf8(&contents) {
+        Ok(string) => runtime.state().push_string(string),
+        Err(_) => return Err("failed to read file".into()),
+    };
 
     Ok(1)
 }
@@ -155,8 +238,12 @@ fn get(runtime: Runtime) -> ScriptResult {
 /// * `path: string`            - Path to the file to write to.
 /// * `contents: string`        - The contents to write.
 fn put(runtime: Runtime) -> ScriptResult {
-    let path = runtime.state().check_string(1).to_string();
+    let path = runtime.check_file_path(1);
     let contents = String::from(runtime.state().check_string(2));
+    if runtime.skip_change(format!("write \"{}\"", path.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
 
     let file = OpenOptions::new()
                    .write(true)
@@ -182,8 +269,12 @@ fn put(runtime: Runtime) -> ScriptResult {
 /// * `path: string`            - Path to the file to append to.
 /// * `contents: string`        - The contents to append.
 fn append(runtime: Runtime) -> ScriptResult {
-    let path = runtime.state().check_string(1).to_string();
+    let path = runtime.check_file_path(1);
     let contents = String::from(runtime.state().check_string(2));
+    if runtime.skip_change(format!("append to \"{}\"", path.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
 
     let file = OpenOptions::new()
                    .write(true)
@@ -213,7 +304,12 @@ fn combine(runtime: Runtime) -> ScriptResult {
     }
 
     // Open the output file for writing.
-    let dest = runtime.state().check_string(2).to_string();
+    let dest = runtime.check_file_path(2);
+    if runtime.skip_change(format!("write \"{}\"", dest.display())) {
+        return Ok(0);
+    }
+    fscache::clear();
+
     let out_file = OpenOptions::new()
                        .write(true)
                        .truncate(true)
@@ -221,37 +317,81 @@ fn combine(runtime: Runtime) -> ScriptResult {
                        .open(&dest);
 
     if out_file.is_err() {
-        return Err(format!("failed to open file \"{}\"", dest).into());
+        return Err(format!("failed to open file \"{}\"", dest.display()).into());
     }
 
     let mut out_file = out_file.unwrap();
 
     // Walk through each path in the sources table and write their contents.
     for (_, value) in runtime.iter(1) {
-        let source = runtime.state().to_str_in_place(value).unwrap().to_string();
-
-        let in_file = File::open(&source);
-        if in_file.is_err() {
-            return Err(format!("failed to open file \"{}\"", source).into());
-        }
+        let source = match runtime.to_path(value) {
+            Some(source) => runtime.environment().resolve(source),
+            None => return Err("sources must be paths".into()),
+        };
 
         // Read the source file's contents.
-        let mut in_file = in_file.unwrap();
-        let mut buffer = String::new();
-
-        if in_file.read_to_string(&mut buffer).is_err() {
-            return Err(format!("failed to read file \"{}\"", source).into());
-        }
+        let contents = match read_file(&source) {
+            Ok(contents) => contents,
+            Err(_) => return Err(format!("failed to open file \"{}\"", source.display()).into()),
+        };
 
         // Write the source file contents into the output file.
-        if out_file.write_all(buffer.as_bytes()).is_err() {
-            return Err(format!("failed to write to file \"{}\"", dest).into());
+        if out_file.write_all(&contents).is_err() {
+            return Err(format!("failed to write to file \"{}\"", dest.display()).into());
         }
     }
 
     Ok(0)
 }
 
+/// Gets the paths matching one or more glob patterns.
+///
+/// Paths are returned as they are matched, so relative patterns give paths relative to the current
+/// directory of the script. Paths matched by more than one pattern are only returned once.
+///
+/// # Lua arguments
+/// * `patterns: string...`     - The glob patterns to match.
+///
+/// # Lua returns
+/// * `paths: table`            - A sorted list of the matching paths.
+fn glob(runtime: Runtime) -> ScriptResult {
+    let count = runtime.state().get_top();
+    let mut paths = Vec::new();
+
+    // Require at least one pattern.
+    runtime.state().check_string(1);
+
+    for index in 1..count + 1 {
+        let pattern = runtime.state().check_string(index).to_string();
+
+        let matches = match runtime.environment().working_dir() {
+            Some(dir) => fscache::glob_in(&dir, &pattern),
+            None => fscache::glob(&pattern),
+        };
+
+        match matches {
+            Ok(matches) => paths.extend(matches),
+            Err(e) => return Err(format!("invalid glob pattern \"{}\": {}", pattern, e).into()),
+        }
+    }
+
+    paths.sort();
+    paths.dedup();
+
+    runtime.state().new_table();
+
+    let mut index = 1;
+    for path in paths {
+        runtime.state().push_number(index as f64);
+        runtime.push_path(path);
+        runtime.state().set_table(-3);
+
+        index += 1;
+    }
+
+    Ok(1)
+}
+
 /// Module loader.
 pub fn load(runtime: Runtime) -> ScriptResult {
     runtime.load_lib(&[
@@ -259,6 +399,7 @@ pub fn load(runtime: Runtime) -> ScriptResult {
         ("is_dir", is_dir),
         ("is_file", is_file),
         ("is_symlink", is_symlink),
+        ("modified", modified),
         ("mkdir", mkdir),
         ("copy", copy),
         ("rename", rename),
@@ -266,7 +407,8 @@ pub fn load(runtime: Runtime) -> ScriptResult {
         ("get", get),
         ("put", put),
         ("append", append),
-        ("combine", combine)
+        ("combine", combine),
+        ("glob", glob)
     ]);
 
     Ok(1)
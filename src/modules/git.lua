@@ -0,0 +1,93 @@
+-- Module for working with Git repositories.
+local git = {
+    -- The git executable to invoke.
+    executable = GIT or "git",
+}
+
+
+-- Runs a git command and returns its output, raising an error if it fails.
+local function capture(...)
+    local stdout, stderr, status = pipe(nil, git.executable, ...)
+
+    if status ~= 0 then
+        error(("git %s failed: %s"):format((...), stderr:match("^%s*(.-)%s*$")), 0)
+    end
+
+    return stdout
+end
+
+-- Creates a tag pointing at `ref`, or at the current commit if not given.
+--
+-- Tags with a message or signature are annotated tags, the others are
+-- lightweight tags.
+function git.tag(name, options)
+    options = options or {}
+    local args = {"tag"}
+
+    if options.sign then
+        table.insert(args, "-s")
+    elseif options.message then
+        table.insert(args, "-a")
+    end
+
+    if options.sign or options.message then
+        table.insert(args, "-m")
+        table.insert(args, options.message or name)
+    end
+
+    if options.force then
+        table.insert(args, "-f")
+    end
+
+    table.insert(args, name)
+    if options.ref then
+        table.insert(args, options.ref)
+    end
+
+    exec(git.executable, table.unpack(args))
+end
+
+-- Returns the name of the most recent tag reachable from `ref`, or nil if
+-- there is none.
+function git.latest_tag(ref)
+    local stdout, _, status = pipe(nil, git.executable, "describe", "--tags", "--abbrev=0", ref or "HEAD")
+
+    if status ~= 0 then
+        return nil
+    end
+
+    return stdout:match("^%s*(.-)%s*$")
+end
+
+-- Writes the files of the tree at `ref` to an archive. The format is chosen
+-- by git from the extension of `dest`, such as `.zip` or `.tar.gz`.
+function git.archive(ref, dest, options)
+    options = options or {}
+    local args = {"archive", "-o", dest}
+
+    if options.prefix then
+        table.insert(args, "--prefix=" .. options.prefix)
+    end
+
+    table.insert(args, ref)
+    exec(git.executable, table.unpack(args))
+end
+
+-- Returns the subjects of the commits made since `tag`, newest first. If no
+-- tag is given, every commit in the history of HEAD is returned.
+function git.log_since(tag, ref)
+    local range = ref or "HEAD"
+    if tag then
+        range = tag .. ".." .. range
+    end
+
+    local subjects = {}
+    for subject in capture("log", "--format=%s", range):gmatch("[^\r\n]+") do
+        table.insert(subjects, subject)
+    end
+
+    return subjects
+end
+
+
+return git
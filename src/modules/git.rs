@@ -0,0 +1,11 @@
+use runtime::{Runtime, ScriptResult};
+
+const SOURCE: &'static str = include_str!("git.lua");
+
+
+/// Module loader.
+pub fn load(runtime: Runtime) -> ScriptResult {
+    try!(runtime.eval_module("git", SOURCE));
+
+    Ok(1)
+}
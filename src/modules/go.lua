@@ -0,0 +1,112 @@
+-- Module for building Go packages.
+local exec = require "exec"
+local go = {
+    -- The go executable to invoke.
+    executable = GO or "go",
+}
+
+
+-- Builds the arguments and environment of a go command.
+--
+-- Cross-compilation is done by setting the target operating system and
+-- architecture in the environment of the command, rather than with flags.
+function go.command(command, options, flags)
+    options = options or {}
+    local args = {command}
+    local env = {}
+
+    if options.goos then
+        env.GOOS = options.goos
+    end
+    if options.goarch then
+        env.GOARCH = options.goarch
+    end
+    if options.cgo ~= nil then
+        env.CGO_ENABLED = options.cgo and "1" or "0"
+    end
+
+    if options.tags and #options.tags > 0 then
+        table.insert(args, "-tags")
+        table.insert(args, table.concat(options.tags, ","))
+    end
+
+    for _, flag in ipairs(flags or {}) do
+        local value = options[flag]
+
+        if value == true then
+            table.insert(args, "-" .. flag)
+        elseif value then
+            table.insert(args, "-" .. flag)
+            table.insert(args, tostring(value))
+        end
+    end
+
+    for _, arg in ipairs(options.args or {}) do
+        table.insert(args, arg)
+    end
+
+    local packages = options.packages or {"./..."}
+    if type(packages) == "string" then
+        packages = {packages}
+    end
+    for _, package in ipairs(packages) do
+        table.insert(args, package)
+    end
+
+    return args, env
+end
+
+-- Runs a go command, echoing its output and raising an error if it fails.
+local function run(command, options, flags)
+    local args, env = go.command(command, options, flags)
+
+    if rote.dry_run() then
+        print("would run: " .. go.executable .. " " .. table.concat(args, " "))
+        return ""
+    end
+
+    local status, stdout, stderr = exec.run(go.executable, args, {
+        cwd = options and options.dir,
+        env = env,
+    })
+
+    io.stdout:write(stdout)
+    io.stderr:write(stderr)
+
+    if status ~= 0 then
+        error(("go %s failed with exit code %d"):format(command, status), 0)
+    end
+
+    return stdout
+end
+
+-- Compiles packages and their dependencies.
+function go.build(options)
+    options = rote.merge({
+        packages = ".",
+    }, options)
+
+    -- Go names binaries for Windows without an extension unless told otherwise.
+    if options.output and options.goos == "windows" and not options.output:match("%.exe$") then
+        options.output = options.output .. ".exe"
+    end
+
+    if options.output then
+        options.o = options.output
+    end
+
+    run("build", options, {"o", "race", "trimpath", "ldflags", "gcflags", "v"})
+end
+
+-- Runs the tests of packages.
+function go.test(options)
+    run("test", options, {"race", "short", "run", "count", "timeout", "coverprofile", "cover", "v"})
+end
+
+-- Reports suspicious constructs in packages.
+function go.vet(options)
+    run("vet", options)
+end
+
+
+return go
@@ -0,0 +1,93 @@
+use hash;
+use modules::fs::read_file;
+use runtime::{Runtime, ScriptResult};
+
+
+/// Hashes the string argument with the given function and returns the hash in hexadecimal.
+fn hash_string<F: Fn(&[u8]) -> String>(runtime: Runtime, f: F) -> ScriptResult {
+    let mut state = runtime.state();
+
+    let hex = match state.to_bytes_in_place(1) {
+        Some(data) => f(data),
+        None => return Err("argument must be a string".into()),
+    };
+
+    runtime.state().push(hex);
+    Ok(1)
+}
+
+/// Hashes the contents of the file given as an argument with the given function and returns the
+/// hash in hexadecimal.
+fn hash_file<F: Fn(&[u8]) -> String>(runtime: Runtime, f: F) -> ScriptResult {
+    let path = runtime.check_file_path(1);
+
+    let contents = match read_file(&path) {
+        Ok(contents) => contents,
+        Err(e) => return Err(format!("failed to read file \"{}\": {}", path.display(), e).into()),
+    };
+
+    runtime.state().push(f(&contents));
+    Ok(1)
+}
+
+/// Computes the MD5 hash of a string.
+///
+/// # Lua arguments
+/// * `data: string`            - The string to hash.
+fn md5(runtime: Runtime) -> ScriptResult {
+    hash_string(runtime, |data| hash::to_hex(&hash::md5(data)))
+}
+
+/// Computes the SHA-1 hash of a string.
+///
+/// # Lua arguments
+/// * `data: string`            - The string to hash.
+fn sha1(runtime: Runtime) -> ScriptResult {
+    hash_string(runtime, |data| hash::to_hex(&hash::sha1(data)))
+}
+
+/// Computes the SHA-256 hash of a string.
+///
+/// # Lua arguments
+/// * `data: string`            - The string to hash.
+fn sha256(runtime: Runtime) -> ScriptResult {
+    hash_string(runtime, |data| hash::to_hex(&hash::sha256(data)))
+}
+
+/// Computes the MD5 hash of a file.
+///
+/// # Lua arguments
+/// * `path: string`            - Path of the file to hash.
+fn file_md5(runtime: Runtime) -> ScriptResult {
+    hash_file(runtime, |data| hash::to_hex(&hash::md5(data)))
+}
+
+/// Computes the SHA-1 hash of a file.
+///
+/// # Lua arguments
+/// * `path: string`            - Path of the file to hash.
+fn file_sha1(runtime: Runtime) -> ScriptResult {
+    hash_file(runtime, |data| hash::to_hex(&hash::sha1(data)))
+}
+
+/// Computes the SHA-256 hash of a file.
+///
+/// # Lua arguments
+/// * `path: string`            - Path of the file to hash.
+fn file_sha256(runtime: Runtime) -> ScriptResult {
+    hash_file(runtime, |data| hash::to_hex(&hash::sha256(data)))
+}
+
+/// Module loader.
+pub fn load(runtime: Runtime) -> ScriptResult {
+    runtime.load_lib(&[
+        ("md5", md5),
+        ("sha1", sha1),
+        ("sha256", sha256),
+        ("file_md5", file_md5),
+        ("file_sha1", file_sha1),
+        ("file_sha256", file_sha256),
+    ]);
+
+    Ok(1)
+}
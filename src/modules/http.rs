@@ -1,11 +1,22 @@
+use console;
+use fscache;
+use hash;
 use hyper::client::Client;
-use runtime::{Runtime, ScriptResult};
-use std::io::Read;
+use hyper::header::Headers;
+use lua;
+use runtime::{FromTable, Runtime, ScriptResult, Table};
+use std::cmp;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 
 /// Sends an HTTP GET request and returns the response.
 fn get(runtime: Runtime) -> ScriptResult {
-    let url = runtime.state().check_string(1).to_string();
+    let url = try!(runtime.args().string(1));
     let client = Client::new();
 
     // Send the request.
@@ -14,34 +25,240 @@ fn get(runtime: Runtime) -> ScriptResult {
     // Return the response text and the status code.
     let mut body = String::new();
     try!(response.read_to_string(&mut body));
-    runtime.state().push(body);
-    runtime.state().push(response.status.to_u16() as f64);
-
-    Ok(2)
+    runtime.ret((body, response.status.to_u16() as f64))
 }
 
 /// Sends an HTTP POST request with a body and returns the response.
+///
+/// # Lua arguments
+/// * `url: string`             - The URL to send the request to.
+/// * `body: string`            - The request body. (Optional)
+/// * `headers: table`          - Request headers to send, by name. (Optional)
 fn post(runtime: Runtime) -> ScriptResult {
-    let url = runtime.state().check_string(1).to_string();
+    let args = runtime.args();
+    let url = try!(args.string(1));
     let client = Client::new();
 
     // Get the request body.
-    let request_body = runtime.state().to_str(2).unwrap_or("").to_string();
+    let request_body = try!(args.opt_string(2)).unwrap_or_else(String::new);
+
+    // Get the request headers.
+    let mut headers = Headers::new();
+    if runtime.state().is_table(3) {
+        for (key, value) in runtime.iter(3) {
+            let name = runtime.state().to_str_in_place(key).map(String::from);
+            let value = runtime.state().to_str_in_place(value).map(String::from);
+
+            match (name, value) {
+                (Some(name), Some(value)) => headers.set_raw(name, vec![value.into_bytes()]),
+                _ => return Err("headers must be strings".into()),
+            }
+        }
+    }
 
     // Send the request.
-    let mut response = try!(client.post(&url).body(&request_body).send());
+    let mut response = try!(client.post(&url).headers(headers).body(&request_body).send());
 
     // Return the response text and the status code.
     let mut response_body = String::new();
     try!(response.read_to_string(&mut response_body));
-    runtime.state().push(response_body);
-    runtime.state().push(response.status.to_u16() as f64);
+    runtime.ret((response_body, response.status.to_u16() as f64))
+}
+
+/// Downloads a file from a URL, writing the response body to a path.
+///
+/// # Lua arguments
+/// * `url: string`             - The URL to download.
+/// * `dest: string`            - The path to write the file to.
+/// * `options: table`          - Options for the download. (Optional)
+///   * `sha256: string`        - The expected SHA-256 hash of the file, in hexadecimal. If the
+///                               file doesn't match, it is removed and an error is raised.
+fn download(runtime: Runtime) -> ScriptResult {
+    let args = runtime.args();
+    let url = try!(args.string(1));
+    let dest = try!(args.string(2));
+    let options: DownloadOptions = try!(args.options(3));
+
+    let path = runtime.environment().resolve(&dest);
+    if runtime.skip_change(format!("download {} to \"{}\"", url, path.display())) {
+        return Ok(0);
+    }
+
+    let size = try!(fetch(&Client::new(), &url, &path, options.sha256.as_ref().map(|s| s.as_str())));
+    info!("downloaded {} ({} bytes)", dest, size);
+
+    Ok(0)
+}
+
+/// Options of a download.
+#[derive(Default)]
+struct DownloadOptions {
+    sha256: Option<String>,
+}
+
+impl FromTable for DownloadOptions {
+    fn from_table(table: &Table) -> Result<DownloadOptions, Box<Error>> {
+        Ok(DownloadOptions {
+            sha256: try!(table.opt_string("sha256")),
+        })
+    }
+}
+
+/// Downloads many files concurrently.
+///
+/// # Lua arguments
+/// * `downloads: table`        - A table mapping destination paths to URLs, or to tables with a
+///                               `url` field and optionally a `sha256` field, like the options of
+///                               `download()`.
+/// * `jobs: number`            - The number of downloads to run at once. Defaults to 4.
+fn download_all(runtime: Runtime) -> ScriptResult {
+    if !runtime.state().is_table(1) {
+        return Err("first argument must be a table".into());
+    }
+
+    let mut downloads = Vec::new();
+    for (key, value) in runtime.iter(1) {
+        // Converting a number key in place would confuse the iteration, so convert a copy.
+        runtime.state().push_value(key);
+        let dest = runtime.state().to_str(-1).map(String::from);
+        runtime.state().pop(1);
+        let mut sha256 = None;
+
+        let url = if runtime.state().is_table(value) {
+            if runtime.state().get_field(value, "sha256") == lua::Type::String {
+                sha256 = runtime.state().to_str(-1).map(String::from);
+            }
+            runtime.state().pop(1);
+
+            runtime.state().get_field(value, "url");
+            let url = runtime.state().to_str_in_place(-1).map(String::from);
+            runtime.state().pop(1);
+            url
+        } else {
+            runtime.state().to_str_in_place(value).map(String::from)
+        };
+
+        match (dest, url) {
+            (Some(dest), Some(url)) => {
+                let path = runtime.environment().resolve(&dest);
+                downloads.push((url, dest, path, sha256));
+            }
+            _ => return Err("downloads must map paths to URLs".into()),
+        }
+    }
+
+    if runtime.environment().is_dry_run() {
+        for &(ref url, _, ref path, _) in &downloads {
+            runtime.skip_change(format!("download {} to \"{}\"", url, path.display()));
+        }
+        return Ok(0);
+    }
+
+    let count = downloads.len();
+    let jobs = try!(runtime.args().opt_integer(2)).unwrap_or(4);
+    let jobs = cmp::min(cmp::max(jobs, 1) as usize, count);
+
+    // Worker threads take downloads off a shared list until it is empty, and the progress of all
+    // of them is reported together.
+    let queue = Arc::new(Mutex::new(downloads));
+    let progress = Arc::new(Mutex::new((0, 0u64, Vec::new())));
+
+    let console_buffer = console::buffer();
+
+    let threads: Vec<_> = (0..jobs).map(|_| {
+        let queue = queue.clone();
+        let progress = progress.clone();
+        let console_buffer = console_buffer.clone();
+
+        thread::spawn(move || {
+            console::set_buffer(console_buffer);
+            let client = Client::new();
+
+            loop {
+                let next = queue.lock().unwrap().pop();
+                let (url, dest, path, sha256) = match next {
+                    Some(download) => download,
+                    None => break,
+                };
+
+                let result = fetch(&client, &url, &path, sha256.as_ref().map(|s| s.as_str()));
+
+                let mut progress = progress.lock().unwrap();
+                progress.0 += 1;
+
+                match result {
+                    Ok(size) => {
+                        progress.1 += size;
+                        info!("downloaded {} ({} of {}, {} bytes total)",
+                              dest,
+                              progress.0,
+                              count,
+                              progress.1);
+                    }
+                    Err(e) => progress.2.push(format!("{}: {}", url, e)),
+                }
+            }
+        })
+    }).collect();
+
+    let mut panicked = false;
+    for thread in threads {
+        panicked |= thread.join().is_err();
+    }
+
+    // The cache of the download threads was cleared, but not the one of this thread.
+    fscache::clear();
+
+    if panicked {
+        return Err("a download thread panicked".into());
+    }
+
+    let errors = &progress.lock().unwrap().2;
+    if !errors.is_empty() {
+        return Err(format!("failed to download {} of {} files:\n{}",
+                           errors.len(),
+                           count,
+                           errors.join("\n")).into());
+    }
+
+    Ok(0)
+}
+
+/// Downloads a URL to a file and returns the number of bytes written.
+///
+/// If a SHA-256 hash is given and the file doesn't match it, the file is removed so that a bad
+/// download is never used by mistake.
+fn fetch(client: &Client, url: &str, dest: &Path, sha256: Option<&str>) -> Result<u64, String> {
+    let mut response = try!(client.get(url).send().map_err(|e| e.to_string()));
+
+    let status = response.status.to_u16();
+    if status < 200 || status >= 300 {
+        return Err(format!("server responded with status {}", status));
+    }
+
+    fscache::clear();
+    let mut file = try!(File::create(dest).map_err(|e| e.to_string()));
+    let size = try!(io::copy(&mut response, &mut file).map_err(|e| e.to_string()));
+    drop(file);
+    fscache::clear();
+
+    if let Some(expected) = sha256 {
+        let actual = hash::to_hex(&try!(hash::sha256_file(dest).map_err(|e| e.to_string())));
+
+        if actual != expected.trim().to_lowercase() {
+            fs::remove_file(dest).ok();
+            fscache::clear();
+            return Err(format!("checksum mismatch for {}: expected sha256 {}, got {}", dest.display(), expected, actual));
+        }
+    }
 
-    Ok(2)
+    Ok(size)
 }
 
 pub fn load(runtime: Runtime) -> ScriptResult {
     runtime.load_lib(&[
+        ("download", download),
+        ("download_all", download_all),
         ("get", get),
         ("post", post),
     ]);
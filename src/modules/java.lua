@@ -1,7 +1,66 @@
 -- Module for Java build tasks.
-local java = {}
+local fs = require "fs"
+local path = require "path"
+local java = {
+    -- The class file compiled from each source file so far, by source file.
+    classes = {},
+}
 
 
+-- Separator between entries of a class path on the current platform.
+local separator = OS == "windows" and ";" or ":"
+
+-- Appends every value in a list to another list.
+local function append(list, values)
+    for _, value in ipairs(values) do
+        table.insert(list, value)
+    end
+    return list
+end
+
+-- Expands a glob pattern or a list of paths and patterns into a list of paths.
+local function expand(patterns)
+    if type(patterns) == "string" then
+        patterns = {patterns}
+    end
+
+    local paths = {}
+    for _, pattern in ipairs(patterns or {}) do
+        if pattern:find("[%*%?%[]") then
+            append(paths, fs.glob(pattern))
+        else
+            table.insert(paths, pattern)
+        end
+    end
+
+    return paths
+end
+
+-- Builds a class path out of a list of directories, jar files, and glob
+-- patterns matching jar files, such as `lib/*.jar`.
+function java.classpath(entries)
+    return table.concat(expand(entries), separator)
+end
+
+-- Gets the class file that a source file is compiled to in `dest`, using the
+-- package declared in the source file.
+function java.class_file(source, dest)
+    local name = source:match("([^/\\]+)%.java$")
+    if not name then
+        error(("'%s' is not a Java source file"):format(source))
+    end
+
+    local package = fs.get(source):match("%f[%w]package%s+([%w_.]+)%s*;")
+    local dir = dest or "."
+    if package then
+        dir = dir .. "/" .. package:gsub("%.", "/")
+    end
+
+    return dir .. "/" .. name .. ".class"
+end
+
+-- Compiles Java sources into class files. Only sources that have changed
+-- since their class file was written are compiled again.
 function java.binary(options)
     options = rote.merge({
         dest = ".",
@@ -10,13 +69,15 @@ function java.binary(options)
         debug = true,
         compiler = "javac",
         encoding = "utf-8",
-        mainClass = "Main"
     }, options)
 
+    -- Already compiled classes are found in the output directory.
+    local classpath = append({options.dest}, options.paths)
+    append(classpath, options.classpath or {})
+
     local compiler_flags = {
-        "-verbose",
         "-encoding", options.encoding,
-        "-cp", table.concat(options.paths, ":"),
+        "-cp", java.classpath(classpath),
         "-d", options.dest
     }
 
@@ -30,33 +91,180 @@ function java.binary(options)
         table.insert(compiler_flags, "-g:none")
     end
 
-    for i, file in ipairs(glob(options.srcs)) do
-        table.insert(compiler_flags, file)
+    local changed = {}
+    for _, file in ipairs(expand(options.srcs)) do
+        local class_file = java.class_file(file, options.dest)
+        java.classes[file] = class_file
+
+        local compiled = fs.modified(class_file)
+        if not compiled or compiled < fs.modified(file) then
+            table.insert(changed, file)
+        end
+    end
+
+    if #changed == 0 then
+        return changed
+    end
+
+    if not fs.exists(options.dest) then
+        fs.mkdir(options.dest)
+    end
+    exec(options.compiler, table.unpack(append(compiler_flags, changed)))
+
+    return changed
+end
+
+-- Formats a table of attributes as the contents of a jar manifest.
+--
+-- Lines of a manifest may be at most 72 bytes long, so longer lines are
+-- continued on the next line, starting with a space.
+function java.manifest(attributes)
+    attributes = rote.merge({
+        ["Manifest-Version"] = "1.0",
+    }, attributes)
+
+    local names = {}
+    for name in pairs(attributes) do
+        if name ~= "Manifest-Version" then
+            table.insert(names, name)
+        end
+    end
+    table.sort(names)
+    table.insert(names, 1, "Manifest-Version")
+
+    local lines = {}
+    for _, name in ipairs(names) do
+        local line = name .. ": " .. tostring(attributes[name])
+
+        table.insert(lines, line:sub(1, 72))
+        for i = 73, #line, 71 do
+            table.insert(lines, " " .. line:sub(i, i + 70))
+        end
     end
 
-    exec(options.compiler .. " " .. table.concat(compiler_flags, " "))
+    return table.concat(lines, "\r\n") .. "\r\n"
 end
 
+-- Packages files into a jar.
 function java.jar(options)
-    local options = core.options(options, {
+    options = rote.merge({
         out = "out.jar",
-        main_class = "org.myapp.Main",
-        manifest = false
-    })
+        dir = ".",
+        files = {"."},
+    }, options)
+
+    local flags = "cf"
+    local jar_flags = {options.out}
+    local manifest = options.manifest
+
+    -- A table of attributes is written to a manifest file first.
+    if type(manifest) == "table" then
+        local attributes = rote.merge(manifest, {
+            ["Main-Class"] = options.main_class,
+        })
 
-    local jar_flags = {"-c", "-v", "-f", options.out}
+        manifest = os.tmpname()
+        fs.put(manifest, java.manifest(attributes))
+    end
+
+    if manifest then
+        flags = flags .. "m"
+        table.insert(jar_flags, manifest)
+    end
+
+    if options.main_class and type(options.manifest) ~= "table" then
+        flags = flags .. "e"
+        table.insert(jar_flags, options.main_class)
+    end
+
+    table.insert(jar_flags, 1, flags)
+
+    for _, file in ipairs(options.files) do
+        table.insert(jar_flags, "-C")
+        table.insert(jar_flags, options.dir)
+        table.insert(jar_flags, file)
+    end
+
+    local ok, err = pcall(exec, "jar", table.unpack(jar_flags))
+
+    if type(options.manifest) == "table" then
+        os.remove(manifest)
+    end
+    if not ok then
+        error(err, 0)
+    end
+end
+
+-- Finds the wrapper script of a build tool in a project directory, falling
+-- back to the build tool installed on the system.
+local function find_wrapper(dir, unix_name, windows_name, executable)
+    local wrapper = path.join(dir, OS == "windows" and windows_name or unix_name)
+
+    if fs.is_file(wrapper) then
+        return wrapper
+    end
+
+    return executable
+end
 
-    if options.mainClass then
-        table.insert(jar_flags, "-e")
-        table.insert(jar_flags, options.mainClass)
+-- Adds the options shared by the Maven and Gradle helpers to a list of
+-- arguments.
+local function build_tool_args(args, targets, options)
+    local properties = options.properties or {}
+    local names = {}
+    for name in pairs(properties) do
+        table.insert(names, name)
     end
+    table.sort(names)
+
+    for _, name in ipairs(names) do
+        table.insert(args, "-D" .. name .. "=" .. tostring(properties[name]))
+    end
+
+    append(args, options.args or {})
+    append(args, type(targets) == "string" and {targets} or targets or {})
+
+    return args
+end
+
+-- Runs Maven goals, using the Maven wrapper of the project if it has one.
+function java.maven(goals, options)
+    options = rote.merge({
+        dir = ".",
+        dry_run = rote.dry_run(),
+    }, options)
+
+    local args = {"-f", options.dir}
+    if options.profiles then
+        table.insert(args, "-P" .. table.concat(options.profiles, ","))
+    end
+    build_tool_args(args, goals, options)
+
+    local executable = find_wrapper(options.dir, "mvnw", "mvnw.cmd", MAVEN or "mvn")
+
+    -- Maven has no way to simulate a build, so just show what would be run.
+    if options.dry_run then
+        print("would run: " .. executable .. " " .. table.concat(args, " "))
+        return
+    end
+
+    exec(executable, table.unpack(args))
+end
+
+-- Runs Gradle tasks, using the Gradle wrapper of the project if it has one.
+function java.gradle(tasks, options)
+    options = rote.merge({
+        dir = ".",
+        dry_run = rote.dry_run(),
+    }, options)
 
-    if options.manifest then
-        table.insert(jar_flags, "-m")
-        table.insert(jar_flags, options.manifest)
+    local args = {"-p", options.dir}
+    if options.dry_run then
+        table.insert(args, "--dry-run")
     end
+    build_tool_args(args, tasks, options)
 
-    exec("jar " .. table.concat(jar_flags, " ") .. options.srcs)
+    exec(find_wrapper(options.dir, "gradlew", "gradlew.bat", GRADLE or "gradle"), table.unpack(args))
 end
 
 
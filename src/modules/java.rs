@@ -5,7 +5,7 @@ const SOURCE: &'static str = include_str!("java.lua");
 
 /// Module loader.
 pub fn load(runtime: Runtime) -> ScriptResult {
-    try!(runtime.eval(SOURCE));
+    try!(runtime.eval_module("java", SOURCE));
 
     Ok(1)
 }
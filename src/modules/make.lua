@@ -0,0 +1,303 @@
+-- Module for importing targets from existing Makefiles.
+local fs = require "fs"
+local make = {}
+
+
+-- Expands variable references in a string.
+--
+-- Supports `$(NAME)`, `${NAME}`, single-character references such as `$@`,
+-- and `$$` as an escaped dollar sign. Variables not defined in the Makefile
+-- fall back to environment variables. Make functions are not supported.
+function make.expand(text, variables, automatic)
+    variables = variables or {}
+    automatic = automatic or {}
+
+    local function lookup(name)
+        if automatic[name] then
+            return automatic[name]
+        end
+
+        if variables[name] then
+            return make.expand(variables[name], variables, automatic)
+        end
+
+        return os.getenv(name) or ""
+    end
+
+    local result = {}
+    local i = 1
+
+    while i <= #text do
+        local char = text:sub(i, i)
+        local next_char = text:sub(i + 1, i + 1)
+
+        if char ~= "$" then
+            table.insert(result, char)
+            i = i + 1
+        elseif next_char == "$" then
+            table.insert(result, "$")
+            i = i + 2
+        elseif next_char == "(" or next_char == "{" then
+            local close = next_char == "(" and ")" or "}"
+            local depth = 1
+            local j = i + 2
+
+            -- Find the matching closing delimiter.
+            while j <= #text and depth > 0 do
+                local c = text:sub(j, j)
+                if c == next_char then
+                    depth = depth + 1
+                elseif c == close then
+                    depth = depth - 1
+                end
+                j = j + 1
+            end
+
+            if depth > 0 then
+                error("unterminated variable reference in '" .. text .. "'")
+            end
+
+            local name = make.expand(text:sub(i + 2, j - 2), variables, automatic)
+            if name:match("%s") then
+                error("unsupported make function '" .. name .. "'")
+            end
+
+            table.insert(result, lookup(name))
+            i = j
+        else
+            table.insert(result, lookup(next_char))
+            i = i + 2
+        end
+    end
+
+    return table.concat(result)
+end
+
+-- Splits a string into whitespace-separated words.
+local function words(text)
+    local result = {}
+    for word in text:gmatch("%S+") do
+        table.insert(result, word)
+    end
+    return result
+end
+
+-- Parses the source of a Makefile.
+--
+-- Returns a table containing `variables`, a map of variable names to their
+-- unexpanded values, and `targets`, a list of targets in the order they were
+-- defined. Each target has a `name`, a list of `deps`, a list of `recipe`
+-- lines, and the flags `phony` and `pattern`.
+function make.parse(source)
+    local makefile = {
+        variables = {},
+        targets = {},
+    }
+    local phony = {}
+    local current
+
+    -- Join continued lines.
+    source = source:gsub("[ \t]*\\\r?\n[ \t]*", " ")
+
+    for line in (source .. "\n"):gmatch("(.-)\r?\n") do
+        if line:match("^\t") then
+            -- Recipe line for the current rule.
+            if current then
+                table.insert(current, line:sub(2))
+            end
+        else
+            line = line:gsub("#.*$", "")
+
+            if not line:match("^%s*$") then
+                local name, op, value = line:match("^%s*([%w_%.%-]+)%s*([:%?%+]?=)%s*(.-)%s*$")
+
+                if name then
+                    local variables = makefile.variables
+
+                    if op == ":=" then
+                        variables[name] = make.expand(value, variables)
+                    elseif op == "?=" then
+                        if not variables[name] and not os.getenv(name) then
+                            variables[name] = value
+                        end
+                    elseif op == "+=" and variables[name] then
+                        variables[name] = variables[name] .. " " .. value
+                    else
+                        variables[name] = value
+                    end
+
+                    current = nil
+                else
+                    local targets, deps = line:match("^([^:=]+)::?%s*(.-)%s*$")
+                    if not targets then
+                        error("unable to parse Makefile line: " .. line)
+                    end
+
+                    -- Recipes may also be given inline after a semicolon.
+                    local inline
+                    deps, inline = deps:match("^([^;]*);?%s*(.*)$")
+
+                    targets = words(make.expand(targets, makefile.variables))
+                    deps = words(make.expand(deps, makefile.variables))
+                    current = {}
+
+                    if inline ~= "" then
+                        table.insert(current, inline)
+                    end
+
+                    if targets[1] == ".PHONY" then
+                        for _, dep in ipairs(deps) do
+                            phony[dep] = true
+                        end
+                        current = nil
+                    elseif not targets[1]:match("^%.") then
+                        for _, target in ipairs(targets) do
+                            table.insert(makefile.targets, {
+                                name = target,
+                                deps = deps,
+                                recipe = current,
+                                pattern = target:find("%", 1, true) ~= nil,
+                            })
+                        end
+                    end
+                end
+            end
+        end
+    end
+
+    for _, target in ipairs(makefile.targets) do
+        target.phony = phony[target.name] == true
+    end
+
+    return makefile
+end
+
+-- Quotes an argument so that the system shell passes it to a command as is.
+local function quote(arg)
+    if OS == "windows" then
+        return '"' .. arg:gsub('"', '""') .. '"'
+    end
+
+    return "'" .. arg:gsub("'", "'\\''") .. "'"
+end
+
+-- Runs the recipe of a target using the system shell.
+local function run_recipe(recipe, variables, automatic, directory)
+    local shell, flag = "sh", "-c"
+    if OS == "windows" then
+        shell, flag = "cmd", "/C"
+    end
+
+    for _, line in ipairs(recipe) do
+        local prefix, command = line:match("^%s*([@%-%+]*)(.*)$")
+        command = make.expand(command, variables, automatic)
+
+        if not prefix:find("@", 1, true) then
+            io.stdout:write(command .. "\n")
+        end
+
+        if directory then
+            command = "cd " .. quote(directory) .. " && " .. command
+        end
+
+        local ok, err = pcall(exec, shell, flag, command)
+        if not ok and not prefix:find("-", 1, true) then
+            error(err, 0)
+        end
+    end
+end
+
+-- Imports all targets from a Makefile as tasks and rules.
+--
+-- Phony targets become named tasks, and all other targets (including pattern
+-- rules) become file rules. Prerequisites that are not themselves targets
+-- are treated as plain source files, and become inputs of the task or rule. Recipes are run through the system shell
+-- from the directory containing the Makefile.
+--
+-- Returns a list of the names that were imported.
+function make.import(path, options)
+    options = rote.merge({
+        prefix = "",
+    }, options)
+
+    path = path or "Makefile"
+    local makefile = make.parse(fs.get(path))
+    local directory = path:match("^(.*)[/\\]")
+    local imported = {}
+
+    -- Build a set of all target names so we know which prerequisites are
+    -- provided by the Makefile itself.
+    local defined = {}
+    local patterns = {}
+    for _, target in ipairs(makefile.targets) do
+        defined[target.name] = true
+
+        if target.pattern then
+            table.insert(patterns, {target.name:match("^(.-)%%(.*)$")})
+        end
+    end
+
+    local function provided(name)
+        if defined[name] then
+            return true
+        end
+
+        for _, pattern in ipairs(patterns) do
+            local prefix, suffix = pattern[1], pattern[2]
+
+            if #name >= #prefix + #suffix
+                and name:sub(1, #prefix) == prefix
+                and name:sub(#name - #suffix + 1) == suffix then
+                return true
+            end
+        end
+
+        return false
+    end
+
+    for _, target in ipairs(makefile.targets) do
+        local name = options.prefix .. target.name
+        local deps = {inputs = {}}
+
+        for _, dep in ipairs(target.deps) do
+            if provided(dep) then
+                table.insert(deps, options.prefix .. dep)
+            else
+                table.insert(deps.inputs, dep)
+            end
+        end
+
+        local function action(output)
+            local automatic = {
+                ["@"] = output and output:sub(#options.prefix + 1) or target.name,
+                ["<"] = target.deps[1] or "",
+                ["^"] = table.concat(target.deps, " "),
+            }
+
+            -- Determine the stem matched by a pattern rule.
+            if target.pattern then
+                local prefix, suffix = target.name:match("^(.-)%%(.*)$")
+                local stem = automatic["@"]:sub(#prefix + 1, #automatic["@"] - #suffix)
+
+                automatic["*"] = stem
+                automatic["<"] = automatic["<"]:gsub("%%", stem)
+                automatic["^"] = automatic["^"]:gsub("%%", stem)
+            end
+
+            run_recipe(target.recipe, makefile.variables, automatic, directory)
+        end
+
+        if target.phony then
+            rote.create_task(name, deps, action)
+        else
+            rote.create_rule(name, deps, action)
+        end
+
+        table.insert(imported, name)
+    end
+
+    return imported
+end
+
+
+return make
@@ -1,18 +1,54 @@
 use runtime::Runtime;
 
+pub mod archive;
+pub mod cargo;
 pub mod cpp;
+pub mod docker;
+pub mod docs;
+pub mod dotnet;
+pub mod env;
+pub mod exec;
 pub mod http;
 pub mod fs;
+pub mod git;
+pub mod go;
+pub mod hash;
 pub mod java;
 pub mod json;
+pub mod make;
+pub mod node;
+pub mod path;
+pub mod php;
+pub mod regex;
+pub mod scripts;
 pub mod stdlib;
+pub mod template;
+pub mod yaml;
 
 
 pub fn register_all(runtime: &Runtime) {
     self::stdlib::load(runtime.clone());
+    runtime.register_lib("archive", self::archive::load);
+    runtime.register_lib("cargo", self::cargo::load);
     runtime.register_lib("cpp", self::cpp::load);
+    runtime.register_lib("docker", self::docker::load);
+    runtime.register_lib("docs", self::docs::load);
+    runtime.register_lib("dotnet", self::dotnet::load);
+    runtime.register_lib("env", self::env::load);
+    runtime.register_lib("exec", self::exec::load);
     runtime.register_lib("http", self::http::load);
     runtime.register_lib("fs", self::fs::load);
+    runtime.register_lib("git", self::git::load);
+    runtime.register_lib("go", self::go::load);
+    runtime.register_lib("hash", self::hash::load);
     runtime.register_lib("java", self::java::load);
     runtime.register_lib("json", self::json::load);
+    runtime.register_lib("make", self::make::load);
+    runtime.register_lib("node", self::node::load);
+    runtime.register_lib("path", self::path::load);
+    runtime.register_lib("php", self::php::load);
+    runtime.register_lib("regex", self::regex::load);
+    runtime.register_lib("scripts", self::scripts::load);
+    runtime.register_lib("template", self::template::load);
+    runtime.register_lib("yaml", self::yaml::load);
 }
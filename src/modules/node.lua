@@ -0,0 +1,113 @@
+-- Module for Node.js projects managed with npm.
+local fs = require "fs"
+local json = require "json"
+local path = require "path"
+local node = {
+    -- The npm executable to invoke.
+    npm_executable = NPM or "npm",
+
+    -- The npx executable to invoke.
+    npx_executable = NPX or "npx",
+}
+
+
+-- Appends every value in a list to another list.
+local function append(list, values)
+    for _, value in ipairs(values or {}) do
+        table.insert(list, value)
+    end
+    return list
+end
+
+-- Reads the package.json of a project, given either the file itself or the
+-- directory containing it.
+function node.read_package(file)
+    file = file or "package.json"
+    if fs.is_dir(file) then
+        file = path.join(file, "package.json")
+    end
+
+    local ok, package = pcall(json.parse, fs.get(file))
+    if not ok or type(package) ~= "table" then
+        error(("failed to parse '%s': %s"):format(file, ok and "not an object" or package), 0)
+    end
+
+    package.scripts = package.scripts or {}
+    package.dependencies = package.dependencies or {}
+    package.devDependencies = package.devDependencies or {}
+
+    return package
+end
+
+-- Checks if the node_modules directory of a project is newer than its
+-- package.json and lock file, meaning that there is nothing to install.
+function node.is_installed(dir)
+    dir = dir or "."
+
+    -- npm records what it installed in this file since version 7.
+    local installed = fs.modified(path.join(dir, "node_modules", ".package-lock.json"))
+    if not installed then
+        return false
+    end
+
+    for _, name in ipairs({"package.json", "package-lock.json"}) do
+        local modified = fs.modified(path.join(dir, name))
+        if modified and modified > installed then
+            return false
+        end
+    end
+
+    return true
+end
+
+-- Installs the dependencies of a project, unless they are already up to date.
+-- Returns true if npm was run.
+--
+-- Projects with a lock file are installed with `npm ci`, which installs
+-- exactly what the lock file says.
+function node.npm_install(options)
+    options = rote.merge({
+        dir = ".",
+    }, options)
+
+    if not options.force and node.is_installed(options.dir) then
+        return false
+    end
+
+    local ci = options.ci
+    if ci == nil then
+        ci = fs.exists(path.join(options.dir, "package-lock.json"))
+    end
+
+    local args = {"--prefix", options.dir, ci and "ci" or "install"}
+    if options.production then
+        table.insert(args, "--production")
+    end
+    append(args, options.args)
+
+    exec(node.npm_executable, table.unpack(args))
+    return true
+end
+
+-- Runs a script defined in the package.json of a project.
+function node.npm_run(script, args, options)
+    options = rote.merge({
+        dir = ".",
+    }, options)
+
+    local run_args = {"--prefix", options.dir, "run", script}
+    if args and #args > 0 then
+        table.insert(run_args, "--")
+        append(run_args, args)
+    end
+
+    exec(node.npm_executable, table.unpack(run_args))
+end
+
+-- Runs a tool from an npm package, such as one installed in node_modules.
+function node.npx(tool, args)
+    exec(node.npx_executable, tool, table.unpack(args or {}))
+end
+
+
+return node
@@ -0,0 +1,190 @@
+use runtime::{Runtime, ScriptResult};
+use std::path::{Component, Path, PathBuf};
+
+
+/// Normalizes a path without touching the file system, by removing `.` components and resolving
+/// `..` components against the components before them.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    let mut depth = 0;
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if depth > 0 {
+                    normalized.pop();
+                    depth -= 1;
+                } else if !normalized.has_root() {
+                    // There is nothing to go up from in a relative path, so keep the `..`.
+                    normalized.push("..");
+                }
+            }
+            Component::Normal(part) => {
+                normalized.push(part);
+                depth += 1;
+            }
+            Component::Prefix(_) | Component::RootDir => {
+                normalized.push(component.as_os_str());
+            }
+        }
+    }
+
+    if normalized.as_os_str().is_empty() {
+        normalized.push(".");
+    }
+
+    normalized
+}
+
+/// Makes a path absolute by joining it to the current directory of the script, and normalizes it.
+fn absolute_path(runtime: &Runtime, path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        normalize_path(path)
+    } else {
+        normalize_path(&runtime.environment().current_dir().join(path))
+    }
+}
+
+/// Joins two or more paths together.
+///
+/// # Lua arguments
+/// * `paths: string...`        - The paths to join. If a path is absolute, it replaces the paths
+///                               before it.
+fn join(runtime: Runtime) -> ScriptResult {
+    let mut path = runtime.check_path(1);
+
+    for index in 2..runtime.state().get_top() + 1 {
+        path.push(runtime.check_path(index));
+    }
+
+    runtime.push_path(path);
+    Ok(1)
+}
+
+/// Gets the last component of a path, such as the name of a file.
+///
+/// # Lua arguments
+/// * `path: string`            - The path.
+fn basename(runtime: Runtime) -> ScriptResult {
+    let path = runtime.check_path(1);
+
+    match path.file_name() {
+        Some(name) => runtime.push_path(name),
+        None => {
+            runtime.state().push_string("");
+        }
+    }
+
+    Ok(1)
+}
+
+/// Gets the path without its last component, such as the directory containing a file.
+///
+/// # Lua arguments
+/// * `path: string`            - The path.
+fn dirname(runtime: Runtime) -> ScriptResult {
+    let path = runtime.check_path(1);
+
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => runtime.push_path(parent),
+        Some(_) => {
+            runtime.state().push_string(".");
+        }
+        None => runtime.push_path(path),
+    }
+
+    Ok(1)
+}
+
+/// Gets the extension of the last component of a path, without the leading dot.
+///
+/// # Lua arguments
+/// * `path: string`            - The path.
+fn extension(runtime: Runtime) -> ScriptResult {
+    let path = runtime.check_path(1);
+
+    match path.extension() {
+        Some(extension) => runtime.push_path(extension),
+        None => {
+            runtime.state().push_string("");
+        }
+    }
+
+    Ok(1)
+}
+
+/// Gets the normalized absolute form of a path, relative to the current directory. The path does
+/// not need to exist, and symbolic links are not resolved.
+///
+/// # Lua arguments
+/// * `path: string`            - The path.
+fn absolute(runtime: Runtime) -> ScriptResult {
+    let path = runtime.check_path(1);
+
+    runtime.push_path(absolute_path(&runtime, &path));
+    Ok(1)
+}
+
+/// Gets a relative path that leads from one path to another.
+///
+/// # Lua arguments
+/// * `from: string`            - The directory to start from.
+/// * `to: string`              - The path to lead to.
+fn relative(runtime: Runtime) -> ScriptResult {
+    let from = absolute_path(&runtime, &runtime.check_path(1));
+    let to = absolute_path(&runtime, &runtime.check_path(2));
+
+    let from: Vec<Component> = from.components().collect();
+    let to: Vec<Component> = to.components().collect();
+
+    // Paths on different drives have no relative path between them.
+    if from.first() != to.first() {
+        runtime.push_path(to.iter().map(|component| component.as_os_str()).collect::<PathBuf>());
+        return Ok(1);
+    }
+
+    let common = from.iter().zip(&to).take_while(|&(a, b)| a == b).count();
+    let mut relative = PathBuf::new();
+
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component.as_os_str());
+    }
+
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+
+    runtime.push_path(relative);
+    Ok(1)
+}
+
+/// Normalizes a path by removing `.` components and resolving `..` components, without touching
+/// the file system.
+///
+/// # Lua arguments
+/// * `path: string`            - The path.
+fn normalize(runtime: Runtime) -> ScriptResult {
+    let path = runtime.check_path(1);
+
+    runtime.push_path(normalize_path(&path));
+    Ok(1)
+}
+
+/// Module loader.
+pub fn load(runtime: Runtime) -> ScriptResult {
+    runtime.load_lib(&[
+        ("join", join),
+        ("basename", basename),
+        ("dirname", dirname),
+        ("extension", extension),
+        ("absolute", absolute),
+        ("relative", relative),
+        ("normalize", normalize),
+    ]);
+
+    Ok(1)
+}
@@ -0,0 +1,122 @@
+-- Module for PHP projects managed with Composer.
+local fs = require "fs"
+local hash = require "hash"
+local path = require "path"
+local php = {
+    -- The composer executable to invoke.
+    composer = COMPOSER or "composer",
+}
+
+
+-- Name of the file in the vendor directory recording the lock file that the
+-- installed dependencies came from.
+local STAMP_FILE = ".composer-lock"
+
+-- Runs a composer command in a project directory.
+local function composer(dir, command, args)
+    exec(php.composer, command, "--no-interaction", "--working-dir=" .. dir, table.unpack(args or {}))
+end
+
+-- Gets the contents of the stamp file that an install with the given options
+-- would write, or nil if the project has no lock file yet.
+function php.install_stamp(options)
+    options = rote.merge({
+        dir = ".",
+        no_dev = true,
+    }, options)
+
+    local lock_file = path.join(options.dir, "composer.lock")
+    if not fs.exists(lock_file) then
+        return nil
+    end
+
+    return hash.file_sha256(lock_file) .. (options.no_dev and " no-dev" or "") .. "\n"
+end
+
+-- Checks if the dependencies in the vendor directory were installed from the
+-- current lock file with the same options.
+function php.is_installed(options)
+    options = rote.merge({
+        dir = ".",
+        vendor_dir = "vendor",
+    }, options)
+
+    local stamp = php.install_stamp(options)
+    local stamp_file = path.join(options.dir, options.vendor_dir, STAMP_FILE)
+
+    return stamp ~= nil and fs.is_file(stamp_file) and fs.get(stamp_file) == stamp
+end
+
+-- Installs the dependencies of a project, unless they are already installed
+-- from the current lock file. Returns true if composer was run.
+function php.composer_install(options)
+    options = rote.merge({
+        dir = ".",
+        vendor_dir = "vendor",
+        no_dev = true,
+        optimize = false,
+    }, options)
+
+    if not options.force and php.is_installed(options) then
+        return false
+    end
+
+    local args = {}
+    if options.no_dev then
+        table.insert(args, "--no-dev")
+    end
+    if options.optimize then
+        table.insert(args, "--optimize-autoloader")
+    end
+    for _, arg in ipairs(options.args or {}) do
+        table.insert(args, arg)
+    end
+
+    composer(options.dir, "install", args)
+
+    -- Composer creates the lock file on the first install.
+    local stamp = php.install_stamp(options)
+    if stamp and not rote.dry_run() then
+        fs.put(path.join(options.dir, options.vendor_dir, STAMP_FILE), stamp)
+    end
+
+    return true
+end
+
+-- Updates the dependencies of a project and its lock file. If a list of
+-- packages is given, only those are updated.
+function php.composer_update(packages, options)
+    options = rote.merge({
+        dir = ".",
+    }, options)
+
+    local args = {}
+    for _, arg in ipairs(options.args or {}) do
+        table.insert(args, arg)
+    end
+    for _, package in ipairs(packages or {}) do
+        table.insert(args, package)
+    end
+
+    composer(options.dir, "update", args)
+end
+
+-- Runs a script defined in the composer.json of a project.
+function php.composer_run(script, args, options)
+    options = rote.merge({
+        dir = ".",
+    }, options)
+
+    local run_args = {script}
+    if args and #args > 0 then
+        table.insert(run_args, "--")
+        for _, arg in ipairs(args) do
+            table.insert(run_args, arg)
+        end
+    end
+
+    composer(options.dir, "run-script", run_args)
+end
+
+
+return php
@@ -0,0 +1,229 @@
+use lua;
+use regex::{Captures, Regex};
+use runtime::{Runtime, ScriptResult};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+
+
+/// Name of the metatable of compiled patterns in the registry.
+const METATABLE: &'static str = "rote.regex";
+
+thread_local! {
+    /// Patterns compiled so far on this thread, so that patterns used in loops aren't compiled
+    /// again every time.
+    static CACHE: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+}
+
+
+/// Gets the compiled pattern of an argument, which is either a pattern string or a compiled
+/// pattern object.
+fn pattern(runtime: &Runtime, index: lua::Index) -> Result<Regex, Box<Error>> {
+    let pattern = if runtime.state().is_table(index) {
+        runtime.state().get_field(index, "pattern");
+        let pattern = runtime.state().to_str(-1).map(String::from);
+        runtime.state().pop(1);
+        pattern
+    } else {
+        try!(runtime.args().opt_string(index))
+    };
+
+    let pattern = match pattern {
+        Some(pattern) => pattern,
+        None => return Err(format!("bad argument #{} (pattern expected)", index).into()),
+    };
+
+    CACHE.with(|cache| {
+        if let Some(regex) = cache.borrow().get(&pattern) {
+            return Ok(regex.clone());
+        }
+
+        let regex = try!(Regex::new(&pattern).map_err(|e| format!("invalid pattern: {}", e)));
+        cache.borrow_mut().insert(pattern, regex.clone());
+
+        Ok(regex)
+    })
+}
+
+/// Pushes a table of the captures of a match: the whole match at index 0, each group at its
+/// index, and named groups by name. Groups that didn't match are nil.
+fn push_captures(runtime: &Runtime, regex: &Regex, captures: &Captures) {
+    runtime.state().new_table();
+
+    for i in 0..captures.len() {
+        if let Some(text) = captures.at(i) {
+            runtime.state().push_string(text);
+            runtime.state().raw_seti(-2, i as lua::Integer);
+        }
+    }
+
+    for (i, name) in regex.capture_names().enumerate() {
+        if let (Some(name), Some(text)) = (name, captures.at(i)) {
+            runtime.state().push_string(text);
+            runtime.state().set_field(-2, name);
+        }
+    }
+}
+
+/// Compiles a pattern into a pattern object, which has the functions of this module as methods.
+///
+/// # Lua arguments
+/// * `pattern: string`         - The regular expression.
+fn new(runtime: Runtime) -> ScriptResult {
+    let regex = try!(pattern(&runtime, 1));
+
+    runtime.state().new_table();
+    runtime.state().push_string(regex.as_str());
+    runtime.state().set_field(-2, "pattern");
+    runtime.state().set_metatable_from_registry(METATABLE);
+
+    Ok(1)
+}
+
+/// Checks if a pattern matches anywhere in a string.
+///
+/// # Lua arguments
+/// * `pattern: string|regex`   - The pattern to match.
+/// * `text: string`            - The string to search.
+fn is_match(runtime: Runtime) -> ScriptResult {
+    let regex = try!(pattern(&runtime, 1));
+    let text = try!(runtime.args().string(2));
+
+    runtime.ret(regex.is_match(&text))
+}
+
+/// Finds the first match of a pattern in a string.
+///
+/// # Lua arguments
+/// * `pattern: string|regex`   - The pattern to match.
+/// * `text: string`            - The string to search.
+///
+/// # Lua returns
+/// * `captures: table`         - The captures of the match, or nil if there is no match.
+fn match_(runtime: Runtime) -> ScriptResult {
+    let regex = try!(pattern(&runtime, 1));
+    let text = try!(runtime.args().string(2));
+
+    match regex.captures(&text) {
+        Some(captures) => push_captures(&runtime, &regex, &captures),
+        None => runtime.state().push_nil(),
+    }
+
+    Ok(1)
+}
+
+/// Finds every match of a pattern in a string.
+///
+/// # Lua arguments
+/// * `pattern: string|regex`   - The pattern to match.
+/// * `text: string`            - The string to search.
+///
+/// # Lua returns
+/// * `matches: table`          - A list of the captures of each match.
+fn find_all(runtime: Runtime) -> ScriptResult {
+    let regex = try!(pattern(&runtime, 1));
+    let text = try!(runtime.args().string(2));
+
+    runtime.state().new_table();
+    for (i, captures) in regex.captures_iter(&text).enumerate() {
+        push_captures(&runtime, &regex, &captures);
+        runtime.state().raw_seti(-2, i as lua::Integer + 1);
+    }
+
+    Ok(1)
+}
+
+/// Replaces matches of a pattern in a string.
+///
+/// # Lua arguments
+/// * `pattern: string|regex`   - The pattern to match.
+/// * `text: string`            - The string to replace matches in.
+/// * `replacement: string|function` - The replacement, in which `$1` or `${name}` is replaced
+///                               with a group. If a function, it is called with the captures of
+///                               each match and returns the replacement, or nil or false to keep
+///                               the match.
+/// * `limit: number`           - The most matches to replace. Defaults to all of them. (Optional)
+fn replace(runtime: Runtime) -> ScriptResult {
+    let regex = try!(pattern(&runtime, 1));
+    let text = try!(runtime.args().string(2));
+    let limit = try!(runtime.args().opt_integer(4)).unwrap_or(0) as usize;
+
+    if !runtime.state().is_fn(3) {
+        let replacement = try!(runtime.args().string(3));
+        return runtime.ret(regex.replacen(&text, limit, replacement.as_str()));
+    }
+
+    let mut error = None;
+    let result = regex.replacen(&text, limit, |captures: &Captures| {
+        if error.is_some() {
+            return String::new();
+        }
+
+        runtime.state().push_value(3);
+        push_captures(&runtime, &regex, captures);
+
+        let replacement = match runtime.call(1, 1, 0) {
+            Ok(_) => {
+                if runtime.state().is_none_or_nil(-1) || runtime.state().type_of(-1) == Some(lua::Type::Boolean) {
+                    captures.at(0).unwrap_or("").to_string()
+                } else {
+                    runtime.state().to_str(-1).unwrap_or("").to_string()
+                }
+            }
+            Err(e) => {
+                error = Some(e);
+                String::new()
+            }
+        };
+        runtime.state().pop(1);
+
+        replacement
+    });
+
+    match error {
+        Some(e) => Err(e),
+        None => runtime.ret(result),
+    }
+}
+
+/// Splits a string at each match of a pattern.
+///
+/// # Lua arguments
+/// * `pattern: string|regex`   - The pattern to split at.
+/// * `text: string`            - The string to split.
+///
+/// # Lua returns
+/// * `parts: table`            - A list of the parts between matches.
+fn split(runtime: Runtime) -> ScriptResult {
+    let regex = try!(pattern(&runtime, 1));
+    let text = try!(runtime.args().string(2));
+
+    runtime.ret(regex.split(&text).map(String::from).collect::<Vec<_>>())
+}
+
+/// Escapes a string so that it matches itself literally.
+fn escape(runtime: Runtime) -> ScriptResult {
+    let text = try!(runtime.args().string(1));
+
+    runtime.ret(::regex::quote(&text))
+}
+
+pub fn load(runtime: Runtime) -> ScriptResult {
+    runtime.load_lib(&[
+        ("escape", escape),
+        ("find_all", find_all),
+        ("is_match", is_match),
+        ("match", match_),
+        ("new", new),
+        ("replace", replace),
+        ("split", split),
+    ]);
+
+    // Pattern objects look up their methods in the module.
+    runtime.state().new_metatable(METATABLE);
+    runtime.state().push_value(-2);
+    runtime.state().set_field(-2, "__index");
+    runtime.state().pop(1);
+
+    Ok(1)
+}
@@ -0,0 +1,88 @@
+-- Module for importing scripts defined by other package managers as tasks.
+local fs = require "fs"
+local json = require "json"
+local scripts = {}
+
+
+-- Reads the `scripts` section of a JSON manifest, returning the directory
+-- containing the manifest and a sorted list of script names.
+local function read_scripts(path)
+    local manifest = json.parse(fs.get(path))
+    local directory = path:match("^(.*)[/\\]") or "."
+    local names = {}
+
+    for name in pairs(manifest.scripts or {}) do
+        table.insert(names, name)
+    end
+
+    table.sort(names)
+    return directory, names, manifest.scripts or {}
+end
+
+-- Describes a script command, which may be a single command or a list.
+local function describe(command)
+    if type(command) == "table" then
+        return table.concat(command, " && ")
+    end
+
+    return tostring(command)
+end
+
+
+-- Imports the scripts from a package.json file as tasks.
+--
+-- Each script becomes a task named `prefix .. name` that runs the script
+-- with `npm run`, so lifecycle hooks such as `prebuild` still apply.
+-- Returns a list of the names that were imported.
+function scripts.npm(path, options)
+    options = rote.merge({
+        prefix = "npm:",
+        executable = NPM or "npm",
+    }, options)
+
+    local directory, names, commands = read_scripts(path or "package.json")
+    local imported = {}
+
+    for _, name in ipairs(names) do
+        local task_name = options.prefix .. name
+
+        desc(describe(commands[name]))
+        rote.create_task(task_name, function()
+            exec(options.executable, "--prefix", directory, "run", name)
+        end)
+
+        table.insert(imported, task_name)
+    end
+
+    return imported
+end
+
+-- Imports the scripts from a composer.json file as tasks.
+--
+-- Each script becomes a task named `prefix .. name` that runs the script
+-- with `composer run-script`. Returns a list of the names that were imported.
+function scripts.composer(path, options)
+    options = rote.merge({
+        prefix = "composer:",
+        executable = COMPOSER or "composer",
+    }, options)
+
+    local directory, names, commands = read_scripts(path or "composer.json")
+    local imported = {}
+
+    for _, name in ipairs(names) do
+        local task_name = options.prefix .. name
+
+        desc(describe(commands[name]))
+        rote.create_task(task_name, function()
+            exec(options.executable, "--working-dir=" .. directory, "run-script", name)
+        end)
+
+        table.insert(imported, task_name)
+    end
+
+    return imported
+end
+
+
+return scripts
@@ -1,14 +1,24 @@
-use glob;
+use console::{self, Segment, Stream};
+use fscache;
 use lua;
+use process;
 use regex::{Captures, Regex};
+use report::Diagnostic;
 use rule::Rule;
-use runtime::{Runtime, ScriptResult};
+use runtime::{Hook, Runtime, ScriptResult};
 use std::env;
+use std::error::Error;
+use std::io;
 use std::io::prelude::*;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::rc::Rc;
 use std::str;
-use task::NamedTask;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use task::{NamedTask, Retry};
 
 
 /// Expands global and environment variables inside a given string.
@@ -45,15 +55,21 @@ fn get_next_description(runtime: Runtime) -> Option<String> {
 }
 
 
-/// Sets the current working directory.
+/// Sets the current working directory of the script.
+///
+/// The current directory of the process is shared by every task running in parallel, so it is
+/// left alone. Instead, the directory is used by the script for resolving relative paths and
+/// running commands. Changing the directory inside a task only lasts until the task finishes.
 fn change_dir(runtime: Runtime) -> ScriptResult {
-    let path = runtime.state().check_string(1).to_string();
+    let path = runtime.environment().current_dir().join(runtime.check_path(1));
 
-    if env::set_current_dir(path).is_err() {
-        Err("failed to change directory".into())
-    } else {
-        Ok(0)
+    if !fscache::metadata(&path).map(|metadata| metadata.is_dir()).unwrap_or(false) {
+        return Err(format!("failed to change directory to \"{}\"", path.display()).into());
     }
+
+    runtime.environment().set_working_dir(Some(path));
+
+    Ok(0)
 }
 
 /// Defines a new rule.
@@ -61,15 +77,35 @@ fn change_dir(runtime: Runtime) -> ScriptResult {
 /// # Lua arguments
 /// * `pattern: string`      - The name of the task.
 /// * `description: string`  - A description of the task. (Optional)
-/// * `dependencies: table`  - A list of task names that the rule depends on. (Optional)
-/// * `func: function`       - A function that should be called when the rule is run. (Optional)
+/// * `dependencies: table`  - A list of task names that the rule depends on. Setting the `cheap`
+///                            field marks tasks as quick to run, so that they may be batched. The
+///                            `retries` and `retry_delay` fields set how many times a task that
+///                            fails is run again, and how many seconds to wait before each try.
+///                            The `timeout` field sets how many seconds a task may run before its
+///                            commands are killed. (Optional)
+/// * `func: function`       - A function that should be called when the rule is run. It is passed
+///                            the name of the output file, and the part of the name matched by
+///                            `%` in the pattern. (Optional)
 fn create_rule(runtime: Runtime) -> ScriptResult {
     let pattern = runtime.state().check_string(1).to_string();
     let mut func_index = 3;
+    let mut cheap = false;
+    let mut retry = Retry::default();
+    let mut timeout = None;
+    let mut inputs = Vec::new();
 
     // Get the list of dependencies if given.
     let deps = if runtime.state().type_of(2) == Some(lua::Type::Table) {
+        runtime.state().get_field(2, "cheap");
+        cheap = runtime.state().to_bool(-1);
+        runtime.state().pop(1);
+
+        inputs = try!(get_file_list(runtime.clone(), 2, "inputs"));
+        retry = try!(get_retry(runtime.clone(), 2));
+        timeout = try!(get_seconds(runtime.clone(), 2, "timeout"));
+
         runtime.iter(2)
+            .filter(|&(key, _)| runtime.state().type_of(key) == Some(lua::Type::Number))
             .map(|(_, value)| runtime.state().to_str_in_place(value).unwrap().to_string())
             .collect()
     } else {
@@ -89,46 +125,141 @@ fn create_rule(runtime: Runtime) -> ScriptResult {
 
     let closure_env = runtime.clone();
     let callback = func.map(|func| {
-        move |name: &str| {
+        move |name: &str, stem: Option<&str>| {
             // Get the function reference onto the Lua stack.
             closure_env.state().raw_geti(lua::REGISTRYINDEX, func.value() as i64);
 
-            // Push the synthesized name onto the stack.
+            // Push the synthesized name and the stem matched by the pattern onto the stack.
             closure_env.state().push(name);
+            match stem {
+                Some(stem) => closure_env.state().push(stem),
+                None => closure_env.state().push_nil(),
+            }
 
             // Invoke the task function.
             closure_env.environment().set_current_task(name);
-            let result = closure_env.call(1, 0, 0).map(|_| ()).map_err(|e| e.into());
+            let result = closure_env.call(2, 0, 0).map(|_| ()).map_err(|e| e.into());
             closure_env.environment().clear_current_task();
 
             result
         }
     });
 
-    runtime.environment().create_rule(Rule::new(pattern, deps, callback));
+    let mut rule = Rule::new(pattern, deps, callback).inputs(inputs).retry(retry).timeout(timeout);
+    if cheap {
+        rule = rule.cheap();
+    }
+
+    let location = runtime.location(1);
+    try!(runtime.environment().create_rule(rule, location));
     Ok(0)
 }
 
+/// Gets a list of file names from a field of a table, such as the inputs of a task.
+fn get_file_list(runtime: Runtime, index: lua::Index, field: &str) -> Result<Vec<String>, Box<Error>> {
+    let mut files = Vec::new();
+
+    match runtime.state().get_field(index, field) {
+        lua::Type::Nil => {}
+        lua::Type::Table => {
+            let table = runtime.state().get_top();
+            for (_, value) in runtime.iter(table) {
+                match runtime.state().to_str_in_place(value) {
+                    Some(file) => files.push(file.to_string()),
+                    None => {
+                        runtime.state().pop(3);
+                        return Err(format!("{} must be a list of file names", field).into());
+                    }
+                }
+            }
+        }
+        _ => {
+            runtime.state().pop(1);
+            return Err(format!("{} must be a list of file names", field).into());
+        }
+    }
+    runtime.state().pop(1);
+
+    Ok(files)
+}
+
+/// Gets how a task is retried from the `retries` and `retry_delay` fields of a table.
+fn get_retry(runtime: Runtime, index: lua::Index) -> Result<Retry, Box<Error>> {
+    let mut retry = Retry::default();
+
+    if runtime.state().get_field(index, "retries") != lua::Type::Nil {
+        match runtime.state().to_integerx(-1) {
+            Some(count) if count >= 0 => retry.count = count as u32,
+            _ => {
+                runtime.state().pop(1);
+                return Err("retries must be a non-negative integer".into());
+            }
+        }
+    }
+    runtime.state().pop(1);
+
+    if let Some(delay) = try!(get_seconds(runtime, index, "retry_delay")) {
+        retry.delay = delay;
+    }
+
+    Ok(retry)
+}
+
+/// Gets a duration given in seconds from a field of a table, if it is set.
+fn get_seconds(runtime: Runtime, index: lua::Index, field: &str) -> Result<Option<Duration>, Box<Error>> {
+    if runtime.state().get_field(index, field) == lua::Type::Nil {
+        runtime.state().pop(1);
+        return Ok(None);
+    }
+
+    let seconds = runtime.state().to_numberx(-1);
+    runtime.state().pop(1);
+
+    match seconds {
+        Some(seconds) if seconds >= 0.0 => {
+            Ok(Some(Duration::new(seconds as u64, (seconds.fract() * 1e9) as u32)))
+        }
+        _ => Err(format!("{} must be a non-negative number of seconds", field).into()),
+    }
+}
+
 /// Creates a new task.
 ///
 /// # Lua arguments
 /// * `name: string`         - The name of the task.
 /// * `description: string`  - A description of the task. (Optional)
-/// * `dependencies: table`  - A list of task names that the task depends on. (Optional)
+/// * `dependencies: table`  - A list of task names that the task depends on. The fields `inputs`
+///                            and `outputs` may be set to lists of files the task reads and
+///                            writes, so that the task is skipped when its outputs are newer than
+///                            its inputs. The `retries` and `retry_delay` fields set how many
+///                            times the task is run again if it fails, and how many seconds to
+///                            wait before each try. The `timeout` field sets how many seconds the
+///                            task may run before its commands are killed. Setting the `cache`
+///                            field to true stores the outputs in the output cache, and restores
+///                            them instead of running the task when its inputs are the same as a
+///                            previous run. (Optional)
 /// * `func: function`       - A function that should be called when the task is run.
 fn create_task(runtime: Runtime) -> ScriptResult {
-    let name = runtime.state().check_string(1).to_string();
+    let name = runtime.environment().namespace() + runtime.state().check_string(1);
     let desc = get_next_description(runtime.clone());
     let mut func_index = 3;
 
-    // Get the list of dependencies if given.
-    let deps = if runtime.state().type_of(2) == Some(lua::Type::Table) {
-        runtime.iter(2)
+    // Get the list of dependencies and the files used by the task if given.
+    let (deps, inputs, outputs, retry, timeout, cached) = if runtime.state().type_of(2) == Some(lua::Type::Table) {
+        let deps = runtime.iter(2)
+            .filter(|&(key, _)| runtime.state().type_of(key) == Some(lua::Type::Number))
             .map(|(_, value)| runtime.state().to_str_in_place(value).unwrap().to_string())
-            .collect()
+            .collect();
+
+        (deps,
+         try!(get_file_list(runtime.clone(), 2, "inputs")),
+         try!(get_file_list(runtime.clone(), 2, "outputs")),
+         try!(get_retry(runtime.clone(), 2)),
+         try!(get_seconds(runtime.clone(), 2, "timeout")),
+         try!(try!(runtime.args().table(2)).opt_bool("cache")).unwrap_or(false))
     } else {
         func_index -= 1;
-        Vec::new()
+        (Vec::new(), Vec::new(), Vec::new(), Retry::default(), None, false)
     };
 
     // Get the task function if given.
@@ -141,6 +272,9 @@ fn create_task(runtime: Runtime) -> ScriptResult {
         None
     };
 
+    // Tasks defined by an included script run their commands in its directory.
+    let working_dir = runtime.environment().working_dir();
+
     let closure_env = runtime.clone();
     let later_name = name.clone();
     let callback = func.map(|func| {
@@ -151,46 +285,209 @@ fn create_task(runtime: Runtime) -> ScriptResult {
             // Invoke the task function.
             let name = name.clone();
             closure_env.environment().set_current_task(name);
+            let previous_dir = closure_env.environment().set_working_dir(working_dir.clone());
             let result = closure_env.call(0, 0, 0).map(|_| ()).map_err(|e| e.into());
+            closure_env.environment().set_working_dir(previous_dir);
             closure_env.environment().clear_current_task();
 
             result
         }
     });
 
-    runtime.environment().create_task(NamedTask::new(later_name, desc, deps, callback));
+    let location = runtime.location(1);
+    let task = NamedTask::new(later_name, desc, deps, callback).files(inputs, outputs).retry(retry).timeout(timeout).cached(cached);
+    try!(runtime.environment().create_task(task, location));
+    Ok(0)
+}
+
+/// Loads another script and defines its tasks in a namespace.
+///
+/// # Lua arguments
+/// * `path: string`         - Path of the script to load, relative to the directory of the
+///                            current script.
+/// * `namespace: string`    - Name of the namespace. The tasks of the script are named
+///                            `namespace:name`.
+fn include(runtime: Runtime) -> ScriptResult {
+    let path = runtime.check_path(1);
+    let name = runtime.state().check_string(2).to_string();
+
+    if name.is_empty() || name.contains(':') {
+        return Err(format!("invalid namespace '{}'", name).into());
+    }
+
+    // Included scripts may include other scripts, so paths and namespaces nest.
+    let environment = runtime.environment();
+    let base = environment.working_dir().unwrap_or(environment.directory().to_path_buf());
+    let path = base.join(path);
+    let directory = match path.parent() {
+        Some(directory) => directory.to_path_buf(),
+        None => return Err("failed to parse script directory".into()),
+    };
+
+    let namespace = format!("{}{}:", environment.namespace(), name);
+    let previous_namespace = environment.set_namespace(namespace.as_str());
+    let previous_dir = environment.set_working_dir(Some(directory));
+
+    let result = runtime.load_file(&path);
+
+    environment.set_namespace(previous_namespace);
+    environment.set_working_dir(previous_dir);
+
+    try!(result);
+    environment.resolve_namespace(&namespace);
+
+    Ok(0)
+}
+
+/// Registers a function to call before a task runs.
+///
+/// # Lua arguments
+/// * `name: string`         - The name of the task.
+/// * `func: function`       - A function that is called with the name of the task before it runs.
+///                            If it raises an error, the task fails without running.
+fn before(runtime: Runtime) -> ScriptResult {
+    add_hook(runtime, Hook::Before)
+}
+
+/// Registers a function to call after a task runs.
+///
+/// # Lua arguments
+/// * `name: string`         - The name of the task.
+/// * `func: function`       - A function that is called after the task runs, even if it failed,
+///                            with the name of the task, whether it succeeded, and the error it
+///                            failed with. If it raises an error, the task fails.
+fn after(runtime: Runtime) -> ScriptResult {
+    add_hook(runtime, Hook::After)
+}
+
+/// Registers a function to call once before any task in a run is started.
+///
+/// # Lua arguments
+/// * `func: function`       - A function that is called with the list of tasks that will be run.
+///                            If it raises an error, no tasks are run.
+fn before_all(runtime: Runtime) -> ScriptResult {
+    add_hook(runtime, Hook::BeforeAll)
+}
+
+/// Registers a function to call once after every task in a run has finished.
+///
+/// # Lua arguments
+/// * `func: function`       - A function that is called with whether every task succeeded. If it
+///                            raises an error, the run fails.
+fn after_all(runtime: Runtime) -> ScriptResult {
+    add_hook(runtime, Hook::AfterAll)
+}
+
+/// Registers the function given as the last argument as a hook. Hooks around a single task are
+/// given the name of the task as the first argument.
+fn add_hook(runtime: Runtime, hook: Hook) -> ScriptResult {
+    let (task, func_index) = match hook {
+        Hook::Before | Hook::After => {
+            let name = runtime.environment().namespace() + runtime.state().check_string(1);
+            (Some(name), 2)
+        }
+        Hook::BeforeAll | Hook::AfterAll => (None, 1),
+    };
+
+    runtime.state().check_type(func_index, lua::Type::Function);
+    runtime.state().push_value(func_index);
+    let func = runtime.state().reference(lua::REGISTRYINDEX);
+
+    runtime.environment().add_hook(hook, task, func);
     Ok(0)
 }
 
-/// Gets the current working directory.
+/// Calls the hook functions registered for a point in a run, stopping at the first one that
+/// fails. The arguments for each call are pushed by `push_args`, which returns how many there are.
+fn call_hooks<F>(runtime: &Runtime, hook: Hook, task: Option<&str>, push_args: F) -> Result<(), Box<Error>>
+    where F: Fn(&Runtime) -> i32
+{
+    for func in runtime.environment().hooks(hook, task) {
+        runtime.state().raw_geti(lua::REGISTRYINDEX, func.value() as i64);
+        let nargs = push_args(runtime);
+        try!(runtime.call(nargs, 0, 0));
+    }
+
+    Ok(())
+}
+
+/// Calls the hooks to run before a task.
+pub fn call_before_hooks(runtime: &Runtime, name: &str) -> Result<(), Box<Error>> {
+    call_hooks(runtime, Hook::Before, Some(name), |runtime| {
+        runtime.state().push_string(name);
+        1
+    })
+}
+
+/// Calls the hooks to run after a task, with the result of the task.
+pub fn call_after_hooks(runtime: &Runtime, name: &str, result: &Result<(), String>) -> Result<(), Box<Error>> {
+    call_hooks(runtime, Hook::After, Some(name), |runtime| {
+        runtime.state().push_string(name);
+        runtime.state().push_bool(result.is_ok());
+        match *result {
+            Ok(()) => runtime.state().push_nil(),
+            Err(ref e) => {
+                runtime.state().push_string(e);
+            }
+        }
+        3
+    })
+}
+
+/// Calls the hooks to run before any task in a run is started, with the names of the tasks.
+pub fn call_before_all_hooks(runtime: &Runtime, names: &[String]) -> Result<(), Box<Error>> {
+    call_hooks(runtime, Hook::BeforeAll, None, |runtime| {
+        push_string_list(runtime, names);
+        1
+    })
+}
+
+/// Calls the hooks to run after every task in a run has finished.
+pub fn call_after_all_hooks(runtime: &Runtime, success: bool) -> Result<(), Box<Error>> {
+    call_hooks(runtime, Hook::AfterAll, None, |runtime| {
+        runtime.state().push_bool(success);
+        1
+    })
+}
+
+/// Gets the current working directory of the script.
 fn current_dir(runtime: Runtime) -> ScriptResult {
-    Ok(env::current_dir()
-        .map(|dir| {
-            runtime.state().push(dir.to_str());
-            1
-        })
-        .unwrap_or(0))
+    runtime.push_path(runtime.environment().current_dir());
+    Ok(1)
+}
+
+/// Gets the root directory of the project, which is the directory containing the Rotefile that
+/// was run.
+fn root_dir(runtime: Runtime) -> ScriptResult {
+    runtime.push_path(runtime.environment().directory());
+    Ok(1)
 }
 
 /// Gets the current Rote executable
 fn current_exe(runtime: Runtime) -> ScriptResult {
     Ok(env::current_exe()
         .map(|dir| {
-            runtime.state().push(dir.to_str());
+            runtime.push_path(dir);
             1
         })
         .unwrap_or(0))
 }
 
+/// Checks if the runner is only simulating running tasks.
+///
+/// # Lua returns
+/// * `dry_run: boolean`        - True if running with `--dry-run`.
+fn dry_run(runtime: Runtime) -> ScriptResult {
+    let dry_run = runtime.environment().is_dry_run();
+    runtime.state().push_bool(dry_run);
+
+    Ok(1)
+}
+
 /// Executes a shell command with a given list of arguments.
 fn execute(runtime: Runtime) -> ScriptResult {
     // Create a command for the given program name.
-    let mut command = Command::new(runtime.state().check_string(1));
-
-    // Set the current directory.
-    if let Ok(dir) = env::current_dir() {
-        command.current_dir(dir);
-    }
+    let mut command = program_command(runtime.state().check_string(1));
 
     // For each other parameter given, add it as a shell argument.
     for i in 2..runtime.state().get_top()+1 {
@@ -198,8 +495,213 @@ fn execute(runtime: Runtime) -> ScriptResult {
         command.arg(expand_string(runtime.state().check_string(i), runtime.clone()));
     }
 
-    // Spawn the command, capturing its status.
-    command.status().map_err(|e| {
+    run_command(runtime, command)
+}
+
+/// Executes a command string using the system shell, which is `cmd` on Windows and `sh`
+/// everywhere else.
+///
+/// # Lua arguments
+/// * `command: string` - The command to run.
+fn shell(runtime: Runtime) -> ScriptResult {
+    let script = expand_string(runtime.state().check_string(1), runtime.clone());
+
+    run_command(runtime, shell_command(script))
+}
+
+/// Creates a command that runs a script with the system shell.
+fn shell_command(script: String) -> Command {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command.arg(script);
+    command
+}
+
+/// Quotes an argument so that the system shell passes it to a program as is.
+fn quote_arg(arg: &str) -> String {
+    let safe = !arg.is_empty() && arg.chars().all(|c| c.is_alphanumeric() || "-_./:=+,@".contains(c));
+
+    if safe {
+        arg.to_string()
+    } else if cfg!(windows) {
+        format!("\"{}\"", arg.replace("\"", "\"\""))
+    } else {
+        format!("'{}'", arg.replace("'", "'\\''"))
+    }
+}
+
+/// Gets the value of a variable of a command template, quoted for the system shell. Lists become
+/// one argument for each item.
+fn quoted_var(runtime: &Runtime, index: lua::Index, name: &str) -> Result<String, String> {
+    if !runtime.state().is_table(index) {
+        return Err(format!("undefined command variable '{}'", name));
+    }
+
+    runtime.state().get_field(index, name);
+    let value = match runtime.state().type_of(-1) {
+        Some(lua::Type::String) | Some(lua::Type::Number) => {
+            Ok(quote_arg(runtime.state().to_str_in_place(-1).unwrap_or("")))
+        }
+        Some(lua::Type::Table) => {
+            let mut args = Vec::new();
+            let list = runtime.state().get_top();
+
+            for (_, item) in runtime.iter(list) {
+                match runtime.state().type_of(item) {
+                    Some(lua::Type::String) | Some(lua::Type::Number) => {
+                        args.push(quote_arg(runtime.state().to_str_in_place(item).unwrap_or("")));
+                    }
+                    _ => return Err(format!("command variable '{}' must be a list of strings", name)),
+                }
+            }
+
+            Ok(args.join(" "))
+        }
+        Some(lua::Type::Nil) | None => Err(format!("undefined command variable '{}'", name)),
+        _ => Err(format!("command variable '{}' must be a string or a list", name)),
+    };
+    runtime.state().pop(1);
+
+    value
+}
+
+/// Runs a command template using the system shell. Each `{name}` in the template is replaced with
+/// the variable of the same name, quoted so that it is passed to the command as a single argument.
+///
+/// # Lua arguments
+/// * `template: string`        - The command to run.
+/// * `vars: table`             - Values of the variables used in the command. (Optional)
+fn sh(runtime: Runtime) -> ScriptResult {
+    let template = runtime.state().check_string(1).to_string();
+    let pattern = Regex::new(r"\{(\w+)\}").unwrap();
+    let mut error = None;
+
+    let script = pattern.replace_all(&template, |caps: &Captures| {
+        match quoted_var(&runtime, 2, caps.at(1).unwrap_or("")) {
+            Ok(value) => value,
+            Err(e) => {
+                if error.is_none() {
+                    error = Some(e);
+                }
+                String::new()
+            }
+        }
+    });
+
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+
+    run_command(runtime, shell_command(script))
+}
+
+/// Executes a PowerShell script. Windows PowerShell is used on Windows, and PowerShell Core
+/// everywhere else.
+///
+/// # Lua arguments
+/// * `script: string` - The script to run.
+fn powershell(runtime: Runtime) -> ScriptResult {
+    let script = expand_string(runtime.state().check_string(1), runtime.clone());
+
+    let mut command = Command::new(if cfg!(windows) {
+        "powershell"
+    } else {
+        "pwsh"
+    });
+    command.arg("-NoProfile").arg("-NonInteractive").arg("-Command").arg(script);
+
+    run_command(runtime, command)
+}
+
+/// Creates a command for running a program.
+///
+/// On Windows, many programs are actually batch files such as `npm.cmd`, which can't be started
+/// without the command interpreter. Programs given without an extension are looked up like the
+/// shell would, and batch files are run through `cmd`.
+pub fn program_command(program: &str) -> Command {
+    if cfg!(windows) {
+        if let Some(path) = find_program(program) {
+            let extension = path.extension()
+                .and_then(|extension| extension.to_str())
+                .map(|extension| extension.to_lowercase());
+
+            if extension.as_ref().map(|e| e == "bat" || e == "cmd").unwrap_or(false) {
+                let mut command = Command::new("cmd");
+                command.arg("/C").arg(path);
+                return command;
+            }
+
+            return Command::new(path);
+        }
+    }
+
+    Command::new(program)
+}
+
+/// Searches the path for a program without an extension, trying each extension in `PATHEXT`.
+fn find_program(program: &str) -> Option<PathBuf> {
+    let program = Path::new(program);
+    if program.extension().is_some() || program.components().count() != 1 {
+        return None;
+    }
+
+    let paths = match env::var_os("PATH") {
+        Some(paths) => paths,
+        None => return None,
+    };
+    let extensions = env::var("PATHEXT").unwrap_or(".COM;.EXE;.BAT;.CMD".to_string());
+
+    for directory in env::split_paths(&paths) {
+        for extension in extensions.split(';').filter(|extension| !extension.is_empty()) {
+            let mut name = program.as_os_str().to_os_string();
+            name.push(extension);
+
+            let candidate = directory.join(name);
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+    }
+
+    None
+}
+
+/// Runs a command in the current directory and returns its exit code, raising an error if it
+/// fails.
+pub fn run_command(runtime: Runtime, mut command: Command) -> ScriptResult {
+    if skip_command(&runtime, &command) {
+        runtime.state().push_number(0.0);
+        return Ok(1);
+    }
+
+    let prefix = prepare_command(&runtime, &mut command);
+
+    // Spawn the command, capturing its status. If output capturing is enabled, the output is also
+    // recorded for the current task. Output is always passed through the console while a status
+    // line is shown, so that the status line stays below it.
+    let piped = runtime.environment().captures_output() || prefix.is_some() || console::has_status();
+    let status = if piped {
+        run_captured(&mut command, prefix).map(|(status, output, _)| {
+            if runtime.environment().captures_output() {
+                runtime.environment().append_output(&output);
+            }
+            status
+        })
+    } else {
+        process::spawn(&mut command).and_then(|(mut child, _group)| child.wait())
+    };
+
+    // The command may have changed any file.
+    fscache::clear();
+
+    status.map_err(|e| {
         format!("failed to execute process: {}", e).into()
     }).and_then(|status| {
         let status = status.code().unwrap_or(1);
@@ -213,15 +715,155 @@ fn execute(runtime: Runtime) -> ScriptResult {
     })
 }
 
+/// Runs a command like `run_command()`, but returns its exit code and everything it wrote to
+/// standard output instead of raising an error if it fails. The output is still echoed to the
+/// console as usual.
+pub fn run_command_output(runtime: &Runtime, mut command: Command)
+                          -> Result<(i32, String), Box<Error>> {
+    if skip_command(runtime, &command) {
+        return Ok((0, String::new()));
+    }
+
+    let prefix = prepare_command(runtime, &mut command);
+
+    let result = run_captured(&mut command, prefix);
+    fscache::clear();
+
+    let (status, output, stdout) = match result {
+        Ok(result) => result,
+        Err(e) => return Err(format!("failed to execute process: {}", e).into()),
+    };
+
+    if runtime.environment().captures_output() {
+        runtime.environment().append_output(&output);
+    }
+
+    Ok((status.code().unwrap_or(1), stdout))
+}
+
+/// Checks if a command should be skipped because this is a dry run, in which case the command
+/// is shown instead. Only commands run for their effects are skipped; commands run by `pipe()` for
+/// their output still run, so that queries keep working.
+fn skip_command(runtime: &Runtime, command: &Command) -> bool {
+    if runtime.environment().is_dry_run() {
+        console::println(format!("would run: {:?}", command));
+        true
+    } else {
+        false
+    }
+}
+
+/// Sets the directory to run a command in, and gets the prefix to write before each line of its
+/// output, if any.
+fn prepare_command(runtime: &Runtime, command: &mut Command) -> Option<String> {
+    // Set the current directory, or the directory of the included script the command is run by.
+    command.current_dir(runtime.environment().current_dir());
+
+    // Lines of output are prefixed with the task name when tasks run in parallel, so that the
+    // output of different tasks can be told apart.
+    if runtime.environment().prefixes_output() {
+        runtime.environment().current_task()
+    } else {
+        None
+    }
+}
+
+/// Runs a command to completion, echoing its output to the console while also capturing it. If a
+/// prefix is given, it is written before each line of output.
+///
+/// Returns the exit status, all of the output in the order it was written, and the output written
+/// to standard output alone.
+fn run_captured(command: &mut Command, prefix: Option<String>)
+                -> io::Result<(ExitStatus, String, String)> {
+    command.stdout(Stdio::piped());
+    command.stderr(Stdio::piped());
+
+    let (mut child, _group) = try!(process::spawn(command));
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let stdout = Arc::new(Mutex::new(Vec::new()));
+
+    // Read both streams at once so that the child never blocks on a full pipe.
+    let threads = vec![
+        tee(child.stdout.take().unwrap(),
+            Stream::Stdout,
+            vec![captured.clone(), stdout.clone()],
+            prefix.clone()),
+        tee(child.stderr.take().unwrap(), Stream::Stderr, vec![captured.clone()], prefix),
+    ];
+
+    let status = try!(child.wait());
+
+    for thread in threads {
+        thread.join().ok();
+    }
+
+    let output = String::from_utf8_lossy(&captured.lock().unwrap()).into_owned();
+    let stdout = String::from_utf8_lossy(&stdout.lock().unwrap()).into_owned();
+    Ok((status, output, stdout))
+}
+
+/// Copies everything from a reader to the console and some shared buffers in a background thread.
+fn tee<R>(mut reader: R, stream: Stream, buffers: Vec<Arc<Mutex<Vec<u8>>>>, prefix: Option<String>)
+    -> thread::JoinHandle<()>
+    where R: Read + Send + 'static
+{
+    // Keep the output with the rest of the task's output if it is being held back.
+    let console_buffer = console::buffer();
+
+    thread::spawn(move || {
+        console::set_buffer(console_buffer);
+        let mut chunk = [0; 4096];
+        let mut line = Vec::new();
+
+        while let Ok(count) = reader.read(&mut chunk) {
+            if count == 0 {
+                break;
+            }
+
+            for buffer in &buffers {
+                buffer.lock().unwrap().extend_from_slice(&chunk[..count]);
+            }
+
+            match prefix {
+                Some(ref prefix) => {
+                    // Only whole lines are written, so that output from other tasks never ends up
+                    // in the middle of one.
+                    line.extend_from_slice(&chunk[..count]);
+
+                    while let Some(end) = line.iter().position(|&byte| byte == b'\n') {
+                        let rest = line.split_off(end + 1);
+                        write_prefixed(stream, prefix, mem::replace(&mut line, rest));
+                    }
+                }
+                None => console::write_bytes(stream, &chunk[..count]),
+            }
+        }
+
+        // Finish off the last line if the command didn't.
+        if let Some(ref prefix) = prefix {
+            if !line.is_empty() {
+                line.push(b'\n');
+                write_prefixed(stream, prefix, line);
+            }
+        }
+    })
+}
+
+/// Writes a line of command output after the name of the task it belongs to.
+fn write_prefixed(stream: Stream, prefix: &str, line: Vec<u8>) {
+    console::write(stream, vec![
+        Segment::colored(format!("[{}] ", prefix), console::task_color(prefix)),
+        Segment::plain(line),
+    ]);
+}
+
 /// Pipes a string into a shell command with a given list of arguments.
 fn pipe(runtime: Runtime) -> ScriptResult {
     // Create a command for the given program name.
-    let mut command = Command::new(runtime.state().check_string(2));
+    let mut command = program_command(runtime.state().check_string(2));
 
-    // Set the current directory.
-    if let Ok(dir) = env::current_dir() {
-        command.current_dir(dir);
-    }
+    // Set the current directory, or the directory of the included script the command is run by.
+    command.current_dir(runtime.environment().current_dir());
 
     // For each other parameter given, add it as a shell argument.
     for i in 3..runtime.state().get_top()+1 {
@@ -242,7 +884,7 @@ fn pipe(runtime: Runtime) -> ScriptResult {
     command.stderr(Stdio::piped());
 
     // Start running the command process.
-    let mut child = match command.spawn() {
+    let (mut child, _group) = match process::spawn(&mut command) {
         Ok(child) => child,
         Err(e) => return Err(format!("failed to execute process: {}", e).into()),
     };
@@ -255,16 +897,15 @@ fn pipe(runtime: Runtime) -> ScriptResult {
     }
 
     // Wait for the program to finish and collect the output.
-    child.wait_with_output().map_err(|e| {
+    let output = child.wait_with_output();
+
+    // The command may have changed any file.
+    fscache::clear();
+
+    output.map_err(|e| {
         format!("failed to execute process: {}", e).into()
     }).and_then(|output| {
-        unsafe {
-            runtime.state().push_string(str::from_utf8_unchecked(&output.stdout));
-            runtime.state().push_string(str::from_utf8_unchecked(&output.stderr));
-        }
-        runtime.state().push_number(output.status.code().unwrap_or(1) as f64);
-
-        Ok(3)
+        runtime.ret((&output.stdout[..], &output.stderr[..], output.status.code().unwrap_or(1) as f64))
     })
 }
 
@@ -297,6 +938,22 @@ fn env(runtime: Runtime) -> ScriptResult {
     Ok(1)
 }
 
+/// Gets the value of a named argument given on the command line, such as `target=release`.
+///
+/// # Lua arguments
+/// * `name: string` - The argument name.
+/// * `default: any` - Value to return if the argument was not given. (Optional)
+fn var(runtime: Runtime) -> ScriptResult {
+    let name = runtime.state().check_string(1).to_string();
+
+    match runtime.environment().arg(&name) {
+        Some(value) => runtime.state().push(value),
+        None => runtime.state().push_value(2),
+    }
+
+    Ok(1)
+}
+
 /// Exports an environment variable.
 ///
 /// # Lua arguments
@@ -319,42 +976,39 @@ fn glob(runtime: Runtime) -> ScriptResult {
     // Get the pattern as the first argument.
     let pattern = runtime.state().check_string(1).to_string();
 
-    // Make the pattern absolute if it isn't already.
-    let mut full_path = PathBuf::from(pattern);
-    if full_path.is_relative() {
-        let path = full_path;
-        full_path = env::current_dir().unwrap();
-        full_path.push(&path);
-    }
+    // Relative patterns are matched against the current directory, which may not be valid UTF-8
+    // itself, so the matches are made absolute afterwards instead.
+    let directory = if Path::new(&pattern).is_relative() {
+        Some(runtime.environment().current_dir())
+    } else {
+        None
+    };
+
+    let paths = match directory {
+        Some(ref directory) => fscache::glob_in(directory, &pattern),
+        None => fscache::glob(&pattern),
+    };
 
     // Create a table of values to return.
     runtime.state().new_table();
 
-    // Get an iterator for the glob result and return a Lua iterator.
-    if let Ok(mut iter) = glob::glob(&full_path.to_str().unwrap()) {
+    // Get the glob result and return a Lua iterator over it.
+    if let Ok(paths) = paths {
+        let mut iter = paths.into_iter();
+
         runtime.push_closure(Box::new(move |runtime: Runtime| {
-            loop {
-                match iter.next() {
-                    Some(Ok(path)) => {
-                        // Turn the path into a string.
-                        if let Some(path) = path.to_str() {
-                            // Push the path onto the return value list.
-                            runtime.state().push(path);
-                            return Ok(1);
-                        } else {
-                            warn!("bad characters in path from glob");
-                        }
-                    }
-                    Some(Err(_)) => {
-                        // if the path matched but was unreadable,
-                        // thereby preventing its contents from matching
-                        warn!("unreadable path in glob");
-                        // just continue the loop until we find a path we care about
-                    }
-                    None => {
-                        runtime.state().push_nil();
-                        return Ok(1);
+            match iter.next() {
+                Some(path) => {
+                    // Push the path onto the return value list.
+                    match directory {
+                        Some(ref directory) => runtime.push_path(directory.join(path)),
+                        None => runtime.push_path(path),
                     }
+                    Ok(1)
+                }
+                None => {
+                    runtime.state().push_nil();
+                    Ok(1)
                 }
             }
         }));
@@ -427,7 +1081,7 @@ fn print(runtime: Runtime) -> ScriptResult {
         runtime.state().pop(1);
 
         let string = expand_string(&string, runtime.clone());
-        println!("{}", string);
+        console::println(string);
     }
 
     Ok(0)
@@ -441,6 +1095,12 @@ fn set_default_task(runtime: Runtime) -> ScriptResult {
     // Get the task name as the first argument.
     let name = runtime.state().check_string(1).to_string();
 
+    // Only the main script decides what to run by default.
+    if !runtime.environment().namespace().is_empty() {
+        trace!("ignoring default task '{}' of included script", name);
+        return Ok(0);
+    }
+
     // Set the default task to the given name.
     runtime.environment().set_default_task(name);
 
@@ -459,6 +1119,131 @@ fn set_description(runtime: Runtime) -> ScriptResult {
     Ok(0)
 }
 
+/// Finds a task defined so far by name. Inside an included script, tasks of the same script are
+/// found by their name without the namespace.
+fn find_task(runtime: &Runtime, name: &str) -> Option<Rc<NamedTask>> {
+    let namespace = runtime.environment().namespace();
+
+    if !namespace.is_empty() {
+        if let Some(task) = runtime.environment().get_task(format!("{}{}", namespace, name)) {
+            return Some(task);
+        }
+    }
+
+    runtime.environment().get_task(name)
+}
+
+/// Pushes a list of strings onto the stack as a table.
+fn push_string_list(runtime: &Runtime, list: &[String]) {
+    runtime.state().new_table();
+    for (i, item) in list.iter().enumerate() {
+        runtime.state().push_number((i + 1) as f64);
+        runtime.state().push_string(item);
+        runtime.state().set_table(-3);
+    }
+}
+
+/// Checks if a task has been defined.
+///
+/// # Lua arguments
+/// * `name: string` - The name of the task.
+fn task_exists(runtime: Runtime) -> ScriptResult {
+    let name = runtime.state().check_string(1).to_string();
+    let exists = find_task(&runtime, &name).is_some();
+
+    runtime.state().push_bool(exists);
+    Ok(1)
+}
+
+/// Gets information about a task, or nil if it isn't defined.
+///
+/// # Lua arguments
+/// * `name: string` - The name of the task.
+fn task_info(runtime: Runtime) -> ScriptResult {
+    let name = runtime.state().check_string(1).to_string();
+
+    let task = match find_task(&runtime, &name) {
+        Some(task) => task,
+        None => {
+            runtime.state().push_nil();
+            return Ok(1);
+        }
+    };
+
+    runtime.state().new_table();
+
+    runtime.state().push_string(&task.name);
+    runtime.state().set_field(-2, "name");
+
+    if let Some(description) = task.description() {
+        runtime.state().push_string(description);
+        runtime.state().set_field(-2, "description");
+    }
+
+    push_string_list(&runtime, &task.dependencies);
+    runtime.state().set_field(-2, "dependencies");
+
+    Ok(1)
+}
+
+/// Gets a sorted list of the names of all tasks defined so far.
+fn tasks(runtime: Runtime) -> ScriptResult {
+    let mut names: Vec<String> = runtime.environment()
+        .tasks()
+        .iter()
+        .map(|task| task.name.clone())
+        .collect();
+    names.sort();
+
+    push_string_list(&runtime, &names);
+    Ok(1)
+}
+
+/// Reports a problem found by the current task, such as a lint warning.
+///
+/// # Lua arguments
+/// * `diagnostic: table` - A table with the fields `tool`, `message`, and optionally `level`,
+///   `rule`, `file`, `line`, and `column`.
+fn report_diagnostic(runtime: Runtime) -> ScriptResult {
+    runtime.state().check_type(1, lua::Type::Table);
+
+    let string_field = |name: &str| {
+        runtime.state().get_field(1, name);
+        let value = runtime.state().to_str_in_place(-1).map(String::from);
+        runtime.state().pop(1);
+        value
+    };
+
+    let number_field = |name: &str| {
+        runtime.state().get_field(1, name);
+        let value = runtime.state().to_integerx(-1).map(|value| value as u32);
+        runtime.state().pop(1);
+        value
+    };
+
+    let message = match string_field("message") {
+        Some(message) => message,
+        None => return Err("diagnostic message must be specified".into()),
+    };
+
+    let level = string_field("level").unwrap_or("warning".to_string());
+    if level != "error" && level != "warning" && level != "note" {
+        return Err(format!("invalid diagnostic level '{}'", level).into());
+    }
+
+    runtime.environment().add_diagnostic(Diagnostic {
+        tool: string_field("tool").unwrap_or("rote".to_string()),
+        rule: string_field("rule"),
+        level: level,
+        message: message,
+        file: string_field("file"),
+        line: number_field("line"),
+        column: number_field("column"),
+    });
+
+    Ok(0)
+}
+
 /// Returns the current version of Rote as a string.
 fn version(runtime: Runtime) -> ScriptResult {
     runtime.state().push_string(::ROTE_VERSION);
@@ -470,20 +1255,35 @@ fn version(runtime: Runtime) -> ScriptResult {
 pub fn load(runtime: Runtime) {
     // Load the module functions.
     runtime.load_lib(&[
+        ("after", after),
+        ("after_all", after_all),
+        ("before", before),
+        ("before_all", before_all),
         ("change_dir", change_dir),
         ("create_rule", create_rule),
         ("create_task", create_task),
         ("current_dir", current_dir),
         ("current_exe", current_exe),
+        ("dry_run", dry_run),
         ("env", env),
         ("execute", execute),
         ("expand", expand),
         ("export", export),
         ("glob", glob),
+        ("include", include),
         ("merge", merge),
         ("pipe", pipe),
+        ("powershell", powershell),
         ("print", print),
+        ("report_diagnostic", report_diagnostic),
+        ("root_dir", root_dir),
         ("set_default_task", set_default_task),
+        ("sh", sh),
+        ("shell", shell),
+        ("task_exists", task_exists),
+        ("task_info", task_info),
+        ("tasks", tasks),
+        ("var", var),
         ("version", version),
     ]);
     runtime.state().set_global("rote");
@@ -498,7 +1298,10 @@ pub fn load(runtime: Runtime) {
     runtime.register_fn("pipe", pipe);
     runtime.register_fn("print", print);
     runtime.register_fn("rule", create_rule);
+    runtime.register_fn("sh", sh);
+    runtime.register_fn("shell", shell);
     runtime.register_fn("task", create_task);
+    runtime.register_fn("var", var);
 
     // Set up reading global values to fallback to environment variables.
     runtime.state().push_global_table();
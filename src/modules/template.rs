@@ -0,0 +1,126 @@
+use fscache;
+use lua;
+use modules::fs::read_file;
+use regex::{Captures, Regex};
+use runtime::{Runtime, ScriptResult};
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::str;
+
+
+/// Replaces every `{{name}}` in a template with the value of the variable of the same name in the
+/// table at the given index. Names may contain dots to look up values in nested tables.
+fn render_string(runtime: &Runtime, template: &str, index: lua::Index) -> Result<String, Box<Error>> {
+    let pattern = Regex::new(r"\{\{\s*([\w.]+)\s*\}\}").unwrap();
+    let mut error = None;
+
+    let result = pattern.replace_all(template, |caps: &Captures| {
+        let name = caps.at(1).unwrap_or("");
+
+        match lookup(runtime, index, name) {
+            Ok(value) => value,
+            Err(e) => {
+                if error.is_none() {
+                    error = Some(e);
+                }
+                String::new()
+            }
+        }
+    });
+
+    match error {
+        Some(e) => Err(e.into()),
+        None => Ok(result),
+    }
+}
+
+/// Gets the value of a template variable as a string.
+fn lookup(runtime: &Runtime, index: lua::Index, name: &str) -> Result<String, String> {
+    runtime.state().push_value(index);
+
+    for part in name.split('.') {
+        if !runtime.state().is_table(-1) {
+            runtime.state().pop(1);
+            return Err(format!("undefined template variable '{}'", name));
+        }
+
+        runtime.state().get_field(-1, part);
+        runtime.state().remove(-2);
+    }
+
+    let value = match runtime.state().type_of(-1) {
+        Some(lua::Type::String) | Some(lua::Type::Number) => {
+            Ok(runtime.state().to_str_in_place(-1).unwrap_or("").to_string())
+        }
+        Some(lua::Type::Boolean) => Ok(runtime.state().to_bool(-1).to_string()),
+        Some(lua::Type::Nil) | None => Err(format!("undefined template variable '{}'", name)),
+        _ => Err(format!("template variable '{}' is not a string", name)),
+    };
+    runtime.state().pop(1);
+
+    value
+}
+
+/// Renders a template string.
+///
+/// # Lua arguments
+/// * `template: string`        - The template to render.
+/// * `vars: table`             - Values of the variables used in the template.
+///
+/// # Lua returns
+/// * `result: string`          - The rendered template.
+fn render(runtime: Runtime) -> ScriptResult {
+    let template = runtime.state().check_string(1).to_string();
+    if !runtime.state().is_table(2) {
+        return Err("variables must be a table".into());
+    }
+
+    let result = try!(render_string(&runtime, &template, 2));
+    runtime.state().push(result);
+
+    Ok(1)
+}
+
+/// Renders a template file and writes the result to another file.
+///
+/// # Lua arguments
+/// * `src: string`             - Path of the template file.
+/// * `dest: string`            - Path of the file to write.
+/// * `vars: table`             - Values of the variables used in the template.
+fn render_file(runtime: Runtime) -> ScriptResult {
+    let src = runtime.check_file_path(1);
+    let dest = runtime.check_file_path(2);
+    if !runtime.state().is_table(3) {
+        return Err("variables must be a table".into());
+    }
+
+    let contents = match read_file(&src) {
+        Ok(contents) => contents,
+        Err(e) => return Err(format!("failed to read file \"{}\": {}", src.display(), e).into()),
+    };
+    let template = match str::from_utf8(&contents) {
+        Ok(template) => template,
+        Err(_) => return Err(format!("template \"{}\" is not valid UTF-8", src.display()).into()),
+    };
+
+    let result = try!(render_string(&runtime, template, 3));
+    if runtime.skip_change(format!("write \"{}\"", dest.display())) {
+        return Ok(0);
+    }
+
+    fscache::clear();
+    try!(try!(File::create(&dest)).write_all(result.as_bytes()));
+
+    Ok(0)
+}
+
+/// Module loader.
+pub fn load(runtime: Runtime) -> ScriptResult {
+    runtime.load_lib(&[
+        ("render", render),
+        ("render_file", render_file),
+    ]);
+
+    Ok(1)
+}
@@ -0,0 +1,158 @@
+use lua;
+use runtime::{Runtime, ScriptResult};
+use std::collections::BTreeMap;
+use std::error::Error;
+use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
+
+
+/// Parses a YAML string and returns the first document in it.
+///
+/// # Lua arguments
+/// * `yaml: string`            - The YAML to parse.
+/// * `all: boolean`            - If true, a list of every document is returned instead. (Optional)
+fn decode(runtime: Runtime) -> ScriptResult {
+    fn push_value(runtime: &Runtime, value: &Yaml) {
+        match *value {
+            Yaml::Real(_) => {
+                match value.as_f64() {
+                    Some(number) => runtime.state().push_number(number),
+                    None => runtime.state().push_nil(),
+                }
+            },
+            Yaml::Integer(number) => {
+                runtime.state().push_number(number as f64);
+            },
+            Yaml::String(ref string) => {
+                runtime.state().push_string(string);
+            },
+            Yaml::Boolean(value) => {
+                runtime.state().push_bool(value);
+            },
+            Yaml::Array(ref array) => {
+                push_array(runtime, array);
+            },
+            Yaml::Hash(ref hash) => {
+                runtime.state().new_table();
+
+                for (key, value) in hash {
+                    push_value(runtime, key);
+                    // Tables can't have nil keys.
+                    if runtime.state().is_nil(-1) {
+                        runtime.state().pop(1);
+                        continue;
+                    }
+                    push_value(runtime, value);
+                    runtime.state().set_table(-3);
+                }
+            },
+            Yaml::Alias(_) | Yaml::Null | Yaml::BadValue => {
+                runtime.state().push_nil();
+            },
+        }
+    }
+
+    fn push_array(runtime: &Runtime, array: &[Yaml]) {
+        runtime.state().new_table();
+
+        let mut index = 1;
+        for value in array {
+            runtime.state().push_number(index as f64);
+            push_value(runtime, value);
+            runtime.state().set_table(-3);
+
+            index += 1;
+        }
+    }
+
+    let source = runtime.state().check_string(1).to_string();
+    let documents = try!(YamlLoader::load_from_str(&source));
+
+    if runtime.state().to_bool(2) {
+        push_array(&runtime, &documents);
+    } else {
+        match documents.first() {
+            Some(document) => push_value(&runtime, document),
+            None => runtime.state().push_nil(),
+        }
+    }
+
+    Ok(1)
+}
+
+/// Converts a value to a YAML document.
+///
+/// # Lua arguments
+/// * `value: any`              - The value to convert.
+fn encode(runtime: Runtime) -> ScriptResult {
+    fn to_yaml(runtime: &Runtime, index: i32) -> Result<Yaml, Box<Error>> {
+        let lua_type = runtime.state().type_of(index);
+
+        match lua_type {
+            Some(lua::Type::Nil) | None => Ok(Yaml::Null),
+            Some(lua::Type::Boolean) => Ok(Yaml::Boolean(runtime.state().to_bool(index))),
+            Some(lua::Type::Number) => {
+                let number = runtime.state().to_number(index);
+
+                // Whole numbers are written without a fractional part.
+                if number.fract() == 0.0 && number.abs() < 9007199254740992.0 {
+                    Ok(Yaml::Integer(number as i64))
+                } else {
+                    Ok(Yaml::Real(number.to_string()))
+                }
+            },
+            Some(lua::Type::String) => {
+                Ok(Yaml::String(runtime.state().to_str_in_place(index).unwrap_or("").to_string()))
+            },
+            Some(lua::Type::Table) => {
+                // Tables with only sequential numeric keys become arrays, just like in JSON.
+                let mut hash = BTreeMap::new();
+                let mut array = Vec::new();
+                let mut is_array = true;
+
+                for (key, value) in runtime.iter(index) {
+                    if !runtime.state().is_number(key) || runtime.state().to_number(key) as usize != array.len() + 1 {
+                        is_array = false;
+                    }
+
+                    let key = try!(to_yaml(runtime, key));
+                    let value = try!(to_yaml(runtime, value));
+
+                    if is_array {
+                        array.push(value.clone());
+                    }
+                    hash.insert(key, value);
+                }
+
+                Ok(if is_array {
+                    Yaml::Array(array)
+                } else {
+                    Yaml::Hash(hash)
+                })
+            },
+            _ => {
+                Err(format!("cannot convert {} to YAML", runtime.state().typename_of(lua_type.unwrap_or(lua::Type::None))).into())
+            },
+        }
+    }
+
+    let value = try!(to_yaml(&runtime, 1));
+
+    let mut string = String::new();
+    if let Err(e) = YamlEmitter::new(&mut string).dump(&value) {
+        return Err(format!("failed to write YAML: {:?}", e).into());
+    }
+    string.push('\n');
+
+    runtime.state().push_string(&string);
+    Ok(1)
+}
+
+/// Module loader.
+pub fn load(runtime: Runtime) -> ScriptResult {
+    runtime.load_lib(&[
+        ("decode", decode),
+        ("encode", encode),
+    ]);
+
+    Ok(1)
+}
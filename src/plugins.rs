@@ -0,0 +1,249 @@
+//! Loads native plugins that add modules to Rote without recompiling it.
+//!
+//! A plugin is a shared library that exports a function named `rote_module_entry`. When a plugin
+//! is loaded, the function is called with a `Registry`, which the plugin uses to register Lua
+//! modules by name along with the C function that loads each of them. The modules can then be
+//! `require`d by any Rotefile, just like built-in modules.
+//!
+//! Plugins are written against the C API of Lua, so they can be written in Rust or in any other
+//! language that can export C functions. See `include/rote_plugin.h` for the C declarations.
+use lua;
+use lua::libc::{c_char, c_int, c_void};
+use std::env;
+use std::env::consts::DLL_EXTENSION;
+use std::error::Error;
+use std::ffi::CStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+
+/// Version of the plugin interface, passed to plugins in `Registry::version`. It is increased
+/// whenever `Registry` changes in an incompatible way.
+pub const PLUGIN_API_VERSION: c_int = 1;
+
+/// Name of the function every plugin must export.
+pub const ENTRY_SYMBOL: &'static str = "rote_module_entry";
+
+/// Signature of the entry function of a plugin. It should return 0 on success, or any other value
+/// if the plugin failed to initialize.
+pub type Entry = unsafe extern "C" fn(registry: *mut Registry) -> c_int;
+
+/// Signature of the function used to register a module. The name is copied, so it only needs to
+/// live until the function returns.
+pub type RegisterFn = unsafe extern "C" fn(registry: *mut Registry,
+                                           name: *const c_char,
+                                           loader: lua::Function);
+
+
+/// Passed to the entry function of a plugin to register modules with.
+#[repr(C)]
+pub struct Registry {
+    /// Version of the plugin interface of the running Rote.
+    pub version: c_int,
+
+    /// Registers a module. Call it with the registry, the name of the module, and the function
+    /// that loads the module, which is called by `require` and should return the module.
+    pub register: RegisterFn,
+
+    /// Modules registered so far.
+    modules: *mut c_void,
+}
+
+/// A module registered by a plugin.
+#[derive(Clone)]
+pub struct Module {
+    /// Name of the module, as given to `require`.
+    pub name: String,
+
+    /// Function that loads the module.
+    pub loader: lua::Function,
+}
+
+// Module loaders are plain functions, so they can be shared with the runtimes of every thread.
+unsafe impl Send for Module {}
+
+
+/// Gets the directories plugins are loaded from, in the order they are loaded: the system plugin
+/// directory, the plugin directory of the user, and the `rote/plugins` directory of the project.
+pub fn dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    if cfg!(windows) {
+        if let Some(dir) = env::var_os("PROGRAMDATA") {
+            dirs.push(PathBuf::from(dir).join("rote").join("plugins"));
+        }
+        if let Some(dir) = env::var_os("APPDATA") {
+            dirs.push(PathBuf::from(dir).join("rote").join("plugins"));
+        }
+    } else {
+        dirs.push(PathBuf::from("/usr/lib/rote/plugins"));
+
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::home_dir().map(|home| home.join(".local").join("share")));
+        if let Some(dir) = data_home {
+            dirs.push(dir.join("rote").join("plugins"));
+        }
+    }
+
+    dirs.push(PathBuf::from("rote").join("plugins"));
+
+    dirs
+}
+
+/// Loads every plugin in a directory and returns the modules they register. Plugins that fail to
+/// load are skipped with a warning, and a directory that doesn't exist has no plugins.
+pub fn load_dir<P: AsRef<Path>>(dir: P) -> Vec<Module> {
+    let mut paths = match fs::read_dir(dir.as_ref()) {
+        Ok(entries) => {
+            entries.filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file() && path.extension().map_or(false, |ext| ext == DLL_EXTENSION))
+                .collect::<Vec<_>>()
+        }
+        Err(_) => return Vec::new(),
+    };
+
+    // Load plugins in the same order every time.
+    paths.sort();
+
+    let mut modules = Vec::new();
+    for path in paths {
+        match load(&path) {
+            Ok(loaded) => modules.extend(loaded),
+            Err(e) => warn!("failed to load plugin '{}': {}", path.to_string_lossy(), e),
+        }
+    }
+
+    modules
+}
+
+/// Loads a plugin and returns the modules it registers.
+///
+/// Loaded libraries are never unloaded, as their module loaders may be called at any time.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Module>, Box<Error>> {
+    let path = path.as_ref();
+    debug!("loading plugin '{}'", path.to_string_lossy());
+
+    let library = try!(sys::open(path));
+    let entry = try!(sys::symbol(library, ENTRY_SYMBOL));
+    let entry: Entry = unsafe { ::std::mem::transmute(entry) };
+
+    let mut modules: Vec<Module> = Vec::new();
+    let mut registry = Registry {
+        version: PLUGIN_API_VERSION,
+        register: register,
+        modules: &mut modules as *mut Vec<Module> as *mut c_void,
+    };
+
+    let result = unsafe { entry(&mut registry) };
+    if result != 0 {
+        return Err(format!("{} returned error code {}", ENTRY_SYMBOL, result).into());
+    }
+
+    for module in &modules {
+        debug!("plugin '{}' registered module '{}'", path.to_string_lossy(), module.name);
+    }
+
+    Ok(modules)
+}
+
+/// Implementation of `Registry::register`.
+unsafe extern "C" fn register(registry: *mut Registry, name: *const c_char, loader: lua::Function) {
+    if registry.is_null() || name.is_null() || loader.is_none() {
+        return;
+    }
+
+    let modules = &mut *((*registry).modules as *mut Vec<Module>);
+    modules.push(Module {
+        name: CStr::from_ptr(name).to_string_lossy().into_owned(),
+        loader: loader,
+    });
+}
+
+#[cfg(unix)]
+mod sys {
+    use lua::libc::{c_char, c_int, c_void};
+    use std::error::Error;
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    const RTLD_NOW: c_int = 2;
+
+    extern "C" {
+        fn dlopen(filename: *const c_char, flag: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+        fn dlerror() -> *mut c_char;
+    }
+
+    pub fn open(path: &Path) -> Result<*mut c_void, Box<Error>> {
+        let path = try!(CString::new(path.as_os_str().as_bytes()));
+        let handle = unsafe { dlopen(path.as_ptr(), RTLD_NOW) };
+
+        if handle.is_null() {
+            Err(last_error().into())
+        } else {
+            Ok(handle)
+        }
+    }
+
+    pub fn symbol(handle: *mut c_void, name: &str) -> Result<*mut c_void, Box<Error>> {
+        let name = try!(CString::new(name));
+        let symbol = unsafe { dlsym(handle, name.as_ptr()) };
+
+        if symbol.is_null() {
+            Err(last_error().into())
+        } else {
+            Ok(symbol)
+        }
+    }
+
+    fn last_error() -> String {
+        unsafe {
+            let error = dlerror();
+            if error.is_null() {
+                String::from("unknown error")
+            } else {
+                CStr::from_ptr(error).to_string_lossy().into_owned()
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use lua::libc::{c_char, c_void};
+    use std::error::Error;
+    use std::ffi::CString;
+    use std::io;
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Path;
+
+    extern "system" {
+        fn LoadLibraryW(filename: *const u16) -> *mut c_void;
+        fn GetProcAddress(module: *mut c_void, name: *const c_char) -> *mut c_void;
+    }
+
+    pub fn open(path: &Path) -> Result<*mut c_void, Box<Error>> {
+        let path: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        let handle = unsafe { LoadLibraryW(path.as_ptr()) };
+
+        if handle.is_null() {
+            Err(io::Error::last_os_error().into())
+        } else {
+            Ok(handle)
+        }
+    }
+
+    pub fn symbol(handle: *mut c_void, name: &str) -> Result<*mut c_void, Box<Error>> {
+        let name = try!(CString::new(name));
+        let symbol = unsafe { GetProcAddress(handle, name.as_ptr()) };
+
+        if symbol.is_null() {
+            Err(io::Error::last_os_error().into())
+        } else {
+            Ok(symbol)
+        }
+    }
+}
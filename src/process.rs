@@ -0,0 +1,322 @@
+//! Starts child processes so that they can be stopped along with everything they started.
+//!
+//! Commands run by tasks often start processes of their own, such as compiler drivers or test
+//! servers. On Unix, each command is started in a process group of its own, and the whole group is
+//! killed when a run is interrupted or stops early because a task failed, or when a task takes
+//! longer than its timeout. On Windows, the process tree of each command is killed instead.
+use lock;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io;
+use std::process::{self, Child, Command};
+use std::sync::{mpsc, Mutex, Once, ONCE_INIT};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+
+/// Exit code used when Rote is interrupted a second time while stopping.
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// How long commands are given to exit after being asked to stop before they are killed, in
+/// milliseconds.
+const STOP_GRACE_PERIOD: u64 = 5000;
+
+static INIT: Once = ONCE_INIT;
+static mut GROUPS: *const Mutex<HashSet<u32>> = 0 as *const Mutex<HashSet<u32>>;
+
+/// Number of times Rote has been interrupted.
+static INTERRUPTS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+thread_local! {
+    /// Tracks the processes started by the task running on the current thread, if it has a
+    /// timeout.
+    static TRACKER: RefCell<Option<Arc<Tracker>>> = RefCell::new(None);
+}
+
+/// Gets the IDs of running child processes that lead a group.
+fn groups() -> &'static Mutex<HashSet<u32>> {
+    unsafe {
+        INIT.call_once(|| {
+            GROUPS = Box::into_raw(Box::new(Mutex::new(HashSet::new())));
+        });
+
+        &*GROUPS
+    }
+}
+
+/// A running child process that will be killed by `kill_all()`. The process is forgotten when this
+/// is dropped, so it should be kept until the process has been waited for.
+pub struct Group(u32, Option<Arc<Tracker>>);
+
+impl Group {
+    /// Kills the process, along with any processes it started.
+    pub fn kill(&self) {
+        kill_group(self.0);
+    }
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        groups().lock().unwrap().remove(&self.0);
+
+        if let Some(ref tracker) = self.1 {
+            tracker.groups.lock().unwrap().remove(&self.0);
+        }
+    }
+}
+
+/// Starts a command in a new process group.
+pub fn spawn(command: &mut Command) -> io::Result<(Child, Group)> {
+    set_group(command);
+    lock::mark_held(command);
+    let child = try!(command.spawn());
+    let id = child.id();
+
+    groups().lock().unwrap().insert(id);
+
+    // Commands started by a task that has already timed out are stopped right away.
+    let tracker = TRACKER.with(|tracker| tracker.borrow().clone());
+    if let Some(ref tracker) = tracker {
+        tracker.groups.lock().unwrap().insert(id);
+
+        if tracker.expired.load(Ordering::SeqCst) {
+            kill_group(id);
+        }
+    }
+
+    Ok((child, Group(id, tracker)))
+}
+
+/// The processes started by a single task.
+pub struct Tracker {
+    groups: Mutex<HashSet<u32>>,
+    expired: AtomicBool,
+}
+
+/// Kills the commands started on the current thread once a timeout expires. Commands started after
+/// the timeout expires are killed right away.
+pub struct Watchdog {
+    tracker: Arc<Tracker>,
+    sender: mpsc::Sender<()>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Watchdog {
+    /// Starts tracking the commands started on the current thread.
+    pub fn start(timeout: Duration) -> Watchdog {
+        let tracker = Arc::new(Tracker {
+            groups: Mutex::new(HashSet::new()),
+            expired: AtomicBool::new(false),
+        });
+        TRACKER.with(|current| *current.borrow_mut() = Some(tracker.clone()));
+
+        let (sender, receiver) = mpsc::channel();
+        let thread = {
+            let tracker = tracker.clone();
+
+            thread::spawn(move || {
+                if receiver.recv_timeout(timeout) == Err(mpsc::RecvTimeoutError::Timeout) {
+                    tracker.expired.store(true, Ordering::SeqCst);
+
+                    let groups: Vec<u32> = tracker.groups.lock().unwrap().iter().cloned().collect();
+                    for id in groups {
+                        debug!("killing process group {} after timeout", id);
+                        kill_group(id);
+                    }
+                }
+            })
+        };
+
+        Watchdog {
+            tracker: tracker,
+            sender: sender,
+            thread: thread,
+        }
+    }
+
+    /// Stops tracking commands, and returns true if the timeout expired.
+    pub fn finish(self) -> bool {
+        TRACKER.with(|current| *current.borrow_mut() = None);
+
+        drop(self.sender);
+        self.thread.join().ok();
+
+        self.tracker.expired.load(Ordering::SeqCst)
+    }
+}
+
+/// Asks all running child processes to stop, along with any processes they started. On Windows,
+/// processes can't be asked to stop, so they are killed instead.
+pub fn terminate_all() {
+    let groups: Vec<u32> = groups().lock().unwrap().iter().cloned().collect();
+
+    for id in groups {
+        debug!("stopping process group {}", id);
+        terminate_group(id);
+    }
+}
+
+/// Kills all running child processes, along with any processes they started.
+pub fn kill_all() {
+    let groups: Vec<u32> = groups().lock().unwrap().iter().cloned().collect();
+
+    for id in groups {
+        debug!("killing process group {}", id);
+        kill_group(id);
+    }
+}
+
+/// Checks if Rote has been interrupted.
+pub fn interrupted() -> bool {
+    INTERRUPTS.load(Ordering::SeqCst) > 0
+}
+
+/// Stops the current run when Rote is interrupted, by setting the given flag and asking all child
+/// processes to stop. Commands that are still running after a grace period are killed, so that
+/// they can clean up after themselves but can't keep Rote waiting. If Rote is interrupted again
+/// while stopping, it exits right away.
+pub fn handle_interrupts(cancelled: Arc<AtomicBool>) {
+    if !install_handler() {
+        warn!("failed to install interrupt handler");
+        return;
+    }
+
+    // The signal handler can't do much safely, so the work is done by a thread that watches for
+    // interrupts instead.
+    thread::spawn(move || {
+        let mut handled = 0;
+        let mut grace = None;
+
+        loop {
+            thread::sleep(Duration::from_millis(50));
+
+            if grace.map_or(false, |started: Instant| started.elapsed() >= Duration::from_millis(STOP_GRACE_PERIOD)) {
+                grace = None;
+                kill_all();
+            }
+
+            let interrupts = INTERRUPTS.load(Ordering::SeqCst);
+            if interrupts == handled {
+                continue;
+            }
+            handled = interrupts;
+
+            if interrupts == 1 {
+                warn!("interrupted, stopping running tasks...");
+                cancelled.store(true, Ordering::SeqCst);
+                terminate_all();
+                grace = Some(Instant::now());
+            } else {
+                kill_all();
+                process::exit(EXIT_INTERRUPTED);
+            }
+        }
+    });
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::os::raw::c_int;
+
+    pub type Pid = c_int;
+
+    pub const SIGHUP: c_int = 1;
+    pub const SIGINT: c_int = 2;
+    pub const SIGKILL: c_int = 9;
+    pub const SIGTERM: c_int = 15;
+    pub const SIG_ERR: usize = !0;
+
+    extern "C" {
+        pub fn kill(pid: Pid, signal: c_int) -> c_int;
+        pub fn setpgid(pid: Pid, pgid: Pid) -> c_int;
+        pub fn signal(signal: c_int, handler: usize) -> usize;
+    }
+}
+
+/// Makes a command start in a process group of its own, whose ID is the ID of the process. The
+/// child moves itself into the group before running the program, so the group exists by the time
+/// the command has been started, and every process the program starts is in it. The command fails
+/// to start if the group can't be created.
+#[cfg(unix)]
+fn set_group(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    unsafe {
+        command.before_exec(|| {
+            if sys::setpgid(0, 0) == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        });
+    }
+}
+
+#[cfg(windows)]
+fn set_group(_: &mut Command) {}
+
+#[cfg(unix)]
+fn terminate_group(id: u32) {
+    unsafe {
+        sys::kill(-(id as sys::Pid), sys::SIGTERM);
+    }
+}
+
+#[cfg(windows)]
+fn terminate_group(id: u32) {
+    kill_group(id);
+}
+
+#[cfg(unix)]
+fn kill_group(id: u32) {
+    unsafe {
+        sys::kill(-(id as sys::Pid), sys::SIGKILL);
+    }
+}
+
+/// Windows has no process groups that can be killed at once, so `taskkill` is used to kill the
+/// process tree instead.
+#[cfg(windows)]
+fn kill_group(id: u32) {
+    let result = Command::new("taskkill")
+        .arg("/F")
+        .arg("/T")
+        .arg("/PID")
+        .arg(id.to_string())
+        .output();
+
+    if let Err(e) = result {
+        warn!("failed to kill process {}: {}", id, e);
+    }
+}
+
+#[cfg(unix)]
+fn install_handler() -> bool {
+    use std::os::raw::c_int;
+
+    extern "C" fn on_signal(_: c_int) {
+        INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // Commands no longer receive signals sent to our process group by the terminal, so we have to
+    // handle hangups and termination requests as well.
+    [sys::SIGHUP, sys::SIGINT, sys::SIGTERM].iter().all(|&signal| unsafe {
+        sys::signal(signal, on_signal as extern "C" fn(c_int) as usize) != sys::SIG_ERR
+    })
+}
+
+#[cfg(windows)]
+fn install_handler() -> bool {
+    extern "system" fn on_control(_: u32) -> i32 {
+        INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+        1
+    }
+
+    extern "system" {
+        fn SetConsoleCtrlHandler(handler: extern "system" fn(u32) -> i32, add: i32) -> i32;
+    }
+
+    unsafe { SetConsoleCtrlHandler(on_control, 1) != 0 }
+}
@@ -0,0 +1,309 @@
+//! Fetches modules from Git repositories when they are required.
+//!
+//! A module named like `github.com/user/repo/path/to/module` is found in the Git repository
+//! `https://github.com/user/repo.git`, in the file `path/to/module.lua` or
+//! `path/to/module/init.lua`. A version can be given after an `@`, such as
+//! `github.com/user/repo/module@v1.2.0`, which can be any tag, branch, or commit; without one, the
+//! default branch of the repository is used.
+//!
+//! Repositories are cloned into a cache shared by every project, with one checkout per commit. The
+//! commit each repository and version resolved to is recorded in `rote.lock` next to the
+//! Rotefile, so that the same commits are used by every later build until the entry is removed.
+use runtime::{Runtime, ScriptResult};
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, Once, ONCE_INIT};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+
+/// Name of the lock file recording the commits of remote modules.
+pub const LOCK_FILE: &'static str = "rote.lock";
+
+static INIT: Once = ONCE_INIT;
+static mut FETCH_LOCK: *const Mutex<()> = 0 as *const Mutex<()>;
+
+/// Gets a lock that is held while fetching modules, so that runtimes on other threads don't clone
+/// the same repository at the same time.
+fn fetch_lock() -> &'static Mutex<()> {
+    unsafe {
+        INIT.call_once(|| {
+            FETCH_LOCK = Box::into_raw(Box::new(Mutex::new(())));
+        });
+
+        &*FETCH_LOCK
+    }
+}
+
+
+/// A reference to a module in a remote repository.
+#[derive(Debug, PartialEq)]
+pub struct ModuleRef {
+    /// The repository, such as `github.com/user/repo`.
+    pub repository: String,
+
+    /// Path of the module inside the repository, without an extension. Empty for the module at
+    /// the root of the repository.
+    pub path: String,
+
+    /// The requested tag, branch, or commit, if any.
+    pub version: Option<String>,
+}
+
+impl ModuleRef {
+    /// Parses a module name, returning `None` if it doesn't name a remote module.
+    pub fn parse(name: &str) -> Option<ModuleRef> {
+        let (name, version) = match name.rfind('@') {
+            Some(at) => (&name[..at], Some(name[at + 1..].to_string())),
+            None => (name, None),
+        };
+
+        if version.as_ref().map_or(false, |version| version.is_empty()) {
+            return None;
+        }
+
+        let parts: Vec<&str> = name.split('/').collect();
+
+        // A remote module starts with a host name, followed by the owner and name of the repository.
+        if parts.len() < 3 || !parts[0].contains('.') || parts.iter().any(|part| {
+            part.is_empty() || *part == "." || *part == ".."
+        }) {
+            return None;
+        }
+
+        Some(ModuleRef {
+            repository: parts[..3].join("/"),
+            path: parts[3..].join("/"),
+            version: version,
+        })
+    }
+
+    /// Gets the URL to clone the repository from.
+    pub fn url(&self) -> String {
+        format!("https://{}.git", self.repository)
+    }
+
+    /// Gets the key of the module's repository and version in the lock file.
+    fn lock_key(&self) -> String {
+        match self.version {
+            Some(ref version) => format!("{}@{}", self.repository, version),
+            None => self.repository.clone(),
+        }
+    }
+}
+
+
+/// Adds a searcher for remote modules to `package.searchers`, right after the searcher for
+/// preloaded modules so that built-in modules are still found first.
+pub fn register(runtime: &Runtime) {
+    runtime.state().get_global("package");
+    runtime.state().get_field(-1, "searchers");
+
+    let count = runtime.state().raw_len(-1) as i64;
+    for i in (2..count + 1).rev() {
+        runtime.state().raw_geti(-1, i);
+        runtime.state().raw_seti(-2, i + 1);
+    }
+
+    runtime.push_fn(search);
+    runtime.state().raw_seti(-2, 2);
+    runtime.state().pop(2);
+}
+
+/// Searcher for remote modules. Returns a loader for the module and the path it was loaded from,
+/// or nothing if the name isn't a remote module.
+fn search(runtime: Runtime) -> ScriptResult {
+    let name = runtime.state().check_string(1).to_string();
+    let module = match ModuleRef::parse(&name) {
+        Some(module) => module,
+        None => return Ok(0),
+    };
+
+    let path = try!(resolve(&module, runtime.environment().directory()));
+
+    let mut source = Vec::new();
+    try!(try!(File::open(&path)).read_to_end(&mut source));
+
+    if runtime.state().load_buffer(&source, &format!("@{}", path.to_string_lossy())).is_err() {
+        return Err(runtime.state().to_str(-1).unwrap().into());
+    }
+    runtime.push_path(&path);
+
+    Ok(2)
+}
+
+/// Finds the file of a remote module, fetching its repository if it isn't cached yet.
+pub fn resolve(module: &ModuleRef, project_dir: &Path) -> Result<PathBuf, Box<Error>> {
+    let _guard = fetch_lock().lock().unwrap();
+
+    let lock_path = project_dir.join(LOCK_FILE);
+    let mut locked = try!(read_lock_file(&lock_path));
+    let key = module.lock_key();
+
+    let repository_dir = cache_dir().join(&module.repository);
+    let commit = match locked.get(&key).cloned() {
+        Some(commit) => {
+            if !repository_dir.join(&commit).is_dir() {
+                try!(fetch(module, Some(&commit), &repository_dir));
+            }
+            commit
+        }
+        None => {
+            let commit = try!(fetch(module, module.version.as_ref().map(|v| v.as_str()), &repository_dir));
+
+            locked.insert(key, commit.clone());
+            try!(write_lock_file(&lock_path, &locked));
+
+            commit
+        }
+    };
+
+    let checkout = repository_dir.join(&commit);
+    let base = if module.path.is_empty() {
+        checkout.join("init")
+    } else {
+        checkout.join(&module.path)
+    };
+
+    let file = base.with_extension("lua");
+    if file.is_file() {
+        return Ok(file);
+    }
+
+    let init = base.join("init.lua");
+    if init.is_file() {
+        return Ok(init);
+    }
+
+    Err(format!("module '{}' not found in {} at commit {}", module.path, module.repository, commit).into())
+}
+
+/// Clones a repository into the cache and checks out a version, returning the commit it resolved
+/// to. The checkout is moved to a directory named after the commit.
+fn fetch(module: &ModuleRef, version: Option<&str>, repository_dir: &Path) -> Result<String, Box<Error>> {
+    info!("fetching {} {}", module.repository, version.unwrap_or("(default branch)"));
+
+    try!(fs::create_dir_all(repository_dir));
+
+    // Clone into a temporary directory first, so that an interrupted fetch never leaves a partial
+    // checkout where a complete one is expected.
+    let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let temp = repository_dir.join(format!(".fetch-{}", stamp));
+    if temp.exists() {
+        try!(fs::remove_dir_all(&temp));
+    }
+
+    let result = fetch_into(module, version, &temp).and_then(|commit| {
+        let dest = repository_dir.join(&commit);
+        if dest.is_dir() {
+            try!(fs::remove_dir_all(&temp));
+        } else {
+            try!(fs::rename(&temp, &dest));
+        }
+        Ok(commit)
+    });
+
+    if result.is_err() && temp.exists() {
+        let _ = fs::remove_dir_all(&temp);
+    }
+
+    result
+}
+
+/// Clones a repository into a directory, checks out a version, and returns its commit.
+fn fetch_into(module: &ModuleRef, version: Option<&str>, dir: &Path) -> Result<String, Box<Error>> {
+    try!(git(None, &["clone", "--quiet", &module.url(), &dir.to_string_lossy()]));
+
+    if let Some(version) = version {
+        try!(git(Some(dir), &["checkout", "--quiet", version]));
+    }
+
+    let commit = try!(git(Some(dir), &["rev-parse", "HEAD"]));
+
+    Ok(commit.trim().to_string())
+}
+
+/// Runs a Git command and returns its output.
+fn git(dir: Option<&Path>, args: &[&str]) -> Result<String, Box<Error>> {
+    let mut command = Command::new(env::var("GIT").unwrap_or(String::from("git")));
+    command.args(args);
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+
+    let output = try!(command.output());
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}",
+                           args[0],
+                           String::from_utf8_lossy(&output.stderr).trim())
+            .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Gets the directory remote modules are cached in.
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("ROTE_MODULE_CACHE") {
+        return PathBuf::from(dir);
+    }
+
+    let cache_home = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::home_dir().map(|home| home.join(".cache")))
+    };
+
+    cache_home.unwrap_or_else(env::temp_dir).join("rote").join("modules")
+}
+
+/// Reads the commits recorded in a lock file. A missing lock file has no commits.
+///
+/// Each line of a lock file has a repository, optionally followed by `@` and a version, and the
+/// commit it resolved to, separated by a space.
+fn read_lock_file(path: &Path) -> Result<BTreeMap<String, String>, Box<Error>> {
+    let mut contents = String::new();
+    match File::open(path) {
+        Ok(mut file) => try!(file.read_to_string(&mut contents)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut locked = BTreeMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(key), Some(commit), None) => {
+                locked.insert(key.to_string(), commit.to_string());
+            }
+            _ => {
+                return Err(format!("{}:{}: invalid entry", path.to_string_lossy(), number + 1).into());
+            }
+        }
+    }
+
+    Ok(locked)
+}
+
+/// Writes a lock file, sorted so that it changes as little as possible.
+fn write_lock_file(path: &Path, locked: &BTreeMap<String, String>) -> io::Result<()> {
+    let mut file = try!(File::create(path));
+    try!(writeln!(file, "# Commits of remote modules used by the Rotefile. Remove an entry to update it."));
+
+    for (key, commit) in locked {
+        try!(writeln!(file, "{} {}", key, commit));
+    }
+
+    Ok(())
+}
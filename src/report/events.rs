@@ -0,0 +1,157 @@
+use console;
+use json::JsonValue;
+use report::{self, Reporter, TaskReport};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+/// A shared stream of newline-delimited JSON events.
+///
+/// Streams can be cloned and written to from any thread; each event is written as a single line.
+#[derive(Clone)]
+pub struct EventStream {
+    out: Arc<Mutex<Box<Write + Send>>>,
+
+    /// If set, events are wrapped in JSON-RPC notifications for this method.
+    method: Option<&'static str>,
+}
+
+impl EventStream {
+    /// Creates a stream that writes to the given writer.
+    pub fn new<W: Write + Send + 'static>(out: W) -> EventStream {
+        EventStream {
+            out: Arc::new(Mutex::new(Box::new(out))),
+            method: None,
+        }
+    }
+
+    /// Creates a stream that writes to standard output.
+    ///
+    /// Anything else that would be written to standard output, such as the output of tasks, is
+    /// written to standard error instead, so that standard output only contains events.
+    pub fn stdout() -> EventStream {
+        console::redirect_stdout();
+        EventStream::new(io::stdout())
+    }
+
+    /// Creates a stream that writes to a file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<EventStream> {
+        let file = try!(File::create(path));
+        Ok(EventStream::new(file))
+    }
+
+    /// Gets a stream to the same output that sends events as JSON-RPC notifications.
+    pub fn notifications(&self, method: &'static str) -> EventStream {
+        EventStream {
+            out: self.out.clone(),
+            method: Some(method),
+        }
+    }
+
+    /// Writes a single JSON value as a line.
+    pub fn write(&self, value: &JsonValue) -> io::Result<()> {
+        let mut out = self.out.lock().unwrap();
+        try!(writeln!(out, "{}", value.dump()));
+        out.flush()
+    }
+
+    /// Writes an event of the given type. The event type and a timestamp are added to the data.
+    pub fn emit(&self, event: &str, mut data: JsonValue) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(report::seconds)
+            .unwrap_or(0.0);
+
+        data["event"] = event.into();
+        data["time"] = time.into();
+
+        if let Some(method) = self.method {
+            let mut notification = object! {
+                "jsonrpc" => "2.0",
+                "method" => method
+            };
+            notification["params"] = data;
+            data = notification;
+        }
+
+        self.write(&data).ok();
+    }
+}
+
+/// Writes run and task events to an event stream.
+pub struct EventReporter {
+    stream: EventStream,
+    succeeded: usize,
+    failed: usize,
+}
+
+impl EventReporter {
+    pub fn new(stream: EventStream) -> EventReporter {
+        EventReporter {
+            stream: stream,
+            succeeded: 0,
+            failed: 0,
+        }
+    }
+}
+
+impl Reporter for EventReporter {
+    fn wants_output(&self) -> bool {
+        true
+    }
+
+    fn run_started(&mut self, tasks: &[String]) {
+        self.stream.emit("run_started", object! {
+            "tasks" => tasks.len()
+        });
+
+        for name in tasks {
+            self.stream.emit("task_scheduled", object! {
+                "task" => name.as_str()
+            });
+        }
+    }
+
+    fn task_skipped(&mut self, name: &str) {
+        self.stream.emit("task_skipped", object! {
+            "task" => name,
+            "reason" => "up to date"
+        });
+    }
+
+    fn task_started(&mut self, name: &str) {
+        self.stream.emit("task_started", object! {
+            "task" => name
+        });
+    }
+
+    fn task_finished(&mut self, report: &TaskReport) {
+        if report.error.is_some() {
+            self.failed += 1;
+        } else {
+            self.succeeded += 1;
+        }
+
+        self.stream.emit("task_finished", object! {
+            "task" => report.name.as_str(),
+            "success" => report.error.is_none(),
+            "duration" => report::seconds(report.duration),
+            "error" => report.error.as_ref().map(|e| e.as_str()),
+            "output" => report.output.as_str()
+        });
+    }
+
+    fn run_finished(&mut self, duration: Duration, success: bool) -> io::Result<()> {
+        self.stream.emit("run_finished", object! {
+            "success" => success,
+            "duration" => report::seconds(duration),
+            "succeeded" => self.succeeded,
+            "failed" => self.failed
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,87 @@
+use console;
+use report::{self, Reporter, TaskReport};
+use std::env;
+use std::path::Path;
+
+
+/// Writes GitHub Actions workflow commands so that task output is grouped into collapsible sections
+/// and failures are shown as annotations.
+///
+/// GitHub does not support nested or overlapping groups, so when running tasks in parallel only one
+/// task is grouped at a time.
+pub struct GithubReporter {
+    /// Name of the task whose group is currently open.
+    group: Option<String>,
+}
+
+impl GithubReporter {
+    pub fn new() -> GithubReporter {
+        GithubReporter {
+            group: None,
+        }
+    }
+}
+
+impl Reporter for GithubReporter {
+    fn task_started(&mut self, name: &str) {
+        if self.group.is_none() {
+            console::println(format!("::group::{}", escape_data(name)));
+            self.group = Some(name.to_string());
+        }
+    }
+
+    fn task_finished(&mut self, report: &TaskReport) {
+        if self.group.as_ref() == Some(&report.name) {
+            console::println("::endgroup::");
+            self.group = None;
+        }
+
+        if let Some(ref error) = report.error {
+            match report::error_location(error) {
+                Some((file, line, message)) => {
+                    let title = format!("task '{}' failed", report.name);
+                    console::println(format!("::error file={},line={},title={}::{}",
+                                             escape_property(&relative_path(&file)),
+                                             line,
+                                             escape_property(&title),
+                                             escape_data(&message)));
+                }
+                None => {
+                    let title = format!("task '{}' failed", report.name);
+                    console::println(format!("::error title={}::{}",
+                                             escape_property(&title),
+                                             escape_data(error)));
+                }
+            }
+        }
+    }
+}
+
+/// Makes a path relative to the workspace so that GitHub can link to it.
+fn relative_path(file: &str) -> String {
+    let root = env::var("GITHUB_WORKSPACE").ok()
+        .map(|root| Path::new(&root).to_path_buf())
+        .or_else(|| env::current_dir().ok());
+
+    if let Some(root) = root {
+        if let Ok(path) = Path::new(file).strip_prefix(&root) {
+            return path.to_string_lossy().into_owned();
+        }
+    }
+
+    file.to_string()
+}
+
+/// Escapes the message of a workflow command.
+fn escape_data(value: &str) -> String {
+    value.replace("%", "%25")
+        .replace("\r", "%0D")
+        .replace("\n", "%0A")
+}
+
+/// Escapes a property value of a workflow command.
+fn escape_property(value: &str) -> String {
+    escape_data(value)
+        .replace(":", "%3A")
+        .replace(",", "%2C")
+}
@@ -0,0 +1,31 @@
+use console;
+use report::{self, Reporter, TaskReport};
+
+
+/// Writes plain-text markers around each task for Jenkins.
+///
+/// Jenkins has no service messages of its own, but these markers are easy to match with plugins
+/// such as Collapsing Console Sections or Build Failure Analyzer.
+pub struct JenkinsReporter;
+
+impl Reporter for JenkinsReporter {
+    fn task_started(&mut self, name: &str) {
+        console::println(format!("[rote] start: {}", name));
+    }
+
+    fn task_finished(&mut self, report: &TaskReport) {
+        match report.error {
+            Some(ref error) => {
+                console::println(format!("[rote] failed: {} ({:.3}s): {}",
+                                         report.name,
+                                         report::seconds(report.duration),
+                                         error));
+            }
+            None => {
+                console::println(format!("[rote] finished: {} ({:.3}s)",
+                                         report.name,
+                                         report::seconds(report.duration)));
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use report::{self, Reporter, TaskReport};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+
+/// Writes a JUnit XML report where each task is recorded as a test case.
+pub struct JunitReporter {
+    /// Path of the file to write.
+    path: PathBuf,
+
+    /// Reports of all tasks that have been run.
+    tasks: Vec<TaskReport>,
+}
+
+impl JunitReporter {
+    pub fn new<P: Into<PathBuf>>(path: P) -> JunitReporter {
+        JunitReporter {
+            path: path.into(),
+            tasks: Vec::new(),
+        }
+    }
+
+    fn write<W: Write>(&self, out: &mut W, duration: Duration) -> io::Result<()> {
+        let failures = self.tasks.iter().filter(|task| task.error.is_some()).count();
+
+        try!(writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+        try!(writeln!(out, "<testsuites name=\"rote\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">",
+                      self.tasks.len(),
+                      failures,
+                      report::seconds(duration)));
+        try!(writeln!(out, "  <testsuite name=\"rote\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"0\" time=\"{:.3}\">",
+                      self.tasks.len(),
+                      failures,
+                      report::seconds(duration)));
+
+        for task in &self.tasks {
+            try!(write!(out, "    <testcase name=\"{}\" classname=\"rote\" time=\"{:.3}\">",
+                        escape(&task.name),
+                        report::seconds(task.duration)));
+
+            if let Some(ref error) = task.error {
+                try!(write!(out, "\n      <failure message=\"{}\">{}</failure>", escape(error), escape(error)));
+            }
+
+            if !task.output.is_empty() {
+                try!(write!(out, "\n      <system-out>{}</system-out>", escape(&task.output)));
+            }
+
+            if task.error.is_some() || !task.output.is_empty() {
+                try!(write!(out, "\n    "));
+            }
+
+            try!(writeln!(out, "</testcase>"));
+        }
+
+        try!(writeln!(out, "  </testsuite>"));
+        try!(writeln!(out, "</testsuites>"));
+
+        Ok(())
+    }
+}
+
+impl Reporter for JunitReporter {
+    fn wants_output(&self) -> bool {
+        true
+    }
+
+    fn task_finished(&mut self, report: &TaskReport) {
+        self.tasks.push(report.clone());
+    }
+
+    fn run_finished(&mut self, duration: Duration, _success: bool) -> io::Result<()> {
+        let mut file = try!(File::create(&self.path));
+        self.write(&mut file, duration)
+    }
+}
+
+/// Escapes a string for use in XML text and attribute values.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters are not allowed in XML 1.0.
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
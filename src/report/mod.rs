@@ -0,0 +1,170 @@
+use regex::Regex;
+use std::env;
+use std::error::Error;
+use std::io;
+use std::time::Duration;
+
+mod events;
+mod github;
+mod jenkins;
+mod junit;
+mod otel;
+mod sarif;
+mod status;
+mod summary;
+mod teamcity;
+
+pub use self::events::{EventReporter, EventStream};
+pub use self::github::GithubReporter;
+pub use self::jenkins::JenkinsReporter;
+pub use self::junit::JunitReporter;
+pub use self::otel::OtelReporter;
+pub use self::sarif::SarifReporter;
+pub use self::status::StatusReporter;
+pub use self::summary::SummaryReporter;
+pub use self::teamcity::TeamcityReporter;
+
+
+/// The outcome of a single task that was run.
+#[derive(Clone)]
+pub struct TaskReport {
+    /// The name of the task.
+    pub name: String,
+
+    /// How long the task took to run.
+    pub duration: Duration,
+
+    /// The error message if the task failed.
+    pub error: Option<String>,
+
+    /// Output captured from the task, if output capturing is enabled.
+    pub output: String,
+
+    /// Diagnostics reported by the task.
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A problem found by a task, such as a compiler error or a lint warning.
+#[derive(Clone)]
+pub struct Diagnostic {
+    /// Name of the tool that found the problem.
+    pub tool: String,
+
+    /// Identifier of the rule that was violated, if any.
+    pub rule: Option<String>,
+
+    /// Severity of the problem; one of `error`, `warning`, or `note`.
+    pub level: String,
+
+    /// Description of the problem.
+    pub message: String,
+
+    /// File the problem was found in.
+    pub file: Option<String>,
+
+    /// Line number the problem was found on, starting at 1.
+    pub line: Option<u32>,
+
+    /// Column number the problem was found at, starting at 1.
+    pub column: Option<u32>,
+}
+
+/// Receives events from the runner as tasks are run.
+///
+/// All methods are called from the master thread, so reporters do not need to be thread-safe.
+pub trait Reporter {
+    /// Checks if the reporter needs the output of tasks to be captured.
+    fn wants_output(&self) -> bool {
+        false
+    }
+
+    /// Called before any tasks are run with the names of all scheduled tasks.
+    fn run_started(&mut self, _tasks: &[String]) {}
+
+    /// Called after the run has started for each task that is not run because it is up to date.
+    fn task_skipped(&mut self, _name: &str) {}
+
+    /// Called when a worker starts running a task.
+    fn task_started(&mut self, _name: &str) {}
+
+    /// Called when a task finishes running, successfully or not.
+    fn task_finished(&mut self, _report: &TaskReport) {}
+
+    /// Called once the run is complete or has been aborted.
+    fn run_finished(&mut self, _duration: Duration, _success: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates a reporter from a `FORMAT=FILE` specification given on the command line.
+pub fn create(spec: &str) -> Result<Box<Reporter>, Box<Error>> {
+    let mut parts = spec.splitn(2, '=');
+    let format = parts.next().unwrap_or("");
+    let path = parts.next();
+
+    match (format, path) {
+        ("junit", Some(path)) => Ok(Box::new(JunitReporter::new(path))),
+        ("sarif", Some(path)) => Ok(Box::new(SarifReporter::new(path))),
+        (_, None) => Err(format!("invalid report '{}', expected FORMAT=FILE", spec).into()),
+        _ => Err(format!("unknown report format '{}'", format).into()),
+    }
+}
+
+/// Opens an event stream from a `FORMAT[=FILE]` specification given on the command line. Events
+/// are written to standard output if no file is given.
+pub fn event_stream(spec: &str) -> Result<EventStream, Box<Error>> {
+    let mut parts = spec.splitn(2, '=');
+
+    match (parts.next().unwrap_or(""), parts.next()) {
+        ("json", Some(path)) => Ok(try!(EventStream::create(path))),
+        ("json", None) => Ok(EventStream::stdout()),
+        (format, _) => Err(format!("unknown output format '{}'", format).into()),
+    }
+}
+
+/// Creates a reporter for the given CI service.
+pub fn ci(name: &str) -> Result<Box<Reporter>, Box<Error>> {
+    match name {
+        "github" => Ok(Box::new(GithubReporter::new())),
+        "jenkins" => Ok(Box::new(JenkinsReporter)),
+        "teamcity" => Ok(Box::new(TeamcityReporter)),
+        _ => Err(format!("unknown CI service '{}'", name).into()),
+    }
+}
+
+/// Detects the CI service the program is running in from the environment, if any.
+pub fn detect_ci() -> Option<&'static str> {
+    if env::var("GITHUB_ACTIONS").map(|value| value == "true").unwrap_or(false) {
+        return Some("github");
+    }
+
+    if env::var_os("TEAMCITY_VERSION").is_some() {
+        return Some("teamcity");
+    }
+
+    if env::var_os("JENKINS_URL").is_some() {
+        return Some("jenkins");
+    }
+
+    None
+}
+
+/// Splits a script error message into the file, line number, and message it refers to.
+///
+/// Errors raised from Lua are prefixed with the location they were raised at, such as
+/// `/path/to/Rotefile:12: something went wrong`.
+pub fn error_location(error: &str) -> Option<(String, u32, String)> {
+    let regex = Regex::new(r"(?s)^(.+?):(\d+): (.*)$").unwrap();
+
+    regex.captures(error).and_then(|captures| {
+        let line = captures.at(2).unwrap().parse().ok();
+        line.map(|line| {
+            (captures.at(1).unwrap().to_string(), line, captures.at(3).unwrap().to_string())
+        })
+    })
+}
+
+/// Converts a duration to fractional seconds.
+pub fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
@@ -0,0 +1,244 @@
+use hyper::client::Client;
+use hyper::header::Headers;
+use json::JsonValue;
+use report::{Reporter, TaskReport};
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+
+/// Exports the run and each task as OpenTelemetry spans using OTLP over HTTP with JSON encoding.
+///
+/// The exporter is configured with the standard `OTEL_*` environment variables. All spans are
+/// collected during the run and sent in a single request at the end.
+pub struct OtelReporter {
+    /// URL to send traces to.
+    endpoint: String,
+
+    /// Extra headers to send with the request.
+    headers: Vec<(String, String)>,
+
+    /// Name of the service reported in the trace resource.
+    service_name: String,
+
+    /// ID of the trace that all spans of the current run belong to.
+    trace_id: String,
+
+    /// ID of the span covering the entire run.
+    run_span_id: String,
+
+    /// When the run started.
+    run_started: SystemTime,
+
+    /// When each task that is currently running started.
+    task_started: HashMap<String, SystemTime>,
+
+    /// Finished task spans.
+    spans: Vec<JsonValue>,
+}
+
+impl OtelReporter {
+    /// Creates a reporter from the OTLP environment variables, if an endpoint is configured and
+    /// the SDK is not disabled. HTTPS endpoints are not supported, since Rote is built without TLS.
+    pub fn from_env() -> Option<OtelReporter> {
+        if env::var("OTEL_SDK_DISABLED").map(|value| value == "true").unwrap_or(false) {
+            return None;
+        }
+
+        let endpoint = match env::var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+            Ok(endpoint) => endpoint,
+            Err(_) => match env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+                Ok(endpoint) => format!("{}/v1/traces", endpoint.trim_right_matches('/')),
+                Err(_) => return None,
+            },
+        };
+
+        if endpoint.starts_with("https://") {
+            warn!("not exporting traces to {}: HTTPS endpoints are not supported", endpoint);
+            return None;
+        }
+
+        let headers = env::var("OTEL_EXPORTER_OTLP_TRACES_HEADERS")
+            .or_else(|_| env::var("OTEL_EXPORTER_OTLP_HEADERS"))
+            .map(|value| parse_headers(&value))
+            .unwrap_or(Vec::new());
+
+        Some(OtelReporter {
+            endpoint: endpoint,
+            headers: headers,
+            service_name: env::var("OTEL_SERVICE_NAME").unwrap_or("rote".to_string()),
+            trace_id: random_id(2),
+            run_span_id: random_id(1),
+            run_started: SystemTime::now(),
+            task_started: HashMap::new(),
+            spans: Vec::new(),
+        })
+    }
+
+    /// Sends all collected spans to the endpoint.
+    fn send(&self, body: &str) -> io::Result<()> {
+        let mut headers = Headers::new();
+        headers.set_raw("Content-Type", vec![b"application/json".to_vec()]);
+
+        for &(ref name, ref value) in &self.headers {
+            headers.set_raw(name.clone(), vec![value.as_bytes().to_vec()]);
+        }
+
+        let mut response = try!(Client::new()
+            .post(&self.endpoint)
+            .headers(headers)
+            .body(body)
+            .send()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e)));
+
+        if !response.status.is_success() {
+            let mut message = String::new();
+            response.read_to_string(&mut message).ok();
+
+            return Err(io::Error::new(io::ErrorKind::Other,
+                                      format!("trace export failed with status {}: {}",
+                                              response.status.to_u16(),
+                                              message)));
+        }
+
+        Ok(())
+    }
+}
+
+impl Reporter for OtelReporter {
+    fn run_started(&mut self, _tasks: &[String]) {
+        // Each run gets a trace of its own, so that runs in watch mode or of the daemon can be
+        // told apart.
+        self.trace_id = random_id(2);
+        self.run_span_id = random_id(1);
+        self.run_started = SystemTime::now();
+        self.task_started.clear();
+        self.spans.clear();
+    }
+
+    fn task_started(&mut self, name: &str) {
+        self.task_started.insert(name.to_string(), SystemTime::now());
+    }
+
+    fn task_finished(&mut self, report: &TaskReport) {
+        let start = self.task_started.remove(&report.name).unwrap_or(self.run_started);
+        let mut span = span(&self.trace_id,
+                            &random_id(1),
+                            &report.name,
+                            start,
+                            start + report.duration,
+                            report.error.as_ref().map(|e| e.as_str()));
+
+        span["parentSpanId"] = self.run_span_id.as_str().into();
+        span["attributes"] = array![attribute("rote.task", &report.name)];
+        self.spans.push(span);
+    }
+
+    fn run_finished(&mut self, duration: Duration, success: bool) -> io::Result<()> {
+        let error = if success {
+            None
+        } else {
+            Some("not all tasks completed successfully")
+        };
+
+        let mut spans = vec![span(&self.trace_id,
+                                  &self.run_span_id,
+                                  "rote",
+                                  self.run_started,
+                                  self.run_started + duration,
+                                  error)];
+        spans.extend(self.spans.drain(..));
+
+        let mut scope_spans = object! {
+            "scope" => object! {
+                "name" => "rote",
+                "version" => ::ROTE_VERSION
+            }
+        };
+        scope_spans["spans"] = JsonValue::Array(spans);
+
+        let mut resource_spans = object! {
+            "resource" => object! {
+                "attributes" => array![attribute("service.name", &self.service_name)]
+            }
+        };
+        resource_spans["scopeSpans"] = JsonValue::Array(vec![scope_spans]);
+
+        let mut body = JsonValue::new_object();
+        body["resourceSpans"] = JsonValue::Array(vec![resource_spans]);
+
+        self.send(&body.dump())
+    }
+}
+
+/// Creates a span object.
+fn span(trace_id: &str,
+        span_id: &str,
+        name: &str,
+        start: SystemTime,
+        end: SystemTime,
+        error: Option<&str>)
+        -> JsonValue {
+    let status = match error {
+        Some(message) => object! {
+            "code" => 2,
+            "message" => message
+        },
+        None => object! {
+            "code" => 1
+        },
+    };
+
+    object! {
+        "traceId" => trace_id,
+        "spanId" => span_id,
+        "name" => name,
+        "kind" => 1,
+        "startTimeUnixNano" => nanos(start),
+        "endTimeUnixNano" => nanos(end),
+        "status" => status
+    }
+}
+
+/// Creates a string attribute object.
+fn attribute(key: &str, value: &str) -> JsonValue {
+    object! {
+        "key" => key,
+        "value" => object! {
+            "stringValue" => value
+        }
+    }
+}
+
+/// Formats a timestamp as nanoseconds since the Unix epoch. Values are strings since they can
+/// exceed the range of a JSON number.
+fn nanos(time: SystemTime) -> String {
+    let duration = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::new(0, 0));
+    format!("{}{:09}", duration.as_secs(), duration.subsec_nanos())
+}
+
+/// Generates a random ID made up of the given number of 64-bit words, encoded as hex.
+fn random_id(words: usize) -> String {
+    (0..words).map(|_| {
+        // The standard library seeds each hasher state randomly, which is random enough for IDs.
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write(nanos(SystemTime::now()).as_bytes());
+        format!("{:016x}", hasher.finish())
+    }).collect()
+}
+
+/// Parses a list of headers in the form `key1=value1,key2=value2`.
+fn parse_headers(value: &str) -> Vec<(String, String)> {
+    value.split(',')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => Some((name.trim().to_string(), value.trim().to_string())),
+                _ => None,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,127 @@
+use json::JsonValue;
+use report::{Diagnostic, Reporter, TaskReport};
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+
+/// Writes all diagnostics reported by tasks to a SARIF 2.1.0 log, with one run for each tool.
+pub struct SarifReporter {
+    /// Path of the file to write.
+    path: PathBuf,
+
+    /// Diagnostics reported so far, with the task that reported them.
+    diagnostics: Vec<(String, Diagnostic)>,
+}
+
+impl SarifReporter {
+    pub fn new<P: Into<PathBuf>>(path: P) -> SarifReporter {
+        SarifReporter {
+            path: path.into(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn log(&self) -> JsonValue {
+        // Group diagnostics by tool, keeping the order tools were first seen in.
+        let mut tools: Vec<&str> = Vec::new();
+        for &(_, ref diagnostic) in &self.diagnostics {
+            if !tools.contains(&diagnostic.tool.as_str()) {
+                tools.push(&diagnostic.tool);
+            }
+        }
+
+        let mut runs = JsonValue::new_array();
+
+        for tool in tools {
+            let mut rules: Vec<&str> = Vec::new();
+            let mut results = JsonValue::new_array();
+
+            for &(ref task, ref diagnostic) in self.diagnostics.iter().filter(|d| d.1.tool == tool) {
+                if let Some(ref rule) = diagnostic.rule {
+                    if !rules.contains(&rule.as_str()) {
+                        rules.push(rule);
+                    }
+                }
+
+                results.push(result(task, diagnostic)).ok();
+            }
+
+            let mut driver = object! {
+                "name" => tool
+            };
+            driver["rules"] = JsonValue::Array(rules.iter()
+                .map(|rule| object! { "id" => *rule })
+                .collect());
+
+            let mut run = JsonValue::new_object();
+            run["tool"] = JsonValue::new_object();
+            run["tool"]["driver"] = driver;
+            run["results"] = results;
+            runs.push(run).ok();
+        }
+
+        let mut log = object! {
+            "$schema" => "https://json.schemastore.org/sarif-2.1.0.json",
+            "version" => "2.1.0"
+        };
+        log["runs"] = runs;
+        log
+    }
+}
+
+impl Reporter for SarifReporter {
+    fn task_finished(&mut self, report: &TaskReport) {
+        for diagnostic in &report.diagnostics {
+            self.diagnostics.push((report.name.clone(), diagnostic.clone()));
+        }
+    }
+
+    fn run_finished(&mut self, _duration: Duration, _success: bool) -> io::Result<()> {
+        let mut file = try!(File::create(&self.path));
+        try!(file.write_all(self.log().pretty(2).as_bytes()));
+        file.write_all(b"\n")
+    }
+}
+
+/// Converts a diagnostic to a SARIF result object.
+fn result(task: &str, diagnostic: &Diagnostic) -> JsonValue {
+    let mut result = object! {
+        "level" => diagnostic.level.as_str(),
+        "message" => object! {
+            "text" => diagnostic.message.as_str()
+        },
+        "properties" => object! {
+            "task" => task
+        }
+    };
+
+    if let Some(ref rule) = diagnostic.rule {
+        result["ruleId"] = rule.as_str().into();
+    }
+
+    if let Some(ref file) = diagnostic.file {
+        let mut location = object! {
+            "artifactLocation" => object! {
+                "uri" => file.replace("\\", "/")
+            }
+        };
+
+        if let Some(line) = diagnostic.line {
+            location["region"] = object! {
+                "startLine" => line
+            };
+
+            if let Some(column) = diagnostic.column {
+                location["region"]["startColumn"] = column.into();
+            }
+        }
+
+        let mut physical = JsonValue::new_object();
+        physical["physicalLocation"] = location;
+        result["locations"] = JsonValue::Array(vec![physical]);
+    }
+
+    result
+}
@@ -0,0 +1,65 @@
+use console::{self, Segment};
+use report::{Reporter, TaskReport};
+use std::io;
+use std::time::{Duration, Instant};
+use term::color;
+
+
+/// Shows a live status line with how many tasks are done, which tasks are running right now and
+/// how long the run has taken so far.
+pub struct StatusReporter {
+    started: Instant,
+    total: usize,
+    finished: usize,
+    running: Vec<String>,
+}
+
+impl StatusReporter {
+    pub fn new() -> StatusReporter {
+        StatusReporter {
+            started: Instant::now(),
+            total: 0,
+            finished: 0,
+            running: Vec::new(),
+        }
+    }
+
+    /// Draws the status line again with the current state of the run.
+    fn update(&self) {
+        let mut segments = vec![
+            Segment::colored(format!("[{}/{}]", self.finished, self.total), color::BRIGHT_GREEN).bold(),
+        ];
+
+        if !self.running.is_empty() {
+            segments.push(Segment::plain(format!(" {}", self.running.join(", "))));
+        }
+
+        console::set_status(segments, self.started);
+    }
+}
+
+impl Reporter for StatusReporter {
+    fn run_started(&mut self, tasks: &[String]) {
+        self.started = Instant::now();
+        self.total = tasks.len();
+        self.finished = 0;
+        self.running.clear();
+        self.update();
+    }
+
+    fn task_started(&mut self, name: &str) {
+        self.running.push(name.to_string());
+        self.update();
+    }
+
+    fn task_finished(&mut self, report: &TaskReport) {
+        self.running.retain(|name| name != &report.name);
+        self.finished += 1;
+        self.update();
+    }
+
+    fn run_finished(&mut self, _duration: Duration, _success: bool) -> io::Result<()> {
+        console::clear_status();
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use console::{self, Segment, Stream};
+use report::{self, Reporter, TaskReport};
+use std::cmp;
+use std::io;
+use std::time::Duration;
+use term::color;
+
+
+/// What happened to a task during a run.
+enum Status {
+    Ran(Duration),
+    Failed(Duration),
+    UpToDate,
+    NotRun,
+}
+
+/// Prints a table of every task in the run, how long it took and whether it was up to date, once
+/// the run is finished.
+pub struct SummaryReporter {
+    /// Tasks in the order they were scheduled in, followed by tasks that were up to date.
+    tasks: Vec<(String, Status)>,
+}
+
+impl SummaryReporter {
+    pub fn new() -> SummaryReporter {
+        SummaryReporter {
+            tasks: Vec::new(),
+        }
+    }
+}
+
+impl Reporter for SummaryReporter {
+    fn run_started(&mut self, tasks: &[String]) {
+        self.tasks = tasks.iter().map(|name| (name.clone(), Status::NotRun)).collect();
+    }
+
+    fn task_skipped(&mut self, name: &str) {
+        self.tasks.push((name.to_string(), Status::UpToDate));
+    }
+
+    fn task_finished(&mut self, report: &TaskReport) {
+        if let Some(task) = self.tasks.iter_mut().find(|task| task.0 == report.name) {
+            task.1 = match report.error {
+                Some(_) => Status::Failed(report.duration),
+                None => Status::Ran(report.duration),
+            };
+        }
+    }
+
+    fn run_finished(&mut self, duration: Duration, _success: bool) -> io::Result<()> {
+        let width = self.tasks.iter().fold(4, |width, task| cmp::max(width, task.0.len()));
+        let (mut ran, mut failed, mut up_to_date) = (0, 0, 0);
+
+        console::println("");
+        console::write(Stream::Stdout, vec![
+            Segment::plain(format!("{:width$}  {:10}  {:>9}\n", "TASK", "STATUS", "TIME", width = width))
+                .bold(),
+        ]);
+
+        for &(ref name, ref status) in &self.tasks {
+            let (label, time, color) = match *status {
+                Status::Ran(duration) => {
+                    ran += 1;
+                    ("ran", format!("{:.3}s", report::seconds(duration)), color::GREEN)
+                }
+                Status::Failed(duration) => {
+                    failed += 1;
+                    ("failed", format!("{:.3}s", report::seconds(duration)), color::RED)
+                }
+                Status::UpToDate => {
+                    up_to_date += 1;
+                    ("up to date", String::new(), color::BRIGHT_BLACK)
+                }
+                Status::NotRun => ("not run", String::new(), color::YELLOW),
+            };
+
+            console::write(Stream::Stdout, vec![
+                Segment::plain(format!("{:width$}  ", name, width = width)),
+                Segment::colored(format!("{:10}", label), color),
+                Segment::plain(format!("  {:>9}\n", time)),
+            ]);
+        }
+
+        console::println(format!("{} ran, {} failed, {} up to date in {:.3}s",
+                                 ran,
+                                 failed,
+                                 up_to_date,
+                                 report::seconds(duration)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use console;
+use report::{Reporter, TaskReport};
+
+
+/// Writes TeamCity service messages so that each task shows up as a collapsible block in the build
+/// log, and failures are reported as build problems.
+pub struct TeamcityReporter;
+
+impl Reporter for TeamcityReporter {
+    fn task_started(&mut self, name: &str) {
+        console::println(format!("##teamcity[blockOpened name='{}' description='rote task']",
+                                 escape(name)));
+    }
+
+    fn task_finished(&mut self, report: &TaskReport) {
+        if let Some(ref error) = report.error {
+            let description = format!("task '{}' failed: {}", report.name, error);
+            console::println(format!("##teamcity[buildProblem description='{}' identity='rote:{}']",
+                                     escape(&description),
+                                     escape(&report.name)));
+        }
+
+        console::println(format!("##teamcity[blockClosed name='{}']", escape(&report.name)));
+    }
+}
+
+/// Escapes a value in a service message.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '|' => escaped.push_str("||"),
+            '\'' => escaped.push_str("|'"),
+            '[' => escaped.push_str("|["),
+            ']' => escaped.push_str("|]"),
+            '\n' => escaped.push_str("|n"),
+            '\r' => escaped.push_str("|r"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
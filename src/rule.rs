@@ -1,10 +1,12 @@
+use fscache;
 use std::error::Error;
 use std::fs;
 use std::rc::Rc;
-use task;
+use std::time::{Duration, SystemTime};
+use task::{self, Retry};
 
 
-type ActionFn = Fn(&str) -> Result<(), Box<Error>>;
+type ActionFn = Fn(&str, Option<&str>) -> Result<(), Box<Error>>;
 
 /// A rule task that matches against files. Rules are used to generate tasks from file name
 /// patterns.
@@ -15,32 +17,92 @@ pub struct Rule {
     /// A list of tasks that must be ran before this task.
     dependencies: Vec<String>,
 
+    /// Files read by tasks created by the rule that aren't built by any task, such as source
+    /// files and headers.
+    inputs: Vec<String>,
+
     /// Rule action.
     action: Option<Rc<ActionFn>>,
+
+    /// Indicates if tasks created by the rule are cheap to run.
+    cheap: bool,
+
+    /// How tasks created by the rule are retried if they fail.
+    retry: Retry,
+
+    /// How long tasks created by the rule may run before their commands are killed.
+    timeout: Option<Duration>,
 }
 
 impl Rule {
     pub fn new<S, V, F>(pattern: S, dependencies: V, action: Option<F>) -> Rule
         where S: Into<String>,
               V: Into<Vec<String>>,
-              F: Fn(&str) -> Result<(), Box<Error>> + 'static
+              F: Fn(&str, Option<&str>) -> Result<(), Box<Error>> + 'static
     {
         Rule {
             pattern: pattern.into(),
             dependencies: dependencies.into(),
+            inputs: Vec::new(),
             action: action.map(|a| Rc::new(a) as Rc<ActionFn>),
+            cheap: false,
+            retry: Retry::default(),
+            timeout: None,
         }
     }
 
+    /// Sets the files read by tasks created by the rule that aren't built by any task. A `%` in a
+    /// file name is replaced by the stem, just like in dependencies.
+    pub fn inputs(mut self, inputs: Vec<String>) -> Rule {
+        self.inputs = inputs;
+        self
+    }
+
+    /// Marks tasks created by the rule as cheap, so that they may be run in batches.
+    pub fn cheap(mut self) -> Rule {
+        self.cheap = true;
+        self
+    }
+
+    /// Sets how tasks created by the rule are retried if they fail.
+    pub fn retry(mut self, retry: Retry) -> Rule {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets how long tasks created by the rule may run before their commands are killed.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Rule {
+        self.timeout = timeout;
+        self
+    }
+
     /// Checks if a file name matches the rule.
     pub fn matches<S: AsRef<str>>(&self, name: S) -> bool {
+        self.capture(name.as_ref()).is_some()
+    }
+
+    /// Matches a file name against the rule and gets the part of the name matched by the `%`
+    /// wildcard, which is called the stem.
+    ///
+    /// Returns `None` if the name doesn't match, and `Some("")` if the rule has no wildcard and
+    /// the name is the same as the pattern.
+    pub fn capture<'a, S: AsRef<str> + ?Sized>(&self, name: &'a S) -> Option<&'a str> {
+        let name = name.as_ref();
+
         if let Some(index) = self.pattern.find("%") {
             let (prefix, suffix) = self.pattern.split_at(index);
             let suffix = &suffix[1..];
 
-            name.as_ref().starts_with(prefix) && name.as_ref().ends_with(suffix)
+            // The prefix and suffix can't overlap, and the stem can't be empty.
+            if name.len() > prefix.len() + suffix.len() && name.starts_with(prefix) && name.ends_with(suffix) {
+                Some(&name[prefix.len()..name.len() - suffix.len()])
+            } else {
+                None
+            }
+        } else if self.pattern == name {
+            Some("")
         } else {
-            &self.pattern == name.as_ref()
+            None
         }
     }
 
@@ -49,28 +111,29 @@ impl Rule {
         let name = name.into();
 
         // First, check if the given filename matches.
-        if !self.matches(&name) {
-            return None;
-        }
+        let stem = match self.capture(&name) {
+            Some(stem) if self.pattern.contains("%") => Some(stem.to_string()),
+            Some(_) => None,
+            None => return None,
+        };
 
-        // Clone the input files (dependencies).
-        let mut inputs = self.dependencies.clone();
-
-        // If the rule name is a pattern, determine the value of the replacement character "%".
-        if let Some(index) = self.pattern.find("%") {
-            let end = index + 1 + name.len() - self.pattern.len();
-            let replacement = &name[index..end];
-
-            // Expand the inputs with the corresponding names that match the output name.
-            inputs = inputs.into_iter()
-                .map(|input| input.replace("%", replacement))
-                .collect();
-        }
+        // Expand the inputs (dependencies) with the stem matched by the output name.
+        let expand = |names: &[String]| -> Vec<String> {
+            match stem {
+                Some(ref stem) => names.iter().map(|input| input.replace("%", stem)).collect(),
+                None => names.to_vec(),
+            }
+        };
 
         Some(FileTask {
-            inputs: inputs,
+            inputs: expand(&self.dependencies),
+            files: expand(&self.inputs),
             output: name,
+            stem: stem,
             action: self.action.clone(),
+            cheap: self.cheap,
+            retry: self.retry,
+            timeout: self.timeout,
         })
     }
 }
@@ -78,7 +141,17 @@ impl Rule {
 pub struct FileTask {
     pub inputs: Vec<String>,
     pub output: String,
+
+    /// Files read by the task that aren't built by any task.
+    pub files: Vec<String>,
+
+    /// The part of the output name matched by the wildcard in the rule pattern, if it has one.
+    pub stem: Option<String>,
+
     action: Option<Rc<ActionFn>>,
+    cheap: bool,
+    retry: Retry,
+    timeout: Option<Duration>,
 }
 
 impl task::Task for FileTask {
@@ -89,13 +162,14 @@ impl task::Task for FileTask {
     /// Checks if the task is dirty by comparing the file modification time of the input and output
     /// files. If any of the input files are newer than the output file, then the task is dirty.
     fn satisfied(&self) -> bool {
-        fs::metadata(&self.output)
+        fscache::metadata(&self.output)
             .and_then(|m| m.modified())
             .map(|time| {
                 self.inputs
                     .iter()
+                    .chain(&self.files)
                     .all(|input| {
-                        fs::metadata(input)
+                        fscache::metadata(input)
                             .and_then(|m| m.modified())
                             .map(|t| t <= time)
                             .unwrap_or(true)
@@ -108,11 +182,97 @@ impl task::Task for FileTask {
         &self.inputs
     }
 
+    fn files(&self) -> &[String] {
+        &self.files
+    }
+
     fn run(&self) -> Result<(), Box<Error>> {
         if let Some(ref action) = self.action {
-            action(&self.output)
+            let mut guard = OutputGuard::new(&self.output);
+            let result = action(&self.output, self.stem.as_ref().map(|stem| stem.as_str()));
+
+            if result.is_ok() {
+                guard.keep();
+            }
+
+            result
         } else {
             Ok(())
         }
     }
+
+    fn is_file(&self) -> bool {
+        true
+    }
+
+    fn has_action(&self) -> bool {
+        self.action.is_some()
+    }
+
+    fn is_cheap(&self) -> bool {
+        self.cheap
+    }
+
+    fn retry(&self) -> Retry {
+        self.retry
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+/// Removes the output file of a task if the task does not finish successfully.
+///
+/// A task that fails or panics may leave behind a truncated output file that is newer than its
+/// inputs and would be considered up to date on the next run. The output is only removed if it was
+/// written to while the task was running, so an older output from a previous run is left alone.
+struct OutputGuard<'a> {
+    path: &'a str,
+    modified: Option<SystemTime>,
+    keep: bool,
+}
+
+impl<'a> OutputGuard<'a> {
+    fn new(path: &'a str) -> OutputGuard<'a> {
+        OutputGuard {
+            path: path,
+            modified: modified(path),
+            keep: false,
+        }
+    }
+
+    /// Keeps the output file in place.
+    fn keep(&mut self) {
+        self.keep = true;
+    }
+}
+
+impl<'a> Drop for OutputGuard<'a> {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+
+        let modified = modified(self.path);
+        if modified.is_some() && modified != self.modified {
+            warn!("removing partially written output '{}'", self.path);
+
+            if let Err(e) = fs::remove_file(self.path) {
+                warn!("failed to remove '{}': {}", self.path, e);
+            }
+            fscache::clear();
+        }
+    }
+}
+
+/// Gets the modification time of a file, or `None` if it is not a file.
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path)
+        .ok()
+        .and_then(|metadata| if metadata.is_file() {
+            metadata.modified().ok()
+        } else {
+            None
+        })
 }
@@ -1,16 +1,36 @@
+use artifacts::{self, OutputCache};
+use cache::Cache;
+use console::{self, Segment, Stream};
+use export;
+use fscache;
 use graph::Graph;
+use json::JsonValue;
+use lock::ProjectLock;
 use modules;
 use num_cpus;
-use runtime::{Environment, Runtime};
+use plugins::{self, Module};
+use process;
+use remote;
+use report::{self, Diagnostic, Reporter, TaskReport};
+use runtime::{ChunkCache, Environment, Runtime};
 use std::cmp;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::env;
 use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc;
 use std::thread;
-use task::Task;
+use std::time::{Duration, Instant};
+use task::{NamedTask, Task};
 use term;
+use watcher::Watcher;
 
 
 #[derive(Clone)]
@@ -24,9 +44,15 @@ pub struct EnvironmentSpec {
     /// Module include paths.
     include_paths: Vec<PathBuf>,
 
+    /// Modules registered by native plugins.
+    plugins: Vec<Module>,
+
     /// Global environment variables.
     variables: Vec<(String, String)>,
 
+    /// Named arguments given on the command line, available to the script in the `args` table.
+    args: Vec<(String, String)>,
+
     /// Indicates if actually running tasks should be skipped.
     dry_run: bool,
 
@@ -35,6 +61,30 @@ pub struct EnvironmentSpec {
 
     /// Indicates task errors should be ignored.
     keep_going: bool,
+
+    /// Indicates if the output of commands run by tasks should be captured.
+    capture_output: bool,
+
+    /// Indicates if the output of tasks is written in schedule order instead of as it happens.
+    ordered_output: bool,
+
+    /// Indicates if command output is prefixed with the name of its task when running in parallel.
+    prefix_output: bool,
+
+    /// Ad-hoc commands to define as tasks, by task name.
+    commands: Vec<(String, Vec<String>)>,
+
+    /// Built-in modules compiled by any runtime created from the specification.
+    chunks: ChunkCache,
+
+    /// Set when the current run is cancelled or stopping, so that failed tasks aren't retried.
+    cancelled: Arc<AtomicBool>,
+
+    /// How long tasks without a timeout of their own may run before their commands are killed.
+    timeout: Option<Duration>,
+
+    /// URL of the remote cache that cached task outputs are shared through.
+    cache_url: Option<String>,
 }
 
 impl EnvironmentSpec {
@@ -42,13 +92,23 @@ impl EnvironmentSpec {
     pub fn create(&self) -> Result<Runtime, Box<Error>> {
         // Prepare a new environment.
         let environment = try!(Environment::new(self.path.clone()));
+        environment.set_capture_output(self.capture_output);
+        environment.set_prefix_output(self.prefix_output);
+        environment.set_dry_run(self.dry_run);
+        environment.set_chunk_cache(self.chunks.clone());
         let runtime = Runtime::new(environment);
 
         // Open standard library functions.
         runtime.state().open_libs();
 
+        // Register modules of plugins first, so that they can't replace built-in modules.
+        for module in &self.plugins {
+            runtime.register_native_lib(&module.name, module.loader);
+        }
+
         // Register modules.
         modules::register_all(&runtime);
+        remote::register(&runtime);
 
         // Set include paths.
         for path in &self.include_paths {
@@ -63,19 +123,213 @@ impl EnvironmentSpec {
         });
         runtime.state().set_global("OS");
 
+        // Set the specific platform, such as the toolchain used on Windows.
+        runtime.state().push_string(platform());
+        runtime.state().set_global("PLATFORM");
+
         // Set configured variables.
         for &(ref name, ref value) in &self.variables {
             runtime.state().push(value.clone());
             runtime.state().set_global(&name);
         }
 
+        // Set named arguments.
+        runtime.state().new_table();
+        for &(ref name, ref value) in &self.args {
+            runtime.environment().set_arg(name.as_str(), value.as_str());
+            runtime.state().push(value.clone());
+            runtime.state().set_field(-2, &name);
+        }
+        runtime.state().set_global("args");
+
         // Load the script.
         try!(runtime.load());
 
+        // Define tasks for ad-hoc commands. They are run with `exec()` just like commands in the
+        // script are.
+        for &(ref name, ref command) in &self.commands {
+            let args: Vec<String> = command.iter().map(|arg| lua_quote(arg)).collect();
+            try!(runtime.eval(format!("rote.create_task({}, function() exec({}) end)",
+                                      lua_quote(name),
+                                      args.join(", "))));
+        }
+
         Ok(runtime)
     }
 }
 
+/// How often watched files are checked for changes, in milliseconds.
+const WATCH_INTERVAL: u64 = 500;
+
+/// The maximum number of cheap tasks sent to a worker thread at once.
+const MAX_BATCH_SIZE: usize = 32;
+
+/// A task sent to a worker thread to run.
+struct Job {
+    /// The name of the task.
+    name: String,
+
+    /// The number of the task in the run, for logging.
+    number: usize,
+
+    /// Buffer to hold the output of the task in, if output is ordered.
+    buffer: Option<console::Buffer>,
+}
+
+/// A batch of tasks sent to a worker thread to run one after another.
+struct Batch {
+    /// The tasks to run.
+    jobs: Vec<Job>,
+
+    /// The total number of tasks in the run, for logging.
+    task_count: usize,
+}
+
+/// A worker thread with its own runtime.
+struct Worker {
+    /// Channel to send batches of tasks to the thread on.
+    channel: mpsc::SyncSender<Batch>,
+
+    /// Handle to the thread.
+    thread: thread::JoinHandle<()>,
+}
+
+/// The worker threads used by a run, along with the channel they report back on.
+struct WorkerPool {
+    /// Workers by thread ID. Workers that have stopped leave an empty slot behind.
+    workers: Vec<Option<Worker>>,
+
+    /// Workers that have been told to stop, but haven't been waited for yet.
+    retired: Vec<(usize, thread::JoinHandle<()>)>,
+
+    sender: mpsc::Sender<WorkerMessage>,
+    receiver: mpsc::Receiver<WorkerMessage>,
+}
+
+impl WorkerPool {
+    fn new() -> WorkerPool {
+        let (sender, receiver) = mpsc::channel();
+
+        WorkerPool {
+            workers: Vec::new(),
+            retired: Vec::new(),
+            sender: sender,
+            receiver: receiver,
+        }
+    }
+
+    /// Gets the worker with the given thread ID, spawning a new one if there isn't one.
+    fn get(&mut self, thread_id: usize, spec: &EnvironmentSpec) -> &Worker {
+        while self.workers.len() <= thread_id {
+            self.workers.push(None);
+        }
+
+        if self.workers[thread_id].is_none() {
+            self.workers[thread_id] = Some(spawn_worker(thread_id, spec.clone(), self.sender.clone()));
+        } else {
+            trace!("reusing thread {}", thread_id);
+        }
+
+        self.workers[thread_id].as_ref().unwrap()
+    }
+
+    /// Closes the channel of a worker so that it exits once it is done with its current batch.
+    fn retire(&mut self, thread_id: usize) {
+        if let Some(worker) = self.workers.get_mut(thread_id).and_then(Option::take) {
+            self.retired.push((thread_id, worker.thread));
+        }
+    }
+
+    /// Waits for retired workers to exit.
+    fn join_retired(&mut self) {
+        for (thread_id, thread) in self.retired.drain(..) {
+            if let Err(e) = thread.join() {
+                trace!("thread {} closed with panic: {:?}", thread_id, e);
+            }
+        }
+    }
+
+    /// Stops every worker and waits for them to exit.
+    fn shutdown(mut self) {
+        for thread_id in 0..self.workers.len() {
+            self.retire(thread_id);
+        }
+        self.join_retired();
+    }
+}
+
+/// Messages sent from worker threads to the master thread.
+enum WorkerMessage {
+    /// The worker started running a task of its batch, given by its position in the batch.
+    Started {
+        thread_id: usize,
+        index: usize,
+    },
+
+    /// The worker finished running a batch of tasks.
+    Finished {
+        thread_id: usize,
+        outcomes: Vec<TaskOutcome>,
+    },
+}
+
+/// The outcome of running a single task.
+struct TaskOutcome {
+    result: Result<(), String>,
+    duration: Duration,
+    output: String,
+    diagnostics: Vec<Diagnostic>,
+    panicked: bool,
+}
+
+/// Error returned when a run stops because tasks failed or the run was cancelled.
+#[derive(Debug)]
+pub struct TaskFailure(String);
+
+impl fmt::Display for TaskFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for TaskFailure {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Error returned when a run stops because of a problem in Rote itself rather than in the script.
+#[derive(Debug)]
+pub struct InternalError(String);
+
+impl fmt::Display for InternalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for InternalError {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Error returned when a run stops because Rote was interrupted, such as by pressing Ctrl-C.
+#[derive(Debug)]
+pub struct Interrupted(String);
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for Interrupted {
+    fn description(&self) -> &str {
+        &self.0
+    }
+}
+
 /// A task runner object that holds the state for defined tasks, dependencies, and the scripting
 /// runtime.
 pub struct Runner {
@@ -85,11 +339,46 @@ pub struct Runner {
     /// The number of threads to use.
     jobs: usize,
 
+    /// Load average above which no new tasks are started.
+    max_load: Option<f64>,
+
+    /// Indicates if only the requested tasks should be run, without their dependencies.
+    no_deps: bool,
+
+    /// Tasks to run even if they are up to date, along with everything depending on them.
+    forced: Vec<String>,
+
     /// Environment specification.
     spec: EnvironmentSpec,
 
     /// Runtime local owned by the master thread.
     runtime: Option<Runtime>,
+
+    /// Reporters that receive events as tasks are run.
+    reporters: Vec<Box<Reporter>>,
+
+    /// Set to cancel the current run.
+    cancelled: Arc<AtomicBool>,
+
+    /// Up-to-date status of tasks checked by previous runs, reused by incremental runs.
+    status: HashMap<String, bool>,
+
+    /// Indicates if a run should wait for other processes building the project to finish.
+    wait_for_lock: bool,
+
+    /// Indicates if a run may build the project at the same time as other runs that share the
+    /// lock.
+    shared_lock: bool,
+
+    /// Indicates if file tasks are up to date by the contents of their files instead of their
+    /// timestamps.
+    check_hashes: bool,
+
+    /// Indicates if worker threads should be kept alive between runs.
+    keep_workers: bool,
+
+    /// Worker threads kept alive from previous runs.
+    workers: Option<WorkerPool>,
 }
 
 impl Runner {
@@ -97,6 +386,7 @@ impl Runner {
     pub fn new<P: Into<PathBuf>>(path: P) -> Result<Runner, Box<Error>> {
         // By default, set the number of jobs to be one less than the number of available CPU cores.
         let jobs = cmp::max(1, num_cpus::get() - 1);
+        let cancelled = Arc::new(AtomicBool::new(false));
 
         let path = path.into();
         let directory: PathBuf = match path.parent() {
@@ -109,16 +399,37 @@ impl Runner {
         Ok(Runner {
             graph: Graph::new(),
             jobs: jobs as usize,
+            max_load: None,
+            no_deps: false,
+            forced: Vec::new(),
             spec: EnvironmentSpec {
                 path: path.into(),
                 directory: directory,
                 include_paths: Vec::new(),
+                plugins: Vec::new(),
                 variables: Vec::new(),
+                args: Vec::new(),
                 dry_run: false,
                 always_run: false,
                 keep_going: false,
+                capture_output: false,
+                ordered_output: false,
+                prefix_output: true,
+                commands: Vec::new(),
+                chunks: Arc::new(Mutex::new(HashMap::new())),
+                cancelled: cancelled.clone(),
+                timeout: None,
+                cache_url: None,
             },
             runtime: None,
+            reporters: Vec::new(),
+            cancelled: cancelled,
+            status: HashMap::new(),
+            wait_for_lock: false,
+            shared_lock: false,
+            check_hashes: false,
+            keep_workers: false,
+            workers: None,
         })
     }
 
@@ -132,8 +443,8 @@ impl Runner {
 
     /// Sets "dry run" mode.
     ///
-    /// When in "dry run" mode, running tasks will operate as normal, except that no task's actions
-    /// will be actually run.
+    /// When in "dry run" mode, running tasks will operate as normal, except that commands run by
+    /// tasks are only shown instead of being run. Commands run only for their output still run.
     pub fn dry_run(&mut self) {
         self.spec.dry_run = true;
     }
@@ -143,26 +454,118 @@ impl Runner {
         self.spec.always_run = true;
     }
 
+    /// Decide if file tasks are up to date by comparing the contents of their files with the last
+    /// time they ran, instead of by their timestamps.
+    pub fn check_hashes(&mut self) {
+        self.check_hashes = true;
+    }
+
+    /// Run a task even if it is up-to-date, along with the tasks depending on it that are run.
+    pub fn force<S: Into<String>>(&mut self, task: S) {
+        self.forced.push(task.into());
+    }
+
     /// Run all tasks even if they throw errors.
     pub fn keep_going(&mut self) {
         self.spec.keep_going = true;
     }
 
+    /// Run only the requested tasks, skipping their dependencies.
+    pub fn no_deps(&mut self) {
+        self.no_deps = true;
+    }
+
+    /// Wait for other processes building the same project to finish instead of failing.
+    pub fn wait_for_lock(&mut self) {
+        self.wait_for_lock = true;
+    }
+
+    /// Shares the project lock with other runs that share it too, such as the runs of tasks in an
+    /// exported build file, instead of taking it for this run alone.
+    pub fn shared_lock(&mut self) {
+        self.shared_lock = true;
+    }
+
+    /// Keeps worker threads and their runtimes alive after a run, so that later runs don't have to
+    /// load the script again for every thread.
+    ///
+    /// Worker runtimes are only replaced when the script is reloaded, so any state a script keeps
+    /// in them carries over from one run to the next.
+    pub fn keep_workers(&mut self) {
+        self.keep_workers = true;
+    }
+
+    /// Writes the output of each task in the order tasks were scheduled in, instead of as tasks
+    /// are running.
+    ///
+    /// Tasks still run in parallel, but their output is held back until every task before them has
+    /// finished, so that the output is the same every time.
+    pub fn ordered_output(&mut self) {
+        self.spec.ordered_output = true;
+    }
+
+    /// Writes the output of commands exactly as it is, instead of prefixing each line with the name
+    /// of its task when running tasks in parallel.
+    pub fn no_prefix(&mut self) {
+        self.spec.prefix_output = false;
+    }
+
+    /// Sets how long tasks may run before their commands are killed and they fail, unless they set
+    /// a timeout of their own.
+    pub fn timeout(&mut self, timeout: Duration) {
+        self.spec.timeout = Some(timeout);
+    }
+
+    /// Shares the outputs of cached tasks through a remote cache at the given URL.
+    pub fn cache_url<S: Into<String>>(&mut self, url: S) -> Result<(), Box<Error>> {
+        let url = url.into();
+        try!(artifacts::backend(&url));
+        self.spec.cache_url = Some(url);
+        Ok(())
+    }
+
     /// Sets the number of threads to use to run tasks.
     pub fn jobs(&mut self, jobs: usize) {
         self.jobs = jobs;
     }
 
+    /// Holds back new tasks while the system load average is above the given value, unless no
+    /// other tasks are running.
+    pub fn max_load(&mut self, load: f64) {
+        self.max_load = Some(load);
+    }
+
     /// Adds a path to Lua's require path for modules.
     pub fn include_path<P: Into<PathBuf>>(&mut self, path: P) {
         self.spec.include_paths.push(path.into());
     }
 
+    /// Loads the native plugins in a directory, making the modules they register available to the
+    /// script.
+    pub fn load_plugins<P: AsRef<Path>>(&mut self, dir: P) {
+        self.spec.plugins.extend(plugins::load_dir(dir));
+    }
+
     /// Sets a variable value.
     pub fn set_var<S: AsRef<str>, V: Into<String>>(&mut self, name: S, value: V) {
         self.spec.variables.push((name.as_ref().to_string(), value.into()));
     }
 
+    /// Sets a named argument, which is available to the script in the `args` table and through
+    /// `var()`.
+    pub fn set_arg<S: AsRef<str>, V: Into<String>>(&mut self, name: S, value: V) {
+        self.spec.args.push((name.as_ref().to_string(), value.into()));
+    }
+
+    /// Adds a task that runs an arbitrary command, and returns the name of the task.
+    ///
+    /// Commands must be added before the script is loaded.
+    pub fn add_command(&mut self, command: Vec<String>) -> String {
+        let name = format!("exec: {}", command.join(" "));
+        self.spec.commands.push((name.clone(), command));
+        name
+    }
+
     /// Load the script.
     pub fn load(&mut self) -> Result<(), Box<Error>> {
         if self.runtime.is_none() {
@@ -172,29 +575,142 @@ impl Runner {
         Ok(())
     }
 
-    /// Prints the list of named tasks for a script.
-    pub fn print_task_list(&mut self) {
-        let mut tasks = self.runtime().environment().tasks();
+    /// Reloads the script, discarding all loaded tasks.
+    pub fn reload(&mut self) -> Result<(), Box<Error>> {
+        if let Some(workers) = self.workers.take() {
+            workers.shutdown();
+        }
+
+        self.runtime = None;
+        self.graph = Graph::new();
+        self.status.clear();
+        self.load()
+    }
+
+    /// Gets all named tasks defined by the script, sorted by name. If the script is not loaded,
+    /// no tasks are returned.
+    pub fn tasks(&self) -> Vec<Rc<NamedTask>> {
+        let mut tasks = match self.runtime {
+            Some(ref runtime) => runtime.environment().tasks(),
+            None => Vec::new(),
+        };
         tasks.sort_by(|a, b| a.name().cmp(b.name()));
+        tasks
+    }
 
-        let mut out = term::stdout().unwrap();
-        println!("Available tasks:");
+    /// Gets the name of the default task, if any.
+    pub fn default_task(&self) -> Option<String> {
+        self.runtime.as_ref().and_then(|runtime| runtime.environment().default_task())
+    }
 
-        for task in tasks {
-            out.fg(term::color::BRIGHT_GREEN).unwrap();
-            write!(out, "  {:16}", task.name()).unwrap();
-            out.reset().unwrap();
+    /// Gets a flag that can be set from another thread to cancel the current run.
+    ///
+    /// Task actions that are already running cannot be interrupted, so a cancelled run stops once
+    /// they finish instead of scheduling any more tasks.
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+
+    /// Cancels the current run and kills the commands of running tasks when Rote is interrupted,
+    /// such as by pressing Ctrl-C.
+    pub fn handle_interrupts(&self) {
+        process::handle_interrupts(self.cancelled.clone());
+    }
 
-            if let Some(ref description) = task.description() {
-                write!(out, "{}", description).unwrap();
+    /// Checks the loaded script for problems without running anything.
+    ///
+    /// Returns a list of problems found, along with the name of the task each problem belongs to
+    /// if there is one. Dependencies that do not match a task or rule and circular dependencies are
+    /// reported.
+    pub fn check(&mut self) -> Vec<(Option<String>, String)> {
+        let mut problems = Vec::new();
+        self.graph = Graph::new();
+
+        for task in self.tasks() {
+            for dependency in task.dependencies() {
+                if let Err(e) = self.resolve_task(dependency) {
+                    let problem = format!("task '{}' depends on '{}': {}", task.name(), dependency, e);
+                    problems.push((Some(task.name().to_string()), problem));
+                }
             }
 
-            writeln!(out, "").unwrap();
+            if let Err(e) = self.resolve_task(task.name()) {
+                problems.push((Some(task.name().to_string()), e.to_string()));
+            }
         }
 
-        if let Some(ref default) = self.runtime().environment().default_task() {
-            println!("");
-            println!("Default task: {}", default);
+        if problems.is_empty() {
+            if let Err(e) = self.graph.solve(false) {
+                problems.push((None, e.to_string()));
+            }
+        }
+
+        problems
+    }
+
+    /// Prints the list of named tasks for a script in the given format.
+    ///
+    /// The `pretty` format is meant to be read by people. The `plain` format prints each task on its
+    /// own line as its name, description, dependencies separated by spaces, and `default` if it is
+    /// the default task, separated by tabs. The `json` format prints the same list as
+    /// `tasks_to_json()`.
+    pub fn print_task_list(&mut self, format: &str) -> Result<(), Box<Error>> {
+        let tasks = self.tasks();
+        let default = self.default_task();
+
+        match format {
+            "pretty" => {
+                console::println("Available tasks:");
+
+                for task in tasks {
+                    console::write(Stream::Stdout, vec![
+                        Segment::colored(format!("  {:16}", task.name()), term::color::BRIGHT_GREEN),
+                        Segment::plain(format!("{}\n", task.description().unwrap_or(""))),
+                    ]);
+                }
+
+                if let Some(ref default) = default {
+                    console::println("");
+                    console::println(format!("Default task: {}", default));
+                }
+            }
+            "plain" => {
+                for task in tasks {
+                    // Keep every task on a single line, no matter how its description is written.
+                    let description: Vec<&str> = task.description().unwrap_or("").split_whitespace().collect();
+                    let is_default = default.as_ref().map(|name| name == task.name()).unwrap_or(false);
+
+                    console::println(format!("{}\t{}\t{}\t{}",
+                                             task.name(),
+                                             description.join(" "),
+                                             task.dependencies().join(" "),
+                                             if is_default { "default" } else { "" }));
+                }
+            }
+            "json" => console::println(self.tasks_to_json().pretty(2)),
+            _ => return Err(format!("unknown list format '{}'", format).into()),
+        }
+
+        Ok(())
+    }
+
+    /// Gets the named tasks and the default task as a JSON value.
+    pub fn tasks_to_json(&self) -> JsonValue {
+        let default = self.default_task();
+        let mut tasks = JsonValue::new_array();
+
+        for task in self.tasks() {
+            tasks.push(object! {
+                "name" => task.name(),
+                "description" => task.description(),
+                "dependencies" => task.dependencies().to_vec(),
+                "default" => default.as_ref().map(|name| name == task.name()).unwrap_or(false)
+            }).ok();
+        }
+
+        object! {
+            "tasks" => tasks,
+            "default" => default
         }
     }
 
@@ -208,183 +724,732 @@ impl Runner {
         }
     }
 
+    /// Writes the graph of the given tasks to a file as a Ninja build file.
+    ///
+    /// If no tasks are given, the default task is exported.
+    pub fn export_ninja<S: AsRef<str>, P: AsRef<Path>>(&mut self, tasks: &[S], path: P) -> Result<(), Box<Error>> {
+        self.export(tasks, path, export::ninja)
+    }
+
+    /// Writes the graph of the given tasks to a file as a Makefile.
+    ///
+    /// If no tasks are given, the default task is exported.
+    pub fn export_make<S: AsRef<str>, P: AsRef<Path>>(&mut self, tasks: &[S], path: P) -> Result<(), Box<Error>> {
+        self.export(tasks, path, export::make)
+    }
+
+    fn export<S, P>(&mut self, tasks: &[S], path: P, exporter: fn(&mut File, &Graph, &[String], &Path, &Path) -> io::Result<()>) -> Result<(), Box<Error>>
+        where S: AsRef<str>,
+              P: AsRef<Path>
+    {
+        let tasks = try!(self.requested_tasks(tasks));
+        for task in &tasks {
+            try!(self.resolve_task(task));
+        }
+
+        let rote = try!(env::current_exe());
+        let mut file = try!(File::create(path.as_ref()));
+        try!(exporter(&mut file, &self.graph, &tasks, &rote, self.path()));
+
+        info!("wrote {}", path.as_ref().to_string_lossy());
+        Ok(())
+    }
+
+    /// Prints the graph of the given tasks and everything they depend on to standard output in the
+    /// given format, either `dot` or `json`.
+    ///
+    /// If no tasks are given, the graph of the default task is printed.
+    pub fn print_graph<S: AsRef<str>>(&mut self, tasks: &[S], format: &str) -> Result<(), Box<Error>> {
+        let tasks = try!(self.requested_tasks(tasks));
+        for task in &tasks {
+            try!(self.resolve_task(task));
+        }
+
+        let mut output = Vec::new();
+        match format {
+            "dot" => try!(self.graph.write_dot(&mut output, &tasks)),
+            "json" => {
+                output = self.graph.to_json(&tasks).pretty(2).into_bytes();
+                output.push(b'\n');
+            }
+            _ => return Err(format!("unknown graph format '{}'", format).into()),
+        }
+
+        console::write_bytes(Stream::Stdout, &output);
+        Ok(())
+    }
+
     /// Runs the specified list of tasks.
     ///
     /// Tasks are run in parallel when possible during execution. The maximum number of parallel
     /// jobs can be set with the `jobs()` method.
     pub fn run<S: AsRef<str>>(&mut self, tasks: &[S]) -> Result<(), Box<Error>> {
+        // Check every task again, since anything could have changed since the last run.
+        self.status.clear();
+        self.run_tasks(tasks)
+    }
+
+    /// Runs the specified list of tasks again after the given files have changed.
+    ///
+    /// Whether tasks are up to date is remembered between runs, and only tasks that depend on the
+    /// changed files, directly or indirectly, are checked again. Changes made to files that are not
+    /// reported here will not be noticed until the next full run.
+    pub fn run_changed<S: AsRef<str>, P: AsRef<Path>>(&mut self, tasks: &[S], changed: &[P]) -> Result<(), Box<Error>> {
+        // Task names are relative to the script directory.
+        let changed: Vec<String> = changed.iter()
+            .map(|path| {
+                let path = path.as_ref();
+                path.strip_prefix(&self.spec.directory).unwrap_or(path).to_string_lossy().into_owned()
+            })
+            .collect();
+
+        for name in self.graph.dependents(&changed) {
+            self.status.remove(&name);
+        }
+
+        self.run_tasks(tasks)
+    }
+
+    /// Runs the specified list of tasks, and then runs them again whenever any of the files they
+    /// depend on change, until Rote is interrupted.
+    ///
+    /// If no tasks are given, the default task is run. When the script itself changes, it is
+    /// reloaded and all tasks are checked again.
+    pub fn watch<S: AsRef<str>>(&mut self, tasks: &[S]) -> Result<(), Box<Error>> {
+        let tasks = try!(self.requested_tasks(tasks));
+        let mut loaded = true;
+        let mut changed: Option<Vec<PathBuf>> = None;
+
+        loop {
+            if loaded {
+                let result = match changed {
+                    Some(ref changed) => self.run_changed(&tasks, changed),
+                    None => self.run(&tasks),
+                };
+
+                if process::interrupted() {
+                    return Err(Box::new(Interrupted("interrupted".to_string())));
+                }
+
+                // Keep watching even if the run failed, so that the problem can be fixed.
+                if let Err(e) = result {
+                    error!("{}", e);
+                }
+            }
+
+            // Watch the script and every file the tasks depend on. Outputs are watched too, since
+            // they could be changed by something else, but changes made by the run are already
+            // over by now and won't be noticed.
+            let mut files = vec![self.spec.path.clone()];
+            if loaded {
+                for task in self.graph.closure(&tasks) {
+                    if task.is_file() {
+                        files.push(self.spec.directory.join(task.name()));
+                    }
+                    files.extend(task.files().iter().chain(task.inputs()).map(|file| self.spec.directory.join(file)));
+                }
+            }
+
+            let mut watcher = Watcher::new(files);
+            info!("watching for changes...");
+
+            let mut changes = Vec::new();
+            while changes.is_empty() {
+                if process::interrupted() {
+                    return Err(Box::new(Interrupted("interrupted".to_string())));
+                }
+
+                thread::sleep(Duration::from_millis(WATCH_INTERVAL));
+                changes = watcher.changed();
+            }
+
+            // Editors often write files in more than one step, so wait for things to settle down
+            // before running anything.
+            loop {
+                thread::sleep(Duration::from_millis(WATCH_INTERVAL));
+                let more = watcher.changed();
+                if more.is_empty() {
+                    break;
+                }
+                changes.extend(more);
+            }
+
+            if changes.contains(&self.spec.path) {
+                info!("script changed, reloading...");
+                changed = None;
+                loaded = match self.reload() {
+                    Ok(()) => true,
+                    Err(e) => {
+                        error!("{}", e);
+                        false
+                    }
+                };
+            } else {
+                debug!("{} file(s) changed", changes.len());
+                changed = Some(changes);
+            }
+        }
+    }
+
+    fn run_tasks<S: AsRef<str>>(&mut self, tasks: &[S]) -> Result<(), Box<Error>> {
+        self.cancelled.store(false, Ordering::SeqCst);
+
+        // Keep other processes from building the project at the same time. Dry runs take the lock
+        // too, since tasks still run in them and commands run for their output may change things.
+        let lock = ProjectLock::acquire(&self.spec.directory, self.wait_for_lock, self.shared_lock);
+        let _lock = match try!(lock) {
+            Some(lock) => lock,
+            None => {
+                return Err("another rote process is already running in this project; use --wait to \
+                            wait for it to finish".into());
+            }
+        };
+
+        // Files may have changed since the script was loaded or since the last run.
+        fscache::clear();
+
+        // Read the build cache again each time, since other processes may have written it since
+        // the last run.
+        let mut cache = Cache::open(&self.spec.directory).hashes_only(self.check_hashes);
+
         // Resolve all tasks given.
         for task in tasks {
             try!(self.resolve_task(task));
         }
 
-        // Determine the schedule of tasks to execute.
-        let mut queue = try!(self.graph.solve(!self.spec.always_run));
-        let task_count = queue.len();
+        // Forced tasks are never up to date, which also makes every task depending on them out of
+        // date.
+        for task in self.forced.clone() {
+            try!(self.resolve_task(&task));
+            self.status.insert(task, false);
+        }
+
+        // Determine the schedule of tasks to execute. If dependencies are disabled, the schedule
+        // is just the requested tasks in the order given.
+        let schedule = if self.no_deps {
+            // Requested tasks still wait for each other, which would never end if they depend on
+            // each other in a circle.
+            try!(self.graph.check_cycles(tasks));
+
+            let mut queue = VecDeque::new();
+            for task in tasks {
+                queue.push_back(try!(self.graph.get(task)));
+            }
+            queue
+        } else {
+            try!(self.graph.solve_cached(tasks, !self.spec.always_run, &mut self.status, Some(&cache)))
+        };
+
+        // Tasks that are run are not up to date anymore, and neither is anything depending on them,
+        // so they have to be checked again next time.
+        let names: Vec<String> = schedule.iter().map(|task| task.name().to_string()).collect();
+        for name in self.graph.dependents(&names) {
+            self.status.remove(&name);
+        }
+
+        // Refer to scheduled tasks by their position in the schedule from here on, so that the
+        // scheduler doesn't have to hash and copy names around. Dependencies that are not
+        // scheduled at all are already satisfied and can be left out.
+        let schedule: Vec<Rc<Task>> = schedule.into_iter().collect();
+        let dependencies: Vec<Vec<usize>> = {
+            let ids: HashMap<&str, usize> = names.iter()
+                .enumerate()
+                .map(|(id, name)| (name.as_str(), id))
+                .collect();
+
+            schedule.iter()
+                .map(|task| {
+                    task.dependencies()
+                        .iter()
+                        .filter_map(|dependency| ids.get(dependency.as_str()).cloned())
+                        .collect()
+                })
+                .collect()
+        };
+
+        // Track how many unfinished dependencies each task has, so that the tasks that become ready
+        // when a task finishes can be found without looking at every task.
+        let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); schedule.len()];
+        for (task_id, task_dependencies) in dependencies.iter().enumerate() {
+            for &dependency in task_dependencies {
+                dependents[dependency].push(task_id);
+            }
+        }
+        let mut waiting_on: Vec<usize> = dependencies.iter().map(|dependencies| dependencies.len()).collect();
+
+        // Tasks whose dependencies have all finished, ordered by priority. Any of them can be
+        // started, but tasks with the longest chains of tasks waiting on them are started first so
+        // that the run finishes sooner, and then earlier tasks in the schedule so that runs are as
+        // predictable as possible.
+        let critical_paths = self.graph.critical_paths(&names);
+        let longest_path = critical_paths.iter().cloned().max().unwrap_or(0);
+        let priorities: Vec<(usize, usize)> = critical_paths.iter()
+            .enumerate()
+            .map(|(task_id, &length)| (longest_path - length, task_id))
+            .collect();
+        let mut ready: BTreeSet<(usize, usize)> = priorities.iter()
+            .cloned()
+            .filter(|&(_, task_id)| waiting_on[task_id] == 0)
+            .collect();
+
+        let task_count = schedule.len();
         let thread_count = cmp::min(self.jobs, task_count);
 
-        debug!("running {} task(s) across {} thread(s)",
+        debug!("running {} task(s) across up to {} thread(s)",
                task_count,
                thread_count);
 
-        // Spawn one thread for each job.
-        let mut threads = Vec::new();
-        let mut free_threads: HashSet<usize> = HashSet::new();
-        let mut channels = Vec::new();
-        let (sender, receiver) = mpsc::channel::<Result<usize, usize>>();
-
-        // Spawn `jobs` number of threads (but no more than the task count!).
-        for thread_id in 0..thread_count {
-            let spec = self.spec.clone();
-            let thread_sender = sender.clone();
+        // Let the script prepare for the run. If it can't, nothing is run.
+        if !self.spec.dry_run {
+            try!(modules::stdlib::call_before_all_hooks(&self.runtime(), &names));
+        }
 
-            let (parent_sender, thread_receiver) = mpsc::sync_channel::<(String, usize)>(0);
-            channels.push(parent_sender);
+        let started = Instant::now();
+        self.report(|reporter| reporter.run_started(&names));
+
+        // Any other task the requested tasks depend on is up to date.
+        if !self.no_deps {
+            let mut skipped: Vec<String> = self.graph
+                .closure(tasks)
+                .into_iter()
+                .map(|task| task.name().to_string())
+                .filter(|name| !names.contains(name))
+                .collect();
+            skipped.sort();
+
+            for name in skipped {
+                self.report(|reporter| reporter.task_skipped(&name));
+            }
+        }
 
-            threads.push(thread::spawn(move || {
-                // Prepare a new runtime.
-                let runtime = spec.create().unwrap_or_else(|e| {
-                    error!("{}", e);
-                    panic!();
-                });
+        // Thread 0 is this thread, which runs tasks inline using the runtime we already have, so
+        // that the script doesn't have to be loaded again. The other threads are workers, which
+        // are spawned lazily as tasks become ready to run, since each one has to load the script
+        // into a runtime of its own first. Running tasks inline keeps this thread from scheduling
+        // tasks on the workers until they are done, so tasks are only run inline when there is
+        // nothing else ready to run, or every worker is busy. Workers kept from a previous run are
+        // reused.
+        let mut inline_idle = true;
+        let mut spawned = 1;
+        let mut free_threads: Vec<usize> = Vec::new();
+        let mut pool = self.workers.take().unwrap_or_else(WorkerPool::new);
+
+        // Keep track of tasks completed and the batch of tasks in progress by each thread.
+        let mut completed_tasks = vec![false; task_count];
+        let mut current_tasks: Vec<Option<Vec<usize>>> = vec![None; thread_count];
+        let mut running = 0;
+
+        // Keep track of tasks that failed, and tasks that can't run because something they depend
+        // on failed, which only happens when keep going is enabled.
+        let mut failed_tasks = vec![false; task_count];
+        let mut failures = Vec::new();
+        let mut blocked = 0;
+
+        // Keep track of how many tasks have been taken from the ready set, and whether the run is
+        // stopping so that no more tasks are started.
+        let mut scheduled = 0;
+        let mut stopped = false;
+
+        // If output is ordered, the output of each task is held in a buffer until the output of
+        // every task before it has been written.
+        let mut buffers: Vec<Option<console::Buffer>> = vec![None; task_count];
+        let mut started_tasks = vec![false; task_count];
+        let mut next_output = 0;
+        let mut internal_failure = false;
+
+        loop {
+            // If the run was cancelled, stop scheduling and wait for running tasks to finish.
+            if self.cancelled.load(Ordering::SeqCst) && !stopped {
+                if scheduled < task_count {
+                    info!("run cancelled, waiting for running tasks to finish...");
+                }
+                stopped = true;
+                ready.clear();
+            }
 
-                if thread_sender.send(Ok(thread_id)).is_err() {
-                    trace!("thread {} failed to send channel", thread_id);
+            // Schedule as many tasks as we can. This is limited by the number of tasks that have
+            // their dependencies already finished, and by the number of threads we may use.
+            loop {
+                let next = match ready.iter().next() {
+                    Some(&(_, next)) => next,
+                    None => break,
+                };
+
+                // If a dependency of the next task failed, the task can never run. Skip it, and
+                // let tasks that depend on it be skipped in turn.
+                if let Some(&dependency) = dependencies[next].iter().find(|&&dependency| failed_tasks[dependency]) {
+                    warn!("not running task '{}' because '{}' failed", names[next], names[dependency]);
+                    ready.remove(&priorities[next]);
+                    scheduled += 1;
+                    completed_tasks[next] = true;
+                    failed_tasks[next] = true;
+                    blocked += 1;
+                    release_dependents(next, &dependents, &priorities, &mut waiting_on, &mut ready);
+                    continue;
                 }
 
-                // Begin executing tasks!
-                while let Ok((name, task_id)) = thread_receiver.recv() {
-                    info!("running task '{}' ({} of {})", name, task_id, task_count);
+                // If the system is too busy, wait for a running task to finish before starting any
+                // more. The load is only checked while other tasks are running, so that we always
+                // make progress.
+                if let Some(max_load) = self.max_load {
+                    if running > 0 && load_average().map(|load| load > max_load).unwrap_or(false) {
+                        trace!("load average is above {}, holding back tasks", max_load);
+                        break;
+                    }
+                }
 
-                    // Lookup the task to run.
-                    let task = {
-                        // Lookup the task to run.
-                        if let Some(task) = runtime.environment().get_task(&name) {
-                            task as Rc<Task>
-                        }
-                        // Find a rule that matches the task name.
-                        else if let Some(rule) = runtime.environment()
-                            .rules()
-                            .iter()
-                            .find(|rule| rule.matches(&name)) {
-                            Rc::new(rule.create_task(name).unwrap()) as Rc<Task>
-                        }
-                        // No matching task.
-                        else {
-                            panic!("no matching task or rule for '{}'", name);
-                        }
-                    };
+                // If the task is cheap, other cheap tasks that are also ready are sent to the
+                // thread along with it, so that dispatching them doesn't take longer than running
+                // them.
+                let mut batch_ids = vec![next];
+                if schedule[next].is_cheap() {
+                    batch_ids.extend(ready.iter()
+                        .map(|&(_, task_id)| task_id)
+                        .filter(|&task_id| {
+                            task_id != next && schedule[task_id].is_cheap() &&
+                            !dependencies[task_id].iter().any(|&dependency| failed_tasks[dependency])
+                        })
+                        .take(MAX_BATCH_SIZE - 1));
+                }
 
-                    // Check for dry run.
-                    if !spec.dry_run {
-                        if let Err(e) = task.run() {
-                            // If we ought to keep going, just issue a warning.
-                            if spec.keep_going {
-                                warn!("ignoring error: {}", e);
-                            } else {
-                                error!("{}", e);
-                                thread_sender.send(Err(thread_id)).unwrap();
-                                return;
-                            }
-                        }
+                // Pick a free thread to run the task in, or start a new one if we can.
+                let last = batch_ids.len() == ready.len();
+                let thread_id = if let Some(thread_id) = free_threads.pop() {
+                    thread_id
+                } else if inline_idle && (last || spawned == thread_count) {
+                    0
+                } else if spawned < thread_count {
+                    let thread_id = spawned;
+                    spawned += 1;
+                    thread_id
+                } else {
+                    // We can schedule now, but there aren't any free threads. 😢
+                    break;
+                };
+
+                // Take the tasks from the ready set.
+                let mut batch = Vec::new();
+                for task_id in batch_ids {
+                    ready.remove(&priorities[task_id]);
+                    scheduled += 1;
+
+                    // Tasks are numbered in the order they are started in, which depends on
+                    // timing. Use the position in the schedule instead if output is ordered.
+                    let number = if self.spec.ordered_output {
+                        buffers[task_id] = Some(console::Buffer::new());
+                        task_id + 1
                     } else {
-                        info!("would run task '{}'", task.name());
-                    }
+                        scheduled
+                    };
+                    batch.push((task_id, number));
+                }
 
-                    if thread_sender.send(Ok(thread_id)).is_err() {
-                        trace!("thread {} failed to send channel", thread_id);
-                        break;
-                    }
+                for &(task_id, _) in &batch {
+                    trace!("scheduling task '{}' on thread {}", names[task_id], thread_id);
                 }
-            }))
-        }
 
-        drop(sender);
+                current_tasks[thread_id] = Some(batch.iter().map(|&(task_id, _)| task_id).collect());
+                running += 1;
 
-        // Keep track of tasks completed and tasks in progress.
-        let mut completed_tasks: HashSet<String> = HashSet::new();
-        let mut current_tasks: HashMap<usize, String> = HashMap::new();
-        let all_tasks: HashSet<String> = queue.iter().map(|s| s.name().to_string()).collect();
+                let jobs = batch.iter()
+                    .map(|&(task_id, number)| {
+                        Job {
+                            name: names[task_id].clone(),
+                            number: number,
+                            buffer: buffers[task_id].clone(),
+                        }
+                    })
+                    .collect();
+
+                if thread_id == 0 {
+                    // Run the tasks right away, and handle the results like any other.
+                    inline_idle = false;
+                    let runtime = self.runtime();
+                    runtime.environment().set_capture_output(self.spec.capture_output);
+                    // Output can't get mixed up with only one thread, so it is only prefixed if there
+                    // are workers too.
+                    let prefix_output = thread_count > 1 && self.spec.prefix_output;
+                    runtime.environment().set_prefix_output(prefix_output);
+
+                    // Report each task as it starts, since nothing else is listening while the
+                    // batch runs.
+                    let outcomes = {
+                        let reporters = &mut self.reporters;
+                        let batch_ids = current_tasks[thread_id].as_ref().unwrap();
+                        run_batch(&runtime, &self.spec, jobs, task_count, &mut |index| {
+                            let task_id = batch_ids[index];
+                            started_tasks[task_id] = true;
+                            console::set_buffer(buffers[task_id].clone());
+                            for reporter in reporters.iter_mut() {
+                                reporter.task_started(&names[task_id]);
+                            }
+                            console::set_buffer(None);
+                        })
+                    };
 
-        while !queue.is_empty() || !current_tasks.is_empty() {
-            // Wait for a thread to request a task.
-            let result = receiver.recv().unwrap();
+                    pool.sender.send(WorkerMessage::Finished {
+                        thread_id: thread_id,
+                        outcomes: outcomes,
+                    }).unwrap();
+                }
+                // Send the task names.
+                else if pool.get(thread_id, &self.spec)
+                    .channel
+                    .send(Batch {
+                        jobs: jobs,
+                        task_count: task_count,
+                    })
+                    .is_err() {
+                    let message = format!("failed to send task to thread {}", thread_id);
+                    return Err(Box::new(InternalError(message)));
+                }
+            }
 
-            // If the thread sent an error, we should stop everything if keep_going isn't enabled.
-            if let Err(thread_id) = result {
-                debug!("thread {} errored, waiting for remaining tasks...",
-                       thread_id);
-                return Err("not all tasks completed successfully".into());
+            // Once there is nothing left to schedule, idle threads will never be needed again in
+            // this run, so let them exit unless they are being kept for the next one.
+            if (stopped || scheduled == task_count) && !self.keep_workers {
+                for thread_id in free_threads.drain(..) {
+                    trace!("retiring idle thread {}", thread_id);
+                    pool.retire(thread_id);
+                }
             }
 
-            let thread_id = result.unwrap();
-            free_threads.insert(thread_id);
-            trace!("thread {} is idle", thread_id);
+            if running == 0 {
+                break;
+            }
 
-            // If the thread was previously running a task, mark it as completed.
-            if let Some(task) = current_tasks.remove(&thread_id) {
-                trace!("task '{}' completed", task);
-                completed_tasks.insert(task);
+            // Wait for a thread to finish its tasks.
+            let (thread_id, outcomes) = match pool.receiver.recv().unwrap() {
+                WorkerMessage::Started { thread_id, index } => {
+                    let task_id = current_tasks[thread_id].as_ref().unwrap()[index];
+                    started_tasks[task_id] = true;
+                    console::set_buffer(buffers[task_id].clone());
+                    self.report(|reporter| reporter.task_started(&names[task_id]));
+                    console::set_buffer(None);
+                    continue;
+                }
+                WorkerMessage::Finished { thread_id, outcomes } => (thread_id, outcomes),
+            };
+            let batch = current_tasks[thread_id].take().unwrap();
+            let panicked = outcomes.iter().any(|outcome| outcome.panicked);
+            let finished = outcomes.len();
+            running -= 1;
+
+            // A batch stops at the first task that fails, so the rest of the batch may not have
+            // been run. Those tasks were never started, so they are not reported at all.
+            for &task_id in &batch[finished..] {
+                trace!("task '{}' was not run", names[task_id]);
+                completed_tasks[task_id] = true;
             }
 
-            // Attempt to schedule more tasks to run. The most we can schedule is the number of free
-            // threads, but it is limited by the number of tasks that have their dependencies already
-            // finished.
-            'schedule: for _ in 0..free_threads.len() {
-                // If the queue is empty, we are done.
-                if queue.is_empty() {
-                    break;
+            for (task_id, outcome) in batch.into_iter().zip(outcomes) {
+                // Messages about the task belong with the rest of its output.
+                console::set_buffer(buffers[task_id].clone());
+
+                // A worker that could not set up its runtime fails its tasks without starting
+                // them.
+                if !started_tasks[task_id] {
+                    started_tasks[task_id] = true;
+                    self.report(|reporter| reporter.task_started(&names[task_id]));
                 }
 
-                // Check the next task in the queue. If any of its dependencies have not yet been
-                // completed, we cannot schedule it yet.
-                for dependency in queue.front().unwrap().dependencies() {
-                    // Check that the dependency needs scheduled at all (some are already satisfied),
-                    // and that it hasn't already finished.
-                    if all_tasks.contains(dependency) && !completed_tasks.contains(dependency) {
-                        // We can't run the next task, so we're done scheduling for now until another
-                        // thread finishes.
-                        break 'schedule;
+                let report = TaskReport {
+                    name: names[task_id].clone(),
+                    duration: outcome.duration,
+                    error: outcome.result.err(),
+                    output: outcome.output,
+                    diagnostics: outcome.diagnostics,
+                };
+
+                self.report(|reporter| reporter.task_finished(&report));
+
+                // If the task failed, we should stop scheduling tasks if keep_going isn't enabled.
+                // Tasks that are already running are allowed to finish so that nothing is left
+                // running in the background when we exit.
+                if let Some(ref e) = report.error {
+                    failed_tasks[task_id] = true;
+                    failures.push(names[task_id].clone());
+
+                    if outcome.panicked {
+                        internal_failure = true;
                     }
-                }
 
-                // Get the available task from the queue.
-                let task = queue.front().unwrap().clone();
+                    // A panic is a bug in Rote, so nothing else can be trusted to run correctly.
+                    if self.spec.keep_going && !outcome.panicked {
+                        warn!("ignoring error: {}", e);
+                    } else {
+                        error!("{}", e);
 
-                // Pick a free thread to run the task in.
-                if let Some(thread_id) = free_threads.iter().next().map(|t| *t) {
-                    trace!("scheduling task '{}' on thread {}", task.name(), thread_id);
-                    let data = (task.name().to_string(), task_count - queue.len() + 1);
+                        if !stopped && scheduled < task_count {
+                            debug!("thread {} errored, waiting for remaining tasks...", thread_id);
+                        }
+                        stopped = true;
+                        ready.clear();
+
+                        // Stop the commands of other running tasks instead of waiting for them, and
+                        // don't retry them after they are stopped. If Rote was interrupted, they
+                        // are already being given a chance to stop on their own.
+                        if running > 0 && !process::interrupted() {
+                            self.cancelled.store(true, Ordering::SeqCst);
+                            process::kill_all();
+                        }
+                    }
+                }
 
-                    // Send the task name.
-                    if channels[thread_id].send(data).is_ok() {
-                        current_tasks.insert(thread_id, task.name().to_string());
-                        free_threads.remove(&thread_id);
+                console::set_buffer(None);
 
-                        // Scheduling was successful, so remove the task frome the queue.
-                        queue.pop_front().unwrap();
+                // Remember the files of file tasks, so that they are not run again just because
+                // their timestamps change.
+                if !self.spec.dry_run && schedule[task_id].is_file() {
+                    if report.error.is_none() {
+                        cache.record(&*schedule[task_id]);
                     } else {
-                        trace!("failed to send channel to thread {}", thread_id);
+                        cache.forget(&names[task_id]);
                     }
-                } else {
-                    // We can schedule now, but there aren't any free threads. 😢
-                    break;
                 }
+
+                trace!("task '{}' completed", names[task_id]);
+                completed_tasks[task_id] = true;
+
+                if !stopped {
+                    release_dependents(task_id, &dependents, &priorities, &mut waiting_on, &mut ready);
+                }
+            }
+
+            // Write the output of finished tasks, as long as every task before them has finished.
+            while next_output < task_count && completed_tasks[next_output] {
+                if let Some(ref buffer) = buffers[next_output] {
+                    buffer.release();
+                }
+                next_output += 1;
+            }
+
+            // A worker stops after a panic, so it can't be given any more tasks.
+            if thread_id == 0 {
+                inline_idle = true;
+            } else if panicked {
+                trace!("thread {} stopped after a panic", thread_id);
+                pool.retire(thread_id);
+            } else {
+                free_threads.push(thread_id);
+                trace!("thread {} is idle", thread_id);
             }
         }
 
-        // Close the input and wait for any remaining threads to finish.
-        drop(channels);
-        for (thread_id, thread) in threads.into_iter().enumerate() {
-            if let Err(e) = thread.join() {
-                trace!("thread {} closed with panic: {:?}", thread_id, e);
+        // Close the input and wait for any remaining threads to finish, or keep them waiting for
+        // the next run.
+        if self.keep_workers {
+            pool.join_retired();
+            self.workers = Some(pool);
+        } else {
+            pool.shutdown();
+        }
+
+        if let Err(e) = cache.save() {
+            warn!("failed to write build cache: {}", e);
+        }
+
+        // Tasks that were scheduled but never run because the run stopped early may still have
+        // output waiting.
+        for buffer in &buffers[next_output..] {
+            if let Some(ref buffer) = *buffer {
+                buffer.release();
             }
         }
 
+        // Let the script clean up after the run, whether or not it succeeded.
+        let mut hook_failed = false;
+        if !self.spec.dry_run {
+            let success = !internal_failure && failures.is_empty() &&
+                          !self.cancelled.load(Ordering::SeqCst);
+
+            if let Err(e) = modules::stdlib::call_after_all_hooks(&self.runtime(), success) {
+                error!("{}", e);
+                hook_failed = true;
+            }
+        }
+
+        // Tasks fail when their commands are stopped, but that isn't their fault.
+        if process::interrupted() {
+            self.cancelled.store(false, Ordering::SeqCst);
+            self.finish_reports(started, false);
+            return Err(Box::new(Interrupted("interrupted".to_string())));
+        }
+
+        if internal_failure {
+            self.finish_reports(started, false);
+            return Err(Box::new(InternalError("a task panicked".to_string())));
+        }
+
+        if !failures.is_empty() {
+            if blocked > 0 {
+                warn!("{} task(s) were not run because a task they depend on failed", blocked);
+            }
+
+            self.finish_reports(started, false);
+            return Err(Box::new(TaskFailure(format!("{} task(s) failed: {}", failures.len(), failures.join(", ")))));
+        }
+
+        if self.cancelled.swap(false, Ordering::SeqCst) {
+            self.finish_reports(started, false);
+            return Err(Box::new(TaskFailure("run cancelled".to_string())));
+        }
+
+        if hook_failed {
+            self.finish_reports(started, false);
+            return Err(Box::new(TaskFailure("after_all hook failed".to_string())));
+        }
+
+        self.finish_reports(started, true);
+
         info!("all tasks up to date");
         Ok(())
     }
 
+    /// Adds a reporter to receive events about tasks being run.
+    pub fn add_reporter(&mut self, reporter: Box<Reporter>) {
+        if reporter.wants_output() {
+            self.spec.capture_output = true;
+        }
+
+        self.reporters.push(reporter);
+    }
+
+    /// Removes the most recently added reporter.
+    pub fn pop_reporter(&mut self) -> Option<Box<Reporter>> {
+        self.reporters.pop()
+    }
+
+    /// Invokes a callback for each registered reporter.
+    fn report<F: FnMut(&mut Reporter)>(&mut self, mut f: F) {
+        for reporter in &mut self.reporters {
+            f(&mut **reporter);
+        }
+    }
+
+    /// Notifies all reporters that the run has finished.
+    fn finish_reports(&mut self, started: Instant, success: bool) {
+        let duration = started.elapsed();
+
+        for reporter in &mut self.reporters {
+            if let Err(e) = reporter.run_finished(duration, success) {
+                warn!("failed to write report: {}", e);
+            }
+        }
+    }
+
+    /// Gets the list of task names to operate on, falling back to the default task if none are
+    /// given.
+    fn requested_tasks<S: AsRef<str>>(&self, tasks: &[S]) -> Result<Vec<String>, Box<Error>> {
+        if !tasks.is_empty() {
+            Ok(tasks.iter().map(|task| task.as_ref().to_string()).collect())
+        } else if let Some(name) = self.runtime().environment().default_task() {
+            Ok(vec![name])
+        } else {
+            Err("no default task defined".into())
+        }
+    }
+
     fn resolve_task<S: AsRef<str>>(&mut self, name: S) -> Result<(), Box<Error>> {
         if !self.graph.contains(&name) {
             // Lookup the task to run.
@@ -421,3 +1486,369 @@ impl Runner {
         self.runtime.as_ref().unwrap().clone()
     }
 }
+
+/// Spawns a worker thread that runs tasks sent to it until its channel is closed.
+fn spawn_worker(thread_id: usize,
+                spec: EnvironmentSpec,
+                sender: mpsc::Sender<WorkerMessage>)
+                -> Worker {
+    // Allow one batch to be queued so that the master thread doesn't have to wait for the runtime
+    // to be created.
+    let (channel, receiver) = mpsc::sync_channel::<Batch>(1);
+
+    let thread = thread::spawn(move || {
+        trace!("thread {} started", thread_id);
+
+        // Prepare a new runtime. If this fails, every task sent to this thread fails with the error.
+        let runtime = match panic::catch_unwind(AssertUnwindSafe(|| spec.create())) {
+            Ok(runtime) => runtime.map_err(|e| e.to_string()),
+            Err(_) => Err("internal error: failed to create runtime".to_string()),
+        };
+
+        // Begin executing tasks!
+        while let Ok(Batch { jobs, task_count }) = receiver.recv() {
+            let outcomes = match runtime {
+                Ok(ref runtime) => {
+                    run_batch(runtime, &spec, jobs, task_count, &mut |index| {
+                        sender.send(WorkerMessage::Started {
+                                thread_id: thread_id,
+                                index: index,
+                            })
+                            .ok();
+                    })
+                }
+                Err(ref e) => {
+                    jobs.iter()
+                        .map(|_| {
+                            TaskOutcome {
+                                result: Err(e.clone()),
+                                duration: Duration::new(0, 0),
+                                output: String::new(),
+                                diagnostics: Vec::new(),
+                                panicked: false,
+                            }
+                        })
+                        .collect()
+                }
+            };
+
+            // The runtime can't be trusted after a panic, so don't run anything else in it.
+            let panicked = outcomes.iter().any(|outcome| outcome.panicked);
+
+            let message = WorkerMessage::Finished {
+                thread_id: thread_id,
+                outcomes: outcomes,
+            };
+
+            if sender.send(message).is_err() {
+                trace!("thread {} failed to send channel", thread_id);
+                break;
+            }
+
+            if panicked {
+                break;
+            }
+        }
+
+        trace!("thread {} exiting", thread_id);
+    });
+
+    Worker {
+        channel: channel,
+        thread: thread,
+    }
+}
+
+/// Runs a batch of tasks one after another in a worker runtime, stopping at the first task that
+/// fails unless errors are ignored. `started` is called with the position of each task in the
+/// batch just before it runs, so tasks after a failure are never reported as started.
+fn run_batch(runtime: &Runtime,
+             spec: &EnvironmentSpec,
+             batch: Vec<Job>,
+             task_count: usize,
+             started: &mut FnMut(usize))
+             -> Vec<TaskOutcome> {
+    // Which tasks end up in a batch depends on timing, so batches aren't logged if output is
+    // ordered.
+    let batched = batch.len() > 1 && !spec.ordered_output;
+    if batched {
+        info!("running {} tasks ({} to {} of {})",
+              batch.len(),
+              batch[0].number,
+              batch[batch.len() - 1].number,
+              task_count);
+    }
+
+    let mut outcomes = Vec::new();
+
+    for (index, Job { name, number, buffer }) in batch.into_iter().enumerate() {
+        started(index);
+        let previous_buffer = console::set_buffer(buffer);
+
+        if batched {
+            debug!("running task '{}' ({} of {})", name, number, task_count);
+        } else {
+            info!("running task '{}' ({} of {})", name, number, task_count);
+        }
+
+        // Catch panics so that the master thread hears about the task instead of waiting for it
+        // forever.
+        let started = Instant::now();
+        let (result, panicked) = match panic::catch_unwind(AssertUnwindSafe(|| {
+            run_task(runtime, spec, name.clone())
+        })) {
+            Ok(result) => (result, false),
+            Err(_) => (Err(format!("internal error: task '{}' panicked", name)), true),
+        };
+        let failed = result.is_err();
+
+        outcomes.push(TaskOutcome {
+            result: result,
+            duration: started.elapsed(),
+            output: runtime.environment().take_output(),
+            diagnostics: runtime.environment().take_diagnostics(),
+            panicked: panicked,
+        });
+
+        console::set_buffer(previous_buffer);
+
+        if panicked || failed && !spec.keep_going {
+            break;
+        }
+    }
+
+    outcomes
+}
+
+/// Marks a task as finished for the tasks that depend on it, adding any of them that have no
+/// unfinished dependencies left to the ready set with their priority.
+fn release_dependents(task_id: usize,
+                      dependents: &[Vec<usize>],
+                      priorities: &[(usize, usize)],
+                      waiting_on: &mut [usize],
+                      ready: &mut BTreeSet<(usize, usize)>) {
+    for &dependent in &dependents[task_id] {
+        waiting_on[dependent] -= 1;
+        if waiting_on[dependent] == 0 {
+            ready.insert(priorities[dependent]);
+        }
+    }
+}
+
+/// Runs a single task by name in a worker runtime.
+fn run_task(runtime: &Runtime, spec: &EnvironmentSpec, name: String) -> Result<(), String> {
+    // Lookup the task to run.
+    let task = {
+        // Lookup the task to run.
+        if let Some(task) = runtime.environment().get_task(&name) {
+            task as Rc<Task>
+        }
+        // Find a rule that matches the task name.
+        else if let Some(rule) = runtime.environment()
+            .rules()
+            .iter()
+            .find(|rule| rule.matches(&name)) {
+            Rc::new(rule.create_task(name).unwrap()) as Rc<Task>
+        }
+        // No matching task.
+        else {
+            return Err(format!("no matching task or rule for '{}'", name));
+        }
+    };
+
+    // Earlier tasks may have changed files since they were last checked.
+    fscache::clear();
+
+    // In a dry run, the task still runs, but the commands it runs are only shown. Hooks run in
+    // the same runtime as the task, so they can share state with it.
+    if !spec.dry_run {
+        try!(modules::stdlib::call_before_hooks(runtime, task.name()).map_err(|e| e.to_string()));
+        let result = run_cached(&*task, spec, &runtime.environment().scripts());
+        let hooks_result = modules::stdlib::call_after_hooks(runtime, task.name(), &result);
+
+        result.and(hooks_result.map_err(|e| e.to_string()))
+    } else {
+        info!("running task '{}' without running commands", task.name());
+        task.run().map_err(|e| e.to_string())
+    }
+}
+
+/// Runs a task, restoring its outputs from the output cache instead if it is cached and its inputs
+/// are the same as a previous run. The outputs of a cached task are stored after it succeeds.
+fn run_cached(task: &Task, spec: &EnvironmentSpec, scripts: &[PathBuf]) -> Result<(), String> {
+    if !task.is_cached() {
+        return run_with_retries(task, spec);
+    }
+
+    let mut cache = OutputCache::open(&spec.directory);
+    if let Some(ref url) = spec.cache_url {
+        // The URL was checked when it was set.
+        cache = cache.remote(artifacts::backend(url).unwrap());
+    }
+
+    let key = match cache.key(task, scripts) {
+        Ok(key) => key,
+        Err(e) => {
+            warn!("not caching outputs of task '{}': {}", task.name(), e);
+            return run_with_retries(task, spec);
+        }
+    };
+
+    // Running every task means running them, not restoring their outputs.
+    if !spec.always_run {
+        match cache.restore(&key, task) {
+            Ok(true) => {
+                info!("restored outputs of task '{}' from cache", task.name());
+                return Ok(());
+            }
+            Ok(false) => {}
+            Err(e) => warn!("failed to restore outputs of task '{}' from cache: {}", task.name(), e),
+        }
+    }
+
+    try!(run_with_retries(task, spec));
+
+    if let Err(e) = cache.store(&key, task) {
+        warn!("failed to cache outputs of task '{}': {}", task.name(), e);
+    }
+
+    Ok(())
+}
+
+/// Runs a task, running it again if it fails for as many times as the task allows.
+fn run_with_retries(task: &Task, spec: &EnvironmentSpec) -> Result<(), String> {
+    let retry = task.retry();
+    let mut tries = 0;
+
+    loop {
+        let error = match run_with_timeout(task, spec) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+
+        if tries >= retry.count || spec.cancelled.load(Ordering::SeqCst) {
+            return Err(error);
+        }
+        tries += 1;
+
+        warn!("task '{}' failed, retrying ({} of {}): {}", task.name(), tries, retry.count, error);
+        thread::sleep(retry.delay);
+
+        // The failed try may have changed files.
+        fscache::clear();
+    }
+}
+
+/// Runs a task, killing its commands and failing it if it takes longer than its timeout.
+fn run_with_timeout(task: &Task, spec: &EnvironmentSpec) -> Result<(), String> {
+    let timeout = match task.timeout().or(spec.timeout) {
+        Some(timeout) => timeout,
+        None => return task.run().map_err(|e| e.to_string()),
+    };
+
+    let watchdog = process::Watchdog::start(timeout);
+    let result = task.run().map_err(|e| e.to_string());
+
+    // Whatever error the task failed with, it is because its commands were killed.
+    if watchdog.finish() {
+        Err(format!("task '{}' timed out after {}s", task.name(), report::seconds(timeout)))
+    } else {
+        result
+    }
+}
+
+/// Parses a number of jobs to run at once, which is either a number, `auto` for as many jobs as
+/// there are CPU cores, or a percentage of the number of CPU cores such as `50%`. At least one job
+/// is always run.
+pub fn parse_jobs(value: &str) -> Option<usize> {
+    let value = value.trim();
+
+    if value == "auto" {
+        Some(num_cpus::get())
+    } else if value.ends_with('%') {
+        match value[..value.len() - 1].parse::<usize>() {
+            Ok(percent) if percent > 0 => Some(cmp::max(1, num_cpus::get() * percent / 100)),
+            _ => None,
+        }
+    } else {
+        match value.parse::<usize>() {
+            Ok(jobs) if jobs > 0 => Some(jobs),
+            _ => None,
+        }
+    }
+}
+
+/// Gets the name of the platform Rote is running on.
+///
+/// On Windows this is the toolchain environment, which is either `msvc`, `mingw`, or `msys` when
+/// running inside an MSYS2 or Git Bash shell. Linux running under the Windows Subsystem for Linux
+/// is reported as `wsl`, and other systems use their usual names such as `linux` or `macos`.
+fn platform() -> &'static str {
+    if cfg!(windows) {
+        if env::var_os("MSYSTEM").is_some() {
+            "msys"
+        } else if cfg!(target_env = "msvc") {
+            "msvc"
+        } else {
+            "mingw"
+        }
+    } else if cfg!(target_os = "linux") && is_wsl() {
+        "wsl"
+    } else {
+        env::consts::OS
+    }
+}
+
+/// Checks if we are running under the Windows Subsystem for Linux.
+fn is_wsl() -> bool {
+    if env::var_os("WSL_DISTRO_NAME").is_some() {
+        return true;
+    }
+
+    let mut version = String::new();
+    File::open("/proc/version")
+        .and_then(|mut file| file.read_to_string(&mut version))
+        .map(|_| version.to_lowercase().contains("microsoft"))
+        .unwrap_or(false)
+}
+
+/// Gets the system load average over the last minute, if it is available.
+#[cfg(unix)]
+fn load_average() -> Option<f64> {
+    use std::os::raw::{c_double, c_int};
+
+    extern "C" {
+        fn getloadavg(loadavg: *mut c_double, nelem: c_int) -> c_int;
+    }
+
+    let mut load = 0.0;
+    if unsafe { getloadavg(&mut load, 1) } == 1 {
+        Some(load)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn load_average() -> Option<f64> {
+    None
+}
+
+/// Quotes a string as a Lua string literal.
+fn lua_quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\0' => quoted.push_str("\\0"),
+            c => quoted.push(c),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
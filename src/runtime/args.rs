@@ -0,0 +1,340 @@
+//! Conversion of the arguments of functions called from Lua into Rust values.
+//!
+//! Functions callable from Lua get their arguments on the stack. Instead of checking the type of
+//! each value by hand, `Runtime::args()` gives typed accessors that convert arguments and fields of
+//! option tables, and produce errors that say which argument or field was wrong:
+//!
+//! ```ignore
+//! let args = runtime.args();
+//! let url = try!(args.string(1));
+//! let options: DownloadOptions = try!(args.options(3));
+//! ```
+use lua;
+use std::error::Error;
+use std::path::PathBuf;
+use super::Runtime;
+
+
+/// A type that can be converted from a Lua value.
+pub trait FromLua: Sized {
+    /// Converts the value at the given stack index, or returns `None` if it has the wrong type.
+    fn from_lua(runtime: &Runtime, index: lua::Index) -> Option<Self>;
+
+    /// Describes the type of Lua value expected, for error messages.
+    fn expected() -> &'static str;
+}
+
+/// A type that can be decoded from a table of options, such as the last argument of many
+/// functions.
+pub trait FromTable: Default {
+    /// Decodes the fields of a table.
+    fn from_table(table: &Table) -> Result<Self, Box<Error>>;
+}
+
+
+impl FromLua for String {
+    fn from_lua(runtime: &Runtime, index: lua::Index) -> Option<String> {
+        if runtime.state().is_string(index) {
+            runtime.state().to_str(index).map(String::from)
+        } else {
+            None
+        }
+    }
+
+    fn expected() -> &'static str {
+        "string"
+    }
+}
+
+impl FromLua for PathBuf {
+    fn from_lua(runtime: &Runtime, index: lua::Index) -> Option<PathBuf> {
+        if runtime.state().is_string(index) {
+            runtime.to_path(index)
+        } else {
+            None
+        }
+    }
+
+    fn expected() -> &'static str {
+        "path"
+    }
+}
+
+impl FromLua for f64 {
+    fn from_lua(runtime: &Runtime, index: lua::Index) -> Option<f64> {
+        if runtime.state().is_number(index) {
+            runtime.state().to_numberx(index)
+        } else {
+            None
+        }
+    }
+
+    fn expected() -> &'static str {
+        "number"
+    }
+}
+
+impl FromLua for i64 {
+    fn from_lua(runtime: &Runtime, index: lua::Index) -> Option<i64> {
+        if runtime.state().is_number(index) {
+            runtime.state().to_integerx(index)
+        } else {
+            None
+        }
+    }
+
+    fn expected() -> &'static str {
+        "integer"
+    }
+}
+
+impl FromLua for bool {
+    fn from_lua(runtime: &Runtime, index: lua::Index) -> Option<bool> {
+        if runtime.state().is_bool(index) {
+            Some(runtime.state().to_bool(index))
+        } else {
+            None
+        }
+    }
+
+    fn expected() -> &'static str {
+        "boolean"
+    }
+}
+
+/// A list of strings can also be given as a single string.
+impl FromLua for Vec<String> {
+    fn from_lua(runtime: &Runtime, index: lua::Index) -> Option<Vec<String>> {
+        if runtime.state().is_string(index) {
+            return String::from_lua(runtime, index).map(|value| vec![value]);
+        }
+
+        if !runtime.state().is_table(index) {
+            return None;
+        }
+
+        let index = runtime.state().abs_index(index);
+        let len = runtime.state().raw_len(index) as i64;
+        let mut values = Vec::new();
+
+        for i in 1..len + 1 {
+            runtime.state().raw_geti(index, i);
+            let value = String::from_lua(runtime, -1);
+            runtime.state().pop(1);
+
+            match value {
+                Some(value) => values.push(value),
+                None => return None,
+            }
+        }
+
+        Some(values)
+    }
+
+    fn expected() -> &'static str {
+        "string or list of strings"
+    }
+}
+
+
+/// The arguments of the function being called.
+pub struct Args<'a> {
+    runtime: &'a Runtime,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(runtime: &'a Runtime) -> Args<'a> {
+        Args {
+            runtime: runtime,
+        }
+    }
+
+    /// Gets the number of arguments.
+    pub fn len(&self) -> i32 {
+        self.runtime.state().get_top()
+    }
+
+    /// Gets a required argument.
+    pub fn get<T: FromLua>(&self, n: i32) -> Result<T, Box<Error>> {
+        match try!(self.opt(n)) {
+            Some(value) => Ok(value),
+            None => Err(self.error::<T>(n)),
+        }
+    }
+
+    /// Gets an optional argument, which is `None` if it is nil or not given.
+    pub fn opt<T: FromLua>(&self, n: i32) -> Result<Option<T>, Box<Error>> {
+        if self.runtime.state().is_none_or_nil(n) {
+            return Ok(None);
+        }
+
+        match T::from_lua(self.runtime, n) {
+            Some(value) => Ok(Some(value)),
+            None => Err(self.error::<T>(n)),
+        }
+    }
+
+    pub fn string(&self, n: i32) -> Result<String, Box<Error>> {
+        self.get(n)
+    }
+
+    pub fn opt_string(&self, n: i32) -> Result<Option<String>, Box<Error>> {
+        self.opt(n)
+    }
+
+    pub fn path(&self, n: i32) -> Result<PathBuf, Box<Error>> {
+        self.get(n)
+    }
+
+    pub fn opt_path(&self, n: i32) -> Result<Option<PathBuf>, Box<Error>> {
+        self.opt(n)
+    }
+
+    pub fn integer(&self, n: i32) -> Result<i64, Box<Error>> {
+        self.get(n)
+    }
+
+    pub fn opt_integer(&self, n: i32) -> Result<Option<i64>, Box<Error>> {
+        self.opt(n)
+    }
+
+    pub fn number(&self, n: i32) -> Result<f64, Box<Error>> {
+        self.get(n)
+    }
+
+    pub fn opt_bool(&self, n: i32) -> Result<Option<bool>, Box<Error>> {
+        self.opt(n)
+    }
+
+    /// Gets an argument that is a string or a list of strings.
+    pub fn list(&self, n: i32) -> Result<Vec<String>, Box<Error>> {
+        self.get(n)
+    }
+
+    /// Gets a required table argument.
+    pub fn table(&self, n: i32) -> Result<Table<'a>, Box<Error>> {
+        match try!(self.opt_table(n)) {
+            Some(table) => Ok(table),
+            None => Err(self.type_error(n, "table")),
+        }
+    }
+
+    /// Gets an optional table argument.
+    pub fn opt_table(&self, n: i32) -> Result<Option<Table<'a>>, Box<Error>> {
+        if self.runtime.state().is_none_or_nil(n) {
+            return Ok(None);
+        }
+
+        if !self.runtime.state().is_table(n) {
+            return Err(self.type_error(n, "table"));
+        }
+
+        Ok(Some(Table {
+            runtime: self.runtime,
+            index: self.runtime.state().abs_index(n),
+            arg: n,
+        }))
+    }
+
+    /// Decodes an optional table of options, using the defaults if it isn't given.
+    pub fn options<T: FromTable>(&self, n: i32) -> Result<T, Box<Error>> {
+        match try!(self.opt_table(n)) {
+            Some(table) => T::from_table(&table),
+            None => Ok(T::default()),
+        }
+    }
+
+    fn error<T: FromLua>(&self, n: i32) -> Box<Error> {
+        self.type_error(n, T::expected())
+    }
+
+    fn type_error(&self, n: i32, expected: &str) -> Box<Error> {
+        format!("bad argument #{} ({} expected, got {})", n, expected, type_name(self.runtime, n)).into()
+    }
+}
+
+
+/// A table passed as an argument.
+pub struct Table<'a> {
+    runtime: &'a Runtime,
+    index: lua::Index,
+    arg: i32,
+}
+
+impl<'a> Table<'a> {
+    /// Gets the stack index of the table.
+    pub fn index(&self) -> lua::Index {
+        self.index
+    }
+
+    /// Gets a required field.
+    pub fn get<T: FromLua>(&self, field: &str) -> Result<T, Box<Error>> {
+        match try!(self.opt(field)) {
+            Some(value) => Ok(value),
+            None => Err(self.error(field, T::expected())),
+        }
+    }
+
+    /// Gets an optional field, which is `None` if it is nil.
+    pub fn opt<T: FromLua>(&self, field: &str) -> Result<Option<T>, Box<Error>> {
+        self.runtime.state().get_field(self.index, field);
+
+        let value = if self.runtime.state().is_nil(-1) {
+            Ok(None)
+        } else {
+            match T::from_lua(self.runtime, -1) {
+                Some(value) => Ok(Some(value)),
+                None => Err(self.error(field, T::expected())),
+            }
+        };
+
+        self.runtime.state().pop(1);
+        value
+    }
+
+    pub fn string(&self, field: &str) -> Result<String, Box<Error>> {
+        self.get(field)
+    }
+
+    pub fn opt_string(&self, field: &str) -> Result<Option<String>, Box<Error>> {
+        self.opt(field)
+    }
+
+    pub fn opt_path(&self, field: &str) -> Result<Option<PathBuf>, Box<Error>> {
+        self.opt(field)
+    }
+
+    pub fn opt_integer(&self, field: &str) -> Result<Option<i64>, Box<Error>> {
+        self.opt(field)
+    }
+
+    pub fn opt_number(&self, field: &str) -> Result<Option<f64>, Box<Error>> {
+        self.opt(field)
+    }
+
+    pub fn opt_bool(&self, field: &str) -> Result<Option<bool>, Box<Error>> {
+        self.opt(field)
+    }
+
+    /// Gets a field that is a string or a list of strings, which is empty if it is nil.
+    pub fn list(&self, field: &str) -> Result<Vec<String>, Box<Error>> {
+        self.opt(field).map(|list| list.unwrap_or_else(Vec::new))
+    }
+
+    fn error(&self, field: &str, expected: &str) -> Box<Error> {
+        self.runtime.state().get_field(self.index, field);
+        let got = type_name(self.runtime, -1);
+        self.runtime.state().pop(1);
+
+        format!("bad field '{}' in argument #{} ({} expected, got {})", field, self.arg, expected, got).into()
+    }
+}
+
+
+/// Gets the name of the type of a value on the stack.
+fn type_name(runtime: &Runtime, index: lua::Index) -> &'static str {
+    match runtime.state().type_of(index) {
+        Some(ty) => runtime.state().typename_of(ty),
+        None => "no value",
+    }
+}
@@ -1,13 +1,42 @@
+use lua;
+use report::Diagnostic;
 use rule::Rule;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::clone::Clone;
 use std::collections::HashMap;
+use std::env;
 use std::error::Error;
+use std::mem;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 use task::{Task, NamedTask};
 
 
+/// Compiled module bytecode that can be shared between runtimes, by module name.
+pub type ChunkCache = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+/// Names that can't be used for tasks.
+const RESERVED_NAMES: &'static [&'static str] = &["default"];
+
+
+/// The points in a run at which hook functions are called.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hook {
+    /// Before a task runs.
+    Before,
+
+    /// After a task runs, whether or not it succeeded.
+    After,
+
+    /// Before any task in a run is started.
+    BeforeAll,
+
+    /// After every task in a run has finished.
+    AfterAll,
+}
+
+
 /// Stores the state of an entire task execution environment.
 pub struct Environment {
     /// A map of all named tasks.
@@ -16,12 +45,54 @@ pub struct Environment {
     /// A vector of all defined file rules.
     rules: RefCell<Vec<Rc<Rule>>>,
 
+    /// Where each task and rule was defined in the script, by task name or rule pattern.
+    locations: RefCell<HashMap<String, String>>,
+
     /// The default task to run.
     default_task: RefCell<Option<String>>,
 
     /// The name of the task currently running.
     current_task: RefCell<Option<String>>,
 
+    /// Output captured from commands run by the current task.
+    output: RefCell<String>,
+
+    /// Indicates if command output should be captured.
+    capture_output: Cell<bool>,
+
+    /// Indicates if each line of command output should be prefixed with the name of its task.
+    prefix_output: Cell<bool>,
+
+    /// Indicates if the runner is only simulating running tasks.
+    dry_run: Cell<bool>,
+
+    /// Diagnostics reported by the current task.
+    diagnostics: RefCell<Vec<Diagnostic>>,
+
+    /// Compiled built-in modules.
+    chunks: RefCell<ChunkCache>,
+
+    /// Named arguments given on the command line.
+    args: RefCell<HashMap<String, String>>,
+
+    /// Prefix added to the names of tasks defined by the script currently being loaded, such as
+    /// `sub:` for an included script.
+    namespace: RefCell<String>,
+
+    /// Functions registered by the script to call around tasks, with the task they are for.
+    hooks: RefCell<Vec<(Hook, Option<String>, lua::Reference)>>,
+
+    /// Directory commands are run in and relative paths are resolved against, if not the current
+    /// directory. Set while an included script is being loaded or one of its tasks is running, and
+    /// by `cd()`.
+    ///
+    /// Tasks running in parallel share the current directory of the process, so it is never
+    /// changed once tasks have started; each environment keeps track of its own directory instead.
+    working_dir: RefCell<Option<PathBuf>>,
+
+    /// Paths of the script and every script it included, in the order they were loaded.
+    scripts: RefCell<Vec<PathBuf>>,
+
     /// Full path to the current script.
     path: PathBuf,
 
@@ -46,8 +117,20 @@ impl Environment {
         Ok(Environment {
             tasks: RefCell::new(HashMap::new()),
             rules: RefCell::new(Vec::new()),
+            locations: RefCell::new(HashMap::new()),
             default_task: RefCell::new(None),
             current_task: RefCell::new(None),
+            output: RefCell::new(String::new()),
+            capture_output: Cell::new(false),
+            prefix_output: Cell::new(false),
+            dry_run: Cell::new(false),
+            diagnostics: RefCell::new(Vec::new()),
+            chunks: RefCell::new(Arc::new(Mutex::new(HashMap::new()))),
+            args: RefCell::new(HashMap::new()),
+            namespace: RefCell::new(String::new()),
+            working_dir: RefCell::new(None),
+            hooks: RefCell::new(Vec::new()),
+            scripts: RefCell::new(Vec::new()),
             path: script,
             directory: directory,
         })
@@ -58,6 +141,16 @@ impl Environment {
         &self.path
     }
 
+    /// Gets the paths of the script and every script it included.
+    pub fn scripts(&self) -> Vec<PathBuf> {
+        self.scripts.borrow().clone()
+    }
+
+    /// Remembers that a script file has been loaded.
+    pub fn add_script(&self, path: &Path) {
+        self.scripts.borrow_mut().push(path.to_path_buf());
+    }
+
     /// Gets the full path of the directory containing the script file.
     pub fn directory(&self) -> &Path {
         &self.directory
@@ -73,15 +166,156 @@ impl Environment {
         self.rules.borrow().iter().map(|rc| rc.clone()).collect()
     }
 
-    /// Creates a new task.
-    pub fn create_task(&self, task: NamedTask) {
+    /// Gets the value of a named argument given on the command line.
+    pub fn arg(&self, name: &str) -> Option<String> {
+        self.args.borrow().get(name).cloned()
+    }
+
+    /// Sets the value of a named argument.
+    pub fn set_arg<S: Into<String>, V: Into<String>>(&self, name: S, value: V) {
+        self.args.borrow_mut().insert(name.into(), value.into());
+    }
+
+    /// Gets the prefix added to the names of tasks defined by the script currently being loaded.
+    pub fn namespace(&self) -> String {
+        self.namespace.borrow().clone()
+    }
+
+    /// Sets the prefix added to the names of tasks, and returns the previous one.
+    pub fn set_namespace<S: Into<String>>(&self, namespace: S) -> String {
+        mem::replace(&mut *self.namespace.borrow_mut(), namespace.into())
+    }
+
+    /// Gets the directory commands should be run in, if not the current directory.
+    pub fn working_dir(&self) -> Option<PathBuf> {
+        self.working_dir.borrow().clone()
+    }
+
+    /// Sets the directory commands should be run in, and returns the previous one.
+    pub fn set_working_dir(&self, dir: Option<PathBuf>) -> Option<PathBuf> {
+        mem::replace(&mut *self.working_dir.borrow_mut(), dir)
+    }
+
+    /// Gets the directory the script is working in: the working directory if one is set, or else
+    /// the current directory of the process.
+    pub fn current_dir(&self) -> PathBuf {
+        self.working_dir()
+            .or_else(|| env::current_dir().ok())
+            .unwrap_or_else(|| self.directory.clone())
+    }
+
+    /// Resolves a path against the working directory. Relative paths are left as they are if no
+    /// working directory is set, since they are already relative to the current directory.
+    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        match *self.working_dir.borrow() {
+            Some(ref dir) => dir.join(path),
+            None => path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Registers a hook function. Hooks that are called around a single task are given the name
+    /// of the task.
+    pub fn add_hook(&self, hook: Hook, task: Option<String>, func: lua::Reference) {
+        self.hooks.borrow_mut().push((hook, task, func));
+    }
+
+    /// Gets the hook functions to call at a point in a run, in the order they were registered.
+    pub fn hooks(&self, hook: Hook, task: Option<&str>) -> Vec<lua::Reference> {
+        self.hooks
+            .borrow()
+            .iter()
+            .filter(|&&(kind, ref name, _)| kind == hook && name.as_ref().map(|name| name.as_str()) == task)
+            .map(|&(_, _, func)| func)
+            .collect()
+    }
+
+    /// Resolves the dependencies of the tasks in a namespace once all of them are defined.
+    ///
+    /// A dependency of a task in the namespace refers to a task in the same namespace if there is
+    /// one with that name, and is left as is otherwise, so that it can refer to tasks in other
+    /// namespaces and to files.
+    pub fn resolve_namespace(&self, namespace: &str) {
+        let mut tasks = self.tasks.borrow_mut();
+        let names: Vec<String> = tasks.keys().filter(|name| name.starts_with(namespace)).cloned().collect();
+
+        for name in names {
+            let dependencies: Vec<String> = tasks[&name]
+                .dependencies
+                .iter()
+                .map(|dependency| {
+                    let qualified = format!("{}{}", namespace, dependency);
+
+                    if !dependency.starts_with(namespace) && tasks.contains_key(&qualified) {
+                        qualified
+                    } else {
+                        dependency.clone()
+                    }
+                })
+                .collect();
+
+            // Tasks aren't shared with anything else until the script is done loading.
+            if let Some(task) = tasks.get_mut(&name).and_then(Rc::get_mut) {
+                task.dependencies = dependencies;
+            }
+        }
+    }
+
+    /// Creates a new task, defined at the given location in the script.
+    ///
+    /// Fails if the name is reserved, or if another task or a rule is already defined for the same
+    /// name, since only one of them could ever be run.
+    pub fn create_task(&self, task: NamedTask, location: Option<String>) -> Result<(), String> {
+        let name = task.name().to_string();
+
+        if RESERVED_NAMES.contains(&name.as_str()) {
+            return Err(format!("'{}' is a reserved name and can't be used for a task", name));
+        }
+
+        if self.tasks.borrow().contains_key(&name) {
+            return Err(format!("task '{}' is already defined{}", name, self.defined_at(&name)));
+        }
+
+        if let Some(rule) = self.rules.borrow().iter().find(|rule| rule.matches(&name)) {
+            return Err(format!("task '{}' conflicts with rule '{}'{}",
+                               name,
+                               rule.pattern,
+                               self.defined_at(&rule.pattern)));
+        }
+
+        if let Some(location) = location {
+            self.locations.borrow_mut().insert(name.clone(), location);
+        }
+
         // Add it to the master list of tasks.
-        self.tasks.borrow_mut().insert(task.name().into(), Rc::new(task));
+        self.tasks.borrow_mut().insert(name, Rc::new(task));
+        Ok(())
     }
 
-    /// Creates a new rule.
-    pub fn create_rule(&self, rule: Rule) {
+    /// Creates a new rule, defined at the given location in the script.
+    ///
+    /// Fails if the rule matches the name of a task that is already defined.
+    pub fn create_rule(&self, rule: Rule, location: Option<String>) -> Result<(), String> {
+        if let Some(name) = self.tasks.borrow().keys().find(|name| rule.matches(name)) {
+            return Err(format!("rule '{}' conflicts with task '{}'{}",
+                               rule.pattern,
+                               name,
+                               self.defined_at(name)));
+        }
+
+        if let Some(location) = location {
+            self.locations.borrow_mut().insert(rule.pattern.clone(), location);
+        }
+
         self.rules.borrow_mut().push(Rc::new(rule));
+        Ok(())
+    }
+
+    /// Describes where a task or rule was defined, for error messages.
+    fn defined_at(&self, name: &str) -> String {
+        match self.locations.borrow().get(name) {
+            Some(location) => format!(" at {}", location),
+            None => String::new(),
+        }
     }
 
     /// Gets a task by name.
@@ -119,4 +353,69 @@ impl Environment {
     pub fn clear_current_task(&self) {
         *self.current_task.borrow_mut() = None;
     }
+
+    /// Shares compiled built-in modules with other environments using the given cache.
+    pub fn set_chunk_cache(&self, chunks: ChunkCache) {
+        *self.chunks.borrow_mut() = chunks;
+    }
+
+    /// Gets the compiled bytecode of a built-in module, if it has been compiled before.
+    pub fn chunk(&self, name: &str) -> Option<Vec<u8>> {
+        self.chunks.borrow().lock().unwrap().get(name).cloned()
+    }
+
+    /// Stores the compiled bytecode of a built-in module.
+    pub fn store_chunk(&self, name: &str, bytecode: Vec<u8>) {
+        self.chunks.borrow().lock().unwrap().insert(name.to_string(), bytecode);
+    }
+
+    /// Checks if command output should be captured.
+    pub fn captures_output(&self) -> bool {
+        self.capture_output.get()
+    }
+
+    /// Sets whether command output should be captured.
+    pub fn set_capture_output(&self, capture: bool) {
+        self.capture_output.set(capture);
+    }
+
+    /// Checks if each line of command output should be prefixed with the name of its task.
+    pub fn prefixes_output(&self) -> bool {
+        self.prefix_output.get()
+    }
+
+    /// Sets whether each line of command output should be prefixed with the name of its task.
+    pub fn set_prefix_output(&self, prefix: bool) {
+        self.prefix_output.set(prefix);
+    }
+
+    /// Checks if the runner is only simulating running tasks.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.get()
+    }
+
+    /// Sets whether the runner is only simulating running tasks.
+    pub fn set_dry_run(&self, dry_run: bool) {
+        self.dry_run.set(dry_run);
+    }
+
+    /// Appends to the output captured for the current task.
+    pub fn append_output(&self, output: &str) {
+        self.output.borrow_mut().push_str(output);
+    }
+
+    /// Takes all of the output captured so far, leaving the buffer empty.
+    pub fn take_output(&self) -> String {
+        mem::replace(&mut *self.output.borrow_mut(), String::new())
+    }
+
+    /// Adds a diagnostic reported by the current task.
+    pub fn add_diagnostic(&self, diagnostic: Diagnostic) {
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+
+    /// Takes all of the diagnostics reported so far.
+    pub fn take_diagnostics(&self) -> Vec<Diagnostic> {
+        mem::replace(&mut *self.diagnostics.borrow_mut(), Vec::new())
+    }
 }
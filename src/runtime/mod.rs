@@ -1,18 +1,24 @@
 use lua::{self, ffi};
-use lua::libc::{c_int, c_void};
+use lua::libc::{c_char, c_int, c_void};
 use std::any::{Any, TypeId};
 use std::clone::Clone;
 use std::error::Error;
 use std::hash::{Hash, Hasher, SipHasher};
+use std::fs::File;
+use std::io::Read;
 use std::mem;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::slice;
 use std::rc::{Rc, Weak};
 
+mod args;
 mod environment;
 mod iter;
+mod value;
 
-pub use self::environment::Environment;
+pub use self::args::{Args, FromTable, Table};
+pub use self::environment::{ChunkCache, Environment, Hook};
 
 /// Results that are returned by functions callable from Lua.
 pub type ScriptResult = Result<i32, Box<Error>>;
@@ -106,54 +112,107 @@ impl Runtime {
 
     /// Executes the script.
     pub fn load(&self) -> Result<(), Box<Error>> {
-        let path_str = if let Some(s) = self.environment.path().to_str() {
-            s
-        } else {
-            return Err("path contains invalid characters".into());
-        };
+        self.load_file(self.environment.path())
+    }
+
+    /// Executes a script file in the runtime.
+    pub fn load_file(&self, path: &Path) -> Result<(), Box<Error>> {
+
+        // Read the file ourselves, since Lua can only open paths that are valid UTF-8 on some
+        // platforms.
+        let mut source = Vec::new();
+        if File::open(path).and_then(|mut file| file.read_to_end(&mut source)).is_err() {
+            return Err(format!("the file \"{}\" could not be read", path.display()).into());
+        }
+
+        self.environment.add_script(path);
 
         // Load the given file.
-        match self.state().do_file(path_str) {
-            lua::ThreadStatus::Ok => {}
-            lua::ThreadStatus::FileError => {
-                return Err(format!("the file \"{}\" could not be read", path_str).into());
-            }
-            _ => {
-                return Err(self.state().to_str(-1).unwrap().into());
-            }
-        };
+        let name = format!("@{}", path.to_string_lossy());
+        if self.state().load_buffer(&source, &name).is_err() {
+            return Err(self.state().to_str(-1).unwrap().into());
+        }
 
+        try!(self.call(0, 0, 0));
         Ok(())
     }
 
+    /// Pushes a path onto the stack as a string.
+    ///
+    /// Lua strings are just bytes, so paths are pushed as is even if they are not valid UTF-8.
+    pub fn push_path<P: AsRef<Path>>(&self, path: P) {
+        self.push_bytes(&path_to_bytes(path.as_ref()));
+    }
+
+    /// Pushes a byte string onto the stack, which does not need to be valid UTF-8.
+    pub fn push_bytes(&self, bytes: &[u8]) {
+        unsafe {
+            ffi::lua_pushlstring(self.state.as_ptr(), bytes.as_ptr() as *const c_char, bytes.len());
+        }
+    }
+
+    /// Gets a path from a string argument, raising an error if the argument is not a string.
+    pub fn check_path(&self, index: lua::Index) -> PathBuf {
+        let mut len = 0;
+
+        let bytes = unsafe {
+            let ptr = ffi::luaL_checklstring(self.state.as_ptr(), index, &mut len);
+            slice::from_raw_parts(ptr as *const u8, len)
+        };
+
+        bytes_to_path(bytes)
+    }
+
+    /// Gets a path from a string argument resolved against the working directory of the script,
+    /// for paths of files to be read or written.
+    pub fn check_file_path(&self, index: lua::Index) -> PathBuf {
+        self.environment().resolve(self.check_path(index))
+    }
+
+    /// Checks if a change to files should be skipped because this is a dry run, in which case the
+    /// change is shown instead, like commands that are skipped.
+    pub fn skip_change<S: AsRef<str>>(&self, change: S) -> bool {
+        if self.environment().is_dry_run() {
+            ::console::println(format!("would {}", change.as_ref()));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Gets a path from a string value on the stack, if it is a string.
+    pub fn to_path(&self, index: lua::Index) -> Option<PathBuf> {
+        self.state().to_bytes_in_place(index).map(bytes_to_path)
+    }
+
     /// Adds a path to Lua's require path for modules.
     pub fn include_path<P: Into<PathBuf>>(&self, path: P) {
         let mut lua_path = path.into();
         let mut native_path = lua_path.clone();
         lua_path.push("?.lua");
-        native_path.push("?.so");
+        native_path.push(if cfg!(windows) {
+            "?.dll"
+        } else {
+            "?.so"
+        });
 
         self.state().get_global("package");
 
         // Set the Lua file path.
         self.state().get_field(-1, "path");
-        let mut search_path = self.state().to_str(-1).unwrap().to_string();
-        search_path.push(';');
-        search_path.push_str(&lua_path.to_string_lossy());
-
-        self.state().push_string(&search_path);
-        self.state().set_field(-4, "path");
-        self.state().pop(2);
+        self.state().push_string(";");
+        self.push_path(&lua_path);
+        self.state().concat(3);
+        self.state().set_field(-2, "path");
 
         // Set the native file path.
         self.state().get_field(-1, "cpath");
-        let mut search_path = self.state().to_str(-1).unwrap().to_string();
-        search_path.push(';');
-        search_path.push_str(&native_path.to_string_lossy());
+        self.state().push_string(";");
+        self.push_path(&native_path);
+        self.state().concat(3);
+        self.state().set_field(-2, "cpath");
 
-        self.state().push_string(&search_path);
-        self.state().set_field(-4, "cpath");
-        self.state().pop(3);
+        self.state().pop(1);
     }
 
     /// Evaluates a Lua string inside the runtime.
@@ -165,6 +224,37 @@ impl Runtime {
         Ok(())
     }
 
+    /// Evaluates the source of a built-in module and leaves its results on the stack.
+    ///
+    /// Modules are only compiled the first time they are loaded, and the bytecode is reused by any
+    /// runtime sharing the same chunk cache.
+    pub fn eval_module(&self, name: &str, source: &str) -> Result<(), Box<Error>> {
+        let status = match self.environment().chunk(name) {
+            Some(bytecode) => self.state().load_buffer(&bytecode, name),
+            None => {
+                let status = self.state().load_buffer(source.as_bytes(), name);
+
+                if !status.is_err() {
+                    let mut bytecode = Vec::new();
+                    self.state().dump(|chunk| {
+                        bytecode.extend_from_slice(chunk);
+                        0
+                    }, false);
+                    self.environment().store_chunk(name, bytecode);
+                }
+
+                status
+            }
+        };
+
+        if status.is_err() {
+            return Err(self.state().to_str(-1).unwrap().into());
+        }
+
+        try!(self.call(0, lua::MULTRET, 0));
+        Ok(())
+    }
+
     /// Registers a global function in the runtime that can be called by Lua scripts.
     pub fn register_fn(&self, name: &str, f: Function) {
         self.push_fn(f);
@@ -182,6 +272,17 @@ impl Runtime {
         self.state().pop(2);
     }
 
+    /// Registers a module loaded by a native Lua function using preloading.
+    pub fn register_native_lib<S: AsRef<str>>(&self, name: S, loader: lua::Function) {
+        self.state().get_global("package");
+        self.state().get_field(-1, "preload");
+
+        self.state().push_fn(loader);
+
+        self.state().set_field(-2, name.as_ref());
+        self.state().pop(2);
+    }
+
     /// Loads a table of functions as a module.
     pub fn load_lib(&self, mtable: &[(&str, Function)]) {
         self.state().create_table(0, mtable.len() as i32);
@@ -277,6 +378,27 @@ impl Runtime {
         }
     }
 
+    /// Gets the location in the script of the function at the given level of the call stack, such
+    /// as `Rotefile:12`. Level 1 is the function that called the current Rust function.
+    pub fn location(&self, level: i32) -> Option<String> {
+        self.state().location(level);
+        let location = self.state().to_str(-1).map(|location| {
+            location.trim_right_matches(": ").to_string()
+        });
+        self.state().pop(1);
+
+        location.and_then(|location| if location.is_empty() {
+            None
+        } else {
+            Some(location)
+        })
+    }
+
+    /// Gets the arguments of the function being called, for converting them into Rust values.
+    pub fn args<'a>(&'a self) -> Args<'a> {
+        Args::new(self)
+    }
+
     /// Returns an iterator for iterating over the table at the top of the stack.
     pub fn iter(&self, index: lua::Index) -> iter::TableIterator {
         iter::TableIterator::new(self.state(), index)
@@ -329,3 +451,30 @@ fn type_id_of<T: Any>() -> u64 {
     type_id.hash(&mut hasher);
     hasher.finish()
 }
+
+/// Gets the bytes of a path to pass to Lua.
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Creates a path from bytes received from Lua.
+#[cfg(unix)]
+fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
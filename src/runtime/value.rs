@@ -0,0 +1,176 @@
+//! Conversion of Rust values into Lua values, for returning them from functions called from Lua.
+//!
+//! Instead of pushing each return value by hand and counting them, a function can return all of
+//! its values at once as a tuple:
+//!
+//! ```ignore
+//! runtime.ret((status, &stdout[..], &stderr[..]))
+//! ```
+use lua;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use super::{Runtime, ScriptResult};
+
+
+/// A type that can be pushed onto the stack as a single Lua value.
+pub trait IntoLua {
+    fn push_lua(self, runtime: &Runtime);
+}
+
+/// A type that can be returned from a function as any number of Lua values.
+pub trait IntoReturn {
+    /// Pushes the values onto the stack and returns how many were pushed.
+    fn push_all(self, runtime: &Runtime) -> i32;
+}
+
+
+impl<'a> IntoLua for &'a str {
+    fn push_lua(self, runtime: &Runtime) {
+        runtime.state().push_string(self);
+    }
+}
+
+impl IntoLua for String {
+    fn push_lua(self, runtime: &Runtime) {
+        runtime.state().push_string(&self);
+    }
+}
+
+/// Byte slices are pushed as strings, since Lua strings can hold any bytes.
+impl<'a> IntoLua for &'a [u8] {
+    fn push_lua(self, runtime: &Runtime) {
+        runtime.push_bytes(self);
+    }
+}
+
+impl<'a> IntoLua for &'a Path {
+    fn push_lua(self, runtime: &Runtime) {
+        runtime.push_path(self);
+    }
+}
+
+impl IntoLua for PathBuf {
+    fn push_lua(self, runtime: &Runtime) {
+        runtime.push_path(&self);
+    }
+}
+
+impl IntoLua for bool {
+    fn push_lua(self, runtime: &Runtime) {
+        runtime.state().push_bool(self);
+    }
+}
+
+impl IntoLua for f64 {
+    fn push_lua(self, runtime: &Runtime) {
+        runtime.state().push_number(self);
+    }
+}
+
+macro_rules! integer_into_lua {
+    ($($ty:ty),*) => {
+        $(
+            impl IntoLua for $ty {
+                fn push_lua(self, runtime: &Runtime) {
+                    runtime.state().push_integer(self as lua::Integer);
+                }
+            }
+        )*
+    }
+}
+
+integer_into_lua!(i32, i64, u32, u64, usize);
+
+/// `None` is pushed as nil.
+impl<T: IntoLua> IntoLua for Option<T> {
+    fn push_lua(self, runtime: &Runtime) {
+        match self {
+            Some(value) => value.push_lua(runtime),
+            None => runtime.state().push_nil(),
+        }
+    }
+}
+
+/// Vectors are pushed as lists.
+impl<T: IntoLua> IntoLua for Vec<T> {
+    fn push_lua(self, runtime: &Runtime) {
+        runtime.state().create_table(self.len() as i32, 0);
+
+        for (i, value) in self.into_iter().enumerate() {
+            value.push_lua(runtime);
+            runtime.state().raw_seti(-2, i as lua::Integer + 1);
+        }
+    }
+}
+
+/// Maps are pushed as tables.
+impl<K: IntoLua + Eq + Hash, V: IntoLua> IntoLua for HashMap<K, V> {
+    fn push_lua(self, runtime: &Runtime) {
+        push_pairs(runtime, self.len(), self.into_iter());
+    }
+}
+
+impl<K: IntoLua + Ord, V: IntoLua> IntoLua for BTreeMap<K, V> {
+    fn push_lua(self, runtime: &Runtime) {
+        push_pairs(runtime, self.len(), self.into_iter());
+    }
+}
+
+fn push_pairs<K: IntoLua, V: IntoLua, I: Iterator<Item = (K, V)>>(runtime: &Runtime, len: usize, pairs: I) {
+    runtime.state().create_table(0, len as i32);
+
+    for (key, value) in pairs {
+        key.push_lua(runtime);
+        value.push_lua(runtime);
+        runtime.state().raw_set(-3);
+    }
+}
+
+
+/// Returning nothing.
+impl IntoReturn for () {
+    fn push_all(self, _: &Runtime) -> i32 {
+        0
+    }
+}
+
+/// Returning a single value.
+impl<T: IntoLua> IntoReturn for T {
+    fn push_all(self, runtime: &Runtime) -> i32 {
+        self.push_lua(runtime);
+        1
+    }
+}
+
+macro_rules! tuple_into_return {
+    ($count:expr; $($name:ident),*) => {
+        impl<$($name: IntoLua),*> IntoReturn for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn push_all(self, runtime: &Runtime) -> i32 {
+                let ($($name,)*) = self;
+                $($name.push_lua(runtime);)*
+                $count
+            }
+        }
+    }
+}
+
+tuple_into_return!(1; A);
+tuple_into_return!(2; A, B);
+tuple_into_return!(3; A, B, C);
+tuple_into_return!(4; A, B, C, D);
+tuple_into_return!(5; A, B, C, D, E);
+
+
+impl Runtime {
+    /// Pushes a Rust value onto the stack as a Lua value.
+    pub fn push_value<T: IntoLua>(&self, value: T) {
+        value.push_lua(self);
+    }
+
+    /// Pushes the values to return from a function, and returns how many there are.
+    pub fn ret<T: IntoReturn>(&self, values: T) -> ScriptResult {
+        Ok(values.push_all(self))
+    }
+}
@@ -1,6 +1,8 @@
+use fscache;
 use std::cmp::Ordering;
 use std::error::Error;
 use std::hash::{Hash, Hasher};
+use std::time::Duration;
 
 
 /// A single task that can be run.
@@ -21,8 +23,60 @@ pub trait Task {
     /// Gets an array of task names that this task depends on.
     fn dependencies(&self) -> &[String];
 
+    /// Gets the files read by the task that aren't built by any task.
+    fn files(&self) -> &[String] {
+        &[]
+    }
+
+    /// Gets the files the task declares that it reads.
+    fn inputs(&self) -> &[String] {
+        &[]
+    }
+
+    /// Gets the files the task declares that it writes.
+    fn outputs(&self) -> &[String] {
+        &[]
+    }
+
+    /// Checks if the outputs of the task may be restored from the output cache instead of running
+    /// the task when its inputs haven't changed.
+    fn is_cached(&self) -> bool {
+        false
+    }
+
     /// Runs the task.
     fn run(&self) -> Result<(), Box<Error>>;
+
+    /// Checks if the task produces a file with the same name as the task.
+    fn is_file(&self) -> bool {
+        false
+    }
+
+    /// Checks if the task has an action to run, or if it only groups its dependencies.
+    fn has_action(&self) -> bool;
+
+    /// Checks if the task is quick enough to run that it may be batched with other cheap tasks.
+    fn is_cheap(&self) -> bool {
+        false
+    }
+
+    /// Gets how the task is retried if it fails.
+    fn retry(&self) -> Retry {
+        Retry::default()
+    }
+
+    /// Gets how long the task may run before its commands are killed, if there is a limit.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// How many times a task that fails is run again before it is reported as failed, and how long to
+/// wait before each try.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Retry {
+    pub count: u32,
+    pub delay: Duration,
 }
 
 type ActionFn = Fn() -> Result<(), Box<Error>>;
@@ -38,6 +92,22 @@ pub struct NamedTask {
     /// A list of tasks that must be ran before this task.
     pub dependencies: Vec<String>,
 
+    /// Files read by the task.
+    pub inputs: Vec<String>,
+
+    /// Files written by the task. A task with outputs is up to date when every output is newer
+    /// than all of its inputs.
+    pub outputs: Vec<String>,
+
+    /// How the task is retried if it fails.
+    pub retry: Retry,
+
+    /// How long the task may run before its commands are killed.
+    pub timeout: Option<Duration>,
+
+    /// Indicates if the outputs of the task are stored in the output cache.
+    pub cached: bool,
+
     /// Rule action.
     action: Option<Box<ActionFn>>,
 }
@@ -56,10 +126,42 @@ impl NamedTask {
             name: name.into(),
             description: description.map(|s| s.into()),
             dependencies: dependencies.into(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            retry: Retry::default(),
+            timeout: None,
+            cached: false,
             action: action.map(|a| Box::new(a) as Box<ActionFn>),
         }
     }
 
+    /// Sets the files read and written by the task, so that it can be skipped when it is up to
+    /// date.
+    pub fn files(mut self, inputs: Vec<String>, outputs: Vec<String>) -> NamedTask {
+        self.inputs = inputs;
+        self.outputs = outputs;
+        self
+    }
+
+    /// Sets how the task is retried if it fails.
+    pub fn retry(mut self, retry: Retry) -> NamedTask {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets how long the task may run before its commands are killed.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> NamedTask {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets whether the outputs of the task are stored in the output cache, so that they can be
+    /// restored instead of running the task again with the same inputs.
+    pub fn cached(mut self, cached: bool) -> NamedTask {
+        self.cached = cached;
+        self
+    }
+
     pub fn description<'a>(&'a self) -> Option<&'a str> {
         match self.description {
             Some(ref description) => Some(description),
@@ -73,15 +175,50 @@ impl Task for NamedTask {
         &self.name
     }
 
-    // Named tasks should always be run.
+    /// Named tasks are always run, unless they declare outputs that are all newer than their
+    /// inputs. Inputs that don't exist are ignored, just like for file tasks.
     fn satisfied(&self) -> bool {
-        false
+        if self.outputs.is_empty() {
+            return false;
+        }
+
+        // Find the oldest output. If any output is missing, the task must run to create it.
+        let mut oldest = None;
+        for output in &self.outputs {
+            match fscache::metadata(output).and_then(|m| m.modified()) {
+                Ok(time) => {
+                    if oldest.map_or(true, |oldest| time < oldest) {
+                        oldest = Some(time);
+                    }
+                }
+                Err(_) => return false,
+            }
+        }
+
+        self.inputs.iter().all(|input| {
+            fscache::metadata(input)
+                .and_then(|m| m.modified())
+                .map(|time| Some(time) <= oldest)
+                .unwrap_or(true)
+        })
     }
 
     fn dependencies(&self) -> &[String] {
         &self.dependencies
     }
 
+    fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
+    fn outputs(&self) -> &[String] {
+        &self.outputs
+    }
+
+    fn is_cached(&self) -> bool {
+        self.cached && !self.outputs.is_empty()
+    }
+
     fn run(&self) -> Result<(), Box<Error>> {
         if let Some(ref action) = self.action {
             action()
@@ -89,6 +226,18 @@ impl Task for NamedTask {
             Ok(())
         }
     }
+
+    fn has_action(&self) -> bool {
+        self.action.is_some()
+    }
+
+    fn retry(&self) -> Retry {
+        self.retry
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
 }
 
 // Implement ordering and comparison for all task types.
@@ -0,0 +1,52 @@
+//! Watches files for changes.
+//!
+//! Files are polled by comparing their modification times, which works the same on every platform
+//! and doesn't need any support from the operating system.
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+
+/// Watches a set of files for changes.
+pub struct Watcher {
+    /// Modification time of each file when it was last checked, or `None` if it did not exist.
+    files: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+impl Watcher {
+    /// Creates a watcher for the given files. Only changes made after this is created are noticed.
+    pub fn new<I: IntoIterator<Item = PathBuf>>(paths: I) -> Watcher {
+        Watcher {
+            files: paths.into_iter()
+                .map(|path| {
+                    let modified = modified(&path);
+                    (path, modified)
+                })
+                .collect(),
+        }
+    }
+
+    /// Gets the files that have been created, modified or removed since the last time this was
+    /// called.
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+
+        for (path, last_modified) in &mut self.files {
+            let modified = modified(path);
+
+            if modified != *last_modified {
+                trace!("file '{}' changed", path.display());
+                *last_modified = modified;
+                changed.push(path.clone());
+            }
+        }
+
+        changed
+    }
+}
+
+/// Gets the modification time of a file, or `None` if it does not exist.
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
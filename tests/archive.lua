@@ -0,0 +1,19 @@
+local archive = require "archive"
+local fs = require "fs"
+
+
+archive.zip({"tests/fixtures/fs1.in", "tests/fixtures/fs2.in"}, "tests/fixtures/archive.zip")
+archive.unzip("tests/fixtures/archive.zip", "tests/fixtures/archive-zip")
+assert(fs.get("tests/fixtures/archive-zip/tests/fixtures/fs1.in") == "hello\n")
+assert(fs.get("tests/fixtures/archive-zip/tests/fixtures/fs2.in") == "world\n")
+
+archive.tar({"tests/fixtures/fs1.in"}, "tests/fixtures/archive.tar.gz", {compression = "gz"})
+archive.untar("tests/fixtures/archive.tar.gz", "tests/fixtures/archive-tar")
+assert(fs.get("tests/fixtures/archive-tar/tests/fixtures/fs1.in") == "hello\n")
+
+assert(not pcall(archive.tar, {"tests/fixtures/fs1.in"}, "tests/fixtures/archive.tar", {compression = "rar"}))
+
+fs.remove("tests/fixtures/archive.zip")
+fs.remove("tests/fixtures/archive.tar.gz")
+fs.remove("tests/fixtures/archive-zip")
+fs.remove("tests/fixtures/archive-tar")
@@ -0,0 +1,39 @@
+require "fs"
+local exec = require "exec"
+
+
+if OS == "unix" then
+    local dir = "tests/fixtures/cache"
+
+    local function runs()
+        local code = exec.run(rote.current_exe(), {"--check-mode", "hash"}, {cwd = dir})
+        assert(code == 0)
+
+        local _, count = fs.get(dir .. "/runs.txt"):gsub("\n", "")
+        return count
+    end
+
+    local function sh(script)
+        assert(exec.run("sh", {"-c", script}, {cwd = dir}) == 0)
+    end
+
+    fs.put(dir .. "/runs.txt", "")
+    fs.put(dir .. "/source.txt", "one\n")
+    assert(runs() == 1)
+
+    -- A file with the same size and modification time as when it was fingerprinted isn't read
+    -- again, even if its contents changed.
+    sh("touch -r source.txt stamp && echo two > source.txt && touch -r stamp source.txt")
+    assert(runs() == 1)
+
+    -- Once its modification time changes, it is read again.
+    sh("touch -d 2001-01-01 source.txt")
+    assert(runs() == 2)
+    assert(fs.get(dir .. "/copy.txt") == "two\n")
+
+    fs.remove(dir .. "/runs.txt")
+    fs.remove(dir .. "/source.txt")
+    fs.remove(dir .. "/copy.txt")
+    fs.remove(dir .. "/stamp")
+    fs.remove(dir .. "/.rote")
+end
@@ -0,0 +1,22 @@
+cargo = require "cargo"
+
+
+do -- cargo.metadata
+    local metadata = cargo.metadata()
+
+    assert(type(metadata.workspace_root) == "string")
+    assert(type(metadata.target_directory) == "string")
+    assert(#metadata.members == 1)
+
+    local package = metadata.members[1]
+    assert(package.name == "rote")
+    assert(package.version == metadata.version)
+    assert(package.targets[1].kind[1] ~= nil)
+
+    assert(not pcall(cargo.metadata, {manifest_path = 42}))
+end
+
+assert(cargo.test)
+assert(cargo.bench)
+assert(cargo.clippy)
+assert(cargo.fmt)
@@ -13,3 +13,76 @@ assert(rote.merge({
 }, {
     foo = "baz"
 }).foo == "baz")
+
+assert(type(args) == "table")
+assert(var("no_such_argument") == nil)
+assert(var("no_such_argument", "default") == "default")
+
+task("core_files_test", {inputs = {"tests/core.lua"}, outputs = {"tests/fixtures/core.out"}})
+assert(not pcall(task, "core_bad_files_test", {outputs = "tests/fixtures/core.out"}))
+
+rote.include("tests/fixtures/include/Rotefile", "included")
+assert(rote.task_exists("included:hello"))
+assert(not rote.task_exists("hello"))
+assert(rote.task_info("no_such_task") == nil)
+assert(rote.task_info("included:hello").dependencies[1] == "world")
+assert(not pcall(task, "included:hello"))
+assert(not pcall(rote.include, "tests/fixtures/include/Rotefile", "bad:namespace"))
+
+rote.before("core_hook_test", function(name) end)
+rote.after_all(function(ok) end)
+assert(not pcall(rote.after, "core_hook_test"))
+assert(not pcall(rote.before_all, "core_hook_test"))
+
+task("core_retry_test", {retries = 2, retry_delay = 0.5})
+assert(not pcall(task, "core_bad_retry_test", {retries = -1}))
+assert(not pcall(rule, "core_bad_retry_%.out", {retry_delay = "soon"}))
+
+rule("core_inputs_%.out", {inputs = {"%.in"}})
+assert(not pcall(rule, "core_bad_inputs_%.out", {inputs = "%.in"}))
+
+task("core_timeout_test", {timeout = 60})
+assert(not pcall(task, "core_bad_timeout_test", {timeout = "never"}))
+
+task("core_cache_test", {inputs = {"tests/core.lua"}, outputs = {"tests/fixtures/core.out"}, cache = true})
+assert(not pcall(task, "core_bad_cache_test", {cache = "yes"}))
+
+desc("Checks task introspection.")
+task("core_info_test", {"core_timeout_test"})
+assert(rote.task_info("core_info_test").description == "Checks task introspection.")
+assert(rote.task_info("core_info_test").dependencies[1] == "core_timeout_test")
+
+local found = false
+for _, name in ipairs(rote.tasks()) do
+    found = found or name == "core_info_test"
+end
+assert(found)
+
+-- Tests are run by a task, which never happens in a dry run.
+assert(rote.dry_run() == false)
+
+if OS ~= "windows" then
+    -- Variables are passed as single arguments, no matter what they contain.
+    sh("test {a} = {b}", {a = "x; exit 1", b = "x; exit 1"})
+    sh("test {args}", {args = {"it's", "=", "it's"}})
+    sh("test -n {n}", {n = 42})
+end
+assert(not pcall(sh, "echo {missing}", {}))
+assert(not pcall(sh, "echo {flag}", {flag = true}))
+
+assert(fs.is_file(rote.root_dir() .. "/Rotefile"))
+
+-- Changing the directory only changes the directory of the script, which relative paths and
+-- commands use, and not the directory of the process shared by parallel tasks.
+local previous_dir = rote.current_dir()
+rote.change_dir("tests/fixtures")
+assert(rote.current_dir() == previous_dir .. "/tests/fixtures")
+assert(fs.is_file("fs1.in"))
+assert(fs.get("fs1.in") == "hello\n")
+assert(#fs.glob("fs*.in") == 2 and fs.glob("fs*.in")[1] == "fs1.in")
+if OS ~= "windows" then
+    assert(pipe("", "pwd"):match("tests/fixtures"))
+end
+assert(not pcall(rote.change_dir, "missing"))
+rote.change_dir(previous_dir)
+assert(fs.is_file("Rotefile"))
@@ -0,0 +1,96 @@
+cpp = require "cpp"
+
+
+do -- cpp.compile_commands
+    cpp.binary {
+        name = "app",
+        srcs = {"main.cpp", "util.cpp"},
+        includes = {"include"},
+    }
+
+    assert(#cpp.compile_commands == 2)
+
+    local command = cpp.compile_commands[1]
+    assert(command.directory == rote.current_dir())
+    assert(command.file == "main.cpp")
+    assert(command.output == "main.cpp.o")
+    assert(command.arguments[#command.arguments] == "main.cpp")
+
+    local has_include = false
+    for _, arg in ipairs(command.arguments) do
+        if arg == "-Iinclude" then
+            has_include = true
+        end
+    end
+    assert(has_include)
+
+    assert(cpp.compile_commands[2].file == "util.cpp")
+end
+
+do -- cpp.read_depfile
+    local deps = cpp.read_depfile("tests/fixtures/cpp/main.cpp.o.d")
+
+    assert(#deps == 3)
+    assert(deps[1] == "main.cpp")
+    assert(deps[2] == "include/util.h")
+    assert(deps[3] == "include/my config.h")
+
+    assert(#cpp.read_depfile("tests/fixtures/cpp/missing.d") == 0)
+end
+
+do -- cpp.toolchains
+    local options = {
+        standard = "c++11",
+        debug = false,
+        includes = {"include"},
+        opt_level = 2,
+        macros = {NDEBUG = true},
+        type = "shared",
+    }
+
+    local gcc = cpp.toolchain("gcc")
+    local args = gcc:compile_args(options, "main.cpp", "main.cpp.o", "main.cpp.o.d")
+    assert(args[#args] == "main.cpp")
+
+    local flags = {}
+    for _, arg in ipairs(args) do
+        flags[arg] = true
+    end
+    assert(flags["-O2"] and flags["-DNDEBUG"] and flags["-fPIC"] and flags["-Iinclude"])
+
+    args = gcc:link_args(options, {"main.cpp.o"}, "libapp.so")
+    assert(args[#args] == "main.cpp.o")
+
+    local msvc = cpp.toolchain("msvc")
+    assert(msvc.object_suffix == ".obj")
+    assert(msvc:depfile("main.cpp.obj") == nil)
+
+    args = msvc:compile_args(options, "main.cpp", "main.cpp.obj")
+    assert(args[1] == "cl")
+    assert(args[#args - 1] == "/Fomain.cpp.obj")
+
+    options.type = "static"
+    args = msvc:link_args(options, {"main.cpp.obj"}, "app.lib")
+    assert(args[1] == "lib")
+    assert(args[3] == "/OUT:app.lib")
+
+    assert(not pcall(cpp.toolchain, "tcc"))
+    assert(not pcall(cpp.binary, {name = "app", srcs = {}, type = "dll"}))
+end
+
+do -- cpp.static_lib
+    local util = cpp.static_lib {
+        name = "util",
+        srcs = {"util.c"},
+        dir = "build",
+        toolchain = "gcc",
+    }
+    assert(util == "build/libutil.a")
+    assert(cpp.library_file("util", "static", "msvc") == "util.lib")
+
+    local found = false
+    for _, file in ipairs(cpp.outputs) do
+        found = found or file == util
+    end
+    assert(found)
+end
@@ -0,0 +1,45 @@
+docker = require "docker"
+
+
+do -- docker.build_args
+    local args = docker.build_args({
+        path = "app",
+        tags = {"app:latest", "app:1.0"},
+        build_args = {VERSION = "1.0", BASE = "alpine"},
+        target = "release",
+        no_cache = true,
+    })
+
+    assert(table.concat(args, " ") == "build -t app:latest -t app:1.0 " ..
+        "--build-arg BASE=alpine --build-arg VERSION=1.0 --target release --no-cache app")
+
+    args = docker.build_args()
+    assert(#args == 2)
+    assert(args[2] == ".")
+end
+
+do -- docker.run_args
+    local args = docker.run_args({
+        image = "alpine",
+        cmd = "echo $HOME",
+        volumes = {["/tmp"] = "/data"},
+        env = {B = 2, A = "1"},
+        workdir = "/data",
+    })
+
+    assert(table.concat(args, " ") == "run --rm -v /tmp:/data -e A=1 -e B=2 -w /data alpine sh -c echo $HOME")
+
+    args = docker.run_args({image = "alpine", cmd = {"ls", "-l"}})
+    assert(table.concat(args, " ") == "run --rm alpine ls -l")
+
+    assert(not pcall(docker.run_args, {cmd = "true"}))
+end
+
+do -- docker.task_in_container
+    docker.task_in_container("docker_test_task", {image = "alpine", cmd = "true"})
+    assert(rote.task_exists("docker_test_task"))
+end
+
+assert(docker.build)
+assert(docker.push)
+assert(docker.run)
@@ -0,0 +1,30 @@
+docs = require "docs"
+
+
+do -- docs.doxygen_config
+    local overrides = {GENERATE_LATEX = "NO", EXTRACT_ALL = "YES"}
+    local config = docs.doxygen_config({
+        config = "tests/fixtures/docs/Doxyfile",
+        dest = "build/docs",
+        srcs = {"tests/fixtures/fs1.in", "tests/fixtures/fs2.in"},
+        overrides = overrides,
+    })
+
+    assert(config == [[
+PROJECT_NAME = Example
+
+EXTRACT_ALL = YES
+GENERATE_LATEX = NO
+INPUT = tests/fixtures/fs1.in tests/fixtures/fs2.in
+OUTPUT_DIRECTORY = build/docs]])
+
+    -- The overrides of the caller are left alone.
+    assert(overrides.OUTPUT_DIRECTORY == nil)
+    assert(overrides.INPUT == nil)
+
+    -- Without a configuration file, only the overrides are used.
+    assert(docs.doxygen_config({
+        config = "tests/fixtures/docs/missing",
+        overrides = {QUIET = true},
+    }) == "\nQUIET = true")
+end
@@ -0,0 +1,29 @@
+dotnet = require "dotnet"
+
+
+do -- dotnet.parse_diagnostics
+    local diagnostics = dotnet.parse_diagnostics([[
+Program.cs(12,17): error CS1002: ; expected [/src/app/app.csproj]
+  Util.cs(3,5): warning CS0168: The variable 'e' is declared but never used [/src/app/app.csproj]
+Build FAILED.
+Program.cs(12,17): error CS1002: ; expected [/src/app/app.csproj]
+MSBUILD : error MSB1009: Project file does not exist.
+]])
+
+    assert(#diagnostics == 3)
+
+    assert(diagnostics[1].severity == "error")
+    assert(diagnostics[1].file == "Program.cs")
+    assert(diagnostics[1].line == 12)
+    assert(diagnostics[1].column == 17)
+    assert(diagnostics[1].code == "CS1002")
+    assert(diagnostics[1].message == "; expected")
+    assert(diagnostics[1].project == "/src/app/app.csproj")
+
+    assert(diagnostics[2].severity == "warning")
+    assert(diagnostics[2].file == "Util.cs")
+
+    assert(diagnostics[3].code == "MSB1009")
+    assert(diagnostics[3].file == nil)
+    assert(diagnostics[3].message == "Project file does not exist.")
+end
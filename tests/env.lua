@@ -0,0 +1,18 @@
+local env = require "env"
+
+
+env.set("ROTE_TEST_VAR", "hello $WORLD")
+assert(env.get("ROTE_TEST_VAR") == "hello $WORLD")
+assert(env.vars().ROTE_TEST_VAR == "hello $WORLD")
+
+env.unset("ROTE_TEST_VAR")
+assert(env.get("ROTE_TEST_VAR") == nil)
+assert(env.get("ROTE_TEST_VAR", "default") == "default")
+assert(env.vars().ROTE_TEST_VAR == nil)
+
+local sep = OS == "windows" and ";" or ":"
+env.set("ROTE_TEST_PATH", "a" .. sep .. "b")
+env.path_prepend("b", "ROTE_TEST_PATH")
+env.path_prepend("c", "ROTE_TEST_PATH")
+assert(env.get("ROTE_TEST_PATH") == "c" .. sep .. "b" .. sep .. "a")
+env.unset("ROTE_TEST_PATH")
@@ -0,0 +1,27 @@
+require "fs"
+local exec = require "exec"
+
+
+if OS == "unix" then
+    local code, stdout, stderr = exec.run("sh", {"-c", "echo out; echo err >&2; exit 3"})
+    assert(code == 3)
+    assert(stdout == "out\n")
+    assert(stderr == "err\n")
+
+    code, stdout = exec.run("cat", {}, {stdin = "hello"})
+    assert(code == 0)
+    assert(stdout == "hello")
+
+    code, stdout = exec.run("sh", {"-c", "echo $GREETING"}, {env = {GREETING = "hi"}})
+    assert(stdout == "hi\n")
+
+    assert(not pcall(exec.run, "sleep", {"5"}, {timeout = 0.1}))
+
+    -- Processes started by a command that times out are killed along with it.
+    local pid_file = "tests/fixtures/exec_sleep.pid"
+    assert(not pcall(exec.run, "sh", {"-c", "sleep 100 & echo $! > " .. pid_file .. "; wait"}, {timeout = 0.5}))
+    local pid = fs.get(pid_file):match("%d+")
+    fs.remove(pid_file)
+    -- A killed process may be left as a zombie for a moment, which doesn't count as running.
+    assert(exec.run("sh", {"-c", "sleep 0.5; ps -o stat= -p " .. pid .. " | grep -qv Z"}) ~= 0)
+end
@@ -31,3 +31,9 @@ fs.remove("tests/fixtures/fs1.out")
 assert(fs.exists("tests/fixtures/fs1.out") == false)
 fs.remove("tests/fixtures/fs2.out")
 assert(fs.exists("tests/fixtures/fs2.out") == false)
+
+local paths = fs.glob("tests/fixtures/fs*.in", "tests/fixtures/fs1.*")
+assert(#paths == 2)
+assert(paths[1] == "tests/fixtures/fs1.in")
+assert(paths[2] == "tests/fixtures/fs2.in")
+assert(#fs.glob("tests/fixtures/*.nothing") == 0)
@@ -0,0 +1,17 @@
+git = require "git"
+
+
+do -- git.log_since
+    local subjects = git.log_since()
+    assert(#subjects > 0)
+    assert(type(subjects[1]) == "string")
+
+    assert(not pcall(git.log_since, "no-such-tag"))
+end
+
+do -- git.latest_tag
+    assert(git.latest_tag("no-such-ref") == nil)
+end
+
+assert(git.tag)
+assert(git.archive)
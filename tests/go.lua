@@ -0,0 +1,27 @@
+go = require "go"
+
+
+do -- go.command
+    local args, env = go.command("build", {
+        packages = "./cmd/server",
+        goos = "linux",
+        goarch = "arm64",
+        cgo = false,
+        tags = {"netgo", "osusergo"},
+        o = "server",
+        race = false,
+    }, {"o", "race"})
+
+    assert(table.concat(args, " ") == "build -tags netgo,osusergo -o server ./cmd/server")
+    assert(env.GOOS == "linux")
+    assert(env.GOARCH == "arm64")
+    assert(env.CGO_ENABLED == "0")
+
+    args, env = go.command("vet")
+    assert(table.concat(args, " ") == "vet ./...")
+    assert(next(env) == nil)
+end
+
+assert(go.build)
+assert(go.test)
+assert(go.vet)
@@ -0,0 +1,12 @@
+local hash = require "hash"
+
+
+assert(hash.md5("abc") == "900150983cd24fb0d6963f7d28e17f72")
+assert(hash.sha1("abc") == "a9993e364706816aba3e25717850c26c9cd0d89d")
+assert(hash.sha256("") == "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+
+assert(hash.file_md5("tests/fixtures/fs1.in") == hash.md5("hello\n"))
+assert(hash.file_sha1("tests/fixtures/fs1.in") == hash.sha1("hello\n"))
+assert(hash.file_sha256("tests/fixtures/fs1.in") == "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03")
+
+assert(not pcall(hash.file_sha256, "tests/fixtures/missing"))
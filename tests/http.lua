@@ -0,0 +1,11 @@
+local http = require "http"
+
+
+-- Arguments of the wrong type are reported without sending any request.
+local ok, err = pcall(http.download, {}, "out")
+assert(not ok)
+assert(err:find("bad argument #1 (string expected, got table)", 1, true))
+
+ok, err = pcall(http.download, "http://localhost/", "out", {sha256 = true})
+assert(not ok)
+assert(err:find("bad field 'sha256' in argument #3 (string expected, got boolean)", 1, true))
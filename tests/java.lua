@@ -0,0 +1,34 @@
+java = require "java"
+
+
+do -- java.class_file
+    assert(java.class_file("tests/fixtures/java/com/example/Main.java", "classes") == "classes/com/example/Main.class")
+    assert(not pcall(java.class_file, "Main.cpp"))
+end
+
+do -- java.classpath
+    local separator = OS == "windows" and ";" or ":"
+
+    assert(java.classpath({"classes", "lib/a.jar"}) == "classes" .. separator .. "lib/a.jar")
+    assert(java.classpath({"tests/fixtures/java/*/*/*.java"}) == "tests/fixtures/java/com/example/Main.java")
+end
+
+do -- java.manifest
+    assert(java.manifest({["Main-Class"] = "com.example.Main"}) ==
+        "Manifest-Version: 1.0\r\nMain-Class: com.example.Main\r\n")
+
+    local manifest = java.manifest({["Class-Path"] = string.rep("lib/a.jar ", 10)})
+    for line in manifest:gmatch("[^\r\n]+") do
+        assert(#line <= 72)
+    end
+    assert(manifest:find("\r\n lib"))
+end
+
+do -- java.maven
+    -- Nothing is run in a dry run.
+    java.maven({"clean", "package"}, {dry_run = true, properties = {skipTests = true}})
+end
+
+assert(java.binary)
+assert(java.jar)
+assert(java.gradle)
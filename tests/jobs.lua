@@ -0,0 +1,23 @@
+require "fs"
+local exec = require "exec"
+
+
+local dir = "tests/fixtures/jobs"
+
+local function loads(jobs, ...)
+    fs.put(dir .. "/loads.txt", "")
+    local code = exec.run(rote.current_exe(), {"-j", jobs, ...}, {cwd = dir})
+    assert(code == 0)
+
+    local _, count = fs.get(dir .. "/loads.txt"):gsub("\n", "")
+    return count
+end
+
+-- With one job, tasks run on the script that was loaded to find them.
+assert(loads("1", "a", "b") == 1)
+
+-- Tasks that can't run at the same time don't need another thread.
+assert(loads("2", "c") == 1)
+
+fs.remove(dir .. "/loads.txt")
+fs.remove(dir .. "/.rote")
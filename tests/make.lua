@@ -0,0 +1,73 @@
+make = require "make"
+
+
+do -- make.expand
+    local variables = {
+        CC = "gcc",
+        CFLAGS = "-O2 $(EXTRA)",
+        EXTRA = "-Wall",
+    }
+
+    assert(make.expand("$(CC) $(CFLAGS)", variables) == "gcc -O2 -Wall")
+    assert(make.expand("${CC} -o $@ $<", variables, {
+        ["@"] = "main",
+        ["<"] = "main.c",
+    }) == "gcc -o main main.c")
+    assert(make.expand("echo $$HOME", variables) == "echo $HOME")
+end
+
+do -- make.parse
+    local makefile = make.parse([[
+# Build settings
+CC = gcc
+OBJS := main.o \
+        util.o
+OBJS += extra.o
+
+.PHONY: all clean
+
+all: app
+
+app: $(OBJS)
+	$(CC) -o $@ $^
+
+%.o: %.c
+	$(CC) -c -o $@ $<
+
+clean:
+	-rm -f app *.o
+]])
+
+    assert(makefile.variables.CC == "gcc")
+    assert(makefile.variables.OBJS == "main.o util.o extra.o")
+    assert(#makefile.targets == 4)
+
+    local all = makefile.targets[1]
+    assert(all.name == "all")
+    assert(all.phony == true)
+    assert(all.deps[1] == "app")
+
+    local app = makefile.targets[2]
+    assert(app.name == "app")
+    assert(app.phony == false)
+    assert(#app.deps == 3)
+    assert(app.recipe[1] == "$(CC) -o $@ $^")
+
+    local objects = makefile.targets[3]
+    assert(objects.pattern == true)
+    assert(objects.deps[1] == "%.c")
+
+    local clean = makefile.targets[4]
+    assert(clean.phony == true)
+    assert(clean.recipe[1] == "-rm -f app *.o")
+end
+
+do -- make.import
+    local imported = make.import("tests/fixtures/make/Makefile", {prefix = "make_"})
+    assert(#imported == 3)
+
+    -- Sources that no target provides are inputs instead of dependencies.
+    local check = rote.task_info("make_check")
+    assert(#check.dependencies == 1)
+    assert(check.dependencies[1] == "make_app")
+end
@@ -0,0 +1,21 @@
+node = require "node"
+
+
+do -- node.read_package
+    local package = node.read_package("tests/fixtures/package.json")
+
+    assert(package.name == "fixture")
+    assert(package.scripts.build == "tsc -p .")
+    assert(next(package.dependencies) == nil)
+
+    assert(node.read_package("tests/fixtures").name == "fixture")
+    assert(not pcall(node.read_package, "tests/fixtures/fs1.in"))
+end
+
+do -- node.is_installed
+    assert(not node.is_installed("tests/fixtures"))
+end
+
+assert(node.npm_install)
+assert(node.npm_run)
+assert(node.npx)
@@ -0,0 +1,18 @@
+local path = require "path"
+
+
+if OS == "unix" then
+    assert(path.join("src", "modules", "path.rs") == "src/modules/path.rs")
+    assert(path.join("src", "/usr") == "/usr")
+    assert(path.dirname("src/modules/path.rs") == "src/modules")
+    assert(path.normalize("./src/../tests/./fs.lua") == "tests/fs.lua")
+    assert(path.normalize("../a/..") == "..")
+    assert(path.relative("/usr/lib", "/usr/share/doc") == "../share/doc")
+    assert(path.relative("/usr", "/usr") == ".")
+    assert(path.absolute("tests") == rote.current_dir() .. "/tests")
+end
+
+assert(path.basename("tests/fs.lua") == "fs.lua")
+assert(path.dirname("fs.lua") == ".")
+assert(path.extension("archive.tar.gz") == "gz")
+assert(path.extension("Rotefile") == "")
@@ -0,0 +1,29 @@
+php = require "php"
+require "fs"
+
+
+do -- php.install_stamp
+    local stamp = php.install_stamp({dir = "tests/fixtures/php"})
+    assert(stamp:match("^%x+ no%-dev\n$"))
+    assert(php.install_stamp({dir = "tests/fixtures/php", no_dev = false}):match("^%x+\n$"))
+
+    assert(php.install_stamp({dir = "tests"}) == nil)
+end
+
+do -- php.is_installed
+    assert(not php.is_installed({dir = "tests/fixtures/php"}))
+
+    if not fs.exists("tests/fixtures/php/vendor") then
+        fs.mkdir("tests/fixtures/php/vendor")
+    end
+    fs.put("tests/fixtures/php/vendor/.composer-lock", php.install_stamp({dir = "tests/fixtures/php"}))
+    assert(php.is_installed({dir = "tests/fixtures/php"}))
+    assert(not php.is_installed({dir = "tests/fixtures/php", no_dev = false}))
+
+    -- Nothing needs to be installed, so composer isn't run.
+    assert(php.composer_install({dir = "tests/fixtures/php"}) == false)
+    fs.remove("tests/fixtures/php/vendor")
+end
+
+assert(php.composer_update)
+assert(php.composer_run)
@@ -0,0 +1,53 @@
+local regex = require "regex"
+
+
+do -- regex.match
+    local m = regex.match([[(?P<file>[^:]+):(?P<line>\d+): (.*)]], "src/main.c:12: warning: unused")
+    assert(m[0] == "src/main.c:12: warning: unused")
+    assert(m.file == "src/main.c")
+    assert(m.line == "12")
+    assert(m[3] == "warning: unused")
+
+    assert(regex.match("x+", "abc") == nil)
+    assert(regex.match("(a)|(b)", "b")[1] == nil)
+end
+
+do -- regex.is_match
+    assert(regex.is_match("^\\d+$", "123"))
+    assert(not regex.is_match("^\\d+$", "12a"))
+end
+
+do -- regex.find_all
+    local matches = regex.find_all("(\\w)=(\\d)", "a=1, b=2, c=x")
+    assert(#matches == 2)
+    assert(matches[1][1] == "a" and matches[1][2] == "1")
+    assert(matches[2][0] == "b=2")
+end
+
+do -- regex.replace
+    assert(regex.replace("(\\w+)@(\\w+)", "me@host", "$2 at $1") == "host at me")
+    assert(regex.replace("a", "aaa", "b", 2) == "bba")
+    assert(regex.replace("\\d+", "1 2 3", function(m)
+        return tostring(tonumber(m[0]) * 2)
+    end) == "2 4 6")
+    assert(regex.replace("\\d", "1 2", function() return nil end) == "1 2")
+end
+
+do -- regex.split
+    local parts = regex.split(",\\s*", "a, b,c")
+    assert(#parts == 3 and parts[2] == "b")
+end
+
+do -- regex.escape
+    assert(regex.is_match("^" .. regex.escape("a.b*") .. "$", "a.b*"))
+    assert(not regex.is_match("^" .. regex.escape("a.b") .. "$", "axb"))
+end
+
+do -- regex.new
+    local re = regex.new("(\\d+)\\.(\\d+)")
+    assert(re.pattern == "(\\d+)\\.(\\d+)")
+    assert(re:match("version 1.2")[2] == "2")
+    assert(re:replace("1.2", "$2.$1") == "2.1")
+
+    assert(not pcall(regex.new, "("))
+end
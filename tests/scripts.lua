@@ -0,0 +1,16 @@
+scripts = require "scripts"
+
+
+do -- scripts.npm
+    local imported = scripts.npm("tests/fixtures/package.json")
+
+    assert(#imported == 2)
+    assert(imported[1] == "npm:build")
+    assert(imported[2] == "npm:test")
+end
+
+do -- scripts.npm with a prefix
+    local imported = scripts.npm("tests/fixtures/package.json", {prefix = "web:"})
+
+    assert(imported[1] == "web:build")
+end
@@ -0,0 +1,15 @@
+local template = require "template"
+local fs = require "fs"
+
+
+assert(template.render("Hello {{ name }}!", {name = "world"}) == "Hello world!")
+assert(template.render("v{{version.major}}.{{version.minor}}", {version = {major = 1, minor = 2}}) == "v1.2")
+assert(template.render("{{debug}}", {debug = false}) == "false")
+assert(not pcall(template.render, "{{missing}}", {}))
+
+fs.put("tests/fixtures/template.in", "#define VERSION \"{{version}}\"\n")
+template.render_file("tests/fixtures/template.in", "tests/fixtures/template.out", {version = "1.0"})
+assert(fs.get("tests/fixtures/template.out") == "#define VERSION \"1.0\"\n")
+
+fs.remove("tests/fixtures/template.in")
+fs.remove("tests/fixtures/template.out")
@@ -0,0 +1,27 @@
+require "fs"
+local exec = require "exec"
+
+
+if OS == "unix" then
+    local dir = "tests/fixtures/watch"
+    fs.put(dir .. "/config.h", "one\n")
+    fs.remove(dir .. "/config.txt")
+
+    -- Changing a header that a rule reads as an input, and doesn't depend on, builds it again.
+    exec.run("sh", {"-c", [[
+        cd "$DIR" || exit 1
+        "$ROTE" --watch >/dev/null 2>&1 &
+        pid=$!
+        sleep 2
+        echo two > config.h
+        sleep 3
+        kill $pid
+        wait $pid
+    ]]}, {env = {DIR = dir, ROTE = rote.current_exe()}, timeout = 30})
+
+    assert(fs.get(dir .. "/config.txt") == "two\n")
+
+    fs.remove(dir .. "/config.h")
+    fs.remove(dir .. "/config.txt")
+    fs.remove(dir .. "/.rote")
+end
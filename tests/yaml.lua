@@ -0,0 +1,39 @@
+local yaml = require "yaml"
+
+
+do -- yaml.decode
+    assert(yaml.decode("~") == nil)
+    assert(yaml.decode("true") == true)
+    assert(yaml.decode("42") == 42)
+    assert(yaml.decode("1.5") == 1.5)
+    assert(yaml.decode("marvin") == "marvin")
+
+    local result = yaml.decode([[
+services:
+  web:
+    image: "nginx:latest"
+    ports:
+      - 80
+      - 443
+]])
+
+    assert(type(result) == "table")
+    assert(result.services.web.image == "nginx:latest")
+    assert(result.services.web.ports[1] == 80)
+    assert(result.services.web.ports[2] == 443)
+
+    local documents = yaml.decode("--- 1\n--- 2\n", true)
+    assert(#documents == 2)
+    assert(documents[2] == 2)
+
+    assert(not pcall(yaml.decode, "key: [unclosed"))
+end
+
+do -- yaml.encode
+    local value = {name = "rote", tags = {"build", "lua"}, stars = 42}
+    local result = yaml.decode(yaml.encode(value))
+
+    assert(result.name == "rote")
+    assert(result.tags[2] == "lua")
+    assert(result.stars == 42)
+end